@@ -0,0 +1,321 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread;
+
+// Extensions of the audio files which can be loaded
+pub const AUDIO_EXTENSIONS: &[&str] = &["wav", "flac", "ogg"];
+
+// Files loaded by `AudioFile::load_shared()` which are still held by a node, with the sample rate they were
+// converted to
+static SHARED_FILES: Mutex<Vec<(PathBuf, f32, Weak<AudioFile>)>> = Mutex::new(Vec::new());
+
+#[derive(Debug)]
+pub enum AudioFileError {
+    Io(io::Error),
+    // The file could be read but not decoded
+    Format(String),
+}
+
+impl fmt::Display for AudioFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioFileError::Io(error) => write!(f, "{}", error),
+            AudioFileError::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AudioFileError {}
+
+impl From<io::Error> for AudioFileError {
+    fn from(error: io::Error) -> Self {
+        AudioFileError::Io(error)
+    }
+}
+
+impl From<claxon::Error> for AudioFileError {
+    fn from(error: claxon::Error) -> Self {
+        match error {
+            claxon::Error::IoError(error) => AudioFileError::Io(error),
+            error => AudioFileError::Format(error.to_string()),
+        }
+    }
+}
+
+impl From<lewton::VorbisError> for AudioFileError {
+    fn from(error: lewton::VorbisError) -> Self {
+        AudioFileError::Format(error.to_string())
+    }
+}
+
+impl From<hound::Error> for AudioFileError {
+    fn from(error: hound::Error) -> Self {
+        match error {
+            hound::Error::IoError(error) => AudioFileError::Io(error),
+            error => AudioFileError::Format(error.to_string()),
+        }
+    }
+}
+
+// Decoded audio file with one buffer of samples per channel.
+//
+// Files are decoded and resampled on the UI thread and then shared with nodes,
+// so the audio thread never has to touch the disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioFile {
+    pub path: PathBuf,
+    pub sample_rate: f32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl AudioFile {
+    // Decode a WAV, FLAC or Ogg Vorbis file, chosen by the file extension
+    pub fn load(path: &Path) -> Result<Self, AudioFileError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let (sample_rate, num_channels, samples) = match extension.as_str() {
+            "flac" => Self::decode_flac(path)?,
+            "ogg" => Self::decode_ogg(path)?,
+            _ => Self::decode_wav(path)?,
+        };
+
+        if num_channels == 0 {
+            return Err(AudioFileError::Format("file has no channels".to_string()));
+        }
+
+        let mut channels = vec![Vec::with_capacity(samples.len() / num_channels); num_channels];
+        for frame in samples.chunks_exact(num_channels) {
+            for (channel, sample) in channels.iter_mut().zip(frame.iter()) {
+                channel.push(*sample);
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sample_rate,
+            channels,
+        })
+    }
+
+    // Each decoder returns the sample rate, the number of channels and the interleaved samples
+    fn decode_wav(path: &Path) -> Result<(f32, usize, Vec<f32>), AudioFileError> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+        };
+
+        Ok((spec.sample_rate as f32, spec.channels as usize, samples))
+    }
+
+    fn decode_flac(path: &Path) -> Result<(f32, usize, Vec<f32>), AudioFileError> {
+        let mut reader = claxon::FlacReader::open(path)?;
+        let info = reader.streaminfo();
+
+        let scale = 1.0 / (1u32 << (info.bits_per_sample - 1)) as f32;
+        let samples = reader
+            .samples()
+            .map(|sample| sample.map(|sample| sample as f32 * scale))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((info.sample_rate as f32, info.channels as usize, samples))
+    }
+
+    fn decode_ogg(path: &Path) -> Result<(f32, usize, Vec<f32>), AudioFileError> {
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(File::open(path)?)?;
+        let sample_rate = reader.ident_hdr.audio_sample_rate as f32;
+        let num_channels = reader.ident_hdr.audio_channels as usize;
+
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl()? {
+            samples.extend(packet.iter().map(|sample| *sample as f32 / 32768.0));
+        }
+
+        Ok((sample_rate, num_channels, samples))
+    }
+
+    // Load a file and convert it to the given sample rate
+    pub fn load_resampled(path: &Path, sample_rate: f32) -> Result<Self, AudioFileError> {
+        Ok(Self::load(path)?.resampled(sample_rate))
+    }
+
+    // Load a file converted to the given sample rate, sharing it with every node which already holds it.
+    //
+    // A patch is built into a new graph after each edit, and a poly node builds its patch once for every voice,
+    // so without sharing the same file would be decoded many times over. A file is decoded again once no node
+    // holds it.
+    pub fn load_shared(path: &Path, sample_rate: f32) -> Result<Arc<Self>, AudioFileError> {
+        let mut shared = SHARED_FILES.lock().unwrap_or_else(|error| error.into_inner());
+        shared.retain(|(_, _, file)| file.strong_count() > 0);
+        let existing = shared
+            .iter()
+            .find(|(shared_path, shared_rate, _)| shared_path == path && *shared_rate == sample_rate)
+            .and_then(|(_, _, file)| file.upgrade());
+        if let Some(file) = existing {
+            return Ok(file);
+        }
+
+        let file = Arc::new(Self::load_resampled(path, sample_rate)?);
+        shared.push((path.to_path_buf(), sample_rate, Arc::downgrade(&file)));
+        Ok(file)
+    }
+
+    // Load and resample a file on a background thread, so neither the UI nor the audio thread waits for it
+    pub fn load_in_background(path: PathBuf, sample_rate: f32) -> PendingAudioFile {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver may have been dropped if the file is no longer wanted
+            let _ = sender.send(Self::load_resampled(&path, sample_rate));
+        });
+
+        PendingAudioFile { receiver }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    // Number of samples in each channel
+    pub fn len(&self) -> usize {
+        self.channels.first().map(|channel| channel.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    // Convert to a different sample rate using cubic interpolation
+    pub fn resampled(&self, sample_rate: f32) -> Self {
+        if (sample_rate - self.sample_rate).abs() < f32::EPSILON || self.is_empty() {
+            return self.clone();
+        }
+
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let length = (self.len() as f64 / ratio).floor() as usize;
+
+        let channels = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let at = |index: isize| channel[index.max(0).min(channel.len() as isize - 1) as usize];
+                (0..length)
+                    .map(|i| {
+                        let position = i as f64 * ratio;
+                        let index = position.floor() as isize;
+                        let t = (position - index as f64) as f32;
+                        let (xm1, x0, x1, x2) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+                        let c1 = 0.5 * (x1 - xm1);
+                        let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+                        let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+                        ((c3 * t + c2) * t + c1) * t + x0
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            path: self.path.clone(),
+            sample_rate,
+            channels,
+        }
+    }
+}
+
+// An audio file being loaded by `AudioFile::load_in_background()`
+pub struct PendingAudioFile {
+    receiver: mpsc::Receiver<Result<AudioFile, AudioFileError>>,
+}
+
+impl PendingAudioFile {
+    // Take the result if loading has finished
+    pub fn poll(&self) -> Option<Result<AudioFile, AudioFileError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Some(Err(AudioFileError::Format("loading stopped unexpectedly".to_string())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(sample_rate: f32, length: usize) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from("ramp.wav"),
+            sample_rate,
+            channels: vec![(0..length).map(|i| i as f32 / length as f32).collect()],
+        }
+    }
+
+    #[test]
+    fn resampling_scales_the_length() {
+        let file = ramp(48000.0, 1000);
+        assert_eq!(file.resampled(48000.0), file);
+
+        let half = file.resampled(24000.0);
+        assert_eq!(half.sample_rate, 24000.0);
+        assert_eq!(half.len(), 500);
+        // Every other sample of a straight line is picked up unchanged
+        assert!((half.channels[0][100] - file.channels[0][200]).abs() < 1.0e-6);
+
+        assert_eq!(file.resampled(44100.0).len(), 918);
+    }
+
+    #[test]
+    fn shared_files_are_loaded_once_per_rate() {
+        let path = std::env::temp_dir().join(format!("shared_file_{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..1000 {
+            writer.write_sample(i as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let first = AudioFile::load_shared(&path, 48000.0).unwrap();
+        let second = AudioFile::load_shared(&path, 48000.0).unwrap();
+        let resampled = AudioFile::load_shared(&path, 24000.0).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &resampled));
+        assert_eq!(first.len(), 1000);
+        assert_eq!(resampled.len(), 500);
+
+        // Once no node holds the file it is decoded again
+        let weak = Arc::downgrade(&first);
+        drop((first, second));
+        assert!(weak.upgrade().is_none());
+        let reloaded = AudioFile::load_shared(&path, 48000.0).unwrap();
+        assert_eq!(reloaded.len(), 1000);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
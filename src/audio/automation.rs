@@ -0,0 +1,445 @@
+use std::time::Instant;
+
+use super::node::*;
+use super::graph::NodeId;
+
+// Time taken by linear and exponential smoothing to reach a new value, in seconds
+pub const SMOOTHING_TIME: f32 = 0.02;
+// Time taken by the output of a node to fade out before a discrete parameter switches, and to fade back in after
+pub const SWITCH_FADE_TIME: f32 = 0.002;
+// Most frames processed between updates of a smoothed parameter
+pub const SMOOTHING_INTERVAL: usize = 16;
+// Most parameter changes which can be scheduled for one block. Extra changes are dropped.
+pub const MAX_PARAM_EVENTS: usize = 256;
+
+// A parameter change scheduled at a frame of the next block the graph processes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamEvent {
+    pub frame: usize,
+    pub node: NodeId,
+    pub index: usize,
+    pub value: f32,
+}
+
+impl ParamEvent {
+    pub fn new(frame: usize, node: NodeId, index: usize, value: f32) -> Self {
+        Self {
+            frame,
+            node,
+            index,
+            value,
+        }
+    }
+}
+
+// Deepest a node can be nested in subpatches for its parameters to be changed or its outputs probed while playing
+pub const MAX_PATH_DEPTH: usize = 8;
+
+// Path to a node through the subpatches containing it: its index in the top level patch, followed by its index in
+// each subpatch it is inside. The path is held inline, so it can be passed to the audio thread and dropped there
+// without freeing memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodePath {
+    ids: [NodeId; MAX_PATH_DEPTH],
+    len: usize,
+}
+
+impl NodePath {
+    // Returns None for an empty path, or one nested more than `MAX_PATH_DEPTH` deep
+    pub fn new(ids: &[NodeId]) -> Option<Self> {
+        if ids.is_empty() || ids.len() > MAX_PATH_DEPTH {
+            return None;
+        }
+
+        let mut path = Self {
+            ids: [0; MAX_PATH_DEPTH],
+            len: ids.len(),
+        };
+        path.ids[..ids.len()].copy_from_slice(ids);
+        Some(path)
+    }
+
+    pub fn as_slice(&self) -> &[NodeId] {
+        &self.ids[..self.len]
+    }
+}
+
+// A parameter edited in the UI, timestamped so the engine can place it in the block it is processing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamChange {
+    pub path: NodePath,
+    pub index: usize,
+    pub value: f32,
+    pub time: Instant,
+}
+
+impl ParamChange {
+    // Frame at which the change happens in a block of `frames` frames which started playing at `block_start`.
+    // Changes made before the block start at its first frame, and changes after it at its last.
+    pub fn frame(&self, block_start: Instant, sample_rate: f32, frames: usize) -> usize {
+        let elapsed = self.time.saturating_duration_since(block_start).as_secs_f32();
+        ((elapsed * sample_rate) as usize).min(frames.saturating_sub(1))
+    }
+}
+
+// A parameter moving smoothly to a new value
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Ramp {
+    index: usize,
+    value: f32,
+    target: f32,
+    smoothing: Smoothing,
+    // Change per frame of a linear ramp, or fraction of the distance left kept each frame of an exponential one
+    rate: f32,
+    // Frames left until the target is reached
+    remaining: usize,
+}
+
+impl Ramp {
+    fn new(index: usize, value: f32, target: f32, smoothing: Smoothing, sample_rate: f32) -> Self {
+        let length = (SMOOTHING_TIME * sample_rate).max(1.0);
+        let rate = match smoothing {
+            Smoothing::Linear => (target - value) / length,
+            // Within 1% of the target by the end of the ramp, where it jumps the rest of the way
+            _ => (-(100.0f32.ln()) / length).exp(),
+        };
+
+        Self {
+            index,
+            value,
+            target,
+            smoothing,
+            rate,
+            remaining: length as usize,
+        }
+    }
+
+    fn advance(&mut self, frames: usize) {
+        if frames >= self.remaining {
+            self.value = self.target;
+            self.remaining = 0;
+            return;
+        }
+
+        self.remaining -= frames;
+        match self.smoothing {
+            Smoothing::Linear => self.value += self.rate * frames as f32,
+            _ => self.value = self.target + (self.value - self.target) * self.rate.powi(frames as i32),
+        }
+    }
+}
+
+// Parameter smoothing and switching of one node in a graph.
+//
+// Smoothed parameters are set at the start of each stretch of at most `SMOOTHING_INTERVAL` frames, and
+// discrete parameters are set once the output of the node has faded out. Buffers are allocated up front
+// so nothing allocates while processing.
+pub struct NodeAutomation {
+    sample_rate: f32,
+    ramps: Vec<Ramp>,
+    // Discrete parameter values waiting for the output to fade out
+    switches: Vec<(usize, f32)>,
+    // Gain applied to the output, and whether it is fading out or back in
+    gain: f32,
+    fading_out: bool,
+    // Output channels which are faded. Gates and events pass through, as fading would move their edges.
+    faded: Vec<bool>,
+}
+
+impl NodeAutomation {
+    pub fn new(node: &dyn AudioNode, sample_rate: f32) -> Self {
+        let num_params = node.params().len();
+        let faded = node
+            .outputs()
+            .iter()
+            .flat_map(|port| {
+                let faded = port.kind == PortKind::Audio || port.kind == PortKind::Control;
+                std::iter::repeat_n(faded, port.channels)
+            })
+            .collect();
+
+        Self {
+            sample_rate,
+            ramps: Vec::with_capacity(num_params),
+            switches: Vec::with_capacity(num_params),
+            gain: 1.0,
+            fading_out: false,
+            faded,
+        }
+    }
+
+    // Start moving a parameter to a new value, in the way given by its smoothing
+    pub fn change(&mut self, node: &mut dyn AudioNode, index: usize, value: f32) {
+        let smoothing = match node.params().get(index) {
+            Some(param) => param.smoothing,
+            None => return,
+        };
+
+        match smoothing {
+            Smoothing::None => node.set_param(index, value),
+
+            Smoothing::Linear | Smoothing::Exponential => {
+                // A ramp already moving the parameter carries on from where it has got to
+                let current = match self.ramps.iter().position(|ramp| ramp.index == index) {
+                    Some(position) => self.ramps.swap_remove(position).value,
+                    None => node.get_param(index),
+                };
+                if self.ramps.len() < self.ramps.capacity() {
+                    self.ramps.push(Ramp::new(index, current, value, smoothing, self.sample_rate));
+                }
+            }
+
+            Smoothing::Switch => {
+                self.switches.retain(|(switch, _)| *switch != index);
+                if self.switches.len() < self.switches.capacity() {
+                    self.switches.push((index, value));
+                }
+                self.fading_out = true;
+            }
+        }
+    }
+
+    // End of the stretch of frames starting at `start` which can be processed with the current values, before `end`
+    pub fn stretch_end(&self, start: usize, end: usize) -> usize {
+        let mut end = end;
+        if !self.ramps.is_empty() {
+            end = end.min(start + SMOOTHING_INTERVAL);
+        }
+
+        // Stop where the output has faded out, so the switch happens at that frame
+        if self.fading_out {
+            let step = self.fade_step();
+            end = end.min(start + (self.gain / step).ceil().max(1.0) as usize);
+        }
+
+        end
+    }
+
+    // Set the values smoothed parameters have reached, before processing a stretch of frames
+    pub fn update(&mut self, node: &mut dyn AudioNode) {
+        for ramp in self.ramps.iter() {
+            node.set_param(ramp.index, ramp.value);
+        }
+    }
+
+    // Move on after processing a stretch of frames, fading the output of the node over the stretch.
+    // Discrete parameters switch once the output has faded out.
+    pub fn advance(&mut self, node: &mut dyn AudioNode, outputs: &mut [Vec<f32>], start: usize, end: usize) {
+        for ramp in self.ramps.iter_mut() {
+            ramp.advance(end - start);
+            if ramp.remaining == 0 {
+                node.set_param(ramp.index, ramp.target);
+            }
+        }
+        self.ramps.retain(|ramp| ramp.remaining > 0);
+
+        if !self.fading_out && self.gain >= 1.0 {
+            return;
+        }
+
+        let step = if self.fading_out { -self.fade_step() } else { self.fade_step() };
+        for (output, faded) in outputs.iter_mut().zip(self.faded.iter()) {
+            if !*faded {
+                continue;
+            }
+
+            let mut gain = self.gain;
+            for sample in output[start..end].iter_mut() {
+                gain = (gain + step).clamp(0.0, 1.0);
+                *sample *= gain;
+            }
+        }
+        self.gain = (self.gain + step * (end - start) as f32).clamp(0.0, 1.0);
+
+        if self.fading_out && self.gain <= 0.0 {
+            for (index, value) in self.switches.drain(..) {
+                node.set_param(index, value);
+            }
+            self.fading_out = false;
+        }
+    }
+
+    // Finish every ramp and switch at once, e.g. when the graph is reset
+    pub fn finish(&mut self, node: &mut dyn AudioNode) {
+        for ramp in self.ramps.drain(..) {
+            node.set_param(ramp.index, ramp.target);
+        }
+        for (index, value) in self.switches.drain(..) {
+            node.set_param(index, value);
+        }
+        self.gain = 1.0;
+        self.fading_out = false;
+    }
+
+    fn fade_step(&self) -> f32 {
+        1.0 / (SWITCH_FADE_TIME * self.sample_rate).max(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const LINEAR: usize = 0;
+    const EXPONENTIAL: usize = 1;
+    const SWITCH: usize = 2;
+    const INSTANT: usize = 3;
+
+    const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out"), PortInfo::gate("Gate")];
+    const PARAMS: &[ParamInfo] = &[
+        ParamInfo::new("Linear", 0.0, 10.0, 0.0),
+        ParamInfo::new("Exponential", 1.0, 1000.0, 1.0).with_smoothing(Smoothing::Exponential),
+        ParamInfo::discrete("Switch", 0.0, 3.0, 0.0).with_smoothing(Smoothing::Switch),
+        ParamInfo::new("Instant", 0.0, 1.0, 0.0).with_smoothing(Smoothing::None),
+    ];
+
+    struct TestNode {
+        values: [f32; 4],
+    }
+
+    impl AudioNode for TestNode {
+        fn inputs(&self) -> &[PortInfo] {
+            &[]
+        }
+
+        fn outputs(&self) -> &[PortInfo] {
+            OUTPUTS
+        }
+
+        fn params(&self) -> &[ParamInfo] {
+            PARAMS
+        }
+
+        fn get_param(&self, index: usize) -> f32 {
+            self.values[index]
+        }
+
+        fn set_param(&mut self, index: usize, value: f32) {
+            self.values[index] = value;
+        }
+
+        fn process(&mut self, _context: &ProcessContext, _inputs: &[&[f32]], _outputs: &mut [&mut [f32]]) {}
+    }
+
+    fn setup() -> (TestNode, NodeAutomation) {
+        let node = TestNode { values: [0.0, 1.0, 0.0, 0.0] };
+        let automation = NodeAutomation::new(&node, SAMPLE_RATE);
+        (node, automation)
+    }
+
+    // Run the automation the way the graph does for a block without events, returning the values of a
+    // parameter at the start of each stretch
+    fn run(node: &mut TestNode, automation: &mut NodeAutomation, outputs: &mut [Vec<f32>], index: usize) -> Vec<(usize, f32)> {
+        let frames = outputs[0].len();
+        let mut values = Vec::new();
+        let mut start = 0;
+        while start < frames {
+            let end = automation.stretch_end(start, frames);
+            automation.update(node);
+            values.push((start, node.get_param(index)));
+            automation.advance(node, outputs, start, end);
+            start = end;
+        }
+        values
+    }
+
+    #[test]
+    fn linear_ramp() {
+        let (mut node, mut automation) = setup();
+        automation.change(&mut node, LINEAR, 10.0);
+        assert_eq!(automation.stretch_end(0, 256), SMOOTHING_INTERVAL);
+
+        let length = (SMOOTHING_TIME * SAMPLE_RATE) as usize;
+        let mut outputs = vec![vec![1.0; length + 64]; 2];
+        let values = run(&mut node, &mut automation, &mut outputs, LINEAR);
+
+        // The value moves in equal steps of one interval at a time until it reaches the target
+        assert_eq!(values[0], (0, 0.0));
+        assert!((values[1].1 - 10.0 * SMOOTHING_INTERVAL as f32 / length as f32).abs() < 1.0e-4);
+        let half = values.iter().find(|(frame, _)| *frame == length / 2).unwrap();
+        assert!((half.1 - 5.0).abs() < 1.0e-3);
+        assert_eq!(node.get_param(LINEAR), 10.0);
+        assert_eq!(automation.stretch_end(0, 256), 256);
+
+        // Smoothed parameters leave the output alone
+        assert!(outputs.iter().all(|output| output.iter().all(|sample| *sample == 1.0)));
+    }
+
+    #[test]
+    fn exponential_ramp() {
+        let (mut node, mut automation) = setup();
+        automation.change(&mut node, EXPONENTIAL, 1000.0);
+
+        let length = (SMOOTHING_TIME * SAMPLE_RATE) as usize;
+        let mut outputs = vec![vec![0.0; length + 64]; 2];
+        let values = run(&mut node, &mut automation, &mut outputs, EXPONENTIAL);
+
+        // Quick at first and slower as it gets close, ending on the target
+        let moving: Vec<f32> = values.iter().filter(|(frame, _)| *frame < length).map(|(_, value)| *value).collect();
+        let steps: Vec<f32> = moving.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(steps.windows(2).all(|pair| pair[1] <= pair[0]));
+        let last = values.iter().rev().find(|(frame, _)| *frame < length).unwrap();
+        assert!(last.1 > 990.0 * 0.98 && last.1 < 1000.0);
+        assert_eq!(node.get_param(EXPONENTIAL), 1000.0);
+    }
+
+    #[test]
+    fn ramp_changed_while_moving() {
+        let (mut node, mut automation) = setup();
+        automation.change(&mut node, LINEAR, 10.0);
+        let mut outputs = vec![vec![0.0; 480]; 2];
+        run(&mut node, &mut automation, &mut outputs, LINEAR);
+        let reached = node.get_param(LINEAR);
+        assert!(reached > 4.0 && reached < 6.0);
+
+        // A new target carries on from where the ramp has got to rather than jumping
+        automation.change(&mut node, LINEAR, 0.0);
+        let mut outputs = vec![vec![0.0; 32]; 2];
+        let values = run(&mut node, &mut automation, &mut outputs, LINEAR);
+        let step = 10.0 * SMOOTHING_INTERVAL as f32 / (SMOOTHING_TIME * SAMPLE_RATE);
+        assert!(values[0].1 > reached && values[0].1 <= reached + step + 1.0e-4);
+        assert!(values[1].1 < values[0].1);
+    }
+
+    #[test]
+    fn switch_fades_out_and_in() {
+        let (mut node, mut automation) = setup();
+        automation.change(&mut node, SWITCH, 2.0);
+        assert_eq!(node.get_param(SWITCH), 0.0);
+
+        let fade = (SWITCH_FADE_TIME * SAMPLE_RATE) as usize;
+        let mut outputs = vec![vec![1.0; 4 * fade]; 2];
+        let values = run(&mut node, &mut automation, &mut outputs, SWITCH);
+
+        // The value changes in the stretch starting where the output has faded out
+        let (switched, value) = values[1];
+        assert_eq!(values[0], (0, 0.0));
+        assert!(switched == fade || switched == fade + 1);
+        assert_eq!(value, 2.0);
+
+        let audio = &outputs[0];
+        assert!(audio[..fade].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(audio[fade - 1] < 0.02);
+        assert!(audio[switched..switched + fade].windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(audio[switched + fade..].iter().all(|sample| *sample == 1.0));
+
+        // Gates pass through untouched
+        assert!(outputs[1].iter().all(|sample| *sample == 1.0));
+    }
+
+    #[test]
+    fn unsmoothed_and_finished() {
+        let (mut node, mut automation) = setup();
+        automation.change(&mut node, INSTANT, 1.0);
+        assert_eq!(node.get_param(INSTANT), 1.0);
+        assert_eq!(automation.stretch_end(0, 256), 256);
+
+        automation.change(&mut node, LINEAR, 10.0);
+        automation.change(&mut node, SWITCH, 3.0);
+        automation.finish(&mut node);
+        assert_eq!(node.get_param(LINEAR), 10.0);
+        assert_eq!(node.get_param(SWITCH), 3.0);
+        assert_eq!(automation.stretch_end(0, 256), 256);
+    }
+}
@@ -0,0 +1,60 @@
+use super::node::*;
+
+const MONO_PORTS: &[PortInfo] = &[PortInfo::new("Left"), PortInfo::new("Right")];
+const STEREO_PORTS: &[PortInfo] = &[PortInfo::stereo("Stereo")];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMode {
+    // Two mono inputs combined into one stereo output
+    Merge,
+    // One stereo input split into two mono outputs
+    Split,
+}
+
+impl ChannelMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMode::Merge => "Stereo Merge",
+            ChannelMode::Split => "Stereo Split",
+        }
+    }
+}
+
+// Converts between a pair of mono ports and a stereo port.
+//
+// A stereo port has a buffer for each channel, so in both directions the buffers are passed through unchanged.
+pub struct ChannelNode {
+    mode: ChannelMode,
+}
+
+impl ChannelNode {
+    pub fn new(mode: ChannelMode) -> Self {
+        Self { mode }
+    }
+
+    pub fn mode(&self) -> ChannelMode {
+        self.mode
+    }
+}
+
+impl AudioNode for ChannelNode {
+    fn inputs(&self) -> &[PortInfo] {
+        match self.mode {
+            ChannelMode::Merge => MONO_PORTS,
+            ChannelMode::Split => STEREO_PORTS,
+        }
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        match self.mode {
+            ChannelMode::Merge => STEREO_PORTS,
+            ChannelMode::Split => MONO_PORTS,
+        }
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            output[..context.frames].copy_from_slice(&input[..context.frames]);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use super::node::*;
+
+const OUTPUTS: &[PortInfo] = &[PortInfo::gate("Clock"), PortInfo::gate("Bar")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::choice("Division", NOTE_DIVISION_NAMES, 1.0),
+    ParamInfo::new("Gate Length", 0.05, 0.95, 0.5).with_style(ParamStyle::Slider),
+    ParamInfo::new("Swing", 0.0, 0.5, 0.0).with_style(ParamStyle::Slider),
+];
+
+// Emits a gate at each note division of the song position while the transport is playing.
+//
+// Each gate lasts for a fraction of the division set by the gate length. Swing delays every
+// second gate by a fraction of the division. The bar output emits a gate at the start of each bar.
+pub struct Clock {
+    division: f32,
+    gate_length: f32,
+    swing: f32,
+}
+
+impl Clock {
+    pub const DIVISION: usize = 0;
+    pub const GATE_LENGTH: usize = 1;
+    pub const SWING: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            division: 1.0,
+            gate_length: 0.5,
+            swing: 0.0,
+        }
+    }
+
+    fn clock_high(&self, steps: f64) -> bool {
+        let step = steps.floor();
+        let offset = if step as i64 % 2 == 1 { self.swing } else { 0.0 };
+        let time = (steps - step) as f32 - offset;
+        // Swung gates are shortened so they end before the next division
+        time >= 0.0 && time < self.gate_length * (1.0 - offset)
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Clock {
+    fn inputs(&self) -> &[PortInfo] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::DIVISION => self.division,
+            Self::GATE_LENGTH => self.gate_length,
+            Self::SWING => self.swing,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::DIVISION => self.division = value.round(),
+            Self::GATE_LENGTH => self.gate_length = value,
+            Self::SWING => self.swing = value,
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let transport = &context.transport;
+        let division = note_division_beats(self.division) as f64;
+        let bar_length = transport.bar_length();
+        let bar_gate = self.gate_length as f64 * division.min(bar_length);
+
+        let (clocks, bars) = outputs.split_at_mut(1);
+        let gates = clocks[0][..context.frames].iter_mut().zip(bars[0][..context.frames].iter_mut());
+        for (i, (clock, bar)) in gates.enumerate() {
+            if !transport.playing {
+                *clock = 0.0;
+                *bar = 0.0;
+                continue;
+            }
+
+            let position = transport.position_at(i, context.sample_rate);
+            *clock = if self.clock_high(position / division) { 1.0 } else { 0.0 };
+            *bar = if position.rem_euclid(bar_length) < bar_gate { 1.0 } else { 0.0 };
+        }
+    }
+}
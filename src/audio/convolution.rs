@@ -0,0 +1,698 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, Once, Weak};
+use std::thread;
+use std::time::Duration;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use super::node::*;
+use super::audio_file::{AudioFile, AUDIO_EXTENSIONS};
+use super::graph::DEFAULT_SAMPLE_RATE;
+
+// Number of samples in each partition of the impulse response, which is also the latency of the convolution
+pub const PARTITION_SIZE: usize = 256;
+const FFT_SIZE: usize = 2 * PARTITION_SIZE;
+// Number of samples over which a convolver fades from one impulse response to the next
+const CROSSFADE_LENGTH: usize = 4 * PARTITION_SIZE;
+
+// Longest impulse response which can be used, in milliseconds
+const MAX_LENGTH_MS: f32 = 10000.0;
+// Furthest the start of the impulse response can be trimmed, in milliseconds
+const MAX_TRIM_MS: f32 = 2000.0;
+
+// Spectra of each partition of an impulse response, which are worked out away from the audio thread
+pub struct ImpulseSpectrum {
+    partitions: Vec<Vec<Complex<f32>>>,
+}
+
+impl ImpulseSpectrum {
+    pub fn new(impulse: &[f32]) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let partitions = impulse
+            .chunks(PARTITION_SIZE)
+            .map(|samples| {
+                let mut partition = vec![Complex::new(0.0, 0.0); FFT_SIZE];
+                for (bin, sample) in partition.iter_mut().zip(samples.iter()) {
+                    *bin = Complex::new(*sample, 0.0);
+                }
+                fft.process(&mut partition);
+                partition
+            })
+            .collect();
+
+        Self { partitions }
+    }
+
+    pub fn num_partitions(&self) -> usize {
+        self.partitions.len()
+    }
+}
+
+// Convolves a single channel with an impulse response using uniformly partitioned overlap-save convolution.
+//
+// Memory for the input history is reserved by `allocate()`, so changing the impulse response with
+// `set_impulse()` and processing do not allocate. Replaced impulse responses are dropped by the convolver, so
+// whoever made them should hold on to them until they are no longer used, to free them off the audio thread.
+pub struct Convolver {
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+
+    impulse: Option<Arc<ImpulseSpectrum>>,
+    // The impulse response being faded out after it was replaced, and how far the fade has got in samples
+    fading: Option<Arc<ImpulseSpectrum>>,
+    fade_position: usize,
+    // Impulse response set during a fade, which is faded to once the fade finishes
+    queued: Option<Arc<ImpulseSpectrum>>,
+    // Spectra of past input blocks, most recent at `history_index`
+    history: Vec<Vec<Complex<f32>>>,
+    history_index: usize,
+
+    // The previous and the current block of input
+    input: Vec<f32>,
+    position: usize,
+    // The output for the block currently being filled
+    output: Vec<f32>,
+
+    buffer: Vec<Complex<f32>>,
+    accumulator: Vec<Complex<f32>>,
+    // Output of the impulse response being faded out
+    fade_accumulator: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Convolver {
+    pub fn new() -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(FFT_SIZE);
+        let ifft = planner.plan_fft_inverse(FFT_SIZE);
+        let scratch_len = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+
+        Self {
+            fft,
+            ifft,
+
+            impulse: None,
+            fading: None,
+            fade_position: 0,
+            queued: None,
+            history: Vec::new(),
+            history_index: 0,
+
+            input: vec![0.0; FFT_SIZE],
+            position: 0,
+            output: vec![0.0; PARTITION_SIZE],
+
+            buffer: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            accumulator: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            fade_accumulator: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+        }
+    }
+
+    // Reserve room for an impulse response of up to `max_length` samples
+    pub fn allocate(&mut self, max_length: usize) {
+        let num_partitions = max_length.div_ceil(PARTITION_SIZE);
+        self.history = vec![vec![Complex::new(0.0, 0.0); FFT_SIZE]; num_partitions.max(1)];
+        self.impulse = None;
+        self.fading = None;
+        self.queued = None;
+        self.reset();
+    }
+
+    // Fade to a new impulse response over `CROSSFADE_LENGTH` samples, or switch straight to it if there was none.
+    // An impulse response set during a fade waits for the fade to finish, replacing any already waiting.
+    // The impulse response is truncated to the allocated length.
+    pub fn set_impulse(&mut self, impulse: Arc<ImpulseSpectrum>) {
+        if self.fading.is_some() {
+            self.queued = Some(impulse);
+            return;
+        }
+
+        self.fading = self.impulse.replace(impulse);
+        self.fade_position = 0;
+    }
+
+    pub fn reset(&mut self) {
+        for spectrum in self.history.iter_mut() {
+            for bin in spectrum.iter_mut() {
+                *bin = Complex::new(0.0, 0.0);
+            }
+        }
+        for sample in self.input.iter_mut().chain(self.output.iter_mut()) {
+            *sample = 0.0;
+        }
+        self.history_index = 0;
+        self.position = 0;
+    }
+
+    // Process one sample, returning the output delayed by `PARTITION_SIZE` samples
+    pub fn process_sample(&mut self, input: f32) -> f32 {
+        self.input[PARTITION_SIZE + self.position] = input;
+        let output = self.output[self.position];
+
+        self.position += 1;
+        if self.position == PARTITION_SIZE {
+            self.process_partition();
+            self.position = 0;
+        }
+
+        output
+    }
+
+    fn process_partition(&mut self) {
+        for (bin, sample) in self.buffer.iter_mut().zip(self.input.iter()) {
+            *bin = Complex::new(*sample, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let history_len = self.history.len();
+        self.history_index = (self.history_index + 1) % history_len;
+        self.history[self.history_index].copy_from_slice(&self.buffer);
+
+        self.convolve(self.impulse.clone().as_deref(), false);
+
+        // The first half is circular convolution wrap around, so only the second half is kept
+        let scale = 1.0 / FFT_SIZE as f32;
+        match self.fading.clone() {
+            Some(fading) => {
+                self.convolve(Some(&fading), true);
+                let outputs = self.accumulator[PARTITION_SIZE..].iter().zip(self.fade_accumulator[PARTITION_SIZE..].iter());
+                for (i, (sample, (bin, fade_bin))) in self.output.iter_mut().zip(outputs).enumerate() {
+                    let fade = (self.fade_position + i) as f32 / CROSSFADE_LENGTH as f32;
+                    *sample = (bin.re * fade + fade_bin.re * (1.0 - fade)) * scale;
+                }
+
+                self.fade_position += PARTITION_SIZE;
+                if self.fade_position >= CROSSFADE_LENGTH {
+                    self.fading = None;
+                    if let Some(queued) = self.queued.take() {
+                        self.set_impulse(queued);
+                    }
+                }
+            }
+
+            None => {
+                for (sample, bin) in self.output.iter_mut().zip(self.accumulator[PARTITION_SIZE..].iter()) {
+                    *sample = bin.re * scale;
+                }
+            }
+        }
+
+        self.input.copy_within(PARTITION_SIZE.., 0);
+    }
+
+    // Multiply the spectra of past input blocks with the partitions of an impulse response, into the accumulator
+    // or the fade accumulator, and transform the sum back into samples
+    fn convolve(&mut self, impulse: Option<&ImpulseSpectrum>, fading: bool) {
+        let accumulator = if fading { &mut self.fade_accumulator } else { &mut self.accumulator };
+        for bin in accumulator.iter_mut() {
+            *bin = Complex::new(0.0, 0.0);
+        }
+
+        let history_len = self.history.len();
+        let partitions = impulse.map(|impulse| &impulse.partitions[..impulse.num_partitions().min(history_len)]).unwrap_or(&[]);
+        for (k, partition) in partitions.iter().enumerate() {
+            let spectrum = &self.history[(self.history_index + history_len - k) % history_len];
+            for ((bin, x), h) in accumulator.iter_mut().zip(spectrum.iter()).zip(partition.iter()) {
+                *bin += x * h;
+            }
+        }
+
+        self.ifft.process_with_scratch(accumulator, &mut self.scratch);
+    }
+}
+
+impl Default for Convolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// How often the impulse worker looks for moved trims and frees the impulse responses which are no longer used
+const WORKER_INTERVAL: Duration = Duration::from_millis(10);
+
+// Left and right impulse responses of a file between a start and an end frame. A mono file gives the same
+// impulse response for both.
+fn trimmed_impulse(file: &AudioFile, start: usize, end: usize) -> (Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>) {
+    let left = Arc::new(ImpulseSpectrum::new(&file.channels[0][start..end]));
+    let right = match file.channels.get(1) {
+        Some(channel) => Arc::new(ImpulseSpectrum::new(&channel[start..end])),
+        None => left.clone(),
+    };
+    (left, right)
+}
+
+// Start and end frame of a trim, packed into one value so the audio thread can change both at once
+fn pack_trim(start: usize, end: usize) -> u64 {
+    ((start as u64) << 32) | (end as u64 & 0xffff_ffff)
+}
+
+fn unpack_trim(trim: u64) -> (usize, usize) {
+    ((trim >> 32) as usize, (trim & 0xffff_ffff) as usize)
+}
+
+// The impulse file of a convolution node at the engine sample rate, and the trim the node last asked for
+struct ImpulseSlot {
+    file: Arc<AudioFile>,
+    trim: AtomicU64,
+}
+
+// A node the worker works for, with the trim of its last impulse response and the impulse response waiting for
+// the node to take it
+struct WatchedSlot {
+    slot: Weak<ImpulseSlot>,
+    impulses: SyncSender<(Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>)>,
+    trim: u64,
+    ready: Option<(Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>)>,
+}
+
+// Slots added since the worker last looked, and the impulse responses worked out for them up front
+struct NewSlots {
+    slots: Vec<WatchedSlot>,
+    impulses: Vec<Arc<ImpulseSpectrum>>,
+}
+
+static NEW_SLOTS: Mutex<NewSlots> = Mutex::new(NewSlots {
+    slots: Vec::new(),
+    impulses: Vec::new(),
+});
+static WORKER: Once = Once::new();
+
+// Works out the impulse responses of every convolution node as they are trimmed, on a thread shared by all of
+// them, so moving the trim doesn't hold up the audio thread. The audio thread only stores the trim in the slot of
+// its node, which the worker looks at every `WORKER_INTERVAL`.
+//
+// The worker holds on to each impulse response it is given or makes until no convolver uses it, so they are
+// freed on its thread too.
+struct ImpulseWorker;
+
+impl ImpulseWorker {
+    // Start working for a node whose convolvers were given `impulse`, sending it impulse responses for new trims
+    fn watch(
+        slot: &Arc<ImpulseSlot>,
+        impulse: &(Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>),
+    ) -> Receiver<(Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>)> {
+        WORKER.call_once(|| {
+            thread::spawn(Self::run);
+        });
+
+        let (sender, impulses) = mpsc::sync_channel(1);
+        let mut new_slots = NEW_SLOTS.lock().unwrap();
+        new_slots.slots.push(WatchedSlot {
+            slot: Arc::downgrade(slot),
+            impulses: sender,
+            trim: slot.trim.load(Ordering::Relaxed),
+            ready: None,
+        });
+        Self::hold(&mut new_slots.impulses, impulse);
+
+        impulses
+    }
+
+    fn run() {
+        let mut watched: Vec<WatchedSlot> = Vec::new();
+        let mut held: Vec<Arc<ImpulseSpectrum>> = Vec::new();
+
+        loop {
+            {
+                let mut new_slots = NEW_SLOTS.lock().unwrap();
+                watched.append(&mut new_slots.slots);
+                held.append(&mut new_slots.impulses);
+            }
+
+            watched.retain_mut(|watched| {
+                let slot = match watched.slot.upgrade() {
+                    Some(slot) => slot,
+                    None => return false,
+                };
+
+                // A newer trim replaces an impulse response the node hasn't taken yet
+                let trim = slot.trim.load(Ordering::Relaxed);
+                if trim != watched.trim {
+                    let (start, end) = unpack_trim(trim);
+                    let impulse = trimmed_impulse(&slot.file, start, end);
+                    Self::hold(&mut held, &impulse);
+                    watched.ready = Some(impulse);
+                    watched.trim = trim;
+                }
+
+                match watched.ready.take().map(|impulse| watched.impulses.try_send(impulse)) {
+                    Some(Err(TrySendError::Full(impulse))) => watched.ready = Some(impulse),
+                    Some(Err(TrySendError::Disconnected(_))) => return false,
+                    _ => {}
+                }
+                true
+            });
+
+            held.retain(|impulse| Arc::strong_count(impulse) > 1);
+            thread::sleep(WORKER_INTERVAL);
+        }
+    }
+
+    // Hold on to an impulse response for each channel, once for the two channels of a mono file
+    fn hold(held: &mut Vec<Arc<ImpulseSpectrum>>, impulse: &(Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>)) {
+        held.push(impulse.0.clone());
+        if !Arc::ptr_eq(&impulse.0, &impulse.1) {
+            held.push(impulse.1.clone());
+        }
+    }
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::stereo("In")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::stereo("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::file("Impulse", AUDIO_EXTENSIONS),
+    ParamInfo::new("Trim Start", 0.0, MAX_TRIM_MS, 0.0)
+        .with_smoothing(Smoothing::None)
+        .with_skew(ParamSkew::Power(2.0))
+        .with_unit("ms"),
+    ParamInfo::new("Length", 1.0, MAX_LENGTH_MS, MAX_LENGTH_MS)
+        .with_smoothing(Smoothing::None)
+        .with_skew(ParamSkew::Logarithmic)
+        .with_unit("ms"),
+    ParamInfo::new("Gain", -48.0, 12.0, 0.0).with_unit("dB"),
+    ParamInfo::new("Mix", 0.0, 1.0, 1.0).with_style(ParamStyle::Slider),
+];
+
+// Convolution reverb using an impulse response loaded from a file.
+//
+// A mono impulse response is applied to both channels, while a stereo one is applied per channel.
+// The wet signal is delayed by `PARTITION_SIZE` samples.
+pub struct Convolution {
+    // Impulse file as it was loaded, kept until `prepare()` knows the sample rate
+    file: Option<Arc<AudioFile>>,
+    trim_start: f32,
+    length: f32,
+    gain: f32,
+    mix: f32,
+
+    sample_rate: f32,
+    left: Convolver,
+    right: Convolver,
+    // Impulse file at the engine sample rate and the trim asked of the worker, once the node is prepared
+    slot: Option<Arc<ImpulseSlot>>,
+    // Impulse responses for new trims, sent by the worker
+    impulses: Option<Receiver<(Arc<ImpulseSpectrum>, Arc<ImpulseSpectrum>)>>,
+}
+
+impl Convolution {
+    pub const IMPULSE: usize = 0;
+    pub const TRIM_START: usize = 1;
+    pub const LENGTH: usize = 2;
+    pub const GAIN: usize = 3;
+    pub const MIX: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            trim_start: 0.0,
+            length: MAX_LENGTH_MS,
+            gain: 0.0,
+            mix: 1.0,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            left: Convolver::new(),
+            right: Convolver::new(),
+            slot: None,
+            impulses: None,
+        }
+    }
+
+    // Start and end frame of a file of `len` frames left by the trim parameters
+    fn trim(&self, len: usize) -> (usize, usize) {
+        let samples_per_ms = 0.001 * self.sample_rate;
+        let start = ((self.trim_start * samples_per_ms) as usize).min(len);
+        let end = (start + (self.length * samples_per_ms) as usize).min(len);
+        (start, end)
+    }
+
+    // Ask the worker for the impulse response left by the trim parameters, which is faded in once it is ready
+    fn update_impulse(&mut self) {
+        if let Some(slot) = self.slot.as_ref() {
+            let (start, end) = self.trim(slot.file.len());
+            slot.trim.store(pack_trim(start, end), Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for Convolution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Convolution {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::TRIM_START => self.trim_start,
+            Self::LENGTH => self.length,
+            Self::GAIN => self.gain,
+            Self::MIX => self.mix,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::TRIM_START => {
+                self.trim_start = value;
+                self.update_impulse();
+            }
+            Self::LENGTH => {
+                self.length = value;
+                self.update_impulse();
+            }
+            Self::GAIN => self.gain = value,
+            Self::MIX => self.mix = value,
+            _ => {}
+        }
+    }
+
+    // The impulse response is worked out in `prepare()`, once the sample rate is known
+    fn set_file(&mut self, index: usize, file: Arc<AudioFile>) {
+        if index == Self::IMPULSE && file.num_channels() > 0 {
+            self.file = Some(file);
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_frames: usize) {
+        self.sample_rate = sample_rate;
+        let file = match self.file.as_ref() {
+            Some(file) => file,
+            None => return,
+        };
+
+        // Files are usually loaded at the engine sample rate already
+        let file = if (file.sample_rate - sample_rate).abs() > f32::EPSILON {
+            Arc::new(file.resampled(sample_rate))
+        } else {
+            file.clone()
+        };
+
+        let max_length = ((MAX_LENGTH_MS * 0.001 * sample_rate) as usize).min(file.len());
+        self.left.allocate(max_length);
+        self.right.allocate(max_length);
+
+        let (start, end) = self.trim(file.len());
+        let impulse = trimmed_impulse(&file, start, end);
+        self.left.set_impulse(impulse.0.clone());
+        self.right.set_impulse(impulse.1.clone());
+
+        let slot = Arc::new(ImpulseSlot {
+            file,
+            trim: AtomicU64::new(pack_trim(start, end)),
+        });
+        self.impulses = Some(ImpulseWorker::watch(&slot, &impulse));
+        self.slot = Some(slot);
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let impulse = self.impulses.as_ref().and_then(|impulses| impulses.try_recv().ok());
+        if let Some((left, right)) = impulse {
+            self.left.set_impulse(left);
+            self.right.set_impulse(right);
+        }
+
+        let wet = 10.0f32.powf(self.gain / 20.0) * self.mix;
+        let dry = 1.0 - self.mix;
+
+        if self.slot.is_none() {
+            for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                for i in 0..context.frames {
+                    output[i] = input[i] * dry;
+                }
+            }
+            return;
+        }
+
+        for i in 0..context.frames {
+            let (left, right) = (inputs[0][i], inputs[1][i]);
+            outputs[0][i] = left * dry + self.left.process_sample(left) * wet;
+            outputs[1][i] = right * dry + self.right.process_sample(right) * wet;
+        }
+    }
+}
+
+impl NodeEditorInfo for Convolution {
+    fn file_path(&self, index: usize) -> Option<&Path> {
+        match index {
+            Self::IMPULSE => self.file.as_ref().map(|file| file.path.as_path()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const BLOCK_SIZE: usize = 64;
+
+    fn impulse_file(channels: Vec<Vec<f32>>) -> Arc<AudioFile> {
+        Arc::new(AudioFile {
+            path: "impulse.wav".into(),
+            sample_rate: SAMPLE_RATE,
+            channels,
+        })
+    }
+
+    // A node fully wet, loaded with a file and prepared
+    fn convolution(file: Arc<AudioFile>) -> Convolution {
+        let mut convolution = Convolution::new();
+        convolution.set_file(Convolution::IMPULSE, file);
+        convolution.prepare(SAMPLE_RATE, BLOCK_SIZE);
+        convolution
+    }
+
+    fn render(convolution: &mut Convolution, left: &[f32], right: &[f32]) -> (Vec<f32>, Vec<f32>) {
+        let mut outputs = (vec![0.0; left.len()], vec![0.0; right.len()]);
+        let blocks = outputs.0.chunks_mut(BLOCK_SIZE).zip(outputs.1.chunks_mut(BLOCK_SIZE));
+        for (start, (left_output, right_output)) in (0..left.len()).step_by(BLOCK_SIZE).zip(blocks) {
+            let inputs = [&left[start..start + BLOCK_SIZE], &right[start..start + BLOCK_SIZE]];
+            convolution.process(&ProcessContext::new(SAMPLE_RATE, BLOCK_SIZE), &inputs, &mut [left_output, right_output]);
+        }
+        outputs
+    }
+
+    // Convolution sum worked out sample by sample, delayed by the latency of the partitioned convolution
+    fn direct(input: &[f32], impulse: &[f32]) -> Vec<f32> {
+        (0..input.len())
+            .map(|frame| {
+                let frame = match frame.checked_sub(PARTITION_SIZE) {
+                    Some(frame) => frame,
+                    None => return 0.0,
+                };
+                impulse
+                    .iter()
+                    .enumerate()
+                    .take(frame + 1)
+                    .map(|(delay, tap)| tap * input[frame - delay])
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn signal(length: usize, seed: usize) -> Vec<f32> {
+        (0..length).map(|frame| (((frame * 7919 + seed * 104729) % 1000) as f32 / 500.0 - 1.0) * 0.5).collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        for (frame, (actual, expected)) in actual.iter().zip(expected.iter()).enumerate() {
+            assert!((actual - expected).abs() < 1.0e-3, "frame {}: {} != {}", frame, actual, expected);
+        }
+    }
+
+    #[test]
+    fn mono_impulse_matches_direct_convolution() {
+        // An impulse response spanning several partitions, applied to both channels
+        let impulse = signal(3 * PARTITION_SIZE + 100, 1);
+        let mut convolution = convolution(impulse_file(vec![impulse.clone()]));
+
+        let (left, right) = (signal(8 * PARTITION_SIZE, 2), signal(8 * PARTITION_SIZE, 3));
+        let outputs = render(&mut convolution, &left, &right);
+        assert_close(&outputs.0, &direct(&left, &impulse));
+        assert_close(&outputs.1, &direct(&right, &impulse));
+    }
+
+    #[test]
+    fn stereo_impulse_matches_direct_convolution() {
+        let impulses = vec![signal(2 * PARTITION_SIZE + 10, 4), signal(2 * PARTITION_SIZE + 10, 5)];
+        let mut convolution = convolution(impulse_file(impulses.clone()));
+
+        let (left, right) = (signal(6 * PARTITION_SIZE, 6), signal(6 * PARTITION_SIZE, 7));
+        let outputs = render(&mut convolution, &left, &right);
+        assert_close(&outputs.0, &direct(&left, &impulses[0]));
+        assert_close(&outputs.1, &direct(&right, &impulses[1]));
+    }
+
+    #[test]
+    fn impulse_set_during_a_fade_waits_for_it() {
+        let gain = |gain: f32| Arc::new(ImpulseSpectrum::new(&[gain]));
+        let mut convolver = Convolver::new();
+        convolver.allocate(PARTITION_SIZE);
+        convolver.set_impulse(gain(1.0));
+
+        // A constant input gives the gain of the impulse response, which should glide from 1 to 2 and then to 3
+        let mut output = Vec::new();
+        for frame in 0..8 * CROSSFADE_LENGTH {
+            if frame == 2 * PARTITION_SIZE {
+                convolver.set_impulse(gain(2.0));
+            }
+            if frame == 3 * PARTITION_SIZE {
+                convolver.set_impulse(gain(3.0));
+            }
+            output.push(convolver.process_sample(1.0));
+        }
+
+        let steps = output[2 * PARTITION_SIZE..].windows(2).map(|pair| pair[1] - pair[0]);
+        assert!(steps.clone().all(|step| (0.0..=1.0e-2).contains(&step)));
+        assert!((output.last().unwrap() - 3.0).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn trim_leaves_the_impulse_between_start_and_length() {
+        // Each sample of the file is its own position, so the response to a click shows which part is left
+        let file: Vec<f32> = (0..SAMPLE_RATE as usize).map(|frame| frame as f32 / SAMPLE_RATE).collect();
+        let mut convolution = Convolution::new();
+        convolution.set_param(Convolution::TRIM_START, 100.0);
+        convolution.set_param(Convolution::LENGTH, 50.0);
+        convolution.set_file(Convolution::IMPULSE, impulse_file(vec![file.clone()]));
+        convolution.prepare(SAMPLE_RATE, BLOCK_SIZE);
+
+        let (start, length) = (4800, 2400);
+        let mut click = vec![0.0; (PARTITION_SIZE + length).next_multiple_of(BLOCK_SIZE) + 4 * BLOCK_SIZE];
+        click[0] = 1.0;
+        let (output, _) = render(&mut convolution, &click, &click);
+        assert_close(&output[PARTITION_SIZE..PARTITION_SIZE + length], &file[start..start + length]);
+        assert!(output[PARTITION_SIZE + length..].iter().all(|sample| sample.abs() < 1.0e-4));
+    }
+}
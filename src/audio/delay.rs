@@ -0,0 +1,281 @@
+use super::node::*;
+use super::filter::*;
+use super::graph::DEFAULT_SAMPLE_RATE;
+
+// Longest delay time which can be set with the time parameter, in milliseconds
+pub const MAX_DELAY_MS: f32 = 5000.0;
+// Extra room in the delay line for the time modulation input, in milliseconds
+const MAX_MODULATION_MS: f32 = 1000.0;
+// Shortest delay which can be read with cubic interpolation
+const MIN_DELAY_SAMPLES: f32 = 2.0;
+// Time constant used to smooth changes to the delay time parameter, in seconds
+const TIME_SMOOTHING: f32 = 0.05;
+
+// Circular buffer of past samples which can be read at fractional delays.
+//
+// The buffer is only allocated by `allocate()`, so reading and writing never allocates.
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_index: usize,
+}
+
+impl DelayLine {
+    pub fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(4)],
+            write_index: 0,
+        }
+    }
+
+    pub fn allocate(&mut self, length: usize) {
+        self.buffer = vec![0.0; length.max(4)];
+        self.write_index = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        for sample in self.buffer.iter_mut() {
+            *sample = 0.0;
+        }
+        self.write_index = 0;
+    }
+
+    pub fn write(&mut self, sample: f32) {
+        self.buffer[self.write_index] = sample;
+        self.write_index = (self.write_index + 1) % self.buffer.len();
+    }
+
+    // Read the sample written `delay` samples ago, where a delay of 1 is the most recent sample
+    pub fn read(&self, delay: usize) -> f32 {
+        let length = self.buffer.len();
+        let delay = delay.max(1).min(length);
+        self.buffer[(self.write_index + length - delay) % length]
+    }
+
+    // Read between samples using cubic hermite interpolation
+    pub fn read_cubic(&self, delay: f32) -> f32 {
+        let delay = delay.max(MIN_DELAY_SAMPLES).min((self.buffer.len() - 2) as f32);
+        let index = delay.floor() as usize;
+        let t = delay - index as f32;
+
+        let xm1 = self.read(index - 1);
+        let x0 = self.read(index);
+        let x1 = self.read(index + 1);
+        let x2 = self.read(index + 2);
+
+        let c1 = 0.5 * (x1 - xm1);
+        let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+        let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+        ((c3 * t + c2) * t + c1) * t + x0
+    }
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::new("In"), PortInfo::control("Time Mod")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Time", 0.0, MAX_DELAY_MS, 250.0).with_skew(ParamSkew::Power(3.0)).with_unit("ms"),
+    ParamInfo::toggle("Sync", 0.0),
+    ParamInfo::choice("Division", NOTE_DIVISION_NAMES, 3.0),
+    ParamInfo::new("Feedback", 0.0, 0.999, 0.4).with_style(ParamStyle::Slider),
+    ParamInfo::new("Mix", 0.0, 1.0, 0.5).with_style(ParamStyle::Slider),
+    ParamInfo::new("Low Cut", 20.0, 2000.0, 20.0)
+        .with_smoothing(Smoothing::Exponential)
+        .with_skew(ParamSkew::Logarithmic)
+        .with_unit("Hz"),
+    ParamInfo::new("High Cut", 200.0, 20000.0, 20000.0)
+        .with_smoothing(Smoothing::Exponential)
+        .with_skew(ParamSkew::Logarithmic)
+        .with_unit("Hz"),
+];
+
+// Feedback delay with a tone filter in the feedback path.
+//
+// The "Time Mod" input is added to the delay time in milliseconds and read with cubic interpolation,
+// so the node can be used for chorus, flanging and Karplus-Strong style plucked strings.
+pub struct Delay {
+    time: f32,
+    sync: bool,
+    division: f32,
+    feedback: f32,
+    mix: f32,
+    low_cut: f32,
+    high_cut: f32,
+
+    sample_rate: f32,
+    line: DelayLine,
+    // Delay time in samples, smoothed towards the time parameter
+    smoothed_delay: f32,
+    low_cut_filter: OnePole,
+    high_cut_filter: OnePole,
+}
+
+impl Delay {
+    pub const TIME: usize = 0;
+    pub const SYNC: usize = 1;
+    pub const DIVISION: usize = 2;
+    pub const FEEDBACK: usize = 3;
+    pub const MIX: usize = 4;
+    pub const LOW_CUT: usize = 5;
+    pub const HIGH_CUT: usize = 6;
+
+    pub fn new() -> Self {
+        let mut delay = Self {
+            time: 250.0,
+            sync: false,
+            division: 3.0,
+            feedback: 0.4,
+            mix: 0.5,
+            low_cut: 20.0,
+            high_cut: 20000.0,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            line: DelayLine::new(0),
+            smoothed_delay: 0.0,
+            low_cut_filter: OnePole::new(),
+            high_cut_filter: OnePole::new(),
+        };
+
+        // The delay line is only allocated once the node is prepared
+        delay.update_filters();
+        delay.reset();
+        delay
+    }
+
+    // Delay time set by the parameters, in milliseconds
+    fn target_time(&self, context: &ProcessContext) -> f32 {
+        if self.sync {
+            1000.0 * context.beats_to_seconds(note_division_beats(self.division))
+        } else {
+            self.time
+        }
+    }
+
+    fn update_filters(&mut self) {
+        self.low_cut_filter.set_cutoff(self.low_cut, self.sample_rate);
+        self.high_cut_filter.set_cutoff(self.high_cut, self.sample_rate);
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Delay {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::TIME => self.time,
+            Self::SYNC => self.sync as u8 as f32,
+            Self::DIVISION => self.division,
+            Self::FEEDBACK => self.feedback,
+            Self::MIX => self.mix,
+            Self::LOW_CUT => self.low_cut,
+            Self::HIGH_CUT => self.high_cut,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::TIME => self.time = value,
+            Self::SYNC => self.sync = value >= 0.5,
+            Self::DIVISION => self.division = value.round(),
+            Self::FEEDBACK => self.feedback = value,
+            Self::MIX => self.mix = value,
+            Self::LOW_CUT => self.low_cut = value,
+            Self::HIGH_CUT => self.high_cut = value,
+            _ => {}
+        }
+
+        self.update_filters();
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_frames: usize) {
+        self.sample_rate = sample_rate;
+        let length = ((MAX_DELAY_MS + MAX_MODULATION_MS) * 0.001 * sample_rate).ceil() as usize + 4;
+        self.line.allocate(length);
+        self.update_filters();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.line.clear();
+        self.low_cut_filter.reset();
+        self.high_cut_filter.reset();
+        self.smoothed_delay = self.time * 0.001 * self.sample_rate;
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let input = inputs[0];
+        let time_mod = inputs[1];
+        let output = &mut outputs[0];
+
+        let samples_per_ms = 0.001 * context.sample_rate;
+        let target_delay = self.target_time(context) * samples_per_ms;
+        let smoothing = 1.0 - (-1.0 / (TIME_SMOOTHING * context.sample_rate)).exp();
+
+        for i in 0..context.frames {
+            self.smoothed_delay += smoothing * (target_delay - self.smoothed_delay);
+            let delay = self.smoothed_delay + time_mod[i] * samples_per_ms;
+
+            let wet = self.line.read_cubic(delay);
+            let filtered = self.low_cut_filter.highpass(self.high_cut_filter.lowpass(wet));
+            self.line.write(input[i] + self.feedback * filtered);
+
+            output[i] = input[i] * (1.0 - self.mix) + wet * self.mix;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn delays_by_its_time_in_samples() {
+        let mut delay = Delay::new();
+        delay.set_param(Delay::TIME, 10.0);
+        delay.set_param(Delay::FEEDBACK, 0.0);
+        delay.set_param(Delay::MIX, 1.0);
+        delay.prepare(SAMPLE_RATE, 1024);
+
+        let mut input = vec![0.0; 1024];
+        input[0] = 1.0;
+        let time_mod = vec![0.0; 1024];
+        let mut output = vec![0.0; 1024];
+        delay.process(&ProcessContext::new(SAMPLE_RATE, 1024), &[&input, &time_mod], &mut [&mut output]);
+
+        // 10 ms at 48 kHz is 480 samples
+        let peak = output.iter().enumerate().fold(0, |peak, (frame, sample)| if *sample > output[peak] { frame } else { peak });
+        assert_eq!(peak, 480);
+        assert!((output[480] - 1.0).abs() < 1.0e-3);
+        assert!(output.iter().enumerate().all(|(frame, sample)| frame == 480 || sample.abs() < 1.0e-3));
+    }
+}
@@ -0,0 +1,514 @@
+use super::node::*;
+use super::graph::DEFAULT_SAMPLE_RATE;
+use super::meter::Meter;
+
+// Longest look-ahead of the limiter, in milliseconds
+const MAX_LOOKAHEAD_MS: f32 = 10.0;
+// Level below which the detector treats the signal as silence, in dB
+const SILENCE_DB: f32 = -120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DynamicsMode {
+    Compressor,
+    // Compressor with an infinite ratio and an optional look-ahead
+    Limiter,
+    // Downward expander which attenuates signals below the threshold
+    Gate,
+}
+
+impl DynamicsMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DynamicsMode::Compressor => "Compressor",
+            DynamicsMode::Limiter => "Limiter",
+            DynamicsMode::Gate => "Gate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    Threshold,
+    Ratio,
+    Knee,
+    Attack,
+    Release,
+    Makeup,
+    Range,
+    Lookahead,
+}
+
+const COMPRESSOR_CONTROLS: &[Control] = &[
+    Control::Threshold,
+    Control::Ratio,
+    Control::Knee,
+    Control::Attack,
+    Control::Release,
+    Control::Makeup,
+];
+const COMPRESSOR_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Threshold", -60.0, 0.0, -18.0).with_unit("dB"),
+    ParamInfo::new("Ratio", 1.0, 20.0, 4.0).with_skew(ParamSkew::Logarithmic),
+    ParamInfo::new("Knee", 0.0, 24.0, 6.0).with_unit("dB"),
+    ParamInfo::new("Attack", 0.1, 200.0, 10.0).with_skew(ParamSkew::Logarithmic).with_unit("ms"),
+    ParamInfo::new("Release", 5.0, 2000.0, 100.0).with_skew(ParamSkew::Logarithmic).with_unit("ms"),
+    ParamInfo::new("Makeup", 0.0, 24.0, 0.0).with_unit("dB"),
+];
+
+const LIMITER_CONTROLS: &[Control] = &[
+    Control::Threshold,
+    Control::Knee,
+    Control::Release,
+    Control::Lookahead,
+];
+const LIMITER_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Ceiling", -24.0, 0.0, -1.0).with_unit("dB"),
+    ParamInfo::new("Knee", 0.0, 12.0, 0.0).with_unit("dB"),
+    ParamInfo::new("Release", 5.0, 2000.0, 50.0).with_skew(ParamSkew::Logarithmic).with_unit("ms"),
+    ParamInfo::new("Lookahead", 0.0, MAX_LOOKAHEAD_MS, 5.0).with_smoothing(Smoothing::None).with_unit("ms"),
+];
+
+const GATE_CONTROLS: &[Control] = &[
+    Control::Threshold,
+    Control::Ratio,
+    Control::Knee,
+    Control::Attack,
+    Control::Release,
+    Control::Range,
+];
+const GATE_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Threshold", -90.0, 0.0, -50.0).with_unit("dB"),
+    ParamInfo::new("Ratio", 1.0, 20.0, 10.0).with_skew(ParamSkew::Logarithmic),
+    ParamInfo::new("Knee", 0.0, 24.0, 6.0).with_unit("dB"),
+    ParamInfo::new("Attack", 0.1, 200.0, 1.0).with_skew(ParamSkew::Logarithmic).with_unit("ms"),
+    ParamInfo::new("Release", 5.0, 2000.0, 100.0).with_skew(ParamSkew::Logarithmic).with_unit("ms"),
+    ParamInfo::new("Range", 0.0, 90.0, 80.0).with_unit("dB"),
+];
+
+const INPUTS: &[PortInfo] = &[PortInfo::stereo("In"), PortInfo::new("Sidechain")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::stereo("Out")];
+
+fn to_db(gain: f32) -> f32 {
+    if gain > 0.0 {
+        (20.0 * gain.log10()).max(SILENCE_DB)
+    } else {
+        SILENCE_DB
+    }
+}
+
+fn from_db(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+// One pole smoothing coefficient for a time in milliseconds
+fn coefficient(time_ms: f32, sample_rate: f32) -> f32 {
+    (-1.0 / (0.001 * time_ms.max(0.001) * sample_rate)).exp()
+}
+
+// Fixed size ring buffer used for the look-ahead, allocated up front
+struct Ring {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Ring {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        for sample in self.buffer.iter_mut() {
+            *sample = 0.0;
+        }
+        self.index = 0;
+    }
+
+    // Write a sample and return the one written `delay` samples ago
+    fn push(&mut self, sample: f32, delay: usize) -> f32 {
+        let length = self.buffer.len();
+        self.buffer[self.index] = sample;
+        let delayed = self.buffer[(self.index + length - delay.min(length - 1)) % length];
+        self.index = (self.index + 1) % length;
+        delayed
+    }
+}
+
+// Maximum of the values pushed over a sliding window, using a monotonic queue allocated up front
+struct SlidingMax {
+    values: Vec<f32>,
+    times: Vec<u64>,
+    head: usize,
+    len: usize,
+    time: u64,
+}
+
+impl SlidingMax {
+    fn new(capacity: usize) -> Self {
+        Self {
+            values: vec![0.0; capacity.max(1)],
+            times: vec![0; capacity.max(1)],
+            head: 0,
+            len: 0,
+            time: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.time = 0;
+    }
+
+    // Push a value and return the maximum of the last `window` values, which must not exceed the capacity
+    fn push(&mut self, value: f32, window: usize) -> f32 {
+        let capacity = self.values.len();
+
+        while self.len > 0 && self.values[(self.head + self.len - 1) % capacity] <= value {
+            self.len -= 1;
+        }
+
+        let back = (self.head + self.len) % capacity;
+        self.values[back] = value;
+        self.times[back] = self.time;
+        self.len += 1;
+
+        while self.times[self.head] + (window.max(1) as u64) <= self.time {
+            self.head = (self.head + 1) % capacity;
+            self.len -= 1;
+        }
+
+        self.time += 1;
+        self.values[self.head]
+    }
+}
+
+// Stereo linked compressor, limiter or noise gate with an optional sidechain input.
+//
+// When the sidechain input is connected it is used to detect the level instead of the main inputs.
+// The current gain reduction in dB is published through `meter()` so it can be shown on the node.
+pub struct Dynamics {
+    mode: DynamicsMode,
+
+    threshold: f32,
+    ratio: f32,
+    knee: f32,
+    attack: f32,
+    release: f32,
+    makeup: f32,
+    range: f32,
+    lookahead: f32,
+
+    sample_rate: f32,
+    sidechain_connected: bool,
+    // Smoothed gain reduction in dB
+    gain_reduction: f32,
+    meter: Meter,
+
+    // Look-ahead state, used only by the limiter
+    delay_left: Ring,
+    delay_right: Ring,
+    // Gain reduction history, averaged so that the gain reaches its target as the delayed peak arrives
+    reduction_history: Ring,
+    reduction_sum: f32,
+    peak_reduction: SlidingMax,
+}
+
+impl Dynamics {
+    pub fn new(mode: DynamicsMode) -> Self {
+        let mut dynamics = Self {
+            mode,
+
+            threshold: 0.0,
+            ratio: 1.0,
+            knee: 0.0,
+            attack: 10.0,
+            release: 100.0,
+            makeup: 0.0,
+            range: 80.0,
+            lookahead: 0.0,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sidechain_connected: false,
+            gain_reduction: 0.0,
+            meter: Meter::new(),
+
+            delay_left: Ring::new(0),
+            delay_right: Ring::new(0),
+            reduction_history: Ring::new(0),
+            reduction_sum: 0.0,
+            peak_reduction: SlidingMax::new(0),
+        };
+
+        for (index, param) in Self::mode_params(mode).iter().enumerate() {
+            dynamics.set_param(index, param.default);
+        }
+
+        // The look-ahead buffers are only allocated once the node is prepared
+
+        dynamics
+    }
+
+    pub fn mode(&self) -> DynamicsMode {
+        self.mode
+    }
+
+    fn mode_params(mode: DynamicsMode) -> &'static [ParamInfo] {
+        match mode {
+            DynamicsMode::Compressor => COMPRESSOR_PARAMS,
+            DynamicsMode::Limiter => LIMITER_PARAMS,
+            DynamicsMode::Gate => GATE_PARAMS,
+        }
+    }
+
+    fn controls(&self) -> &'static [Control] {
+        match self.mode {
+            DynamicsMode::Compressor => COMPRESSOR_CONTROLS,
+            DynamicsMode::Limiter => LIMITER_CONTROLS,
+            DynamicsMode::Gate => GATE_CONTROLS,
+        }
+    }
+
+    // Gain reduction in dB needed for a signal at `level` dB, before smoothing
+    fn target_reduction(&self, level: f32) -> f32 {
+        let overshoot = level - self.threshold;
+        let half_knee = 0.5 * self.knee;
+
+        match self.mode {
+            DynamicsMode::Compressor | DynamicsMode::Limiter => {
+                let slope = match self.mode {
+                    DynamicsMode::Limiter => 1.0,
+                    _ => 1.0 - 1.0 / self.ratio,
+                };
+
+                if overshoot <= -half_knee {
+                    0.0
+                } else if overshoot < half_knee {
+                    slope * (overshoot + half_knee).powi(2) / (2.0 * self.knee)
+                } else {
+                    slope * overshoot
+                }
+            }
+
+            DynamicsMode::Gate => {
+                let slope = self.ratio - 1.0;
+                let reduction = if overshoot >= half_knee {
+                    0.0
+                } else if overshoot > -half_knee {
+                    slope * (overshoot - half_knee).powi(2) / (2.0 * self.knee)
+                } else {
+                    -slope * overshoot
+                };
+
+                reduction.min(self.range)
+            }
+        }
+    }
+
+    fn lookahead_samples(&self) -> usize {
+        match self.mode {
+            DynamicsMode::Limiter => {
+                let lookahead = (self.lookahead * 0.001 * self.sample_rate).round() as usize;
+                lookahead.min(self.delay_left.buffer.len() - 1)
+            }
+            _ => 0,
+        }
+    }
+
+    // Hold each peak for the look-ahead time, then average over the same time so the gain ramps down smoothly
+    fn lookahead_reduction(&mut self, target: f32, lookahead: usize) -> f32 {
+        let held = self.peak_reduction.push(target, lookahead + 1);
+        let oldest = self.reduction_history.push(held, lookahead);
+        self.reduction_sum += held - oldest;
+        self.reduction_sum.max(0.0) / lookahead as f32
+    }
+}
+
+impl AudioNode for Dynamics {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        Self::mode_params(self.mode)
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match self.controls().get(index) {
+            Some(Control::Threshold) => self.threshold,
+            Some(Control::Ratio) => self.ratio,
+            Some(Control::Knee) => self.knee,
+            Some(Control::Attack) => self.attack,
+            Some(Control::Release) => self.release,
+            Some(Control::Makeup) => self.makeup,
+            Some(Control::Range) => self.range,
+            Some(Control::Lookahead) => self.lookahead,
+            None => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match self.params().get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match self.controls()[index] {
+            Control::Threshold => self.threshold = value,
+            Control::Ratio => self.ratio = value,
+            Control::Knee => self.knee = value,
+            Control::Attack => self.attack = value,
+            Control::Release => self.release = value,
+            Control::Makeup => self.makeup = value,
+            Control::Range => self.range = value,
+            Control::Lookahead => {
+                self.lookahead = value;
+                self.reset();
+            }
+        }
+    }
+
+    fn input_connected(&mut self, index: usize, connected: bool) {
+        if index == 1 {
+            self.sidechain_connected = connected;
+        }
+    }
+
+    fn meter(&self) -> Option<Meter> {
+        Some(self.meter.clone())
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_frames: usize) {
+        self.sample_rate = sample_rate;
+        let length = (MAX_LOOKAHEAD_MS * 0.001 * sample_rate).ceil() as usize + 1;
+        self.delay_left = Ring::new(length);
+        self.delay_right = Ring::new(length);
+        self.reduction_history = Ring::new(length);
+        self.peak_reduction = SlidingMax::new(length + 1);
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.gain_reduction = 0.0;
+        self.delay_left.clear();
+        self.delay_right.clear();
+        self.reduction_history.clear();
+        self.reduction_sum = 0.0;
+        self.peak_reduction.clear();
+        self.meter.set(0.0);
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let (left, right, sidechain) = (inputs[0], inputs[1], inputs[2]);
+
+        let attack = coefficient(self.attack, context.sample_rate);
+        let release = coefficient(self.release, context.sample_rate);
+        let makeup = from_db(self.makeup);
+        let lookahead = self.lookahead_samples();
+        let mut max_reduction: f32 = 0.0;
+
+        for i in 0..context.frames {
+            let level = if self.sidechain_connected {
+                to_db(sidechain[i].abs())
+            } else {
+                to_db(left[i].abs().max(right[i].abs()))
+            };
+
+            let target = self.target_reduction(level);
+
+            if lookahead > 0 {
+                // The look-ahead already ramps the gain down in time, so only the release is smoothed
+                let target = self.lookahead_reduction(target, lookahead);
+                if target > self.gain_reduction {
+                    self.gain_reduction = target;
+                } else {
+                    self.gain_reduction = target + release * (self.gain_reduction - target);
+                }
+            } else {
+                // A gate opens as the reduction falls, so the attack and release are swapped
+                let rising = target > self.gain_reduction;
+                let coefficient = match (self.mode, rising) {
+                    (DynamicsMode::Limiter, true) => 0.0,
+                    (DynamicsMode::Gate, true) | (DynamicsMode::Compressor, false) => release,
+                    (DynamicsMode::Gate, false) | (DynamicsMode::Compressor, true) => attack,
+                    (DynamicsMode::Limiter, false) => release,
+                };
+                self.gain_reduction = target + coefficient * (self.gain_reduction - target);
+            }
+
+            let gain = from_db(-self.gain_reduction) * makeup;
+            let (left, right) = if lookahead > 0 {
+                (self.delay_left.push(left[i], lookahead), self.delay_right.push(right[i], lookahead))
+            } else {
+                (left[i], right[i])
+            };
+
+            outputs[0][i] = left * gain;
+            outputs[1][i] = right * gain;
+
+            max_reduction = max_reduction.max(self.gain_reduction);
+        }
+
+        self.meter.set(max_reduction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn render(dynamics: &mut Dynamics, input: &[f32]) -> Vec<f32> {
+        dynamics.prepare(SAMPLE_RATE, input.len());
+        let silence = vec![0.0; input.len()];
+        let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+        dynamics.process(&ProcessContext::new(SAMPLE_RATE, input.len()), &[input, input, &silence], &mut [&mut left, &mut right]);
+        assert_eq!(left, right);
+        left
+    }
+
+    #[test]
+    fn compressor_settles_at_its_ratio() {
+        let mut compressor = Dynamics::new(DynamicsMode::Compressor);
+        compressor.set_param(0, -20.0);
+        compressor.set_param(1, 4.0);
+        compressor.set_param(2, 0.0);
+
+        // A signal 20 dB over the threshold comes out 5 dB over it once the attack has passed
+        let output = render(&mut compressor, &[1.0; 4800]);
+        assert!((to_db(output[4799]) + 15.0).abs() < 0.01, "{}", to_db(output[4799]));
+        assert!(output[0] > output[4799]);
+    }
+
+    #[test]
+    fn limiter_keeps_a_step_below_its_ceiling() {
+        let mut limiter = Dynamics::new(DynamicsMode::Limiter);
+        limiter.set_param(0, -6.0);
+        limiter.set_param(3, 5.0);
+
+        // The step is delayed by the look-ahead, by which time the gain has already come down
+        let mut input = vec![0.0; 480];
+        input.extend_from_slice(&[1.0; 4320]);
+        let output = render(&mut limiter, &input);
+        let ceiling = from_db(-6.0);
+        assert!(output[..480 + 240].iter().all(|sample| *sample == 0.0));
+        assert!(output[480 + 240] > 0.0);
+        assert!(output.iter().all(|sample| *sample <= ceiling + 1.0e-4));
+        assert!((output[4799] - ceiling).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn look_ahead_is_off_before_prepare() {
+        let mut limiter = Dynamics::new(DynamicsMode::Limiter);
+        assert_eq!(limiter.lookahead_samples(), 0);
+        limiter.prepare(SAMPLE_RATE, 64);
+        assert_eq!(limiter.lookahead_samples(), 240);
+    }
+}
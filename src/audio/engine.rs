@@ -0,0 +1,460 @@
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::{Duration, Instant};
+
+use super::automation::{NodePath, ParamChange, MAX_PARAM_EVENTS};
+use super::graph::{AudioGraph, NodeId};
+use super::meter::Meter;
+use super::monitor::NodeMonitor;
+use super::scope::ScopeTap;
+use super::patch::{Patch, PatchError};
+use super::registry::NodeRegistry;
+use super::transport::Transport;
+
+// Most commands which can wait for the engine at once. Commands sent while the queue is full are dropped.
+pub const COMMAND_CAPACITY: usize = 1024;
+
+// A graph built from the patch after it was edited, with the nodes which carry on from the graph it replaces
+pub struct EngineGraph {
+    pub graph: AudioGraph,
+    // Each node in the new graph which is the same as a node in the last one, with the id of that node
+    pub kept: Vec<(NodeId, NodeId)>,
+}
+
+// A change sent from the UI to the engine, applied before the next buffer is played
+pub enum EngineCommand {
+    // Play a new graph in place of the last one. The transport and the kept nodes carry on from the last graph,
+    // so editing the patch doesn't cut off delay tails or restart envelopes.
+    SetGraph(Box<EngineGraph>),
+    // Start, stop or edit the transport, e.g. from the transport bar
+    SetTransport(Transport),
+    ParamChanged(ParamChange),
+    // Hear an output port of a node on its own, with the node found by its path as in `ParamChange`, or None to
+    // go back to the output node
+    SetProbe(Option<(NodePath, usize)>),
+}
+
+// Plays the graph of the patch being edited, on the audio thread.
+//
+// Parameter changes arrive timestamped and are placed in the next buffer at the frame they were made, so a knob
+// turned while one buffer plays moves just as smoothly in the next, a buffer late.
+//
+// The audio thread never allocates or frees memory: commands arrive through a queue allocated up front, and
+// replaced graphs are sent back through another to be freed by the controller.
+pub struct Engine {
+    graph: Option<Box<EngineGraph>>,
+    commands: Receiver<EngineCommand>,
+    retired: SyncSender<Box<EngineGraph>>,
+    // Parameter changes waiting for the block they happen in, in the order they were made
+    changes: Vec<ParamChange>,
+    // Song position in beats at the end of the last buffer, for the transport bar to show
+    position: Meter,
+}
+
+impl Engine {
+    // Create an engine and the controller which sends it changes
+    pub fn new(sample_rate: f32, block_size: usize) -> (Engine, EngineController) {
+        // Each retired graph was sent as a command, and the controller takes them back before sending another
+        // graph, so the queue of retired graphs never fills up
+        let (command_sender, commands) = mpsc::sync_channel(COMMAND_CAPACITY);
+        let (retired, retired_receiver) = mpsc::sync_channel(COMMAND_CAPACITY);
+        let position = Meter::new();
+
+        let engine = Engine {
+            graph: None,
+            commands,
+            retired,
+            changes: Vec::with_capacity(MAX_PARAM_EVENTS),
+            position: position.clone(),
+        };
+
+        let controller = EngineController {
+            commands: command_sender,
+            retired: retired_receiver,
+            registry: NodeRegistry::with_builtin_nodes(),
+            sample_rate,
+            block_size,
+            probe: None,
+            transport: Transport::new(),
+            position,
+            patch: None,
+            monitors: Vec::new(),
+        };
+
+        (engine, controller)
+    }
+
+    fn receive_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                // The old graph goes back holding the nodes built for the new one in place of the kept nodes
+                EngineCommand::SetGraph(mut new) => {
+                    if let Some(mut old) = self.graph.take() {
+                        for (id, old_id) in new.kept.iter() {
+                            new.graph.swap_node(*id, &mut old.graph, *old_id);
+                        }
+                        new.graph.set_transport(*old.graph.transport());
+                        let _ = self.retired.try_send(old);
+                    }
+                    self.graph = Some(new);
+                }
+
+                EngineCommand::SetTransport(transport) => {
+                    if let Some(graph) = self.graph.as_mut() {
+                        graph.graph.set_transport(transport);
+                    }
+                }
+
+                // Changes past the most a block can hold are dropped, as the graph would drop them
+                EngineCommand::ParamChanged(change) => {
+                    if self.changes.len() < MAX_PARAM_EVENTS {
+                        self.changes.push(change);
+                    }
+                }
+
+                // A port which can't be probed leaves the output node heard
+                EngineCommand::SetProbe(probe) => {
+                    if let Some(graph) = self.graph.as_mut() {
+                        let _ = graph.graph.set_probe_path(probe.as_ref().map(|(path, output)| (path.as_slice(), *output)));
+                    }
+                }
+            }
+        }
+    }
+
+    // Fill an interleaved buffer of `channels` channels with the output of the graph, for a buffer which starts
+    // playing at `now`. Channels the graph doesn't output are silent.
+    pub fn render(&mut self, output: &mut [f32], channels: usize, now: Instant) {
+        self.receive_commands();
+
+        let graph = match self.graph.as_mut() {
+            Some(graph) => &mut graph.graph,
+            None => {
+                output.iter_mut().for_each(|sample| *sample = 0.0);
+                self.changes.clear();
+                return;
+            }
+        };
+
+        let channels = channels.max(1);
+        let frames = output.len() / channels;
+        let sample_rate = graph.sample_rate();
+        let frame_time = |frame: usize| Duration::from_secs_f64(frame as f64 / sample_rate as f64);
+        // Changes made while the previous buffer played are placed in this one at the same spacing
+        let buffer_start = now.checked_sub(frame_time(frames)).unwrap_or(now);
+
+        let mut start = 0;
+        while start < frames {
+            let block = (frames - start).min(graph.block_size());
+            let block_start = buffer_start + frame_time(start);
+            let block_end = buffer_start + frame_time(start + block);
+            let last = start + block == frames;
+
+            // Changes made after the buffer started are late, so the last block takes them at its last frame
+            self.changes.retain(|change| {
+                if change.time >= block_end && !last {
+                    return true;
+                }
+                graph.schedule_param_path(change.path.as_slice(), change.frame(block_start, sample_rate, block), change.index, change.value);
+                false
+            });
+
+            graph.process(block);
+
+            let block_output = &mut output[start * channels..(start + block) * channels];
+            for (frame, samples) in block_output.chunks_mut(channels).enumerate() {
+                for (channel, sample) in samples.iter_mut().enumerate() {
+                    *sample = graph.output_buffer(channel).map(|buffer| buffer[frame]).unwrap_or(0.0);
+                }
+            }
+
+            start += block;
+        }
+
+        self.position.set(graph.transport().position as f32);
+    }
+}
+
+// The UI's side of an engine, which builds the graph of the patch being edited and sends changes to the engine
+pub struct EngineController {
+    commands: SyncSender<EngineCommand>,
+    retired: Receiver<Box<EngineGraph>>,
+    registry: NodeRegistry,
+    sample_rate: f32,
+    block_size: usize,
+    // Output port being heard on its own, which carries on into each new graph
+    probe: Option<(NodePath, usize)>,
+    // Transport given to the first graph. Later graphs carry on from the one they replace.
+    transport: Transport,
+    position: Meter,
+    // Patch the engine is playing and the monitors of its nodes, which nodes of the next patch are matched with
+    patch: Option<Patch>,
+    monitors: Vec<Option<NodeMonitor>>,
+}
+
+impl EngineController {
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    // Song position in beats reached by the engine, updated after each buffer
+    pub fn position(&self) -> Meter {
+        self.position.clone()
+    }
+
+    // Build the graph of a patch and play it in place of the last one, returning the monitor of each node by its
+    // index in the patch. If the patch can't be built the engine carries on playing the last graph.
+    //
+    // Nodes which are the same as in the last patch apart from their parameters carry on playing in the new
+    // graph, keeping their monitors. Only nodes showing a scope have their samples kept for it, in the same
+    // tap as in the last graph where there was one.
+    pub fn set_patch(&mut self, patch: &Patch) -> Result<Vec<Option<NodeMonitor>>, PatchError> {
+        // Graphs the engine has replaced are freed here rather than on the audio thread
+        while self.retired.try_recv().is_ok() {}
+
+        let mut graph = patch.build_graph(&self.registry, self.sample_rate, self.block_size)?;
+        graph.set_transport(self.transport);
+        // The probed node may be gone from the new patch
+        let probe = self.probe.as_ref().map(|(path, output)| (path.as_slice(), *output));
+        if graph.set_probe_path(probe).is_err() {
+            self.probe = None;
+        }
+
+        let kept = match self.patch.as_ref() {
+            Some(last) => patch.unchanged_nodes(last),
+            None => Vec::new(),
+        };
+        for id in 0..patch.nodes.len() {
+            let scoped = patch.nodes[id].show_scope
+                || graph
+                    .node(id)
+                    .and_then(|node| node.editor_info())
+                    .map(|info| info.scope().is_some())
+                    .unwrap_or(false);
+            let tap = if scoped {
+                Some(self.last_scope(patch, &kept, id).unwrap_or_default())
+            } else {
+                None
+            };
+            graph.set_scope_tap(id, tap);
+        }
+
+        let mut monitors: Vec<Option<NodeMonitor>> = (0..patch.nodes.len()).map(|id| graph.monitor(id)).collect();
+        for (id, old_id) in kept.iter() {
+            let scope = monitors[*id].as_ref().and_then(|monitor| monitor.scope());
+            monitors[*id] = self.monitors[*old_id].clone().map(|mut monitor| {
+                monitor.set_scope(scope);
+                monitor
+            });
+        }
+
+        // A graph which doesn't reach the engine leaves the last one playing, to be matched with the next patch
+        if self.send(EngineCommand::SetGraph(Box::new(EngineGraph { graph, kept }))) {
+            self.patch = Some(patch.clone());
+            self.monitors = monitors.clone();
+        }
+
+        Ok(monitors)
+    }
+
+    // Scope tap of a node of the patch in the last graph, found by the node it carries on from or otherwise by a
+    // node of the same kind at the same index
+    fn last_scope(&self, patch: &Patch, kept: &[(NodeId, NodeId)], id: NodeId) -> Option<ScopeTap> {
+        let last = self.patch.as_ref()?;
+        let old_id = match kept.iter().find(|(new_id, _)| *new_id == id) {
+            Some((_, old_id)) => *old_id,
+            None => {
+                if last.nodes.get(id)?.kind != patch.nodes[id].kind {
+                    return None;
+                }
+                id
+            }
+        };
+        self.monitors.get(old_id)?.as_ref()?.scope()
+    }
+
+    // Pass on a parameter edited in the UI, which the engine places at the frame it was made
+    pub fn change_param(&self, change: ParamChange) {
+        self.send(EngineCommand::ParamChanged(change));
+    }
+
+    pub fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+        self.send(EngineCommand::SetTransport(transport));
+    }
+
+    // Hear an output port of a node on its own, or go back to the output node
+    pub fn set_probe(&mut self, probe: Option<(NodePath, usize)>) {
+        self.probe = probe;
+        self.send(EngineCommand::SetProbe(probe));
+    }
+
+    // Commands sent after the engine has stopped, or while its queue is full, are dropped. Returns whether the
+    // command was sent.
+    fn send(&self, command: EngineCommand) -> bool {
+        self.commands.try_send(command).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::graph::Connection;
+    use crate::audio::node::*;
+    use crate::audio::patch::PatchNode;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out")];
+    const PARAMS: &[ParamInfo] = &[ParamInfo::new("Level", 0.0, 1.0, 0.0).with_smoothing(Smoothing::None)];
+
+    // Outputs its parameter as a constant signal
+    struct Level {
+        value: f32,
+    }
+
+    impl AudioNode for Level {
+        fn inputs(&self) -> &[PortInfo] {
+            &[]
+        }
+
+        fn outputs(&self) -> &[PortInfo] {
+            OUTPUTS
+        }
+
+        fn params(&self) -> &[ParamInfo] {
+            PARAMS
+        }
+
+        fn get_param(&self, _index: usize) -> f32 {
+            self.value
+        }
+
+        fn set_param(&mut self, _index: usize, value: f32) {
+            self.value = value;
+        }
+
+        fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            outputs[0][..context.frames].iter_mut().for_each(|sample| *sample = self.value);
+        }
+    }
+
+    #[test]
+    fn changes_land_at_their_frame_a_buffer_late() {
+        let (mut engine, controller) = Engine::new(SAMPLE_RATE, 64);
+        let mut graph = AudioGraph::new(SAMPLE_RATE, 64);
+        let level = graph.add_node(Box::new(Level { value: 0.0 })).unwrap();
+        graph.set_output(level).unwrap();
+        controller.send(EngineCommand::SetGraph(Box::new(EngineGraph { graph, kept: Vec::new() })));
+
+        // A change made 100 frames into the previous buffer lands 100 frames into this one, in its second block
+        let now = Instant::now();
+        let buffer_start = now - Duration::from_secs_f64(256.0 / SAMPLE_RATE as f64);
+        controller.change_param(ParamChange {
+            path: NodePath::new(&[level]).unwrap(),
+            index: 0,
+            value: 1.0,
+            time: buffer_start + Duration::from_secs_f64(100.5 / SAMPLE_RATE as f64),
+        });
+
+        let mut output = vec![-1.0; 256 * 2];
+        engine.render(&mut output, 2, now);
+        let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+        assert!(left[..100].iter().all(|sample| *sample == 0.0));
+        assert!(left[100..].iter().all(|sample| *sample == 1.0));
+        // The graph has one output channel, so the second is silent
+        assert!(output.iter().skip(1).step_by(2).all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn transport_carries_on_into_a_new_graph() {
+        let (mut engine, mut controller) = Engine::new(SAMPLE_RATE, 64);
+        let position = controller.position();
+        controller.set_patch(&Patch::default()).unwrap();
+        let mut transport = Transport::new();
+        transport.play();
+        controller.set_transport(transport);
+
+        // A tenth of a second at 120 BPM is a fifth of a beat
+        let mut output = vec![0.0; SAMPLE_RATE as usize / 10 * 2];
+        engine.render(&mut output, 2, Instant::now());
+        assert!((position.get() - 0.2).abs() < 1.0e-4);
+
+        controller.set_patch(&Patch::default()).unwrap();
+        engine.render(&mut output, 2, Instant::now());
+        assert!((position.get() - 0.4).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn unchanged_nodes_carry_on_into_a_new_graph() {
+        let registry = NodeRegistry::with_builtin_nodes();
+        let patch_node = |kind: &str| {
+            let node = registry.create(kind).unwrap();
+            PatchNode::new(kind, 0.0, 0.0, node.as_ref())
+        };
+        let patch = Patch {
+            nodes: vec![patch_node("White Noise"), patch_node("Output")],
+            connections: vec![Connection::new(0, 0, 1, 0)],
+        };
+        // The same patch with a node added in front, which moves the others along
+        let mut edited = patch.clone();
+        edited.nodes.insert(0, patch_node("Add"));
+        edited.connections = vec![Connection::new(1, 0, 2, 0)];
+
+        // An engine left playing the first patch gives the sound the edited patch should carry on with
+        let (mut expected_engine, mut expected_controller) = Engine::new(SAMPLE_RATE, 64);
+        expected_controller.set_patch(&patch).unwrap();
+        let mut expected = vec![0.0; 512];
+        expected_engine.render(&mut expected, 2, Instant::now());
+        expected_engine.render(&mut expected, 2, Instant::now());
+
+        let (mut engine, mut controller) = Engine::new(SAMPLE_RATE, 64);
+        let monitors = controller.set_patch(&patch).unwrap();
+        let mut output = vec![0.0; 512];
+        engine.render(&mut output, 2, Instant::now());
+        let edited_monitors = controller.set_patch(&edited).unwrap();
+        engine.render(&mut output, 2, Instant::now());
+
+        assert_eq!(output, expected);
+        assert_eq!(edited_monitors[1], monitors[0]);
+        assert_eq!(edited_monitors[2], monitors[1]);
+        assert_ne!(edited_monitors[0], monitors[0]);
+    }
+
+    #[test]
+    fn scope_taps_follow_the_scoped_nodes() {
+        let registry = NodeRegistry::with_builtin_nodes();
+        let patch_node = |kind: &str| {
+            let node = registry.create(kind).unwrap();
+            PatchNode::new(kind, 0.0, 0.0, node.as_ref())
+        };
+        let mut patch = Patch {
+            nodes: vec![patch_node("White Noise"), patch_node("Output")],
+            connections: vec![Connection::new(0, 0, 1, 0)],
+        };
+        patch.nodes[0].show_scope = true;
+
+        let (mut engine, mut controller) = Engine::new(SAMPLE_RATE, 64);
+        let monitors = controller.set_patch(&patch).unwrap();
+        assert!(monitors[1].as_ref().unwrap().scope().is_none());
+        let tap = monitors[0].as_ref().unwrap().scope().unwrap();
+        let mut output = vec![0.0; 512];
+        engine.render(&mut output, 2, Instant::now());
+        assert_eq!(tap.written(), 256);
+
+        // A node added in front doesn't change the tap of the scoped node, which carries on being written
+        let mut edited = patch.clone();
+        edited.nodes.insert(0, patch_node("Add"));
+        edited.connections = vec![Connection::new(1, 0, 2, 0)];
+        let edited_monitors = controller.set_patch(&edited).unwrap();
+        assert!(edited_monitors[0].as_ref().unwrap().scope().is_none());
+        engine.render(&mut output, 2, Instant::now());
+        assert_eq!(edited_monitors[1].as_ref().unwrap().scope().unwrap().written(), 512);
+        assert_eq!(tap.written(), 512);
+
+        // Once the scope is hidden the node stops being written to a tap
+        edited.nodes[1].show_scope = false;
+        let hidden_monitors = controller.set_patch(&edited).unwrap();
+        assert!(hidden_monitors[1].as_ref().unwrap().scope().is_none());
+        engine.render(&mut output, 2, Instant::now());
+        assert_eq!(tap.written(), 512);
+    }
+}
@@ -0,0 +1,357 @@
+use super::node::*;
+
+// Longest time any single stage of the envelope can take, in seconds
+pub const MAX_STAGE_TIME: f32 = 10.0;
+// Shortest stage time, used to avoid clicks and division by zero
+const MIN_STAGE_TIME: f32 = 0.0005;
+// How strongly a curvature of +/-1.0 bends a stage
+const CURVE_STEEPNESS: f32 = 6.0;
+// Gate and retrigger inputs are considered high above this level
+const GATE_THRESHOLD: f32 = 0.5;
+
+// Shape the linear progress `t` (0 to 1) through an envelope stage by `curvature` (-1 to 1).
+// Positive values start slowly and finish quickly, negative values do the opposite.
+pub fn shape_curve(t: f32, curvature: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    if curvature.abs() < 1.0e-3 {
+        return t;
+    }
+
+    let k = curvature * CURVE_STEEPNESS;
+    ((k * t).exp() - 1.0) / (k.exp() - 1.0)
+}
+
+// The settings of an ADSR envelope, shared between the audio node and the envelope editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdsrShape {
+    // Stage times in seconds
+    pub attack: f32,
+    pub decay: f32,
+    pub release: f32,
+    // Sustain level from 0 to 1
+    pub sustain: f32,
+    // Curvature of each stage from -1 to 1
+    pub attack_curve: f32,
+    pub decay_curve: f32,
+    pub release_curve: f32,
+}
+
+impl AdsrShape {
+    // Value of the parameter of an envelope node with an index, such as `Adsr::ATTACK`
+    pub fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Adsr::ATTACK => self.attack,
+            Adsr::DECAY => self.decay,
+            Adsr::SUSTAIN => self.sustain,
+            Adsr::RELEASE => self.release,
+            Adsr::ATTACK_CURVE => self.attack_curve,
+            Adsr::DECAY_CURVE => self.decay_curve,
+            Adsr::RELEASE_CURVE => self.release_curve,
+            _ => 0.0,
+        }
+    }
+
+    // Set a parameter of an envelope node, kept within the range of the parameter
+    pub fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Adsr::ATTACK => self.attack = value,
+            Adsr::DECAY => self.decay = value,
+            Adsr::SUSTAIN => self.sustain = value,
+            Adsr::RELEASE => self.release = value,
+            Adsr::ATTACK_CURVE => self.attack_curve = value,
+            Adsr::DECAY_CURVE => self.decay_curve = value,
+            Adsr::RELEASE_CURVE => self.release_curve = value,
+            _ => {}
+        }
+    }
+}
+
+impl Default for AdsrShape {
+    fn default() -> Self {
+        Self {
+            attack: 0.01,
+            decay: 0.2,
+            release: 0.5,
+            sustain: 0.7,
+            attack_curve: 0.0,
+            decay_curve: -0.5,
+            release_curve: -0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::gate("Gate"), PortInfo::gate("Retrigger")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::control("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Attack", 0.0, MAX_STAGE_TIME, 0.01).with_skew(ParamSkew::Power(3.0)).with_unit("s"),
+    ParamInfo::new("Decay", 0.0, MAX_STAGE_TIME, 0.2).with_skew(ParamSkew::Power(3.0)).with_unit("s"),
+    ParamInfo::new("Sustain", 0.0, 1.0, 0.7).with_style(ParamStyle::Slider),
+    ParamInfo::new("Release", 0.0, MAX_STAGE_TIME, 0.5).with_skew(ParamSkew::Power(3.0)).with_unit("s"),
+    ParamInfo::new("Attack Curve", -1.0, 1.0, 0.0),
+    ParamInfo::new("Decay Curve", -1.0, 1.0, -0.5),
+    ParamInfo::new("Release Curve", -1.0, 1.0, -0.5),
+];
+
+// ADSR envelope generator driven by a gate input.
+//
+// A rising edge on the gate starts the attack stage and a falling edge starts the release stage.
+// A rising edge on the retrigger input while the gate is held restarts the attack from the current level.
+pub struct Adsr {
+    shape: AdsrShape,
+
+    stage: Stage,
+    // Progress through the current stage from 0 to 1
+    position: f32,
+    // Level when the current stage started
+    start_level: f32,
+    level: f32,
+
+    gate_high: bool,
+    retrigger_high: bool,
+}
+
+impl Adsr {
+    pub const ATTACK: usize = 0;
+    pub const DECAY: usize = 1;
+    pub const SUSTAIN: usize = 2;
+    pub const RELEASE: usize = 3;
+    pub const ATTACK_CURVE: usize = 4;
+    pub const DECAY_CURVE: usize = 5;
+    pub const RELEASE_CURVE: usize = 6;
+
+    pub fn new() -> Self {
+        Self {
+            shape: AdsrShape::default(),
+
+            stage: Stage::Idle,
+            position: 0.0,
+            start_level: 0.0,
+            level: 0.0,
+
+            gate_high: false,
+            retrigger_high: false,
+        }
+    }
+
+    pub fn shape(&self) -> AdsrShape {
+        self.shape
+    }
+
+    pub fn set_shape(&mut self, shape: AdsrShape) {
+        self.shape = shape;
+    }
+
+    fn enter_stage(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.position = 0.0;
+        self.start_level = self.level;
+    }
+
+    fn next_sample(&mut self, increments: &[f32; 3]) -> f32 {
+        match self.stage {
+            Stage::Idle => {
+                self.level = 0.0;
+            }
+
+            Stage::Attack => {
+                self.position += increments[0];
+                if self.position >= 1.0 {
+                    self.level = 1.0;
+                    self.enter_stage(Stage::Decay);
+                } else {
+                    let t = shape_curve(self.position, self.shape.attack_curve);
+                    self.level = self.start_level + (1.0 - self.start_level) * t;
+                }
+            }
+
+            Stage::Decay => {
+                self.position += increments[1];
+                if self.position >= 1.0 {
+                    self.level = self.shape.sustain;
+                    self.enter_stage(Stage::Sustain);
+                } else {
+                    let t = shape_curve(self.position, self.shape.decay_curve);
+                    self.level = 1.0 + (self.shape.sustain - 1.0) * t;
+                }
+            }
+
+            Stage::Sustain => {
+                self.level = self.shape.sustain;
+            }
+
+            Stage::Release => {
+                self.position += increments[2];
+                if self.position >= 1.0 {
+                    self.level = 0.0;
+                    self.enter_stage(Stage::Idle);
+                } else {
+                    let t = shape_curve(self.position, self.shape.release_curve);
+                    self.level = self.start_level * (1.0 - t);
+                }
+            }
+        }
+
+        self.level
+    }
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Adsr {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.shape.get_param(index)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        self.shape.set_param(index, value);
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.position = 0.0;
+        self.start_level = 0.0;
+        self.level = 0.0;
+        self.gate_high = false;
+        self.retrigger_high = false;
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let gate = inputs[0];
+        let retrigger = inputs[1];
+        let output = &mut outputs[0];
+
+        let increment = |time: f32| 1.0 / (time.max(MIN_STAGE_TIME) * context.sample_rate);
+        let increments = [
+            increment(self.shape.attack),
+            increment(self.shape.decay),
+            increment(self.shape.release),
+        ];
+
+        for i in 0..context.frames {
+            let gate_high = gate[i] > GATE_THRESHOLD;
+            let retrigger_high = retrigger[i] > GATE_THRESHOLD;
+
+            if gate_high && !self.gate_high {
+                self.enter_stage(Stage::Attack);
+            } else if !gate_high && self.gate_high {
+                self.enter_stage(Stage::Release);
+            } else if gate_high && retrigger_high && !self.retrigger_high {
+                self.enter_stage(Stage::Attack);
+            }
+
+            self.gate_high = gate_high;
+            self.retrigger_high = retrigger_high;
+
+            output[i] = self.next_sample(&increments);
+        }
+    }
+}
+
+impl NodeEditorInfo for Adsr {
+    fn envelope_shape(&self) -> Option<AdsrShape> {
+        Some(self.shape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn linear_envelope() -> Adsr {
+        let mut envelope = Adsr::new();
+        envelope.set_shape(AdsrShape {
+            attack: 0.01,
+            decay: 0.02,
+            release: 0.01,
+            sustain: 0.5,
+            attack_curve: 0.0,
+            decay_curve: 0.0,
+            release_curve: 0.0,
+        });
+        envelope
+    }
+
+    fn render(envelope: &mut Adsr, gate: &[f32], retrigger: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.0; gate.len()];
+        envelope.process(&ProcessContext::new(SAMPLE_RATE, gate.len()), &[gate, retrigger], &mut [&mut output]);
+        output
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1.0e-3, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn stages_take_their_times() {
+        // The gate is held for 50 ms, then released for 20 ms
+        let mut gate = vec![1.0; 2400];
+        gate.extend_from_slice(&[0.0; 960]);
+        let output = render(&mut linear_envelope(), &gate, &[0.0; 3360]);
+
+        // Attack of 480 samples, decay of 960 samples down to the sustain level, then release of 480 samples
+        assert_near(output[239], 0.5);
+        assert_near(output[479], 1.0);
+        assert_near(output[479 + 480], 0.75);
+        assert_near(output[479 + 960], 0.5);
+        assert_near(output[2399], 0.5);
+        assert_near(output[2399 + 240], 0.25);
+        assert_near(output[2399 + 480], 0.0);
+        assert_eq!(output[3359], 0.0);
+    }
+
+    #[test]
+    fn retrigger_restarts_the_attack_on_its_rising_edge() {
+        let mut envelope = linear_envelope();
+        let gate = [1.0; 2400];
+
+        // Once sustaining, a retrigger held high restarts the attack from the sustain level only once
+        let mut retrigger = vec![0.0; 1500];
+        retrigger.extend_from_slice(&[1.0; 900]);
+        let output = render(&mut envelope, &gate, &retrigger);
+        assert_near(output[1499], 0.5);
+        assert_near(output[1500 + 239], 0.75);
+        assert_near(output[1500 + 479], 1.0);
+        assert_near(output[1500 + 479 + 240], 0.875);
+
+        // A retrigger without the gate does nothing
+        envelope.reset();
+        let output = render(&mut envelope, &[0.0; 2400], &retrigger);
+        assert!(output.iter().all(|level| *level == 0.0));
+    }
+}
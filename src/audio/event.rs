@@ -0,0 +1,51 @@
+// Most events a single event port can carry in one block. Extra events are dropped.
+pub const MAX_BLOCK_EVENTS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+}
+
+// A MIDI message sent through an event port, timestamped with the frame in the block it happens at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiEvent {
+    pub frame: usize,
+    pub message: MidiMessage,
+}
+
+impl MidiEvent {
+    pub fn new(frame: usize, message: MidiMessage) -> Self {
+        Self { frame, message }
+    }
+}
+
+// Add an event to a buffer which is kept in order of frame, without growing it past `MAX_BLOCK_EVENTS`.
+//
+// Events at the same frame stay in the order they were added. Returns false if the buffer is full.
+pub fn insert_event(buffer: &mut Vec<MidiEvent>, event: MidiEvent) -> bool {
+    if buffer.len() >= MAX_BLOCK_EVENTS {
+        return false;
+    }
+
+    let index = buffer
+        .iter()
+        .rposition(|existing| existing.frame <= event.frame)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    buffer.insert(index, event);
+    true
+}
@@ -0,0 +1,676 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use super::node::*;
+
+// Highest numbered input variable, e.g. `in8`
+pub const MAX_EXPRESSION_INPUTS: usize = 8;
+// Deepest the evaluation stack of an expression can get, which also limits how deeply a formula can be nested
+const MAX_STACK_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    // Character offset in the formula where the error was found
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Tanh,
+    Abs,
+    Sqrt,
+    Exp,
+    Log,
+    Floor,
+    Ceil,
+    Fract,
+    Sign,
+    Min,
+    Max,
+    Pow,
+    Clamp,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "tanh" => Function::Tanh,
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "exp" => Function::Exp,
+            "log" => Function::Log,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "fract" => Function::Fract,
+            "sign" => Function::Sign,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "pow" => Function::Pow,
+            "clamp" => Function::Clamp,
+            _ => return None,
+        })
+    }
+
+    fn num_args(&self) -> usize {
+        match self {
+            Function::Min | Function::Max | Function::Pow => 2,
+            Function::Clamp => 3,
+            _ => 1,
+        }
+    }
+}
+
+// A single instruction of a compiled expression, which works on a stack of values.
+//
+// Values are 64 bit so the time stays precise enough for oscillators after the patch has played for hours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Const(f64),
+    // Value of the input port with this index
+    Input(usize),
+    Time,
+    SampleRate,
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Call(Function),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    // Byte range of the identifier in the formula
+    Ident(usize, usize),
+    Symbol(char),
+    LessEqual,
+    GreaterEqual,
+    End,
+}
+
+// Recursive descent parser which emits instructions as it goes
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    token: Token,
+    token_start: usize,
+    ops: Vec<Op>,
+    // Input numbers referenced by the formula, e.g. 2 for `in2`
+    inputs: Vec<usize>,
+    // Levels of negation, powers, brackets and function calls the parser is inside
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Result<Self, ParseError> {
+        let mut parser = Self {
+            text,
+            position: 0,
+            token: Token::End,
+            token_start: 0,
+            ops: Vec::new(),
+            inputs: Vec::new(),
+            depth: 0,
+        };
+        parser.advance()?;
+        Ok(parser)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+        Err(ParseError {
+            message: message.to_string(),
+            position: self.token_start,
+        })
+    }
+
+    fn advance(&mut self) -> Result<(), ParseError> {
+        let bytes = self.text.as_bytes();
+        while self.position < bytes.len() && bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+
+        self.token_start = self.position;
+        if self.position >= bytes.len() {
+            self.token = Token::End;
+            return Ok(());
+        }
+
+        let c = bytes[self.position];
+        if c.is_ascii_digit() || c == b'.' {
+            while self.position < bytes.len() && (bytes[self.position].is_ascii_digit() || bytes[self.position] == b'.') {
+                self.position += 1;
+            }
+            let number = &self.text[self.token_start..self.position];
+            self.token = match number.parse() {
+                Ok(value) => Token::Number(value),
+                Err(_) => return self.error("invalid number"),
+            };
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while self.position < bytes.len() && (bytes[self.position].is_ascii_alphanumeric() || bytes[self.position] == b'_') {
+                self.position += 1;
+            }
+            self.token = Token::Ident(self.token_start, self.position);
+        } else if (c == b'<' || c == b'>') && bytes.get(self.position + 1) == Some(&b'=') {
+            self.position += 2;
+            self.token = if c == b'<' { Token::LessEqual } else { Token::GreaterEqual };
+        } else if b"+-*/%^()<>,".contains(&c) {
+            self.position += 1;
+            self.token = Token::Symbol(c as char);
+        } else {
+            return self.error(&format!("unexpected character '{}'", self.text[self.position..].chars().next().unwrap_or('?')));
+        }
+
+        Ok(())
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if self.token != Token::Symbol(symbol) {
+            return self.error(&format!("expected '{}'", symbol));
+        }
+        self.advance()
+    }
+
+    fn parse_comparison(&mut self) -> Result<(), ParseError> {
+        self.parse_additive()?;
+        let op = match self.token {
+            Token::Symbol('<') => Op::Less,
+            Token::Symbol('>') => Op::Greater,
+            Token::LessEqual => Op::LessEqual,
+            Token::GreaterEqual => Op::GreaterEqual,
+            _ => return Ok(()),
+        };
+        self.advance()?;
+        self.parse_additive()?;
+        self.ops.push(op);
+        Ok(())
+    }
+
+    fn parse_additive(&mut self) -> Result<(), ParseError> {
+        self.parse_term()?;
+        loop {
+            let op = match self.token {
+                Token::Symbol('+') => Op::Add,
+                Token::Symbol('-') => Op::Sub,
+                _ => return Ok(()),
+            };
+            self.advance()?;
+            self.parse_term()?;
+            self.ops.push(op);
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<(), ParseError> {
+        self.parse_unary()?;
+        loop {
+            let op = match self.token {
+                Token::Symbol('*') => Op::Mul,
+                Token::Symbol('/') => Op::Div,
+                Token::Symbol('%') => Op::Rem,
+                _ => return Ok(()),
+            };
+            self.advance()?;
+            self.parse_unary()?;
+            self.ops.push(op);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<(), ParseError> {
+        // Every level of nesting recurses through here, so stopping at the stack limit keeps a long run of
+        // brackets from overflowing the thread's stack
+        if self.depth >= MAX_STACK_DEPTH {
+            return self.error("formula is too deeply nested");
+        }
+        self.depth += 1;
+
+        if self.token == Token::Symbol('-') {
+            self.advance()?;
+            self.parse_unary()?;
+            self.ops.push(Op::Neg);
+        } else {
+            self.parse_primary()?;
+            // Powers bind tighter than negation and are right associative, so -2^2 is -4 and 2^3^2 is 2^9
+            if self.token == Token::Symbol('^') {
+                self.advance()?;
+                self.parse_unary()?;
+                self.ops.push(Op::Pow);
+            }
+        }
+
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn parse_primary(&mut self) -> Result<(), ParseError> {
+        match self.token {
+            Token::Number(value) => {
+                self.ops.push(Op::Const(value));
+                self.advance()
+            }
+
+            Token::Ident(start, end) => {
+                let name = &self.text[start..end];
+                self.advance()?;
+
+                if self.token == Token::Symbol('(') {
+                    let function = match Function::from_name(name) {
+                        Some(function) => function,
+                        None => return Err(ParseError { message: format!("unknown function '{}'", name), position: start }),
+                    };
+                    self.advance()?;
+                    for arg in 0..function.num_args() {
+                        if arg > 0 {
+                            self.expect(',')?;
+                        }
+                        self.parse_comparison()?;
+                    }
+                    self.expect(')')?;
+                    self.ops.push(Op::Call(function));
+                    return Ok(());
+                }
+
+                let op = match name {
+                    "x" | "t" => Op::Time,
+                    "sr" => Op::SampleRate,
+                    "pi" => Op::Const(std::f64::consts::PI),
+                    "e" => Op::Const(std::f64::consts::E),
+                    _ => {
+                        let number = name.strip_prefix("in").and_then(|number| number.parse::<usize>().ok());
+                        match number {
+                            Some(number) if (1..=MAX_EXPRESSION_INPUTS).contains(&number) => {
+                                self.inputs.push(number);
+                                Op::Input(number)
+                            }
+                            _ => return Err(ParseError { message: format!("unknown variable '{}'", name), position: start }),
+                        }
+                    }
+                };
+                self.ops.push(op);
+                Ok(())
+            }
+
+            Token::Symbol('(') => {
+                self.advance()?;
+                self.parse_comparison()?;
+                self.expect(')')
+            }
+
+            Token::End => self.error("unexpected end of formula"),
+
+            _ => self.error("expected a number, variable or function"),
+        }
+    }
+}
+
+// An expression compiled into instructions which can be evaluated without allocating.
+//
+// Variables are `x` (or `t`) for the time in seconds, `sr` for the sample rate, `pi`, `e`
+// and `in1` to `in8` for the inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    ops: Vec<Op>,
+    // Input numbers used by the formula in ascending order. `Op::Input` refers to an index into this list.
+    inputs: Vec<usize>,
+}
+
+impl Program {
+    pub fn compile(text: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(text)?;
+        parser.parse_comparison()?;
+        if parser.token != Token::End {
+            return parser.error("unexpected text after the formula");
+        }
+
+        let mut inputs = parser.inputs;
+        inputs.sort_unstable();
+        inputs.dedup();
+
+        let mut ops = parser.ops;
+        for op in ops.iter_mut() {
+            if let Op::Input(number) = op {
+                *number = inputs.iter().position(|input| input == number).unwrap_or(0);
+            }
+        }
+
+        let program = Self { ops, inputs };
+        if program.stack_depth() > MAX_STACK_DEPTH {
+            return Err(ParseError {
+                message: "formula is too deeply nested".to_string(),
+                position: 0,
+            });
+        }
+
+        Ok(program)
+    }
+
+    // Numbers of the inputs used by the formula, e.g. 2 for `in2`, in ascending order
+    pub fn inputs(&self) -> &[usize] {
+        &self.inputs
+    }
+
+    fn stack_depth(&self) -> usize {
+        let mut depth: usize = 0;
+        let mut max_depth = 0;
+        for op in self.ops.iter() {
+            match op {
+                Op::Const(_) | Op::Input(_) | Op::Time | Op::SampleRate => depth += 1,
+                Op::Neg => {}
+                Op::Call(function) => depth = depth + 1 - function.num_args(),
+                _ => depth -= 1,
+            }
+            max_depth = max_depth.max(depth);
+        }
+        max_depth
+    }
+
+    // Evaluate the program using `stack` as scratch space, which must hold at least `MAX_STACK_DEPTH` values
+    pub fn evaluate(&self, inputs: &[f32], time: f64, sample_rate: f32, stack: &mut [f64]) -> f32 {
+        let mut top = 0;
+        for op in self.ops.iter() {
+            match *op {
+                Op::Const(value) => {
+                    stack[top] = value;
+                    top += 1;
+                }
+                Op::Input(index) => {
+                    stack[top] = inputs.get(index).cloned().unwrap_or(0.0) as f64;
+                    top += 1;
+                }
+                Op::Time => {
+                    stack[top] = time;
+                    top += 1;
+                }
+                Op::SampleRate => {
+                    stack[top] = sample_rate as f64;
+                    top += 1;
+                }
+                Op::Neg => stack[top - 1] = -stack[top - 1],
+                Op::Call(function) => {
+                    let args = function.num_args();
+                    let a = stack[top - args];
+                    let value = match function {
+                        Function::Sin => a.sin(),
+                        Function::Cos => a.cos(),
+                        Function::Tan => a.tan(),
+                        Function::Tanh => a.tanh(),
+                        Function::Abs => a.abs(),
+                        Function::Sqrt => a.sqrt(),
+                        Function::Exp => a.exp(),
+                        Function::Log => a.ln(),
+                        Function::Floor => a.floor(),
+                        Function::Ceil => a.ceil(),
+                        Function::Fract => a - a.floor(),
+                        Function::Sign => {
+                            if a > 0.0 {
+                                1.0
+                            } else if a < 0.0 {
+                                -1.0
+                            } else {
+                                0.0
+                            }
+                        }
+                        Function::Min => a.min(stack[top - 1]),
+                        Function::Max => a.max(stack[top - 1]),
+                        Function::Pow => a.powf(stack[top - 1]),
+                        Function::Clamp => a.max(stack[top - 2]).min(stack[top - 1]),
+                    };
+                    top -= args;
+                    stack[top] = value;
+                    top += 1;
+                }
+                binary => {
+                    let (a, b) = (stack[top - 2], stack[top - 1]);
+                    let truth = |condition: bool| if condition { 1.0 } else { 0.0 };
+                    stack[top - 2] = match binary {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        Op::Rem => a % b,
+                        Op::Pow => a.powf(b),
+                        Op::Less => truth(a < b),
+                        Op::Greater => truth(a > b),
+                        Op::LessEqual => truth(a <= b),
+                        Op::GreaterEqual => truth(a >= b),
+                        _ => 0.0,
+                    };
+                    top -= 1;
+                }
+            }
+        }
+
+        if top == 0 {
+            return 0.0;
+        }
+
+        let value = stack[top - 1] as f32;
+        // Division by zero and similar mistakes shouldn't blow up the rest of the patch
+        if value.is_finite() {
+            value
+        } else {
+            0.0
+        }
+    }
+}
+
+const PARAMS: &[ParamInfo] = &[ParamInfo::text("Formula")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out")];
+const DEFAULT_FORMULA: &str = "sin(2*pi*x*in1) * in2";
+
+// Computes its output from a formula typed into the node, such as `sin(2*pi*x*in1) * in2`.
+//
+// There is an input for each input variable used in the formula. An invalid formula outputs silence.
+pub struct Expression {
+    formula: String,
+    program: Option<Program>,
+    error: Option<String>,
+    inputs: Vec<PortInfo>,
+
+    stack: [f64; MAX_STACK_DEPTH],
+    values: [f32; MAX_EXPRESSION_INPUTS],
+    // Samples processed since the last reset
+    position: u64,
+}
+
+impl Expression {
+    pub const FORMULA: usize = 0;
+
+    pub fn new() -> Self {
+        let mut expression = Self {
+            formula: String::new(),
+            program: None,
+            error: None,
+            inputs: Vec::new(),
+
+            stack: [0.0; MAX_STACK_DEPTH],
+            values: [0.0; MAX_EXPRESSION_INPUTS],
+            position: 0,
+        };
+        // The default formula is known to be valid
+        let _ = expression.set_text(Self::FORMULA, DEFAULT_FORMULA);
+        expression
+    }
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Expression {
+    fn inputs(&self) -> &[PortInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn text(&self, index: usize) -> Option<&str> {
+        match index {
+            Self::FORMULA => Some(&self.formula),
+            _ => None,
+        }
+    }
+
+    fn set_text(&mut self, index: usize, text: &str) -> Result<(), String> {
+        if index != Self::FORMULA {
+            return Ok(());
+        }
+
+        // The formula is kept even if it is invalid, so it can be corrected later
+        self.formula = text.to_string();
+        match Program::compile(text) {
+            Ok(program) => {
+                self.inputs = program
+                    .inputs()
+                    .iter()
+                    .map(|number| PortInfo {
+                        name: Cow::Owned(format!("in{}", number)),
+                        kind: PortKind::Audio,
+                        channels: 1,
+                    })
+                    .collect();
+                self.program = Some(program);
+                self.error = None;
+                Ok(())
+            }
+
+            Err(error) => {
+                self.inputs.clear();
+                self.program = None;
+                self.error = Some(error.to_string());
+                Err(error.to_string())
+            }
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let program = match self.program.as_ref() {
+            Some(program) => program,
+            None => {
+                for sample in outputs[0][..context.frames].iter_mut() {
+                    *sample = 0.0;
+                }
+                return;
+            }
+        };
+
+        let num_inputs = inputs.len().min(MAX_EXPRESSION_INPUTS);
+        for i in 0..context.frames {
+            for (value, input) in self.values[..num_inputs].iter_mut().zip(inputs.iter()) {
+                *value = input[i];
+            }
+
+            let time = self.position as f64 / context.sample_rate as f64;
+            outputs[0][i] = program.evaluate(&self.values[..num_inputs], time, context.sample_rate, &mut self.stack);
+            self.position += 1;
+        }
+    }
+}
+
+impl NodeEditorInfo for Expression {
+    fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(text: &str, inputs: &[f32], time: f64) -> f32 {
+        let program = Program::compile(text).unwrap();
+        let mut stack = [0.0; MAX_STACK_DEPTH];
+        program.evaluate(inputs, time, 48000.0, &mut stack)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("1 + 2 * 3", &[], 0.0), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3", &[], 0.0), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3", &[], 0.0), 3.0);
+        assert_eq!(evaluate("12 / 3 % 3", &[], 0.0), 1.0);
+        assert_eq!(evaluate("-2^2", &[], 0.0), -4.0);
+        assert_eq!(evaluate("2^3^2", &[], 0.0), 512.0);
+        assert_eq!(evaluate("1 + 2 < 4", &[], 0.0), 1.0);
+        assert_eq!(evaluate("clamp(5, 0, 1) + max(2, 3)", &[], 0.0), 4.0);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| Program::compile(text).unwrap_err();
+
+        assert_eq!(error("").message, "unexpected end of formula");
+        assert_eq!(error("1 +").position, 3);
+        assert_eq!(error("foo(1)").message, "unknown function 'foo'");
+        assert_eq!(error("in9").message, "unknown variable 'in9'");
+        assert_eq!(error("min(1)").message, "expected ','");
+        assert_eq!(error("sin(1").message, "expected ')'");
+        assert_eq!(error("1 2").message, "unexpected text after the formula");
+        assert_eq!(error("3 $ 4").position, 2);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| "(".repeat(depth) + "1" + &")".repeat(depth);
+        assert!(Program::compile(&nested(MAX_STACK_DEPTH - 1)).is_ok());
+        assert_eq!(Program::compile(&nested(3000)).unwrap_err().message, "formula is too deeply nested");
+        assert!(Program::compile(&"-".repeat(3000)).is_err());
+    }
+
+    #[test]
+    fn input_numbering() {
+        let program = Program::compile("in3 - in1 + in3").unwrap();
+        assert_eq!(program.inputs(), &[1, 3]);
+
+        // Inputs are passed in the order of their numbers, skipping unused ones
+        assert_eq!(evaluate("in3 - in1", &[1.0, 10.0], 0.0), 9.0);
+
+        let mut expression = Expression::new();
+        expression.set_text(Expression::FORMULA, "in4 * in2").unwrap();
+        let names: Vec<&str> = expression.inputs().iter().map(|port| port.name.as_ref()).collect();
+        assert_eq!(names, ["in2", "in4"]);
+    }
+
+    #[test]
+    fn time_stays_precise() {
+        // A quarter of a cycle of 440 Hz after an hour of playback
+        let time = 3600.0 + 0.25 / 440.0;
+        assert!((evaluate("sin(2 * pi * 440 * x)", &[], time) - 1.0).abs() < 1.0e-3);
+    }
+}
@@ -0,0 +1,65 @@
+use std::f32::consts::PI;
+
+// Simple one pole filter, used for damping and tone controls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnePole {
+    coefficient: f32,
+    state: f32,
+}
+
+impl OnePole {
+    pub fn new() -> Self {
+        Self {
+            coefficient: 1.0,
+            state: 0.0,
+        }
+    }
+
+    pub fn set_cutoff(&mut self, cutoff: f32, sample_rate: f32) {
+        let cutoff = cutoff.max(0.0).min(0.5 * sample_rate);
+        self.coefficient = 1.0 - (-2.0 * PI * cutoff / sample_rate).exp();
+    }
+
+    // Set the filter coefficient directly, where 1.0 lets everything through and 0.0 blocks everything
+    pub fn set_coefficient(&mut self, coefficient: f32) {
+        self.coefficient = coefficient.clamp(0.0, 1.0);
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+    }
+
+    pub fn lowpass(&mut self, input: f32) -> f32 {
+        self.state += self.coefficient * (input - self.state);
+        self.state
+    }
+
+    pub fn highpass(&mut self, input: f32) -> f32 {
+        input - self.lowpass(input)
+    }
+}
+
+impl Default for OnePole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowpass_is_3_db_down_at_cutoff() {
+        let sample_rate = 48000.0;
+        let mut filter = OnePole::new();
+        filter.set_cutoff(1000.0, sample_rate);
+
+        // Peak of a sine at the cutoff once the filter has settled
+        let output: Vec<f32> = (0..4800)
+            .map(|frame| filter.lowpass((2.0 * PI * 1000.0 * frame as f32 / sample_rate).sin()))
+            .collect();
+        let peak = output[2400..].iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - 0.5f32.sqrt()).abs() < 0.01, "{}", peak);
+    }
+}
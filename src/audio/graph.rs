@@ -0,0 +1,991 @@
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::node::*;
+use super::automation::{NodeAutomation, ParamEvent, MAX_PARAM_EVENTS};
+use super::event::{insert_event, MidiEvent, MAX_BLOCK_EVENTS};
+use super::monitor::NodeMonitor;
+use super::scope::ScopeTap;
+use super::transport::Transport;
+
+// Maximum number of input or output ports a node in the graph can have
+pub const MAX_PORTS: usize = 32;
+// Number of frames processed at a time when none is specified
+pub const DEFAULT_BLOCK_SIZE: usize = 256;
+// Sample rate used when none is specified
+pub const DEFAULT_SAMPLE_RATE: f32 = 48000.0;
+
+pub type NodeId = usize;
+
+// A wire from an output port of one node to an input port of another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connection {
+    pub from: NodeId,
+    pub output: usize,
+    pub to: NodeId,
+    pub input: usize,
+    // A feedback connection carries the output of the previous block, which lets it close a loop
+    #[serde(default)]
+    pub feedback: bool,
+}
+
+impl Connection {
+    pub const fn new(from: NodeId, output: usize, to: NodeId, input: usize) -> Self {
+        Self {
+            from,
+            output,
+            to,
+            input,
+            feedback: false,
+        }
+    }
+}
+
+// Check whether adding a connection would close a loop through the other connections.
+//
+// Feedback connections don't count, as they are delayed by a block and so don't need their source to be processed first.
+pub fn creates_cycle(connections: &[Connection], connection: Connection) -> bool {
+    if connection.feedback {
+        return false;
+    }
+
+    // Search for a path from the destination of the connection back to its source
+    let mut visited = Vec::new();
+    let mut pending = vec![connection.to];
+    while let Some(id) = pending.pop() {
+        if id == connection.from {
+            return true;
+        }
+        if visited.contains(&id) {
+            continue;
+        }
+        visited.push(id);
+
+        for next in connections.iter().filter(|next| next.from == id && !next.feedback) {
+            pending.push(next.to);
+        }
+    }
+
+    false
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    // The node id does not refer to a node in the graph
+    InvalidNode,
+    // The port index is out of range for the node
+    InvalidPort,
+    // The node has more than `MAX_PORTS` input or output channels
+    TooManyPorts,
+    // The connection would create a loop, which needs a feedback connection instead
+    Cycle,
+    // Event ports can only be connected to other event ports
+    IncompatiblePorts,
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::InvalidNode => write!(f, "node does not exist"),
+            GraphError::InvalidPort => write!(f, "port does not exist"),
+            GraphError::TooManyPorts => write!(f, "node has more than {} channels", MAX_PORTS),
+            GraphError::Cycle => write!(f, "connection would create a cycle"),
+            GraphError::IncompatiblePorts => write!(f, "event ports can only be connected to event ports"),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+const OUTPUT_INPUTS: &[PortInfo] = &[PortInfo::stereo("In")];
+const OUTPUT_OUTPUTS: &[PortInfo] = &[PortInfo::stereo("Out")];
+
+// Final node of a graph, whose inputs are the audio sent to the speakers or rendered to a file
+pub struct Output;
+
+impl AudioNode for Output {
+    fn inputs(&self) -> &[PortInfo] {
+        OUTPUT_INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUT_OUTPUTS
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            output[..context.frames].copy_from_slice(&input[..context.frames]);
+        }
+    }
+}
+
+// Add a signal to the channels of an input. When the number of channels differs, the source is up-mixed by
+// repeating its channels, or down-mixed by averaging the source channels which fall on each input channel.
+fn mix_into(inputs: &mut [Vec<f32>], source: &[Vec<f32>], frames: usize) {
+    let (num_sources, num_inputs) = (source.len(), inputs.len());
+    if num_sources == 0 || num_inputs == 0 {
+        return;
+    }
+
+    for (channel, buffer) in source.iter().enumerate() {
+        if num_sources <= num_inputs {
+            for input in inputs.iter_mut().skip(channel).step_by(num_sources) {
+                for (sample, value) in input[..frames].iter_mut().zip(buffer.iter()) {
+                    *sample += *value;
+                }
+            }
+        } else {
+            let input = channel % num_inputs;
+            let gain = 1.0 / (num_sources - input).div_ceil(num_inputs) as f32;
+            for (sample, value) in inputs[input][..frames].iter_mut().zip(buffer.iter()) {
+                *sample += gain * *value;
+            }
+        }
+    }
+}
+
+struct GraphNode {
+    node: Box<dyn AudioNode>,
+    // One buffer for each output channel
+    outputs: Vec<Vec<f32>>,
+    // Buffers of each input and output port
+    input_ranges: Vec<Range<usize>>,
+    output_ranges: Vec<Range<usize>>,
+    automation: NodeAutomation,
+    // Nodes with event ports are processed a block at a time, as the frames of their events are relative to the block
+    has_events: bool,
+    monitor: NodeMonitor,
+}
+
+// A feedback connection and the output of its source from the previous block
+struct FeedbackEdge {
+    connection: Connection,
+    buffers: Vec<Vec<f32>>,
+}
+
+// Processes a set of connected audio nodes in dependency order.
+//
+// Feedback connections are left out of the order. Their signal arrives one block late, so a loop such as
+// delay feedback works at the cost of a block of extra delay around the loop.
+//
+// All buffers are allocated when nodes are added, so `process()` does not allocate.
+pub struct AudioGraph {
+    sample_rate: f32,
+    block_size: usize,
+    transport: Transport,
+
+    nodes: Vec<Option<GraphNode>>,
+    // Summed input buffers for each input channel of each node, kept apart from the nodes so they can be filled while reading outputs
+    inputs: Vec<Vec<Vec<f32>>>,
+    // Merged events arriving at each input of each node. Only event inputs have room for events.
+    input_events: Vec<Vec<Vec<MidiEvent>>>,
+    connections: Vec<Connection>,
+    // Connections grouped by destination node, not including feedback connections
+    incoming: Vec<Vec<Connection>>,
+    feedback: Vec<FeedbackEdge>,
+    // Parameter changes for the next block, in order of frame
+    param_events: Vec<ParamEvent>,
+    // Order in which the nodes are processed
+    order: Vec<NodeId>,
+    output: Option<NodeId>,
+    // Output port of a node heard in place of the output node, while probing a signal
+    probe: Option<(NodeId, usize)>,
+    // Node whose subpatch holds the probed port, while probing a signal inside a subpatch
+    nested_probe: Option<NodeId>,
+}
+
+impl AudioGraph {
+    pub fn new(sample_rate: f32, block_size: usize) -> Self {
+        Self {
+            sample_rate,
+            block_size,
+            transport: Transport::new(),
+
+            nodes: Vec::new(),
+            inputs: Vec::new(),
+            input_events: Vec::new(),
+            connections: Vec::new(),
+            incoming: Vec::new(),
+            feedback: Vec::new(),
+            param_events: Vec::with_capacity(MAX_PARAM_EVENTS),
+            order: Vec::new(),
+            output: None,
+            probe: None,
+            nested_probe: None,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.transport.tempo = tempo;
+    }
+
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    // Replace the transport, e.g. when play is pressed or the song position is moved
+    pub fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    pub fn add_node(&mut self, mut node: Box<dyn AudioNode>) -> Result<NodeId, GraphError> {
+        let num_inputs = port_channels(node.inputs());
+        let num_outputs = port_channels(node.outputs());
+        if num_inputs > MAX_PORTS || num_outputs > MAX_PORTS {
+            return Err(GraphError::TooManyPorts);
+        }
+
+        node.prepare(self.sample_rate, self.block_size);
+
+        // Only event inputs are given room for events
+        let input_events = node
+            .inputs()
+            .iter()
+            .map(|port| {
+                if port.kind == PortKind::Event {
+                    Vec::with_capacity(MAX_BLOCK_EVENTS)
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect();
+
+        let has_events = node
+            .inputs()
+            .iter()
+            .chain(node.outputs().iter())
+            .any(|port| port.kind == PortKind::Event);
+
+        let id = self.nodes.len();
+        self.nodes.push(Some(GraphNode {
+            input_ranges: channel_ranges(node.inputs()),
+            output_ranges: channel_ranges(node.outputs()),
+            automation: NodeAutomation::new(node.as_ref(), self.sample_rate),
+            has_events,
+            monitor: NodeMonitor::new(node.outputs()).with_meters(node.as_ref()),
+            node,
+            outputs: vec![vec![0.0; self.block_size]; num_outputs],
+        }));
+        self.inputs.push(vec![vec![0.0; self.block_size]; num_inputs]);
+        self.input_events.push(input_events);
+        self.rebuild()?;
+
+        Ok(id)
+    }
+
+    pub fn remove_node(&mut self, id: NodeId) -> Option<Box<dyn AudioNode>> {
+        let removed = self.nodes.get_mut(id)?.take()?;
+        self.inputs[id].clear();
+        self.input_events[id].clear();
+        self.connections.retain(|connection| connection.from != id && connection.to != id);
+        if self.output == Some(id) {
+            self.output = None;
+        }
+        if self.probe.map(|(probed, _)| probed) == Some(id) {
+            self.probe = None;
+        }
+        if self.nested_probe == Some(id) {
+            self.nested_probe = None;
+        }
+        // Removing a node can't create a cycle
+        let _ = self.rebuild();
+
+        Some(removed.node)
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&dyn AudioNode> {
+        self.nodes.get(id)?.as_ref().map(|graph_node| graph_node.node.as_ref())
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut (dyn AudioNode + 'static)> {
+        self.nodes.get_mut(id)?.as_mut().map(|graph_node| graph_node.node.as_mut())
+    }
+
+    // Swap a node with a node of another graph which has the same ports, so it carries on playing with the state
+    // it built up there, along with its automation and monitor. The node takes the parameter values of the node
+    // it replaces, smoothed from where it was. Returns false if either node is missing or their ports differ.
+    //
+    // Doesn't allocate or free memory, so graphs can be swapped on the audio thread.
+    pub fn swap_node(&mut self, id: NodeId, other: &mut AudioGraph, other_id: NodeId) -> bool {
+        let (graph_node, other_node) = match (self.nodes.get_mut(id), other.nodes.get_mut(other_id)) {
+            (Some(Some(graph_node)), Some(Some(other_node))) => (graph_node, other_node),
+            _ => return false,
+        };
+        if graph_node.input_ranges != other_node.input_ranges
+            || graph_node.output_ranges != other_node.output_ranges
+            || graph_node.node.params().len() != other_node.node.params().len()
+        {
+            return false;
+        }
+
+        mem::swap(&mut graph_node.node, &mut other_node.node);
+        mem::swap(&mut graph_node.automation, &mut other_node.automation);
+        mem::swap(&mut graph_node.monitor, &mut other_node.monitor);
+        // Which nodes have a scope is chosen for each graph, so the scope taps stay where they were
+        graph_node.monitor.swap_scope(&mut other_node.monitor);
+
+        for index in 0..graph_node.node.params().len() {
+            let value = other_node.node.get_param(index);
+            if graph_node.node.get_param(index) != value {
+                graph_node.automation.change(graph_node.node.as_mut(), index, value);
+            }
+        }
+
+        for index in 0..graph_node.node.inputs().len() {
+            let connected = self
+                .connections
+                .iter()
+                .any(|connection| connection.to == id && connection.input == index);
+            graph_node.node.input_connected(index, connected);
+        }
+
+        true
+    }
+
+    // Processing time and output levels of a node, updated after each block
+    pub fn monitor(&self, id: NodeId) -> Option<NodeMonitor> {
+        self.nodes.get(id)?.as_ref().map(|graph_node| graph_node.monitor.clone())
+    }
+
+    // Keep the latest samples of the first output of a node for a scope to show, or stop keeping them
+    pub fn set_scope_tap(&mut self, id: NodeId, tap: Option<ScopeTap>) {
+        if let Some(Some(graph_node)) = self.nodes.get_mut(id) {
+            graph_node.monitor.set_scope(tap);
+        }
+    }
+
+    pub fn set_param(&mut self, id: NodeId, index: usize, value: f32) {
+        if let Some(node) = self.node_mut(id) {
+            node.set_param(index, value);
+        }
+    }
+
+    // Change a parameter at a frame of the next block, smoothed as its node asks. Returns false if too
+    // many changes have been scheduled for the block.
+    pub fn schedule_param(&mut self, event: ParamEvent) -> bool {
+        if self.param_events.len() >= MAX_PARAM_EVENTS {
+            return false;
+        }
+
+        let index = self
+            .param_events
+            .iter()
+            .rposition(|existing| existing.frame <= event.frame)
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        self.param_events.insert(index, event);
+        true
+    }
+
+    // Schedule a change for a node in this graph or in the subpatch of one of its nodes, with the node found by
+    // its path as in `ParamChange`
+    pub fn schedule_param_path(&mut self, path: &[usize], frame: usize, index: usize, value: f32) -> bool {
+        match path {
+            [] => false,
+            [id] => self.schedule_param(ParamEvent::new(frame, *id, index, value)),
+            [id, rest @ ..] => match self.node_mut(*id) {
+                Some(node) => node.schedule_param(rest, frame, index, value),
+                None => false,
+            },
+        }
+    }
+
+    // Apply the changes scheduled for the next block at once, for a graph which won't process the block
+    pub fn apply_scheduled_params(&mut self) {
+        for event in self.param_events.drain(..) {
+            if let Some(graph_node) = self.nodes.get_mut(event.node).and_then(|graph_node| graph_node.as_mut()) {
+                graph_node.automation.change(graph_node.node.as_mut(), event.index, event.value);
+                graph_node.automation.finish(graph_node.node.as_mut());
+            }
+        }
+    }
+
+    pub fn connections(&self) -> &[Connection] {
+        &self.connections
+    }
+
+    pub fn connect(&mut self, connection: Connection) -> Result<(), GraphError> {
+        let from = self.node(connection.from).ok_or(GraphError::InvalidNode)?;
+        if connection.output >= from.outputs().len() {
+            return Err(GraphError::InvalidPort);
+        }
+
+        let to = self.node(connection.to).ok_or(GraphError::InvalidNode)?;
+        if connection.input >= to.inputs().len() {
+            return Err(GraphError::InvalidPort);
+        }
+
+        let from_events = from.outputs()[connection.output].kind == PortKind::Event;
+        let to_events = to.inputs()[connection.input].kind == PortKind::Event;
+        if from_events != to_events {
+            return Err(GraphError::IncompatiblePorts);
+        }
+
+        // Only audio is delayed by feedback connections
+        if connection.feedback && from_events {
+            return Err(GraphError::IncompatiblePorts);
+        }
+
+        if self.connections.contains(&connection) {
+            return Ok(());
+        }
+
+        self.connections.push(connection);
+        if let Err(error) = self.rebuild() {
+            self.connections.pop();
+            let _ = self.rebuild();
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, connection: Connection) {
+        self.connections.retain(|existing| *existing != connection);
+        let _ = self.rebuild();
+    }
+
+    // Set the node whose outputs are the output of the graph
+    pub fn set_output(&mut self, id: NodeId) -> Result<(), GraphError> {
+        self.node(id).ok_or(GraphError::InvalidNode)?;
+        self.output = Some(id);
+        Ok(())
+    }
+
+    // Hear an output port of a node in place of the output node, bypassing the nodes after it, or None to go back
+    // to the output node. Event ports carry no audio, so they can't be probed.
+    pub fn set_probe(&mut self, probe: Option<(NodeId, usize)>) -> Result<(), GraphError> {
+        if let Some((id, output)) = probe {
+            let port = self
+                .node(id)
+                .ok_or(GraphError::InvalidNode)?
+                .outputs()
+                .get(output)
+                .ok_or(GraphError::InvalidPort)?;
+            if port.kind == PortKind::Event {
+                return Err(GraphError::IncompatiblePorts);
+            }
+        }
+
+        self.probe = probe;
+        Ok(())
+    }
+
+    pub fn probe(&self) -> Option<(NodeId, usize)> {
+        self.probe
+    }
+
+    // Hear an output port of a node in this graph or in the subpatch of one of its nodes, with the node found by
+    // its path as in `ParamChange`, or None to go back to the output node
+    pub fn set_probe_path(&mut self, probe: Option<(&[usize], usize)>) -> Result<(), GraphError> {
+        if let Some(id) = self.nested_probe.take() {
+            if let Some(node) = self.node_mut(id) {
+                node.set_probe(None);
+            }
+        }
+
+        match probe {
+            None => self.set_probe(None),
+            Some(([], _)) => Err(GraphError::InvalidNode),
+            Some(([id], output)) => self.set_probe(Some((*id, output))),
+            Some(([id, rest @ ..], output)) => {
+                let node = self.node_mut(*id).ok_or(GraphError::InvalidNode)?;
+                if !node.set_probe(Some((rest, output))) {
+                    return Err(GraphError::InvalidPort);
+                }
+                self.probe = None;
+                self.nested_probe = Some(*id);
+                Ok(())
+            }
+        }
+    }
+
+    // Output buffer of the output node for the most recently processed block.
+    //
+    // While probing, the probed port is heard instead, with a mono port sent to every channel.
+    pub fn output_buffer(&self, channel: usize) -> Option<&[f32]> {
+        if let Some(id) = self.nested_probe {
+            return self.node(id)?.probe_buffer(channel);
+        }
+
+        if let Some((id, output)) = self.probe {
+            let graph_node = self.nodes.get(id)?.as_ref()?;
+            let channels = graph_node.output_ranges.get(output)?;
+            if channels.is_empty() {
+                return None;
+            }
+            return graph_node
+                .outputs
+                .get(channels.start + channel % channels.len())
+                .map(|buffer| buffer.as_slice());
+        }
+
+        self.output_node_buffer(channel)
+    }
+
+    // Output buffer of the output node whether or not a signal is being probed, for a group to pass on
+    pub fn output_node_buffer(&self, channel: usize) -> Option<&[f32]> {
+        let graph_node = self.nodes.get(self.output?)?.as_ref()?;
+        graph_node.outputs.get(channel).map(|buffer| buffer.as_slice())
+    }
+
+    // Events sent from an output of the output node during the most recently processed block
+    pub fn output_events(&self, channel: usize) -> &[MidiEvent] {
+        match self.output.and_then(|id| self.node(id)) {
+            Some(node) => node.output_events(channel),
+            None => &[],
+        }
+    }
+
+    // Sort the nodes so that every node is processed after the nodes connected to its inputs
+    fn rebuild(&mut self) -> Result<(), GraphError> {
+        let num_nodes = self.nodes.len();
+        let mut incoming = vec![Vec::new(); num_nodes];
+        let mut num_dependencies = vec![0; num_nodes];
+        for connection in self.connections.iter().filter(|connection| !connection.feedback) {
+            incoming[connection.to].push(*connection);
+            num_dependencies[connection.to] += 1;
+        }
+
+        let mut order = Vec::with_capacity(num_nodes);
+        let mut ready: Vec<NodeId> = (0..num_nodes)
+            .filter(|id| self.nodes[*id].is_some() && num_dependencies[*id] == 0)
+            .rev()
+            .collect();
+
+        while let Some(id) = ready.pop() {
+            order.push(id);
+            for connection in self.connections.iter().filter(|connection| connection.from == id && !connection.feedback) {
+                num_dependencies[connection.to] -= 1;
+                if num_dependencies[connection.to] == 0 {
+                    ready.push(connection.to);
+                }
+            }
+        }
+
+        let num_live = self.nodes.iter().filter(|node| node.is_some()).count();
+        if order.len() != num_live {
+            return Err(GraphError::Cycle);
+        }
+
+        for (id, graph_node) in self.nodes.iter_mut().enumerate() {
+            if let Some(graph_node) = graph_node {
+                for index in 0..graph_node.node.inputs().len() {
+                    let connected = self
+                        .connections
+                        .iter()
+                        .any(|connection| connection.to == id && connection.input == index);
+                    graph_node.node.input_connected(index, connected);
+                }
+            }
+        }
+
+        let block_size = self.block_size;
+        self.feedback = self
+            .connections
+            .iter()
+            .filter(|connection| connection.feedback)
+            .filter_map(|connection| {
+                let source = self.nodes[connection.from].as_ref()?;
+                let channels = source.output_ranges[connection.output].len();
+                Some(FeedbackEdge {
+                    connection: *connection,
+                    buffers: vec![vec![0.0; block_size]; channels],
+                })
+            })
+            .collect();
+        self.incoming = incoming;
+        self.order = order;
+        Ok(())
+    }
+
+    // Clear the state of every node
+    pub fn reset(&mut self) {
+        for graph_node in self.nodes.iter_mut().flatten() {
+            graph_node.automation.finish(graph_node.node.as_mut());
+            graph_node.node.reset();
+        }
+
+        for edge in self.feedback.iter_mut() {
+            for sample in edge.buffers.iter_mut().flatten() {
+                *sample = 0.0;
+            }
+        }
+    }
+
+    // Process the next block of `frames` samples, which must not be more than the block size
+    pub fn process(&mut self, frames: usize) {
+        self.process_with_inputs(frames, &[], &[], &[]);
+    }
+
+    // Process the next block with signals from outside the graph, which reach the nodes through the process context.
+    //
+    // `inputs` are the signals at each input channel and `input_events` at each input of the group containing the graph, and `events`
+    // are sent to every node, such as the notes of a voice. Events must be in order of frame.
+    pub fn process_with_inputs(&mut self, frames: usize, inputs: &[&[f32]], input_events: &[&[MidiEvent]], events: &[MidiEvent]) {
+        let frames = frames.min(self.block_size);
+        let mut context = ProcessContext::new(self.sample_rate, frames);
+        context.transport = self.transport;
+        context.events = events;
+        context.inputs = inputs;
+        context.input_events = input_events;
+
+        for &id in self.order.iter() {
+            let inputs = &mut self.inputs[id];
+            for buffer in inputs.iter_mut() {
+                for sample in buffer[..frames].iter_mut() {
+                    *sample = 0.0;
+                }
+            }
+
+            let input_ranges = match self.nodes[id].as_ref() {
+                Some(graph_node) => &graph_node.input_ranges,
+                None => continue,
+            };
+
+            for connection in self.incoming[id].iter() {
+                if let Some(source) = self.nodes[connection.from].as_ref() {
+                    let channels = source.output_ranges[connection.output].clone();
+                    mix_into(&mut inputs[input_ranges[connection.input].clone()], &source.outputs[channels], frames);
+                }
+            }
+
+            for edge in self.feedback.iter().filter(|edge| edge.connection.to == id) {
+                mix_into(&mut inputs[input_ranges[edge.connection.input].clone()], &edge.buffers, frames);
+            }
+
+            let input_events = &mut self.input_events[id];
+            for events in input_events.iter_mut() {
+                events.clear();
+            }
+
+            for connection in self.incoming[id].iter() {
+                if let (Some(source), Some(events)) = (self.nodes[connection.from].as_ref(), input_events.get_mut(connection.input)) {
+                    // Only event inputs have room for events, so audio connections are skipped here
+                    if events.capacity() == 0 {
+                        continue;
+                    }
+                    for event in source.node.output_events(connection.output) {
+                        if event.frame < frames {
+                            insert_event(events, *event);
+                        }
+                    }
+                }
+            }
+
+            if let Some(graph_node) = self.nodes[id].as_mut() {
+                for (index, events) in input_events.iter().enumerate() {
+                    if events.capacity() > 0 {
+                        graph_node.node.input_events(index, events);
+                    }
+                }
+
+                // Parameter changes split the block, so they happen at their frame and smoothed parameters move
+                // during the block. Nodes with events are processed whole, with changes applied at the start.
+                let split = !graph_node.has_events && events.is_empty();
+                let started = Instant::now();
+                let mut param_events = self.param_events.iter().filter(|event| event.node == id).peekable();
+                let mut start = 0;
+                while start < frames {
+                    while let Some(event) = param_events.next_if(|event| !split || event.frame.min(frames - 1) <= start) {
+                        graph_node.automation.change(graph_node.node.as_mut(), event.index, event.value);
+                    }
+
+                    let mut end = frames;
+                    if split {
+                        if let Some(event) = param_events.peek() {
+                            end = end.min(event.frame.min(frames - 1));
+                        }
+                        end = graph_node.automation.stretch_end(start, end);
+                    }
+                    graph_node.automation.update(graph_node.node.as_mut());
+
+                    let mut input_refs: [&[f32]; MAX_PORTS] = Default::default();
+                    for (input_ref, buffer) in input_refs.iter_mut().zip(inputs.iter()) {
+                        *input_ref = &buffer[start..end];
+                    }
+
+                    let num_outputs = graph_node.outputs.len();
+                    let mut output_refs: [&mut [f32]; MAX_PORTS] = Default::default();
+                    for (output_ref, buffer) in output_refs.iter_mut().zip(graph_node.outputs.iter_mut()) {
+                        *output_ref = &mut buffer[start..end];
+                    }
+
+                    let mut stretch = context;
+                    stretch.frames = end - start;
+                    stretch.transport.advance(start, self.sample_rate);
+                    graph_node.node.process(&stretch, &input_refs[..inputs.len()], &mut output_refs[..num_outputs]);
+
+                    graph_node.automation.advance(graph_node.node.as_mut(), &mut graph_node.outputs, start, end);
+                    start = end;
+                }
+
+                graph_node.monitor.record(started.elapsed(), frames, self.sample_rate, &graph_node.outputs);
+            }
+        }
+
+        // Keep the sources of the feedback connections for the next block
+        for edge in self.feedback.iter_mut() {
+            if let Some(source) = self.nodes[edge.connection.from].as_ref() {
+                let channels = source.output_ranges[edge.connection.output].clone();
+                for (buffer, output) in edge.buffers.iter_mut().zip(source.outputs[channels].iter()) {
+                    buffer[..frames].copy_from_slice(&output[..frames]);
+                    for sample in buffer[frames..].iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+            }
+        }
+
+        self.param_events.clear();
+        self.transport.advance(frames, self.sample_rate);
+    }
+
+    // Render `length` samples from the start, returning one buffer per output channel.
+    //
+    // Every node is reset and the song position rewound first, so rendering the same graph twice gives identical results.
+    pub fn render_offline(&mut self, length: usize) -> Vec<Vec<f32>> {
+        self.reset();
+        self.transport.position = 0.0;
+
+        let num_channels = self
+            .output
+            .and_then(|id| self.node(id))
+            .map(|node| port_channels(node.outputs()))
+            .unwrap_or(0);
+        let mut rendered = vec![Vec::with_capacity(length); num_channels];
+
+        let mut position = 0;
+        while position < length {
+            let frames = (length - position).min(self.block_size);
+            self.process(frames);
+
+            for (channel, buffer) in rendered.iter_mut().enumerate() {
+                if let Some(output) = self.output_buffer(channel) {
+                    buffer.extend_from_slice(&output[..frames]);
+                }
+            }
+
+            position += frames;
+        }
+
+        rendered
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{
+        ChannelMode, ChannelNode, Delay, Group, GroupInput, GroupOutput, MathNode, MathOp, Noise, NoiseColor, Patch, PatchNode, Reverb,
+        GROUP_INPUT, GROUP_OUTPUT,
+    };
+
+    #[test]
+    fn cycles() {
+        let connections = [Connection::new(0, 0, 1, 0), Connection::new(1, 0, 2, 0)];
+        assert!(creates_cycle(&connections, Connection::new(2, 0, 0, 0)));
+        assert!(creates_cycle(&connections, Connection::new(1, 0, 1, 1)));
+        assert!(!creates_cycle(&connections, Connection::new(0, 0, 2, 1)));
+
+        // Feedback connections neither close a loop nor count towards one
+        let feedback = Connection { feedback: true, ..Connection::new(2, 0, 0, 0) };
+        assert!(!creates_cycle(&connections, feedback));
+        assert!(!creates_cycle(&[connections[0], feedback], Connection::new(0, 0, 2, 0)));
+    }
+
+    #[test]
+    fn feedback_arrives_a_block_late() {
+        let mut graph = AudioGraph::new(DEFAULT_SAMPLE_RATE, 4);
+        let input = graph.add_node(Box::new(GroupInput::new("In"))).unwrap();
+        let add = graph.add_node(Box::new(MathNode::new(MathOp::Add))).unwrap();
+        let output = graph.add_node(Box::new(Output)).unwrap();
+        graph.set_output(output).unwrap();
+        graph.connect(Connection::new(input, 0, add, 0)).unwrap();
+        graph.connect(Connection::new(add, 0, output, 0)).unwrap();
+
+        assert_eq!(graph.connect(Connection::new(add, 0, add, 1)), Err(GraphError::Cycle));
+        graph.connect(Connection { feedback: true, ..Connection::new(add, 0, add, 1) }).unwrap();
+
+        // The sum grows by the input each block, as the feedback carries the previous block's output
+        let ones = [1.0; 4];
+        for block in 1..4 {
+            graph.process_with_inputs(4, &[&ones], &[], &[]);
+            assert_eq!(graph.output_buffer(0).unwrap(), &[block as f32; 4]);
+        }
+
+        graph.reset();
+        graph.process_with_inputs(4, &[&ones], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[1.0; 4]);
+    }
+
+    #[test]
+    fn feedback_source_processed_after_destination() {
+        // The feedback connection runs against the order of the other connections, so the delayed node
+        // reads the previous block of a node processed after it
+        let mut graph = AudioGraph::new(DEFAULT_SAMPLE_RATE, 4);
+        let input = graph.add_node(Box::new(GroupInput::new("In"))).unwrap();
+        let first = graph.add_node(Box::new(MathNode::new(MathOp::Add))).unwrap();
+        let second = graph.add_node(Box::new(MathNode::new(MathOp::Add))).unwrap();
+        let output = graph.add_node(Box::new(Output)).unwrap();
+        graph.set_output(output).unwrap();
+        graph.connect(Connection::new(input, 0, first, 0)).unwrap();
+        graph.connect(Connection::new(first, 0, second, 0)).unwrap();
+        graph.connect(Connection::new(input, 0, second, 1)).unwrap();
+        graph.connect(Connection::new(second, 0, output, 0)).unwrap();
+        graph.connect(Connection { feedback: true, ..Connection::new(second, 0, first, 1) }).unwrap();
+
+        let ones = [1.0; 4];
+        graph.process_with_inputs(4, &[&ones], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[2.0; 4]);
+        graph.process_with_inputs(4, &[&ones], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[4.0; 4]);
+    }
+
+    #[test]
+    fn up_mixing() {
+        let mut inputs = vec![vec![0.0; 2]; 2];
+        mix_into(&mut inputs, &[vec![1.0, 2.0]], 2);
+        assert_eq!(inputs, [[1.0, 2.0], [1.0, 2.0]]);
+
+        // Channels are repeated in turn when there are more than one
+        let mut inputs = vec![vec![0.0; 1]; 4];
+        mix_into(&mut inputs, &[vec![1.0], vec![2.0]], 1);
+        assert_eq!(inputs, [[1.0], [2.0], [1.0], [2.0]]);
+    }
+
+    #[test]
+    fn down_mixing() {
+        let mut inputs = vec![vec![0.0; 2]; 1];
+        mix_into(&mut inputs, &[vec![1.0, 1.0], vec![3.0, -1.0]], 2);
+        assert_eq!(inputs, [[2.0, 0.0]]);
+
+        // Each input averages the channels which fall on it
+        let mut inputs = vec![vec![0.0; 1]; 2];
+        mix_into(&mut inputs, &[vec![1.0], vec![2.0], vec![3.0]], 1);
+        assert_eq!(inputs, [[2.0], [2.0]]);
+
+        // Signals from several connections add up
+        let mut inputs = vec![vec![1.0; 1]; 2];
+        mix_into(&mut inputs, &[vec![1.0], vec![2.0]], 1);
+        assert_eq!(inputs, [[2.0], [3.0]]);
+    }
+
+    #[test]
+    fn stereo_connections() {
+        let mut graph = AudioGraph::new(DEFAULT_SAMPLE_RATE, 4);
+        let input = graph.add_node(Box::new(GroupInput::new("Left, Right"))).unwrap();
+        let merge = graph.add_node(Box::new(ChannelNode::new(ChannelMode::Merge))).unwrap();
+        let add = graph.add_node(Box::new(MathNode::new(MathOp::Add))).unwrap();
+        let output = graph.add_node(Box::new(Output)).unwrap();
+        graph.set_output(output).unwrap();
+        graph.connect(Connection::new(input, 0, merge, 0)).unwrap();
+        graph.connect(Connection::new(input, 1, merge, 1)).unwrap();
+        graph.connect(Connection::new(merge, 0, add, 0)).unwrap();
+        graph.connect(Connection::new(add, 0, output, 0)).unwrap();
+
+        // The stereo signal is down-mixed into the mono input of the add node, then up-mixed into the output
+        let (left, right) = ([1.0; 4], [3.0; 4]);
+        graph.process_with_inputs(4, &[&left, &right], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[2.0; 4]);
+        assert_eq!(graph.output_buffer(1).unwrap(), &[2.0; 4]);
+
+        graph.disconnect(Connection::new(add, 0, output, 0));
+        graph.connect(Connection::new(merge, 0, output, 0)).unwrap();
+        graph.process_with_inputs(4, &[&left, &right], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[1.0; 4]);
+        assert_eq!(graph.output_buffer(1).unwrap(), &[3.0; 4]);
+    }
+
+    #[test]
+    fn param_events_past_a_short_block() {
+        let mut graph = AudioGraph::new(DEFAULT_SAMPLE_RATE, 64);
+        let input = graph.add_node(Box::new(GroupInput::new("In"))).unwrap();
+        let scale = graph.add_node(Box::new(MathNode::new(MathOp::ScaleOffset))).unwrap();
+        let output = graph.add_node(Box::new(Output)).unwrap();
+        graph.set_output(output).unwrap();
+        graph.connect(Connection::new(input, 0, scale, 0)).unwrap();
+        graph.connect(Connection::new(scale, 0, output, 0)).unwrap();
+
+        // An event beyond the end of a short block starts at its last frame rather than being dropped
+        let offset = graph.node(scale).unwrap().params().iter().position(|param| param.name == "Offset").unwrap();
+        assert!(graph.schedule_param(ParamEvent::new(40, scale, offset, 0.5)));
+        let ones = [1.0; 64];
+        graph.process_with_inputs(32, &[&ones], &[], &[]);
+        for _ in 0..20 {
+            graph.process_with_inputs(64, &[&ones], &[], &[]);
+        }
+        assert_eq!(graph.node(scale).unwrap().get_param(offset), 0.5);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[1.5; 64]);
+    }
+
+    #[test]
+    fn probe_inside_a_group() {
+        let mut scale = MathNode::new(MathOp::ScaleOffset);
+        let offset = scale.params().iter().position(|param| param.name == "Offset").unwrap();
+        scale.set_param(offset, 0.5);
+        let mut group = Group::new();
+        group.set_subpatch(Patch {
+            nodes: vec![
+                PatchNode::new(GROUP_INPUT, 0.0, 0.0, &GroupInput::new("In")),
+                PatchNode::new("Scale Offset", 0.0, 0.0, &scale),
+                PatchNode::new(GROUP_OUTPUT, 0.0, 0.0, &GroupOutput::new("Out")),
+            ],
+            connections: vec![Connection::new(0, 0, 1, 0), Connection::new(0, 0, 2, 0)],
+        });
+
+        let mut graph = AudioGraph::new(DEFAULT_SAMPLE_RATE, 4);
+        let input = graph.add_node(Box::new(GroupInput::new("In"))).unwrap();
+        let group = graph.add_node(Box::new(group)).unwrap();
+        let output = graph.add_node(Box::new(Output)).unwrap();
+        graph.set_output(output).unwrap();
+        graph.connect(Connection::new(input, 0, group, 0)).unwrap();
+        graph.connect(Connection::new(group, 0, output, 0)).unwrap();
+
+        // The node inside the group is heard, while the group passes on its own output as before
+        graph.set_probe_path(Some((&[group, 1], 0))).unwrap();
+        let ones = [1.0; 4];
+        graph.process_with_inputs(4, &[&ones], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[1.5; 4]);
+        assert_eq!(graph.output_node_buffer(0).unwrap(), &[1.0; 4]);
+
+        assert!(graph.set_probe_path(Some((&[group, 7], 0))).is_err());
+        graph.set_probe_path(None).unwrap();
+        graph.process_with_inputs(4, &[&ones], &[], &[]);
+        assert_eq!(graph.output_buffer(0).unwrap(), &[1.0; 4]);
+    }
+
+    #[test]
+    fn offline_renders_are_identical() {
+        let mut graph = AudioGraph::new(DEFAULT_SAMPLE_RATE, 64);
+        let noise = graph.add_node(Box::new(Noise::new(NoiseColor::White))).unwrap();
+        let delay = graph.add_node(Box::new(Delay::new())).unwrap();
+        let reverb = graph.add_node(Box::new(Reverb::new())).unwrap();
+        let output = graph.add_node(Box::new(Output)).unwrap();
+        graph.set_output(output).unwrap();
+        graph.connect(Connection::new(noise, 0, delay, 0)).unwrap();
+        graph.connect(Connection::new(delay, 0, reverb, 0)).unwrap();
+        graph.connect(Connection::new(reverb, 0, output, 0)).unwrap();
+
+        // The second render starts from scratch rather than carrying on from the end of the first
+        let first = graph.render_offline(1000);
+        let second = graph.render_offline(1000);
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|channel| channel.len() == 1000));
+        assert!(first[0].iter().any(|sample| *sample != 0.0));
+        assert_eq!(first, second);
+    }
+}
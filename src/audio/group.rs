@@ -0,0 +1,732 @@
+use std::borrow::Cow;
+
+use super::node::*;
+use super::event::{MidiEvent, MAX_BLOCK_EVENTS};
+use super::graph::{AudioGraph, Connection, GraphError, MAX_PORTS};
+use super::patch::{Patch, PatchError, PatchNode};
+use super::registry::NodeRegistry;
+
+// Registry names of the nodes which pass signals into and out of the patch of a group
+pub const GROUP_INPUT: &str = "Group Input";
+pub const GROUP_OUTPUT: &str = "Group Output";
+
+// Parse a list of ports such as "In:2, Cutoff:control, Notes:event". Ports are audio unless another kind is
+// given, and have one channel unless a number of channels is given.
+pub fn parse_ports(text: &str) -> Result<Vec<PortInfo>, String> {
+    let mut ports = Vec::new();
+    for item in text.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let mut parts = item.split(':').map(|part| part.trim());
+        let name = parts.next().unwrap_or_default();
+        if name.is_empty() {
+            return Err(format!("port '{}' has no name", item));
+        }
+
+        let mut kind = PortKind::Audio;
+        let mut channels = 1;
+        for part in parts {
+            if let Ok(number) = part.parse::<usize>() {
+                channels = number;
+                continue;
+            }
+
+            kind = match part.to_lowercase().as_str() {
+                "audio" => PortKind::Audio,
+                "control" => PortKind::Control,
+                "gate" => PortKind::Gate,
+                "event" => PortKind::Event,
+                _ => return Err(format!("unknown port kind '{}'", part)),
+            };
+        }
+
+        if channels == 0 || (kind == PortKind::Event && channels != 1) {
+            return Err(format!("port '{}' can't have {} channels", name, channels));
+        }
+
+        ports.push(PortInfo {
+            name: Cow::Owned(name.to_string()),
+            kind,
+            channels,
+        });
+    }
+
+    if port_channels(&ports) > MAX_PORTS {
+        return Err(format!("more than {} channels", MAX_PORTS));
+    }
+
+    Ok(ports)
+}
+
+// Write ports in the form read by `parse_ports()`
+pub fn format_ports(ports: &[PortInfo]) -> String {
+    ports
+        .iter()
+        .map(|port| {
+            let mut text = match port.kind {
+                PortKind::Audio => port.name.to_string(),
+                PortKind::Control => format!("{}:control", port.name),
+                PortKind::Gate => format!("{}:gate", port.name),
+                PortKind::Event => format!("{}:event", port.name),
+            };
+            if port.channels != 1 {
+                text.push_str(&format!(":{}", port.channels));
+            }
+            text
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+const PORTS_PARAMS: &[ParamInfo] = &[ParamInfo::text("Ports")];
+
+// Ports of a group input or output node, set by its text parameter
+struct PortList {
+    text: String,
+    ports: Vec<PortInfo>,
+    error: Option<String>,
+    // Events passing through each port during the block. Only event ports have room for events.
+    events: Vec<Vec<MidiEvent>>,
+}
+
+impl PortList {
+    fn new(text: &str) -> Self {
+        let mut list = Self {
+            text: String::new(),
+            ports: Vec::new(),
+            error: None,
+            events: Vec::new(),
+        };
+        // The default ports are known to be valid
+        let _ = list.set_text(text);
+        list
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        // The text is kept even if it is invalid, so it can be corrected later
+        self.text = text.to_string();
+        match parse_ports(text) {
+            Ok(ports) => {
+                self.events = ports
+                    .iter()
+                    .map(|port| {
+                        if port.kind == PortKind::Event {
+                            Vec::with_capacity(MAX_BLOCK_EVENTS)
+                        } else {
+                            Vec::new()
+                        }
+                    })
+                    .collect();
+                self.ports = ports;
+                self.error = None;
+                Ok(())
+            }
+
+            Err(error) => {
+                self.ports.clear();
+                self.events.clear();
+                self.error = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    fn set_events(&mut self, index: usize, events: &[MidiEvent]) {
+        if let Some(buffer) = self.events.get_mut(index) {
+            buffer.clear();
+            if buffer.capacity() > 0 {
+                buffer.extend_from_slice(&events[..events.len().min(MAX_BLOCK_EVENTS)]);
+            }
+        }
+    }
+
+    fn events(&self, index: usize) -> &[MidiEvent] {
+        self.events.get(index).map(|events| events.as_slice()).unwrap_or(&[])
+    }
+}
+
+// Outputs the signals arriving at the inputs of the group containing it. The ports of the
+// group are the ports of this node.
+pub struct GroupInput {
+    ports: PortList,
+}
+
+impl GroupInput {
+    pub const PORTS: usize = 0;
+
+    pub fn new(ports: &str) -> Self {
+        Self {
+            ports: PortList::new(ports),
+        }
+    }
+}
+
+impl AudioNode for GroupInput {
+    fn inputs(&self) -> &[PortInfo] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        &self.ports.ports
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PORTS_PARAMS
+    }
+
+    fn text(&self, index: usize) -> Option<&str> {
+        match index {
+            Self::PORTS => Some(&self.ports.text),
+            _ => None,
+        }
+    }
+
+    fn set_text(&mut self, index: usize, text: &str) -> Result<(), String> {
+        match index {
+            Self::PORTS => self.ports.set_text(text),
+            _ => Ok(()),
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn output_events(&self, index: usize) -> &[MidiEvent] {
+        self.ports.events(index)
+    }
+
+    fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for (index, output) in outputs.iter_mut().enumerate() {
+            match context.inputs.get(index) {
+                Some(input) => output.copy_from_slice(&input[..context.frames]),
+                None => {
+                    for sample in output.iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+            }
+        }
+
+        for index in 0..self.ports.events.len() {
+            let events = context.input_events.get(index).copied().unwrap_or(&[]);
+            self.ports.set_events(index, events);
+        }
+    }
+}
+
+impl NodeEditorInfo for GroupInput {
+    fn error(&self) -> Option<&str> {
+        self.ports.error.as_deref()
+    }
+}
+
+// Passes its inputs to the outputs of the group containing it. The ports of the group are the ports of this node.
+pub struct GroupOutput {
+    ports: PortList,
+}
+
+impl GroupOutput {
+    pub const PORTS: usize = 0;
+
+    pub fn new(ports: &str) -> Self {
+        Self {
+            ports: PortList::new(ports),
+        }
+    }
+}
+
+impl AudioNode for GroupOutput {
+    fn inputs(&self) -> &[PortInfo] {
+        &self.ports.ports
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        &self.ports.ports
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PORTS_PARAMS
+    }
+
+    fn text(&self, index: usize) -> Option<&str> {
+        match index {
+            Self::PORTS => Some(&self.ports.text),
+            _ => None,
+        }
+    }
+
+    fn set_text(&mut self, index: usize, text: &str) -> Result<(), String> {
+        match index {
+            Self::PORTS => self.ports.set_text(text),
+            _ => Ok(()),
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn input_events(&mut self, index: usize, events: &[MidiEvent]) {
+        self.ports.set_events(index, events);
+    }
+
+    fn output_events(&self, index: usize) -> &[MidiEvent] {
+        self.ports.events(index)
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            output[..context.frames].copy_from_slice(&input[..context.frames]);
+        }
+    }
+}
+
+impl NodeEditorInfo for GroupOutput {
+    fn error(&self) -> Option<&str> {
+        self.ports.error.as_deref()
+    }
+}
+
+// Find the ports of the group input or output node of a patch
+fn patch_ports(patch: &Patch, kind: &str) -> Result<Vec<PortInfo>, String> {
+    let text = patch
+        .nodes
+        .iter()
+        .find(|patch_node| patch_node.kind == kind)
+        .and_then(|patch_node| patch_node.texts.iter().find(|(index, _)| *index == 0))
+        .map(|(_, text)| text.as_str())
+        .unwrap_or_default();
+
+    parse_ports(text)
+}
+
+const GROUP_PARAMS: &[ParamInfo] = &[ParamInfo::text("Name")];
+
+// A patch collapsed into a single node.
+//
+// The inputs and outputs of the group are the ports of the group input and output nodes inside
+// its patch. Events sent into the graph containing the group, such as the notes of a voice, are
+// passed on to the patch of the group.
+pub struct Group {
+    name: String,
+    patch: Patch,
+    inputs: Vec<PortInfo>,
+    outputs: Vec<PortInfo>,
+
+    graph: Option<AudioGraph>,
+    error: Option<String>,
+    // Events arriving at each input during the block. Only event inputs have room for events.
+    input_events: Vec<Vec<MidiEvent>>,
+    sample_rate: f32,
+    // Zero until the node is prepared
+    block_size: usize,
+}
+
+impl Group {
+    pub const NAME: usize = 0;
+
+    pub fn new() -> Self {
+        let patch = Patch {
+            nodes: vec![
+                PatchNode::new(GROUP_INPUT, 0.0, 100.0, &GroupInput::new("In")),
+                PatchNode::new(GROUP_OUTPUT, 300.0, 100.0, &GroupOutput::new("Out")),
+            ],
+            connections: vec![Connection::new(0, 0, 1, 0)],
+        };
+
+        let mut group = Self {
+            name: String::from("Group"),
+            patch: Patch::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+
+            graph: None,
+            error: None,
+            input_events: Vec::new(),
+            sample_rate: 0.0,
+            block_size: 0,
+        };
+        group.set_subpatch(patch);
+        group
+    }
+
+    // Build the graph of the patch, which loads any files used by the patch
+    fn build_graph(&mut self) {
+        self.graph = None;
+        if self.error.is_some() {
+            return;
+        }
+
+        let registry = NodeRegistry::with_builtin_nodes();
+        match self.patch.build_graph(&registry, self.sample_rate, self.block_size) {
+            Ok(graph) => self.graph = Some(graph),
+            Err(error) => self.error = Some(error.to_string()),
+        }
+    }
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Group {
+    fn inputs(&self) -> &[PortInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        &self.outputs
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        GROUP_PARAMS
+    }
+
+    fn text(&self, index: usize) -> Option<&str> {
+        match index {
+            Self::NAME => Some(&self.name),
+            _ => None,
+        }
+    }
+
+    fn set_text(&mut self, index: usize, text: &str) -> Result<(), String> {
+        if index == Self::NAME {
+            self.name = text.to_string();
+        }
+        Ok(())
+    }
+
+    fn subpatch(&self) -> Option<&Patch> {
+        Some(&self.patch)
+    }
+
+    fn set_subpatch(&mut self, patch: Patch) {
+        self.patch = patch;
+
+        let ports = patch_ports(&self.patch, GROUP_INPUT).and_then(|inputs| {
+            patch_ports(&self.patch, GROUP_OUTPUT).map(|outputs| (inputs, outputs))
+        });
+        match ports {
+            Ok((inputs, outputs)) => {
+                self.input_events = inputs
+                    .iter()
+                    .map(|port| {
+                        if port.kind == PortKind::Event {
+                            Vec::with_capacity(MAX_BLOCK_EVENTS)
+                        } else {
+                            Vec::new()
+                        }
+                    })
+                    .collect();
+                self.inputs = inputs;
+                self.outputs = outputs;
+                self.error = None;
+            }
+
+            Err(error) => {
+                self.inputs.clear();
+                self.outputs.clear();
+                self.input_events.clear();
+                self.error = Some(error);
+            }
+        }
+
+        if self.block_size > 0 {
+            self.build_graph();
+        }
+    }
+
+    fn schedule_param(&mut self, path: &[usize], frame: usize, index: usize, value: f32) -> bool {
+        match self.graph.as_mut() {
+            Some(graph) => graph.schedule_param_path(path, frame, index, value),
+            None => false,
+        }
+    }
+
+    fn set_probe(&mut self, probe: Option<(&[usize], usize)>) -> bool {
+        match self.graph.as_mut() {
+            Some(graph) => graph.set_probe_path(probe).is_ok(),
+            None => probe.is_none(),
+        }
+    }
+
+    fn probe_buffer(&self, channel: usize) -> Option<&[f32]> {
+        self.graph.as_ref()?.output_buffer(channel)
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_frames: usize) {
+        self.sample_rate = sample_rate;
+        self.block_size = max_frames;
+        self.build_graph();
+    }
+
+    fn reset(&mut self) {
+        if let Some(graph) = self.graph.as_mut() {
+            graph.reset();
+        }
+    }
+
+    fn input_events(&mut self, index: usize, events: &[MidiEvent]) {
+        if let Some(buffer) = self.input_events.get_mut(index) {
+            buffer.clear();
+            buffer.extend_from_slice(&events[..events.len().min(MAX_BLOCK_EVENTS)]);
+        }
+    }
+
+    fn output_events(&self, index: usize) -> &[MidiEvent] {
+        match self.graph.as_ref() {
+            Some(graph) => graph.output_events(index),
+            None => &[],
+        }
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let graph = match self.graph.as_mut() {
+            Some(graph) => graph,
+            None => {
+                for output in outputs.iter_mut() {
+                    for sample in output.iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+                return;
+            }
+        };
+
+        let mut input_events: [&[MidiEvent]; MAX_PORTS] = Default::default();
+        for (events_ref, events) in input_events.iter_mut().zip(self.input_events.iter()) {
+            *events_ref = events;
+        }
+
+        graph.set_transport(context.transport);
+        graph.process_with_inputs(context.frames, inputs, &input_events[..self.input_events.len()], context.events);
+
+        for (channel, output) in outputs.iter_mut().enumerate() {
+            // A signal probed inside the group is heard in place of the whole graph, not of the group's outputs
+            match graph.output_node_buffer(channel) {
+                Some(buffer) => output.copy_from_slice(&buffer[..context.frames]),
+                None => {
+                    for sample in output.iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl NodeEditorInfo for Group {
+    fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+// Horizontal gap between the group input and output nodes and the nodes moved into a group
+const GROUP_SPACING: f32 = 250.0;
+
+// Add a port for a connection crossing the edge of a group, or find the port already added for the same source.
+// Feedback connections from a source get a port of their own, as the delay stays on the connection outside the group.
+// Names are numbered to keep them apart, as the ports are saved as a list of names.
+fn group_port(ports: &mut Vec<((usize, usize, bool), PortInfo)>, source: (usize, usize, bool), port: Option<&PortInfo>) -> usize {
+    if let Some(index) = ports.iter().position(|(existing, _)| *existing == source) {
+        return index;
+    }
+
+    let mut port = port.cloned().unwrap_or_else(|| PortInfo::new("In"));
+    let base = port.name.to_string();
+    let mut number = 2;
+    while ports.iter().any(|(_, existing)| existing.name == port.name) {
+        port.name = Cow::Owned(format!("{} {}", base, number));
+        number += 1;
+    }
+
+    ports.push((source, port));
+    ports.len() - 1
+}
+
+// Replace some of the nodes of a patch with a group containing them, which is added after the remaining nodes.
+//
+// Connections between the grouped nodes move into the group. Connections between a grouped node and
+// the rest of the patch are routed through new ports of the group, with one port for each output
+// which feeds into or out of the group.
+pub fn collapse_into_group(patch: &Patch, indices: &[usize], registry: &NodeRegistry) -> Result<Patch, PatchError> {
+    let grouped: Vec<&PatchNode> = indices.iter().filter_map(|index| patch.nodes.get(*index)).collect();
+    if grouped.is_empty() || grouped.len() != indices.len() {
+        return Err(PatchError::Graph(GraphError::InvalidNode));
+    }
+
+    let nodes = patch
+        .nodes
+        .iter()
+        .map(|patch_node| patch_node.create(registry))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Index of each grouped node inside the group, after the group input node
+    let inner_index = |index: usize| indices.iter().position(|grouped| *grouped == index).map(|position| position + 1);
+    let output_index = indices.len() + 1;
+    // Stands for the group node until the remaining nodes are numbered
+    let group = usize::MAX;
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut inner_connections = Vec::new();
+    let mut outer_connections = Vec::new();
+    for connection in patch.connections.iter() {
+        match (inner_index(connection.from), inner_index(connection.to)) {
+            (Some(from), Some(to)) => inner_connections.push(Connection { from, to, ..*connection }),
+            (None, None) => outer_connections.push(*connection),
+
+            (None, Some(to)) => {
+                let port = nodes.get(connection.to).and_then(|node| node.inputs().get(connection.input));
+                let index = group_port(&mut inputs, (connection.from, connection.output, connection.feedback), port);
+                inner_connections.push(Connection::new(0, index, to, connection.input));
+                // Several grouped inputs fed by the same output share a port and its connection
+                let outer = Connection { to: group, input: index, ..*connection };
+                if !outer_connections.contains(&outer) {
+                    outer_connections.push(outer);
+                }
+            }
+
+            (Some(from), None) => {
+                let port = nodes.get(connection.from).and_then(|node| node.outputs().get(connection.output));
+                let index = group_port(&mut outputs, (connection.from, connection.output, connection.feedback), port);
+                inner_connections.push(Connection::new(from, connection.output, output_index, index));
+                outer_connections.push(Connection { from: group, output: index, ..*connection });
+            }
+        }
+    }
+
+    // Keep the layout of the grouped nodes, between the group input and output nodes
+    let min_x = grouped.iter().map(|patch_node| patch_node.x).fold(f32::INFINITY, f32::min);
+    let min_y = grouped.iter().map(|patch_node| patch_node.y).fold(f32::INFINITY, f32::min);
+    let max_x = grouped.iter().map(|patch_node| patch_node.x).fold(f32::NEG_INFINITY, f32::max);
+
+    let input_ports: Vec<PortInfo> = inputs.into_iter().map(|(_, port)| port).collect();
+    let output_ports: Vec<PortInfo> = outputs.into_iter().map(|(_, port)| port).collect();
+
+    let mut inner_nodes = Vec::with_capacity(indices.len() + 2);
+    inner_nodes.push(PatchNode::new(GROUP_INPUT, 0.0, 0.0, &GroupInput::new(&format_ports(&input_ports))));
+    for patch_node in grouped.iter() {
+        let mut patch_node = (*patch_node).clone();
+        patch_node.x += GROUP_SPACING - min_x;
+        patch_node.y -= min_y;
+        inner_nodes.push(patch_node);
+    }
+    let output_x = max_x - min_x + 2.0 * GROUP_SPACING;
+    inner_nodes.push(PatchNode::new(GROUP_OUTPUT, output_x, 0.0, &GroupOutput::new(&format_ports(&output_ports))));
+
+    let mut group_node = Group::new();
+    group_node.set_subpatch(Patch {
+        nodes: inner_nodes,
+        connections: inner_connections,
+    });
+
+    // The group is placed in the middle of the nodes it replaces
+    let x = grouped.iter().map(|patch_node| patch_node.x).sum::<f32>() / grouped.len() as f32;
+    let y = grouped.iter().map(|patch_node| patch_node.y).sum::<f32>() / grouped.len() as f32;
+
+    let mut new_index = Vec::with_capacity(patch.nodes.len());
+    let mut outer_nodes = Vec::with_capacity(patch.nodes.len() - indices.len() + 1);
+    for (index, patch_node) in patch.nodes.iter().enumerate() {
+        if indices.contains(&index) {
+            new_index.push(None);
+        } else {
+            new_index.push(Some(outer_nodes.len()));
+            outer_nodes.push(patch_node.clone());
+        }
+    }
+    let group_index = outer_nodes.len();
+    outer_nodes.push(PatchNode::new("Group", x, y, &group_node));
+
+    let renumber = |index: usize| {
+        if index == group {
+            Some(group_index)
+        } else {
+            new_index.get(index).copied().flatten()
+        }
+    };
+    let outer_connections = outer_connections
+        .iter()
+        .filter_map(|connection| {
+            Some(Connection {
+                from: renumber(connection.from)?,
+                to: renumber(connection.to)?,
+                ..*connection
+            })
+        })
+        .collect();
+
+    Ok(Patch {
+        nodes: outer_nodes,
+        connections: outer_connections,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{MathNode, MathOp, Oscillator, Output};
+
+    #[test]
+    fn ports_parse_and_format() {
+        let ports = parse_ports("In:2, Cutoff:control, Notes:event, ").unwrap();
+        assert_eq!(ports.len(), 3);
+        assert_eq!((ports[0].kind, ports[0].channels), (PortKind::Audio, 2));
+        assert_eq!((ports[1].kind, ports[1].channels), (PortKind::Control, 1));
+        assert_eq!(ports[2].kind, PortKind::Event);
+        assert_eq!(format_ports(&ports), "In:2, Cutoff:control, Notes:event");
+
+        assert!(parse_ports(":control").is_err());
+        assert!(parse_ports("In:sideways").is_err());
+        assert!(parse_ports("Notes:event:2").is_err());
+        assert!(parse_ports("In:0").is_err());
+    }
+
+    #[test]
+    fn collapsed_nodes_sound_the_same() {
+        // The oscillator feeds both nodes to be grouped, so they share one input of the group
+        let mut scale = MathNode::new(MathOp::ScaleOffset);
+        let offset = scale.params().iter().position(|param| param.name == "Offset").unwrap();
+        scale.set_param(offset, 0.25);
+        let patch = Patch {
+            nodes: vec![
+                PatchNode::new("Oscillator", 0.0, 0.0, &Oscillator::new()),
+                PatchNode::new("Scale Offset", 200.0, 0.0, &scale),
+                PatchNode::new("Add", 400.0, 100.0, &MathNode::new(MathOp::Add)),
+                PatchNode::new("Output", 600.0, 0.0, &Output),
+            ],
+            connections: vec![
+                Connection::new(0, 0, 1, 0),
+                Connection::new(0, 0, 2, 1),
+                Connection::new(1, 0, 2, 0),
+                Connection::new(2, 0, 3, 0),
+            ],
+        };
+
+        let registry = NodeRegistry::with_builtin_nodes();
+        let collapsed = collapse_into_group(&patch, &[1, 2], &registry).unwrap();
+        assert_eq!(collapsed.nodes.iter().map(|node| node.kind.as_str()).collect::<Vec<_>>(), ["Oscillator", "Output", "Group"]);
+        assert_eq!(collapsed.connections, [Connection::new(0, 0, 2, 0), Connection::new(2, 0, 1, 0)]);
+
+        let group = collapsed.nodes[2].create(&registry).unwrap();
+        assert_eq!(group.inputs().len(), 1);
+        assert_eq!(group.outputs().len(), 1);
+
+        let render = |patch: &Patch| patch.build_graph(&registry, 48000.0, 64).unwrap().render_offline(1000);
+        let original = render(&patch);
+        assert!(original[0].iter().any(|sample| *sample != 0.0));
+        assert_eq!(render(&collapsed), original);
+
+        assert!(collapse_into_group(&patch, &[7], &registry).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::f32::consts::PI;
+
+use super::node::*;
+
+// Reset input is considered high above this level
+const RESET_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Saw,
+    Ramp,
+    Square,
+}
+
+impl LfoShape {
+    pub const ALL: &'static [LfoShape] = &[
+        LfoShape::Sine,
+        LfoShape::Triangle,
+        LfoShape::Saw,
+        LfoShape::Ramp,
+        LfoShape::Square,
+    ];
+
+    pub fn from_param(value: f32) -> Self {
+        let index = (value.round().max(0.0) as usize).min(Self::ALL.len() - 1);
+        Self::ALL[index]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "Sine",
+            LfoShape::Triangle => "Triangle",
+            LfoShape::Saw => "Saw",
+            LfoShape::Ramp => "Ramp",
+            LfoShape::Square => "Square",
+        }
+    }
+
+    // Bipolar value of the shape at `phase` (0 to 1)
+    pub fn value(&self, phase: f32) -> f32 {
+        match self {
+            LfoShape::Sine => (2.0 * PI * phase).sin(),
+            LfoShape::Triangle => {
+                if phase < 0.25 {
+                    4.0 * phase
+                } else if phase < 0.75 {
+                    2.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 4.0
+                }
+            }
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::Ramp => 1.0 - 2.0 * phase,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::gate("Reset")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::control("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::choice("Shape", &["Sine", "Triangle", "Saw", "Ramp", "Square"], 0.0),
+    ParamInfo::new("Rate", 0.01, 50.0, 1.0)
+        .with_smoothing(Smoothing::Exponential)
+        .with_skew(ParamSkew::Logarithmic)
+        .with_unit("Hz"),
+    ParamInfo::toggle("Sync", 0.0),
+    ParamInfo::choice("Division", NOTE_DIVISION_NAMES, 6.0),
+    ParamInfo::new("Phase", 0.0, 1.0, 0.0),
+    ParamInfo::toggle("Unipolar", 0.0),
+];
+
+// Low frequency oscillator with a free running rate in Hz or a tempo-synced note division.
+//
+// A rising edge on the reset input restarts the cycle.
+pub struct Lfo {
+    shape: LfoShape,
+    rate: f32,
+    sync: bool,
+    division: f32,
+    phase_offset: f32,
+    unipolar: bool,
+
+    phase: f32,
+    reset_high: bool,
+}
+
+impl Lfo {
+    pub const SHAPE: usize = 0;
+    pub const RATE: usize = 1;
+    pub const SYNC: usize = 2;
+    pub const DIVISION: usize = 3;
+    pub const PHASE: usize = 4;
+    pub const UNIPOLAR: usize = 5;
+
+    pub fn new() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            rate: 1.0,
+            sync: false,
+            division: 6.0,
+            phase_offset: 0.0,
+            unipolar: false,
+
+            phase: 0.0,
+            reset_high: false,
+        }
+    }
+
+    // Frequency of the LFO in Hz for the current tempo
+    fn frequency(&self, context: &ProcessContext) -> f32 {
+        if self.sync {
+            1.0 / context.beats_to_seconds(note_division_beats(self.division))
+        } else {
+            self.rate
+        }
+    }
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Lfo {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::SHAPE => LfoShape::ALL.iter().position(|shape| *shape == self.shape).unwrap_or(0) as f32,
+            Self::RATE => self.rate,
+            Self::SYNC => self.sync as u8 as f32,
+            Self::DIVISION => self.division,
+            Self::PHASE => self.phase_offset,
+            Self::UNIPOLAR => self.unipolar as u8 as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::SHAPE => self.shape = LfoShape::from_param(value),
+            Self::RATE => self.rate = value,
+            Self::SYNC => self.sync = value >= 0.5,
+            Self::DIVISION => self.division = value.round(),
+            Self::PHASE => self.phase_offset = value,
+            Self::UNIPOLAR => self.unipolar = value >= 0.5,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+        self.reset_high = false;
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let reset = inputs[0];
+        let output = &mut outputs[0];
+
+        let increment = self.frequency(context) / context.sample_rate;
+
+        for i in 0..context.frames {
+            let reset_high = reset[i] > RESET_THRESHOLD;
+            if reset_high && !self.reset_high {
+                self.phase = 0.0;
+            }
+            self.reset_high = reset_high;
+
+            let value = self.shape.value((self.phase + self.phase_offset).fract());
+            output[i] = if self.unipolar { 0.5 * (value + 1.0) } else { value };
+
+            self.phase = (self.phase + increment).fract();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::patch::{Patch, PatchError, PATCH_EXTENSION};
+
+// Folder, relative to the working directory, holding patches saved for reuse, such as groups
+pub const LIBRARY_DIRECTORY: &str = "library";
+
+// A patch saved in the library, which can be added to other patches from the node menu
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryAsset {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+// Find the patches in a library folder, sorted by name. A missing folder is an empty library.
+pub fn library_assets(directory: &Path) -> Vec<LibraryAsset> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut assets: Vec<LibraryAsset> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map(|extension| extension == PATCH_EXTENSION).unwrap_or(false))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            Some(LibraryAsset { name, path })
+        })
+        .collect();
+
+    assets.sort_by_key(|asset| asset.name.to_lowercase());
+    assets
+}
+
+// Save a patch into a library folder, replacing any asset with the same name.
+//
+// Characters which can't be used in file names are replaced, so the name of the asset may differ from `name`.
+pub fn save_library_asset(directory: &Path, name: &str, patch: &Patch) -> Result<LibraryAsset, PatchError> {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let name = if name.is_empty() { String::from("Untitled") } else { name };
+
+    fs::create_dir_all(directory)?;
+    let path = directory.join(format!("{}.{}", name, PATCH_EXTENSION));
+    patch.save(&path)?;
+
+    Ok(LibraryAsset { name, path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_assets_are_listed_by_name() {
+        let directory = std::env::temp_dir().join(format!("library_{}", std::process::id()));
+        assert!(library_assets(&directory).is_empty());
+
+        let saved = save_library_asset(&directory, " bass/lead? ", &Patch::new()).unwrap();
+        assert_eq!(saved.name, "bass_lead_");
+        save_library_asset(&directory, "Arp", &Patch::new()).unwrap();
+        fs::write(directory.join("notes.txt"), "not a patch").unwrap();
+
+        let assets = library_assets(&directory);
+        assert_eq!(assets.iter().map(|asset| asset.name.as_str()).collect::<Vec<_>>(), ["Arp", "bass_lead_"]);
+        assert_eq!(assets[1], saved);
+        assert_eq!(Patch::load(&saved.path).unwrap(), Patch::new());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
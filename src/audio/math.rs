@@ -0,0 +1,313 @@
+use super::node::*;
+
+// Trigger input is considered high above this level
+const TRIGGER_THRESHOLD: f32 = 0.5;
+// Largest magnitude of the value parameters of math nodes
+const MAX_VALUE: f32 = 10000.0;
+// Longest slew time in milliseconds
+const MAX_SLEW_TIME: f32 = 10000.0;
+// Most parameters any math operation has, not counting the rate
+const MAX_OP_PARAMS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Abs,
+    Clamp,
+    MapRange,
+    ScaleOffset,
+    Crossfade,
+    Compare,
+    SampleAndHold,
+    Slew,
+    Quantize,
+}
+
+impl MathOp {
+    pub const ALL: &'static [MathOp] = &[
+        MathOp::Add,
+        MathOp::Subtract,
+        MathOp::Multiply,
+        MathOp::Divide,
+        MathOp::Min,
+        MathOp::Max,
+        MathOp::Abs,
+        MathOp::Clamp,
+        MathOp::MapRange,
+        MathOp::ScaleOffset,
+        MathOp::Crossfade,
+        MathOp::Compare,
+        MathOp::SampleAndHold,
+        MathOp::Slew,
+        MathOp::Quantize,
+    ];
+
+    // Name of the node in the registry
+    pub fn name(&self) -> &'static str {
+        match self {
+            MathOp::Add => "Add",
+            MathOp::Subtract => "Subtract",
+            MathOp::Multiply => "Multiply",
+            MathOp::Divide => "Divide",
+            MathOp::Min => "Min",
+            MathOp::Max => "Max",
+            MathOp::Abs => "Abs",
+            MathOp::Clamp => "Clamp",
+            MathOp::MapRange => "Map Range",
+            MathOp::ScaleOffset => "Scale Offset",
+            MathOp::Crossfade => "Crossfade",
+            MathOp::Compare => "Compare",
+            MathOp::SampleAndHold => "Sample & Hold",
+            MathOp::Slew => "Slew Limiter",
+            MathOp::Quantize => "Quantize",
+        }
+    }
+
+    fn input_names(&self) -> &'static [&'static str] {
+        match self {
+            MathOp::Abs | MathOp::Clamp | MathOp::MapRange | MathOp::ScaleOffset | MathOp::Slew | MathOp::Quantize => &["In"],
+            MathOp::Crossfade => &["A", "B", "Mix"],
+            MathOp::SampleAndHold => &["In", "Trigger"],
+            _ => &["A", "B"],
+        }
+    }
+
+    // Parameters of the operation, which follow the rate parameter
+    fn params(&self) -> &'static [ParamInfo] {
+        match self {
+            MathOp::Clamp => CLAMP_PARAMS,
+            MathOp::MapRange => MAP_RANGE_PARAMS,
+            MathOp::ScaleOffset => SCALE_OFFSET_PARAMS,
+            MathOp::Crossfade => CROSSFADE_PARAMS,
+            MathOp::Compare => COMPARE_PARAMS,
+            MathOp::Slew => SLEW_PARAMS,
+            MathOp::Quantize => QUANTIZE_PARAMS,
+            _ => &[],
+        }
+    }
+}
+
+const CLAMP_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Min", -MAX_VALUE, MAX_VALUE, -1.0),
+    ParamInfo::new("Max", -MAX_VALUE, MAX_VALUE, 1.0),
+];
+const MAP_RANGE_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("In Min", -MAX_VALUE, MAX_VALUE, -1.0),
+    ParamInfo::new("In Max", -MAX_VALUE, MAX_VALUE, 1.0),
+    ParamInfo::new("Out Min", -MAX_VALUE, MAX_VALUE, 0.0),
+    ParamInfo::new("Out Max", -MAX_VALUE, MAX_VALUE, 1.0),
+];
+const SCALE_OFFSET_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Scale", -MAX_VALUE, MAX_VALUE, 1.0),
+    ParamInfo::new("Offset", -MAX_VALUE, MAX_VALUE, 0.0),
+];
+const CROSSFADE_PARAMS: &[ParamInfo] = &[ParamInfo::new("Mix", 0.0, 1.0, 0.5).with_style(ParamStyle::Slider)];
+const COMPARE_PARAMS: &[ParamInfo] = &[ParamInfo::new("Hysteresis", 0.0, 1.0, 0.0)];
+const SLEW_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Rise", 0.0, MAX_SLEW_TIME, 100.0).with_skew(ParamSkew::Power(3.0)).with_unit("ms"),
+    ParamInfo::new("Fall", 0.0, MAX_SLEW_TIME, 100.0).with_skew(ParamSkew::Power(3.0)).with_unit("ms"),
+];
+const QUANTIZE_PARAMS: &[ParamInfo] = &[ParamInfo::new("Step", 0.0, MAX_VALUE, 1.0)];
+
+const RATE_PARAM: ParamInfo = ParamInfo::toggle("Control Rate", 0.0);
+
+// Small building block which combines or shapes signals.
+//
+// At audio rate every sample is computed. At control rate only the first sample of each block
+// is computed and held for the rest of the block, which is cheaper for slowly changing values.
+// The ports are typed to match the rate, except for triggers which are always gates.
+pub struct MathNode {
+    op: MathOp,
+    control_rate: bool,
+    values: [f32; MAX_OP_PARAMS],
+
+    params: Vec<ParamInfo>,
+    inputs: Vec<PortInfo>,
+    outputs: Vec<PortInfo>,
+    mix_connected: bool,
+
+    // Output of the previous sample, used by operations with memory
+    previous: f32,
+    trigger_high: bool,
+}
+
+impl MathNode {
+    pub const CONTROL_RATE: usize = 0;
+
+    pub fn new(op: MathOp) -> Self {
+        let mut params = vec![RATE_PARAM];
+        params.extend_from_slice(op.params());
+
+        let mut values = [0.0; MAX_OP_PARAMS];
+        for (value, info) in values.iter_mut().zip(op.params().iter()) {
+            *value = info.default;
+        }
+
+        let mut node = Self {
+            op,
+            control_rate: false,
+            values,
+
+            params,
+            inputs: op.input_names().iter().map(|name| PortInfo::new(name)).collect(),
+            outputs: vec![PortInfo::new("Out")],
+            mix_connected: false,
+
+            previous: 0.0,
+            trigger_high: false,
+        };
+        node.update_port_kinds();
+        node
+    }
+
+    pub fn op(&self) -> MathOp {
+        self.op
+    }
+
+    fn update_port_kinds(&mut self) {
+        let kind = if self.control_rate { PortKind::Control } else { PortKind::Audio };
+        let op = self.op;
+        for port in self.inputs.iter_mut().chain(self.outputs.iter_mut()) {
+            port.kind = match (op, port.name.as_ref()) {
+                (MathOp::SampleAndHold, "Trigger") => PortKind::Gate,
+                (MathOp::Compare, "Out") => PortKind::Gate,
+                _ => kind,
+            };
+        }
+    }
+
+    // Compute one output from the inputs. `elapsed` is the number of samples since the previous output.
+    fn compute(&mut self, a: f32, b: f32, c: f32, elapsed: f32, sample_rate: f32) -> f32 {
+        let values = &self.values;
+        let output = match self.op {
+            MathOp::Add => a + b,
+            MathOp::Subtract => a - b,
+            MathOp::Multiply => a * b,
+            MathOp::Divide => {
+                if b.abs() > f32::EPSILON {
+                    a / b
+                } else {
+                    0.0
+                }
+            }
+            MathOp::Min => a.min(b),
+            MathOp::Max => a.max(b),
+            MathOp::Abs => a.abs(),
+            MathOp::Clamp => a.max(values[0].min(values[1])).min(values[1].max(values[0])),
+            MathOp::MapRange => {
+                let range = values[1] - values[0];
+                let t = if range.abs() > f32::EPSILON { (a - values[0]) / range } else { 0.0 };
+                values[2] + (values[3] - values[2]) * t
+            }
+            MathOp::ScaleOffset => a * values[0] + values[1],
+            MathOp::Crossfade => {
+                // The mix input replaces the mix parameter when it is connected
+                let mix = if self.mix_connected { c } else { values[0] };
+                let mix = mix.clamp(0.0, 1.0);
+                a + (b - a) * mix
+            }
+            MathOp::Compare => {
+                // Once high, the output stays high until A falls below B by the hysteresis
+                let threshold = if self.previous > 0.5 { b - values[0] } else { b };
+                if a > threshold { 1.0 } else { 0.0 }
+            }
+            MathOp::SampleAndHold => {
+                let trigger_high = b > TRIGGER_THRESHOLD;
+                let output = if trigger_high && !self.trigger_high { a } else { self.previous };
+                self.trigger_high = trigger_high;
+                output
+            }
+            MathOp::Slew => {
+                // Times are how long a change of 1.0 takes, in milliseconds
+                let time = if a > self.previous { values[0] } else { values[1] };
+                if time <= 0.0 {
+                    a
+                } else {
+                    let max_step = elapsed * 1000.0 / (time * sample_rate);
+                    self.previous + (a - self.previous).max(-max_step).min(max_step)
+                }
+            }
+            MathOp::Quantize => {
+                if values[0] > f32::EPSILON {
+                    (a / values[0]).round() * values[0]
+                } else {
+                    a
+                }
+            }
+        };
+
+        self.previous = output;
+        output
+    }
+}
+
+impl AudioNode for MathNode {
+    fn inputs(&self) -> &[PortInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        &self.outputs
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &self.params
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::CONTROL_RATE => self.control_rate as u32 as f32,
+            _ => self.values.get(index - 1).cloned().unwrap_or(0.0),
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match self.params.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::CONTROL_RATE => {
+                self.control_rate = value >= 0.5;
+                self.update_port_kinds();
+            }
+            _ => self.values[index - 1] = value,
+        }
+    }
+
+    fn input_connected(&mut self, index: usize, connected: bool) {
+        if self.op == MathOp::Crossfade && index == 2 {
+            self.mix_connected = connected;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.previous = 0.0;
+        self.trigger_high = false;
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let input = |port: usize, i: usize| inputs.get(port).map(|buffer| buffer[i]).unwrap_or(0.0);
+
+        if self.control_rate {
+            if context.frames == 0 {
+                return;
+            }
+
+            let value = self.compute(input(0, 0), input(1, 0), input(2, 0), context.frames as f32, context.sample_rate);
+            for sample in outputs[0][..context.frames].iter_mut() {
+                *sample = value;
+            }
+        } else {
+            for (i, sample) in outputs[0][..context.frames].iter_mut().enumerate() {
+                *sample = self.compute(input(0, i), input(1, i), input(2, i), 1.0, context.sample_rate);
+            }
+        }
+    }
+}
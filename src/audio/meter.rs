@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+// A single value written by a node on the audio thread and read by the UI, such as a gain reduction level.
+//
+// Cloning a meter shares the same value, and reading or writing never blocks.
+#[derive(Debug, Clone, Default)]
+pub struct Meter {
+    value: Arc<AtomicU32>,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, value: f32) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    // Whether two meters are clones sharing the same value
+    pub fn shares(&self, other: &Meter) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+// Meters are equal when they share the same value
+impl PartialEq for Meter {
+    fn eq(&self, other: &Self) -> bool {
+        self.shares(other)
+    }
+}
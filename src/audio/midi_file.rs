@@ -0,0 +1,195 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::event::MidiMessage;
+
+// Extensions of the MIDI files which can be loaded
+pub const MIDI_EXTENSIONS: &[&str] = &["mid", "midi"];
+// Tempo used to convert files timed in seconds rather than beats
+const TIMECODE_TEMPO: f64 = 120.0;
+
+#[derive(Debug)]
+pub enum MidiFileError {
+    Io(io::Error),
+    // The file could be read but not parsed
+    Format(String),
+}
+
+impl fmt::Display for MidiFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiFileError::Io(error) => write!(f, "{}", error),
+            MidiFileError::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MidiFileError {}
+
+impl From<io::Error> for MidiFileError {
+    fn from(error: io::Error) -> Self {
+        MidiFileError::Io(error)
+    }
+}
+
+impl From<midly::Error> for MidiFileError {
+    fn from(error: midly::Error) -> Self {
+        MidiFileError::Format(error.to_string())
+    }
+}
+
+// Whether a file should be loaded as a MIDI file rather than an audio file, chosen by its extension
+pub fn is_midi_file(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| MIDI_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+// A note or controller event of a MIDI file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiFileEvent {
+    // Position in beats from the start of the file
+    pub beat: f64,
+    pub track: usize,
+    pub message: MidiMessage,
+}
+
+// The note and controller events of a Standard MIDI File.
+//
+// Event times are kept in beats rather than seconds so playback follows the tempo of the transport.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    pub path: PathBuf,
+    // Name of each track, empty if the track has none
+    pub track_names: Vec<String>,
+    // Events of every track in order of beat
+    pub events: Vec<MidiFileEvent>,
+    // Position in beats of the last event
+    pub length: f64,
+}
+
+impl MidiFile {
+    pub fn load(path: &Path) -> Result<Self, MidiFileError> {
+        let bytes = fs::read(path)?;
+        Self::parse(path.to_path_buf(), &bytes)
+    }
+
+    pub fn parse(path: PathBuf, bytes: &[u8]) -> Result<Self, MidiFileError> {
+        let smf = midly::Smf::parse(bytes)?;
+
+        let ticks_per_beat = match smf.header.timing {
+            midly::Timing::Metrical(ticks) => ticks.as_int().max(1) as f64,
+            midly::Timing::Timecode(fps, subframes) => {
+                let ticks_per_second = fps.as_int() as f64 * subframes.max(1) as f64;
+                ticks_per_second * 60.0 / TIMECODE_TEMPO
+            }
+        };
+
+        let mut track_names = Vec::with_capacity(smf.tracks.len());
+        let mut events = Vec::new();
+        for (track, track_events) in smf.tracks.iter().enumerate() {
+            let mut name = String::new();
+            let mut ticks: u64 = 0;
+
+            for event in track_events.iter() {
+                ticks += event.delta.as_int() as u64;
+                let beat = ticks as f64 / ticks_per_beat;
+
+                let message = match event.kind {
+                    midly::TrackEventKind::Midi { channel, message } => {
+                        let channel = channel.as_int();
+                        match message {
+                            // A note on with no velocity is a note off by convention
+                            midly::MidiMessage::NoteOn { key, vel } if vel.as_int() == 0 => MidiMessage::NoteOff {
+                                channel,
+                                note: key.as_int(),
+                                velocity: 0,
+                            },
+                            midly::MidiMessage::NoteOn { key, vel } => MidiMessage::NoteOn {
+                                channel,
+                                note: key.as_int(),
+                                velocity: vel.as_int(),
+                            },
+                            midly::MidiMessage::NoteOff { key, vel } => MidiMessage::NoteOff {
+                                channel,
+                                note: key.as_int(),
+                                velocity: vel.as_int(),
+                            },
+                            midly::MidiMessage::Controller { controller, value } => MidiMessage::ControlChange {
+                                channel,
+                                controller: controller.as_int(),
+                                value: value.as_int(),
+                            },
+                            _ => continue,
+                        }
+                    }
+
+                    midly::TrackEventKind::Meta(midly::MetaMessage::TrackName(bytes)) => {
+                        name = String::from_utf8_lossy(bytes).trim().to_string();
+                        continue;
+                    }
+
+                    _ => continue,
+                };
+
+                events.push(MidiFileEvent { beat, track, message });
+            }
+
+            track_names.push(name);
+        }
+
+        // The sort is stable, so events at the same time stay in track order
+        events.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap_or(std::cmp::Ordering::Equal));
+        let length = events.last().map(|event| event.beat).unwrap_or(0.0);
+
+        Ok(Self {
+            path,
+            track_names,
+            events,
+            length,
+        })
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn num_tracks(&self) -> usize {
+        self.track_names.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_notes_and_controllers_in_beats() {
+        // Format 1 file at 96 ticks per beat, with a named track holding a note a beat long,
+        // and a second track changing a controller half a beat in
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60".to_vec();
+        bytes.extend_from_slice(b"MTrk\x00\x00\x00\x14");
+        bytes.extend_from_slice(b"\x00\xff\x03\x04Bass\x00\x90\x3c\x64\x60\x90\x3c\x00\x00\xff\x2f\x00");
+        bytes.extend_from_slice(b"MTrk\x00\x00\x00\x08");
+        bytes.extend_from_slice(b"\x30\xb1\x07\x40\x00\xff\x2f\x00");
+
+        let file = MidiFile::parse(PathBuf::from("song.mid"), &bytes).unwrap();
+        assert_eq!(file.track_names, ["Bass", ""]);
+        assert_eq!(file.length, 1.0);
+        assert_eq!(
+            file.events,
+            [
+                MidiFileEvent { beat: 0.0, track: 0, message: MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 } },
+                MidiFileEvent { beat: 0.5, track: 1, message: MidiMessage::ControlChange { channel: 1, controller: 7, value: 64 } },
+                MidiFileEvent { beat: 1.0, track: 0, message: MidiMessage::NoteOff { channel: 0, note: 60, velocity: 0 } },
+            ]
+        );
+
+        assert!(MidiFile::parse(PathBuf::from("song.mid"), b"MThd").is_err());
+    }
+}
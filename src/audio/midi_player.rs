@@ -0,0 +1,299 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use super::node::*;
+use super::event::{MidiEvent, MidiMessage, MAX_BLOCK_EVENTS};
+use super::midi_file::{MidiFile, MIDI_EXTENSIONS};
+
+// Number of MIDI channels
+const CHANNELS: usize = 16;
+
+const OUTPUTS: &[PortInfo] = &[PortInfo::event("Events")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::file("File", MIDI_EXTENSIONS),
+    ParamInfo::discrete("Track", 0.0, 64.0, 0.0),
+    ParamInfo::toggle("Loop", 1.0),
+];
+
+// Plays the notes and controllers of a MIDI file in time with the song position of the transport.
+//
+// Track 0 plays every track, otherwise only the chosen track is played. When looping, the file
+// repeats after its length rounded up to a whole number of bars. Any notes still playing are
+// stopped when the transport stops, jumps or loops.
+pub struct MidiPlayer {
+    file: Option<Arc<MidiFile>>,
+    track: usize,
+    looping: bool,
+
+    events: Vec<MidiEvent>,
+    // Notes which have been started and not stopped, one bit per note for each channel
+    sounding: [u128; CHANNELS],
+    // Song position expected at the start of the next block, to notice when the transport jumps
+    next_position: Option<f64>,
+}
+
+impl MidiPlayer {
+    pub const FILE: usize = 0;
+    pub const TRACK: usize = 1;
+    pub const LOOP: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            track: 0,
+            looping: true,
+
+            events: Vec::with_capacity(MAX_BLOCK_EVENTS),
+            sounding: [0; CHANNELS],
+            next_position: None,
+        }
+    }
+
+    fn push_event(&mut self, event: MidiEvent) {
+        if self.events.len() >= MAX_BLOCK_EVENTS {
+            return;
+        }
+
+        match event.message {
+            MidiMessage::NoteOn { channel, note, .. } => self.sounding[channel as usize % CHANNELS] |= 1u128 << (note % 128),
+            MidiMessage::NoteOff { channel, note, .. } => self.sounding[channel as usize % CHANNELS] &= !(1u128 << (note % 128)),
+            MidiMessage::ControlChange { .. } => {}
+        }
+        self.events.push(event);
+    }
+
+    fn all_notes_off(&mut self, frame: usize) {
+        for channel in 0..CHANNELS {
+            for note in 0..128u8 {
+                if self.sounding[channel] & (1u128 << note) != 0 {
+                    self.push_event(MidiEvent::new(frame, MidiMessage::NoteOff {
+                        channel: channel as u8,
+                        note,
+                        velocity: 0,
+                    }));
+                }
+            }
+        }
+    }
+
+    // Send the events of the file from beat `from` up to beat `to`, where `from` is at frame `first_frame` of the block
+    fn play_range(&mut self, file: &MidiFile, from: f64, to: f64, first_frame: f64, beats_per_sample: f64, frames: usize) {
+        // Events play at the first frame at or after their beat. A margin of a fraction of a sample makes
+        // sure rounding errors in the song position can't drop an event between blocks or loops.
+        let margin = 1e-6 * beats_per_sample;
+        let start = file.events.partition_point(|event| event.beat < from - margin);
+        for event in file.events[start..].iter() {
+            if event.beat >= to - margin {
+                break;
+            }
+
+            if self.track != 0 && event.track + 1 != self.track {
+                continue;
+            }
+
+            let frame = (first_frame + (event.beat - from) / beats_per_sample - 1e-6).ceil().max(0.0) as usize;
+            self.push_event(MidiEvent::new(frame.min(frames - 1), event.message));
+        }
+    }
+}
+
+impl Default for MidiPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for MidiPlayer {
+    fn inputs(&self) -> &[PortInfo] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::TRACK => self.track as f32,
+            Self::LOOP => self.looping as u8 as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::TRACK => self.track = value.round() as usize,
+            Self::LOOP => self.looping = value >= 0.5,
+            _ => {}
+        }
+    }
+
+    fn set_midi_file(&mut self, index: usize, file: Arc<MidiFile>) {
+        if index == Self::FILE {
+            self.file = Some(file);
+            self.next_position = None;
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn output_events(&self, index: usize) -> &[MidiEvent] {
+        match index {
+            0 => &self.events,
+            _ => &[],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.events.clear();
+        self.sounding = [0; CHANNELS];
+        self.next_position = None;
+    }
+
+    fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], _outputs: &mut [&mut [f32]]) {
+        self.events.clear();
+        if context.frames == 0 {
+            return;
+        }
+
+        let transport = context.transport;
+        if !transport.playing {
+            self.all_notes_off(0);
+            self.next_position = None;
+            return;
+        }
+
+        let start = transport.position;
+        if self.next_position != Some(start) {
+            self.all_notes_off(0);
+        }
+        let end = transport.position_at(context.frames, context.sample_rate);
+        self.next_position = Some(end);
+
+        // Cloning the file only copies a pointer
+        let file = match self.file.clone() {
+            Some(file) => file,
+            None => return,
+        };
+        let beats_per_sample = transport.beats_per_sample(context.sample_rate);
+
+        if !self.looping {
+            self.play_range(&file, start, end, 0.0, beats_per_sample, context.frames);
+            return;
+        }
+
+        let bar_length = transport.bar_length();
+        let loop_length = ((file.length / bar_length).ceil() * bar_length).max(bar_length);
+        let loop_start = start.rem_euclid(loop_length);
+        let loop_end = loop_start + (end - start);
+
+        // Frame at which the loop starts again, which may be beyond this block
+        let wrap = (loop_length - loop_start) / beats_per_sample;
+        if loop_end <= loop_length || wrap > context.frames as f64 - 1e-6 {
+            self.play_range(&file, loop_start, loop_end, 0.0, beats_per_sample, context.frames);
+        } else {
+            self.play_range(&file, loop_start, loop_length, 0.0, beats_per_sample, context.frames);
+            self.all_notes_off((wrap - 1e-6).ceil().max(0.0) as usize);
+            self.play_range(&file, 0.0, loop_end - loop_length, wrap, beats_per_sample, context.frames);
+        }
+    }
+}
+
+impl NodeEditorInfo for MidiPlayer {
+    fn file_path(&self, index: usize) -> Option<&Path> {
+        match index {
+            Self::FILE => self.file.as_ref().map(|file| file.path.as_path()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::audio::MidiFileEvent;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    // Frames in a beat at the default tempo of 120 bpm
+    const BEAT: usize = 24000;
+
+    fn note_on(note: u8) -> MidiMessage {
+        MidiMessage::NoteOn { channel: 0, note, velocity: 100 }
+    }
+
+    fn note_off(note: u8) -> MidiMessage {
+        MidiMessage::NoteOff { channel: 0, note, velocity: 0 }
+    }
+
+    // Two beats long, with a note on the first track over the first beat and a held note on the second track
+    fn player() -> MidiPlayer {
+        let events = vec![
+            MidiFileEvent { beat: 0.0, track: 0, message: note_on(60) },
+            MidiFileEvent { beat: 1.0, track: 0, message: note_off(60) },
+            MidiFileEvent { beat: 2.0, track: 1, message: note_on(64) },
+        ];
+        let mut player = MidiPlayer::new();
+        player.set_midi_file(
+            MidiPlayer::FILE,
+            Arc::new(MidiFile { path: PathBuf::from("song.mid"), track_names: vec![String::new(); 2], events, length: 2.0 }),
+        );
+        player
+    }
+
+    // Play blocks of `frames` from the start of the song, collecting the events of each block
+    fn play(player: &mut MidiPlayer, blocks: usize, frames: usize) -> Vec<Vec<MidiEvent>> {
+        let mut context = ProcessContext::new(SAMPLE_RATE, frames);
+        context.transport.play();
+        (0..blocks)
+            .map(|_| {
+                player.process(&context, &[], &mut []);
+                context.transport.advance(frames, SAMPLE_RATE);
+                player.output_events(0).to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_events_at_their_frames() {
+        let mut player = player();
+        player.set_param(MidiPlayer::LOOP, 0.0);
+        let events = play(&mut player, 1, 3 * BEAT);
+        assert_eq!(
+            events[0],
+            [MidiEvent::new(0, note_on(60)), MidiEvent::new(BEAT, note_off(60)), MidiEvent::new(2 * BEAT, note_on(64))]
+        );
+
+        // Only the chosen track is played
+        let mut player = self::player();
+        player.set_param(MidiPlayer::TRACK, 2.0);
+        let events = play(&mut player, 1, 3 * BEAT);
+        assert_eq!(events[0], [MidiEvent::new(2 * BEAT, note_on(64))]);
+    }
+
+    #[test]
+    fn loops_after_a_whole_bar_and_stops_held_notes() {
+        // Blocks of a beat and a quarter put the end of the four beat loop a quarter of a beat into the fourth block
+        let mut player = player();
+        let events = play(&mut player, 4, BEAT * 5 / 4);
+        assert_eq!(events[1], [MidiEvent::new(BEAT * 3 / 4, note_on(64))]);
+        assert!(events[2].is_empty());
+        assert_eq!(events[3], [MidiEvent::new(BEAT / 4, note_off(64)), MidiEvent::new(BEAT / 4, note_on(60))]);
+
+        // Stopping the transport stops the note left playing
+        player.process(&ProcessContext::new(SAMPLE_RATE, 64), &[], &mut []);
+        assert_eq!(player.output_events(0), [MidiEvent::new(0, note_off(60))]);
+    }
+}
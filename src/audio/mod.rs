@@ -0,0 +1,101 @@
+pub mod node;
+pub use node::*;
+
+pub mod transport;
+pub use transport::*;
+
+pub mod audio_file;
+pub use audio_file::*;
+
+pub mod event;
+pub use event::*;
+
+pub mod automation;
+pub use automation::*;
+
+pub mod modulation;
+pub use modulation::*;
+
+pub mod midi_file;
+pub use midi_file::*;
+
+pub mod meter;
+pub use meter::*;
+
+pub mod monitor;
+pub use monitor::*;
+
+pub mod scope;
+pub use scope::*;
+
+pub mod envelope;
+pub use envelope::*;
+
+pub mod lfo;
+pub use lfo::*;
+
+pub mod clock;
+pub use clock::*;
+
+pub mod sequencer;
+pub use sequencer::*;
+
+pub mod midi_player;
+pub use midi_player::*;
+
+pub mod oscillator;
+pub use oscillator::*;
+
+pub mod noise;
+pub use noise::*;
+
+pub mod sampler;
+pub use sampler::*;
+
+pub mod filter;
+pub use filter::*;
+
+pub mod delay;
+pub use delay::*;
+
+pub mod reverb;
+pub use reverb::*;
+
+pub mod convolution;
+pub use convolution::*;
+
+pub mod dynamics;
+pub use dynamics::*;
+
+pub mod waveshaper;
+pub use waveshaper::*;
+
+pub mod math;
+pub use math::*;
+
+pub mod expression;
+pub use expression::*;
+
+pub mod channels;
+pub use channels::*;
+
+pub mod group;
+pub use group::*;
+
+pub mod poly;
+pub use poly::*;
+
+pub mod library;
+pub use library::*;
+
+pub mod graph;
+pub use graph::*;
+
+pub mod registry;
+pub use registry::*;
+
+pub mod patch;
+pub use patch::*;
+
+pub mod engine;
+pub use engine::*;
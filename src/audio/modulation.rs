@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::node::*;
+use super::audio_file::AudioFile;
+use super::automation::SMOOTHING_INTERVAL;
+use super::event::MidiEvent;
+use super::graph::MAX_PORTS;
+use super::meter::Meter;
+use super::midi_file::MidiFile;
+use super::patch::Patch;
+
+// A parameter exposed as a control input, which moves the parameter around its set value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Modulation {
+    // Index of the parameter
+    pub param: usize,
+    // Fraction of the travel of the parameter's knob moved by an input of 1, negative to move it the other way
+    pub depth: f32,
+}
+
+impl Modulation {
+    pub fn new(param: usize) -> Self {
+        Self {
+            param,
+            depth: ParamInfo::depth(param).default,
+        }
+    }
+}
+
+// Whether a parameter can be exposed as a modulation input. Parameters which switch, or which the graph doesn't
+// smooth because changing them is costly, would click or restart their node every time the modulation moved them.
+pub fn can_modulate(param: &ParamInfo) -> bool {
+    param.kind == ParamKind::Float && (param.smoothing == Smoothing::Linear || param.smoothing == Smoothing::Exponential)
+}
+
+// Node with some of its parameters moved by modulation inputs.
+//
+// An input is added after the inputs of the wrapped node for each modulated parameter, and a depth parameter
+// after its parameters. The modulated value is worked out along the skew of the parameter, so modulating a
+// frequency moves it by equal ratios, and is set at the start of each stretch of at most `SMOOTHING_INTERVAL` frames.
+pub struct ModulatedNode {
+    node: Box<dyn AudioNode>,
+    modulations: Vec<Modulation>,
+    inputs: Vec<PortInfo>,
+    params: Vec<ParamInfo>,
+    // Value each modulated parameter was set to, which the modulation moves around
+    values: Vec<f32>,
+    // Value each modulated parameter has been moved to, shown on its widget
+    meters: Vec<Meter>,
+    // Value last set on the wrapped node for each modulated parameter, so it is only set again when it changes
+    applied: Vec<f32>,
+    // Split blocks into stretches, which isn't possible for nodes with event ports as events are timed from the block start
+    split: bool,
+}
+
+impl ModulatedNode {
+    pub fn new(node: Box<dyn AudioNode>, modulations: Vec<Modulation>) -> Self {
+        let mut modulations: Vec<Modulation> = modulations
+            .into_iter()
+            .filter(|modulation| {
+                node.params()
+                    .get(modulation.param)
+                    .map(can_modulate)
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        let mut params = node.params().to_vec();
+        for modulation in modulations.iter_mut() {
+            let depth = ParamInfo::depth(modulation.param);
+            modulation.depth = depth.clamp(modulation.depth);
+            params.push(depth);
+        }
+
+        let values: Vec<f32> = modulations.iter().map(|modulation| node.get_param(modulation.param)).collect();
+        let meters = modulations
+            .iter()
+            .map(|modulation| {
+                let meter = Meter::new();
+                meter.set(node.get_param(modulation.param));
+                meter
+            })
+            .collect();
+
+        let mut modulated = Self {
+            node,
+            modulations,
+            inputs: Vec::new(),
+            params,
+            applied: values.clone(),
+            values,
+            meters,
+            split: true,
+        };
+        modulated.update_ports();
+        modulated
+    }
+
+    // Number of inputs of the wrapped node, which come before the modulation inputs
+    pub fn node_inputs(&self) -> usize {
+        self.node.inputs().len()
+    }
+
+    // The ports of the wrapped node can change, e.g. when its text is edited
+    fn update_ports(&mut self) {
+        self.inputs = self.node.inputs().to_vec();
+        for modulation in self.modulations.iter() {
+            self.inputs.push(PortInfo {
+                name: Cow::Owned(format!("{} Mod", self.params[modulation.param].name)),
+                kind: PortKind::Control,
+                channels: 1,
+            });
+        }
+
+        let has_events = |ports: &[PortInfo]| ports.iter().any(|port| port.kind == PortKind::Event);
+        self.split = !has_events(self.node.inputs()) && !has_events(self.node.outputs());
+    }
+
+    // Set each modulated parameter from the modulation inputs at a frame
+    fn modulate(&mut self, modulation_inputs: &[&[f32]], frame: usize) {
+        for (index, modulation) in self.modulations.iter().enumerate() {
+            let input = modulation_inputs.get(index).and_then(|input| input.get(frame)).copied().unwrap_or(0.0);
+            let param = &self.params[modulation.param];
+            let value = param.denormalize(param.normalize(self.values[index]) + modulation.depth * input);
+
+            if value != self.applied[index] {
+                self.node.set_param(modulation.param, value);
+                self.applied[index] = value;
+                self.meters[index].set(value);
+            }
+        }
+    }
+}
+
+impl AudioNode for ModulatedNode {
+    fn inputs(&self) -> &[PortInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        self.node.outputs()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &self.params
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        let num_params = self.node.params().len();
+        if index >= num_params {
+            return self.modulations.get(index - num_params).map(|modulation| modulation.depth).unwrap_or(0.0);
+        }
+
+        match self.modulations.iter().position(|modulation| modulation.param == index) {
+            Some(position) => self.values[position],
+            None => self.node.get_param(index),
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let num_params = self.node.params().len();
+        if index >= num_params {
+            if let Some(modulation) = self.modulations.get_mut(index - num_params) {
+                modulation.depth = self.params[index].clamp(value);
+            }
+            return;
+        }
+
+        match self.modulations.iter().position(|modulation| modulation.param == index) {
+            Some(position) => self.values[position] = self.params[index].clamp(value),
+            None => self.node.set_param(index, value),
+        }
+    }
+
+    fn set_file(&mut self, index: usize, file: Arc<AudioFile>) {
+        self.node.set_file(index, file);
+    }
+
+    fn set_midi_file(&mut self, index: usize, file: Arc<MidiFile>) {
+        self.node.set_midi_file(index, file);
+    }
+
+    fn set_text(&mut self, index: usize, text: &str) -> Result<(), String> {
+        let result = self.node.set_text(index, text);
+        self.update_ports();
+        result
+    }
+
+    fn text(&self, index: usize) -> Option<&str> {
+        self.node.text(index)
+    }
+
+    fn state(&self) -> Vec<f32> {
+        self.node.state()
+    }
+
+    fn set_state(&mut self, state: &[f32]) {
+        self.node.set_state(state);
+    }
+
+    fn subpatch(&self) -> Option<&Patch> {
+        self.node.subpatch()
+    }
+
+    fn set_subpatch(&mut self, patch: Patch) {
+        self.node.set_subpatch(patch);
+        self.update_ports();
+    }
+
+    fn schedule_param(&mut self, path: &[usize], frame: usize, index: usize, value: f32) -> bool {
+        self.node.schedule_param(path, frame, index, value)
+    }
+
+    fn set_probe(&mut self, probe: Option<(&[usize], usize)>) -> bool {
+        self.node.set_probe(probe)
+    }
+
+    fn probe_buffer(&self, channel: usize) -> Option<&[f32]> {
+        self.node.probe_buffer(channel)
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        self.node.editor_info()
+    }
+
+    fn input_connected(&mut self, index: usize, connected: bool) {
+        if index < self.node_inputs() {
+            self.node.input_connected(index, connected);
+        }
+    }
+
+    fn meter(&self) -> Option<Meter> {
+        self.node.meter()
+    }
+
+    fn modulation(&self, index: usize) -> Option<Meter> {
+        let position = self.modulations.iter().position(|modulation| modulation.param == index)?;
+        Some(self.meters[position].clone())
+    }
+
+    fn input_events(&mut self, index: usize, events: &[MidiEvent]) {
+        self.node.input_events(index, events);
+    }
+
+    fn output_events(&self, index: usize) -> &[MidiEvent] {
+        self.node.output_events(index)
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_frames: usize) {
+        self.node.prepare(sample_rate, max_frames);
+    }
+
+    fn reset(&mut self) {
+        self.node.reset();
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let num_channels = port_channels(self.node.inputs()).min(inputs.len());
+        let (node_inputs, modulation_inputs) = inputs.split_at(num_channels);
+
+        if !self.split || !context.events.is_empty() {
+            self.modulate(modulation_inputs, 0);
+            self.node.process(context, node_inputs, outputs);
+            return;
+        }
+
+        let mut start = 0;
+        while start < context.frames {
+            let end = (start + SMOOTHING_INTERVAL).min(context.frames);
+            self.modulate(modulation_inputs, start);
+
+            let mut input_refs: [&[f32]; MAX_PORTS] = Default::default();
+            for (input_ref, input) in input_refs.iter_mut().zip(node_inputs.iter()) {
+                *input_ref = &input[start..end];
+            }
+
+            let num_outputs = outputs.len().min(MAX_PORTS);
+            let mut output_refs: [&mut [f32]; MAX_PORTS] = Default::default();
+            for (output_ref, output) in output_refs.iter_mut().zip(outputs.iter_mut()) {
+                *output_ref = &mut output[start..end];
+            }
+
+            let mut stretch = *context;
+            stretch.frames = end - start;
+            stretch.transport.advance(start, context.sample_rate);
+            self.node.process(&stretch, &input_refs[..node_inputs.len()], &mut output_refs[..num_outputs]);
+
+            start = end;
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::mem;
+use std::ops::Range;
+use std::time::Duration;
+
+use super::node::{channel_ranges, port_channels, AudioNode, PortInfo, PortKind};
+use super::meter::Meter;
+use super::scope::ScopeTap;
+
+// Processing time and output levels of a node in a graph, written by the graph on the audio thread after each
+// block and read by the UI, along with the meters of the node itself.
+//
+// Cloning a monitor shares the same values, and reading or writing never blocks.
+#[derive(Debug, Clone, Default)]
+pub struct NodeMonitor {
+    // Time spent processing the last block, as a fraction of the time the block lasts
+    cpu_load: Meter,
+    // Peak and RMS level of each output channel over the last block
+    peaks: Vec<Meter>,
+    rms: Vec<Meter>,
+    // Channels and kind of each output port
+    ports: Vec<Range<usize>>,
+    kinds: Vec<PortKind>,
+    // Latest samples of the first output channel, only kept for nodes a scope is showing
+    scope: Option<ScopeTap>,
+    // Meter published by the node, and the meter of each parameter moved by a modulation input with its index
+    meter: Option<Meter>,
+    modulations: Vec<(usize, Meter)>,
+}
+
+impl NodeMonitor {
+    pub fn new(outputs: &[PortInfo]) -> Self {
+        let num_outputs = port_channels(outputs);
+        Self {
+            cpu_load: Meter::new(),
+            peaks: (0..num_outputs).map(|_| Meter::new()).collect(),
+            rms: (0..num_outputs).map(|_| Meter::new()).collect(),
+            ports: channel_ranges(outputs),
+            kinds: outputs.iter().map(|port| port.kind).collect(),
+            scope: None,
+            meter: None,
+            modulations: Vec::new(),
+        }
+    }
+
+    // Share the meters of the node in the graph, so the UI shows the values of the node which is playing rather
+    // than of the copy it built its widgets from
+    pub fn with_meters(mut self, node: &dyn AudioNode) -> Self {
+        self.meter = node.meter();
+        self.modulations = (0..node.params().len())
+            .filter_map(|index| node.modulation(index).map(|modulation| (index, modulation)))
+            .collect();
+        self
+    }
+
+    // Record a processed block of `frames` frames, which took `elapsed` to process
+    pub fn record(&self, elapsed: Duration, frames: usize, sample_rate: f32, outputs: &[Vec<f32>]) {
+        if frames == 0 {
+            return;
+        }
+
+        let block_time = frames as f32 / sample_rate;
+        self.cpu_load.set(elapsed.as_secs_f32() / block_time);
+
+        for ((output, peak), rms) in outputs.iter().zip(self.peaks.iter()).zip(self.rms.iter()) {
+            let samples = &output[..frames];
+            peak.set(samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())));
+            rms.set((samples.iter().map(|sample| sample * sample).sum::<f32>() / frames as f32).sqrt());
+        }
+
+        if let (Some(scope), Some(output)) = (self.scope.as_ref(), outputs.first()) {
+            scope.write(&output[..frames], sample_rate);
+        }
+    }
+
+    pub fn cpu_load(&self) -> f32 {
+        self.cpu_load.get()
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        self.peaks.len()
+    }
+
+    pub fn peak(&self, channel: usize) -> f32 {
+        self.peaks.get(channel).map(|peak| peak.get()).unwrap_or(0.0)
+    }
+
+    pub fn rms(&self, channel: usize) -> f32 {
+        self.rms.get(channel).map(|rms| rms.get()).unwrap_or(0.0)
+    }
+
+    // Highest peak level of the channels of an output port
+    pub fn port_peak(&self, port: usize) -> f32 {
+        self.ports
+            .get(port)
+            .map(|channels| channels.clone().map(|channel| self.peak(channel)).fold(0.0, f32::max))
+            .unwrap_or(0.0)
+    }
+
+    // Highest RMS level of the channels of an output port
+    pub fn port_rms(&self, port: usize) -> f32 {
+        self.ports
+            .get(port)
+            .map(|channels| channels.clone().map(|channel| self.rms(channel)).fold(0.0, f32::max))
+            .unwrap_or(0.0)
+    }
+
+    // Whether an audio output port went over full scale in the last block. Control and gate signals often sit
+    // at 1 or beyond, so they are never counted as clipping.
+    pub fn port_clipping(&self, port: usize) -> bool {
+        self.kinds.get(port) == Some(&PortKind::Audio) && self.port_peak(port) > 1.0
+    }
+
+    // Whether an output channel went over full scale in the last block, counting only audio ports
+    pub fn clipping(&self, channel: usize) -> bool {
+        self.ports
+            .iter()
+            .position(|channels| channels.contains(&channel))
+            .map(|port| self.kinds[port] == PortKind::Audio && self.peak(channel) > 1.0)
+            .unwrap_or(false)
+    }
+
+    pub fn scope(&self) -> Option<ScopeTap> {
+        self.scope.clone()
+    }
+
+    // Start or stop keeping the latest samples of the node for a scope. Taps are large, so they are only given
+    // to the monitors of nodes being shown.
+    pub fn set_scope(&mut self, scope: Option<ScopeTap>) {
+        self.scope = scope;
+    }
+
+    // Swap the scope taps of two monitors, without allocating
+    pub fn swap_scope(&mut self, other: &mut NodeMonitor) {
+        mem::swap(&mut self.scope, &mut other.scope);
+    }
+
+    pub fn meter(&self) -> Option<Meter> {
+        self.meter.clone()
+    }
+
+    pub fn modulation(&self, index: usize) -> Option<Meter> {
+        self.modulations.iter().find(|(param, _)| *param == index).map(|(_, modulation)| modulation.clone())
+    }
+}
+
+// Monitors are equal when they share the same values
+impl PartialEq for NodeMonitor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cpu_load.shares(&other.cpu_load)
+    }
+}
@@ -0,0 +1,615 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::audio_file::AudioFile;
+use super::envelope::AdsrShape;
+use super::event::MidiEvent;
+use super::midi_file::MidiFile;
+use super::meter::Meter;
+use super::patch::Patch;
+use super::scope::ScopeSettings;
+use super::sequencer::StepPattern;
+use super::transport::Transport;
+use super::waveshaper::TransferCurve;
+
+// Information shared with every node each time a block of audio is processed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessContext<'a> {
+    // Engine sample rate in Hz
+    pub sample_rate: f32,
+    // Number of frames in the current block
+    pub frames: usize,
+    // Tempo, time signature and song position at the start of the block, used by tempo-synced nodes
+    pub transport: Transport,
+    // Events sent into the graph from outside during the block in order of frame, such as the notes of a voice
+    pub events: &'a [MidiEvent],
+    // Audio arriving at each input channel and events at each input of the group containing the graph,
+    // passed on by its group input node
+    pub inputs: &'a [&'a [f32]],
+    pub input_events: &'a [&'a [MidiEvent]],
+}
+
+impl<'a> ProcessContext<'a> {
+    pub fn new(sample_rate: f32, frames: usize) -> Self {
+        Self {
+            sample_rate,
+            frames,
+            transport: Transport::new(),
+            events: &[],
+            inputs: &[],
+            input_events: &[],
+        }
+    }
+
+    // Length in seconds of the given number of beats at the current tempo
+    pub fn beats_to_seconds(&self, beats: f32) -> f32 {
+        beats * 60.0 / self.transport.tempo.max(1.0)
+    }
+}
+
+// Musical note lengths, in beats, which tempo-synced parameters can select from
+pub const NOTE_DIVISIONS: &[(&str, f32)] = &[
+    ("1/32", 0.125),
+    ("1/16", 0.25),
+    ("1/8T", 1.0 / 3.0),
+    ("1/8", 0.5),
+    ("1/4T", 2.0 / 3.0),
+    ("1/8.", 0.75),
+    ("1/4", 1.0),
+    ("1/4.", 1.5),
+    ("1/2", 2.0),
+    ("1/1", 4.0),
+    ("2/1", 8.0),
+    ("4/1", 16.0),
+];
+
+// Names of the note divisions, in the same order, for parameters choosing between them
+pub const NOTE_DIVISION_NAMES: &[&str] = &[
+    "1/32", "1/16", "1/8T", "1/8", "1/4T", "1/8.", "1/4", "1/4.", "1/2", "1/1", "2/1", "4/1",
+];
+
+// Look up the length in beats of a note division selected by a parameter value
+pub fn note_division_beats(value: f32) -> f32 {
+    let index = (value.round().max(0.0) as usize).min(NOTE_DIVISIONS.len() - 1);
+    NOTE_DIVISIONS[index].1
+}
+
+// The kind of signal a port carries. Every port is processed at the audio rate,
+// but control and gate signals are only expected to change slowly or between two levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortKind {
+    // A waveform, such as the output of an oscillator
+    Audio,
+    // A slowly changing value, such as an envelope or a modulation source
+    Control,
+    // A trigger or gate, which is high above 0.5
+    Gate,
+    // MIDI events, passed with `AudioNode::input_events()` and `AudioNode::output_events()`.
+    // The audio buffer of an event port is unused.
+    Event,
+}
+
+// Description of an input or output port of an audio node
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortInfo {
+    pub name: Cow<'static, str>,
+    pub kind: PortKind,
+    // Number of audio channels, such as two for a stereo port. Event ports have one channel.
+    pub channels: usize,
+}
+
+impl PortInfo {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            kind: PortKind::Audio,
+            channels: 1,
+        }
+    }
+
+    pub const fn control(name: &'static str) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            kind: PortKind::Control,
+            channels: 1,
+        }
+    }
+
+    pub const fn gate(name: &'static str) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            kind: PortKind::Gate,
+            channels: 1,
+        }
+    }
+
+    pub const fn event(name: &'static str) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            kind: PortKind::Event,
+            channels: 1,
+        }
+    }
+
+    pub const fn stereo(name: &'static str) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            kind: PortKind::Audio,
+            channels: 2,
+        }
+    }
+}
+
+// Total number of channels of a list of ports, which is the number of buffers passed for them
+pub fn port_channels(ports: &[PortInfo]) -> usize {
+    ports.iter().map(|port| port.channels).sum()
+}
+
+// Buffers of the channels of each port in a list of ports
+pub fn channel_ranges(ports: &[PortInfo]) -> Vec<Range<usize>> {
+    let mut start = 0;
+    ports
+        .iter()
+        .map(|port| {
+            start += port.channels;
+            start - port.channels..start
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamKind {
+    // A number between `min` and `max`, set with `AudioNode::set_param()`
+    Float,
+    // An audio file, set with `AudioNode::set_file()`
+    File {
+        extensions: &'static [&'static str],
+    },
+    // A position from 0 to 1 in the file of the parameter with index `file`, shown as a marker on its waveform
+    Marker {
+        file: usize,
+    },
+    // A line of text, set with `AudioNode::set_text()`
+    Text,
+    // How far a modulation input moves the parameter with index `param`, shown below its widget
+    Depth {
+        param: usize,
+    },
+}
+
+// How the graph moves a parameter to a new value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing {
+    // Set at once, for parameters the node smooths itself or which are costly to change
+    None,
+    // Ramp in a straight line to the new value
+    Linear,
+    // Approach the new value on an exponential curve, suited to rates and frequencies
+    Exponential,
+    // A choice between settings, such as a waveform. The output of the node fades out before the value
+    // changes and fades back in after, so switching doesn't click.
+    Switch,
+}
+
+// Widget used to edit a float parameter on its node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamStyle {
+    Knob,
+    Slider,
+    // On or off, for parameters from 0 to 1
+    Toggle,
+    // A list of the names in `ParamInfo::options`
+    Choice,
+    // A field for typing a number, for whole numbers such as a count or a seed
+    Number,
+}
+
+// How the position of a knob or slider maps to a parameter value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamSkew {
+    // Evenly spread over the range
+    Linear,
+    // The position raised to a power, so values above 1 give more of the travel to the low end of the range
+    Power(f32),
+    // Equal ratios take equal travel, suited to frequencies and times. Needs a range above zero.
+    Logarithmic,
+}
+
+// Description of a parameter of an audio node
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub smoothing: Smoothing,
+    pub style: ParamStyle,
+    pub skew: ParamSkew,
+    // Unit shown after the value, such as "Hz" or "dB"
+    pub unit: &'static str,
+    // Names of the settings of a `ParamStyle::Choice` parameter, for the values from 0 up
+    pub options: &'static [&'static str],
+}
+
+impl ParamInfo {
+    pub const fn new(name: &'static str, min: f32, max: f32, default: f32) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Float,
+            min,
+            max,
+            default,
+            smoothing: Smoothing::Linear,
+            style: ParamStyle::Knob,
+            skew: ParamSkew::Linear,
+            unit: "",
+            options: &[],
+        }
+    }
+
+    // A parameter choosing between a few settings, such as a waveform or an on/off switch
+    pub const fn discrete(name: &'static str, min: f32, max: f32, default: f32) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Float,
+            min,
+            max,
+            default,
+            smoothing: Smoothing::Switch,
+            style: ParamStyle::Number,
+            skew: ParamSkew::Linear,
+            unit: "",
+            options: &[],
+        }
+    }
+
+    // An on/off switch, from 0 to 1
+    pub const fn toggle(name: &'static str, default: f32) -> Self {
+        Self {
+            style: ParamStyle::Toggle,
+            ..Self::discrete(name, 0.0, 1.0, default)
+        }
+    }
+
+    // A choice between the named settings in `options`, with values counting up from 0
+    pub const fn choice(name: &'static str, options: &'static [&'static str], default: f32) -> Self {
+        Self {
+            style: ParamStyle::Choice,
+            options,
+            ..Self::discrete(name, 0.0, (options.len() - 1) as f32, default)
+        }
+    }
+
+    pub const fn file(name: &'static str, extensions: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            kind: ParamKind::File { extensions },
+            min: 0.0,
+            max: 0.0,
+            default: 0.0,
+            smoothing: Smoothing::None,
+            style: ParamStyle::Knob,
+            skew: ParamSkew::Linear,
+            unit: "",
+            options: &[],
+        }
+    }
+
+    pub const fn marker(name: &'static str, file: usize, default: f32) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Marker { file },
+            min: 0.0,
+            max: 1.0,
+            default,
+            smoothing: Smoothing::None,
+            style: ParamStyle::Knob,
+            skew: ParamSkew::Linear,
+            unit: "",
+            options: &[],
+        }
+    }
+
+    // Depth of the modulation input of the parameter with index `param`, from -1 to 1
+    pub const fn depth(param: usize) -> Self {
+        Self {
+            name: "Depth",
+            kind: ParamKind::Depth { param },
+            min: -1.0,
+            max: 1.0,
+            default: 0.5,
+            smoothing: Smoothing::Linear,
+            style: ParamStyle::Slider,
+            skew: ParamSkew::Linear,
+            unit: "",
+            options: &[],
+        }
+    }
+
+    pub const fn text(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Text,
+            min: 0.0,
+            max: 0.0,
+            default: 0.0,
+            smoothing: Smoothing::None,
+            style: ParamStyle::Knob,
+            skew: ParamSkew::Linear,
+            unit: "",
+            options: &[],
+        }
+    }
+
+    pub const fn with_smoothing(self, smoothing: Smoothing) -> Self {
+        Self { smoothing, ..self }
+    }
+
+    pub const fn with_style(self, style: ParamStyle) -> Self {
+        Self { style, ..self }
+    }
+
+    pub const fn with_skew(self, skew: ParamSkew) -> Self {
+        Self { skew, ..self }
+    }
+
+    pub const fn with_unit(self, unit: &'static str) -> Self {
+        Self { unit, ..self }
+    }
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.max(self.min).min(self.max)
+    }
+
+    // Whether the parameter only takes whole numbers
+    pub fn is_discrete(&self) -> bool {
+        self.smoothing == Smoothing::Switch
+    }
+
+    // Position from 0 to 1 of a value along a knob or slider
+    pub fn normalize(&self, value: f32) -> f32 {
+        if self.max <= self.min {
+            return 0.0;
+        }
+
+        let value = self.clamp(value);
+        match self.skew {
+            ParamSkew::Linear => (value - self.min) / (self.max - self.min),
+            ParamSkew::Power(power) => ((value - self.min) / (self.max - self.min)).powf(1.0 / power),
+            ParamSkew::Logarithmic => (value / self.min).ln() / (self.max / self.min).ln(),
+        }
+    }
+
+    // Value at a position from 0 to 1 along a knob or slider, rounded for discrete parameters
+    pub fn denormalize(&self, position: f32) -> f32 {
+        let position = position.clamp(0.0, 1.0);
+        let value = match self.skew {
+            ParamSkew::Linear => self.min + (self.max - self.min) * position,
+            ParamSkew::Power(power) => self.min + (self.max - self.min) * position.powf(power),
+            ParamSkew::Logarithmic => self.min * (self.max / self.min).powf(position),
+        };
+
+        if self.is_discrete() {
+            self.clamp(value.round())
+        } else {
+            self.clamp(value)
+        }
+    }
+
+    // Text shown for a value, with the name of the setting or the unit
+    pub fn format(&self, value: f32) -> String {
+        match self.style {
+            ParamStyle::Choice => {
+                let index = (value.round().max(0.0) as usize).min(self.options.len().saturating_sub(1));
+                return self.options.get(index).copied().unwrap_or_default().to_string();
+            }
+
+            ParamStyle::Toggle => return if value >= 0.5 { "On" } else { "Off" }.to_string(),
+
+            _=> {}
+        }
+
+        let decimals = if self.is_discrete() || value.abs() >= 100.0 {
+            0
+        } else if value.abs() >= 10.0 {
+            1
+        } else {
+            2
+        };
+        if self.unit.is_empty() {
+            format!("{:.*}", decimals, value)
+        } else {
+            format!("{:.*} {}", decimals, value, self.unit)
+        }
+    }
+
+    // Read a value typed by the user, which may end with the unit, or with "k" for thousands.
+    // Choices can be typed by name. Returns None for text which isn't a valid value, and
+    // otherwise a value within the range.
+    pub fn parse(&self, text: &str) -> Option<f32> {
+        let text = text.trim();
+        match self.style {
+            ParamStyle::Choice => {
+                if let Some(index) = self.options.iter().position(|option| option.eq_ignore_ascii_case(text)) {
+                    return Some(index as f32);
+                }
+            }
+
+            ParamStyle::Toggle => {
+                match text.to_ascii_lowercase().as_str() {
+                    "on" | "true" | "yes" => return Some(1.0),
+                    "off" | "false" | "no" => return Some(0.0),
+                    _=> {}
+                }
+            }
+
+            _=> {}
+        }
+
+        let mut number = text;
+        if !self.unit.is_empty() && number.len() >= self.unit.len() {
+            let split = number.len() - self.unit.len();
+            if number.is_char_boundary(split) && number[split..].eq_ignore_ascii_case(self.unit) {
+                number = number[..split].trim_end();
+            }
+        }
+
+        let mut scale = 1.0;
+        if let Some(thousands) = number.strip_suffix('k').or_else(|| number.strip_suffix('K')) {
+            number = thousands;
+            scale = 1000.0;
+        }
+
+        let value = number.trim().parse::<f32>().ok()? * scale;
+        if !value.is_finite() {
+            return None;
+        }
+
+        if self.is_discrete() {
+            Some(self.clamp(value.round()))
+        } else {
+            Some(self.clamp(value))
+        }
+    }
+}
+
+// Trait implemented by every node which can be processed by the audio engine.
+//
+// Buffers are passed in the same order as the ports returned by `inputs()` and `outputs()`, with one
+// buffer for each channel of a port. Unconnected inputs receive a buffer of zeros.
+pub trait AudioNode: Send {
+    fn inputs(&self) -> &[PortInfo];
+
+    fn outputs(&self) -> &[PortInfo];
+
+    fn params(&self) -> &[ParamInfo] {
+        &[]
+    }
+
+    fn get_param(&self, _index: usize) -> f32 {
+        0.0
+    }
+
+    fn set_param(&mut self, _index: usize, _value: f32) {}
+
+    // Replace the file of a file parameter. The file should already be resampled to the engine sample rate.
+    fn set_file(&mut self, _index: usize, _file: Arc<AudioFile>) {}
+
+    // Replace the file of a file parameter which accepts MIDI files
+    fn set_midi_file(&mut self, _index: usize, _file: Arc<MidiFile>) {}
+
+    // Replace the text of a text parameter, returning a description of the problem if the text is invalid.
+    // This may change the ports of the node.
+    fn set_text(&mut self, _index: usize, _text: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn text(&self, _index: usize) -> Option<&str> {
+        None
+    }
+
+    // Extra data saved in the patch file alongside the parameters, such as a hand drawn curve
+    fn state(&self) -> Vec<f32> {
+        Vec::new()
+    }
+
+    // Restore data returned by `state()`
+    fn set_state(&mut self, _state: &[f32]) {}
+
+    // Patch contained by the node, such as the graph played by each voice of a poly node
+    fn subpatch(&self) -> Option<&Patch> {
+        None
+    }
+
+    // Replace the patch contained by the node. Nodes with a subpatch build its graph in `prepare()`.
+    fn set_subpatch(&mut self, _patch: Patch) {}
+
+    // Change a parameter of a node in the graph of the subpatch at a frame of the next block, with the node found
+    // by its index in the subpatch followed by its index in any subpatches it is inside. Returns false if the
+    // change can't be scheduled.
+    fn schedule_param(&mut self, _path: &[usize], _frame: usize, _index: usize, _value: f32) -> bool {
+        false
+    }
+
+    // Hear an output port of a node in the graph of the subpatch in place of the output of the whole graph, with the
+    // node found as in `schedule_param()`, or None to stop. Returns false if the port can't be probed.
+    fn set_probe(&mut self, _probe: Option<(&[usize], usize)>) -> bool {
+        false
+    }
+
+    // Channel of the port probed in the subpatch, for the most recently processed block
+    fn probe_buffer(&self, _channel: usize) -> Option<&[f32]> {
+        None
+    }
+
+    // What the node widget shows beyond the ports and parameters, for nodes which show more
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        None
+    }
+
+    // Called by the graph whenever a connection to an input is added or removed
+    fn input_connected(&mut self, _index: usize, _connected: bool) {}
+
+    // A value shown on the node widget while audio is running, such as gain reduction
+    fn meter(&self) -> Option<Meter> {
+        None
+    }
+
+    // Value a parameter moved by a modulation input has been set to while audio is running, shown on its widget
+    fn modulation(&self, _index: usize) -> Option<Meter> {
+        None
+    }
+
+    // Called before `process()` with the events arriving at an event input during the block, in order of frame
+    fn input_events(&mut self, _index: usize, _events: &[MidiEvent]) {}
+
+    // Events sent from an event output during the most recently processed block, in order of frame
+    fn output_events(&self, _index: usize) -> &[MidiEvent] {
+        &[]
+    }
+
+    // Called off the audio thread before processing starts, so any allocation should happen here
+    fn prepare(&mut self, _sample_rate: f32, _max_frames: usize) {}
+
+    // Clear any internal state, e.g. when playback is restarted
+    fn reset(&mut self) {}
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]);
+}
+
+// What the node widget shows of a node beyond its ports and parameters, returned by `AudioNode::editor_info()`.
+// None of it is needed to play the node.
+pub trait NodeEditorInfo {
+    // File loaded by a file parameter, saved in the patch and shown on the widget
+    fn file_path(&self, _index: usize) -> Option<&Path> {
+        None
+    }
+
+    // A problem with the settings of the node
+    fn error(&self) -> Option<&str> {
+        None
+    }
+
+    // Curve shown in an editable curve widget on the node
+    fn transfer_curve(&self) -> Option<TransferCurve> {
+        None
+    }
+
+    // Steps shown in an editable step grid on the node
+    fn step_pattern(&self) -> Option<StepPattern> {
+        None
+    }
+
+    // Envelope shown in an editable envelope editor on the node, whose breakpoints move the node's parameters
+    fn envelope_shape(&self) -> Option<AdsrShape> {
+        None
+    }
+
+    // Display settings of a node which always shows a scope of its output
+    fn scope(&self) -> Option<ScopeSettings> {
+        None
+    }
+}
@@ -0,0 +1,170 @@
+use std::f32::consts::PI;
+
+use super::node::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+impl Waveform {
+    pub const ALL: &'static [Waveform] = &[Waveform::Sine, Waveform::Triangle, Waveform::Saw, Waveform::Square];
+
+    pub fn from_param(value: f32) -> Self {
+        let index = (value.round().max(0.0) as usize).min(Self::ALL.len() - 1);
+        Self::ALL[index]
+    }
+
+    // Value of the waveform at `phase` (0 to 1), moving on by `increment` each sample. The steps of the saw and
+    // square are rounded off over a sample either side, which keeps most of their harmonics below Nyquist.
+    pub fn value(&self, phase: f32, increment: f32) -> f32 {
+        match self {
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Saw => 2.0 * phase - 1.0 - poly_blep(phase, increment),
+            Waveform::Square => {
+                let square = if phase < 0.5 { 1.0 } else { -1.0 };
+                square + poly_blep(phase, increment) - poly_blep((phase + 0.5).fract(), increment)
+            }
+        }
+    }
+}
+
+// Correction for a step of -2 at phase 0, over the samples either side of the step
+fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if increment <= 0.0 {
+        0.0
+    } else if phase < increment {
+        let t = phase / increment;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - increment {
+        let t = (phase - 1.0) / increment;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::choice("Waveform", &["Sine", "Triangle", "Saw", "Square"], 0.0),
+    ParamInfo::new("Freq", 20.0, 20000.0, 440.0)
+        .with_smoothing(Smoothing::Exponential)
+        .with_skew(ParamSkew::Logarithmic)
+        .with_unit("Hz"),
+    ParamInfo::new("Level", 0.0, 1.0, 0.5),
+];
+
+// Audio rate oscillator playing a waveform at a frequency in Hz
+pub struct Oscillator {
+    waveform: Waveform,
+    frequency: f32,
+    level: f32,
+
+    phase: f32,
+}
+
+impl Oscillator {
+    pub const WAVEFORM: usize = 0;
+    pub const FREQUENCY: usize = 1;
+    pub const LEVEL: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            waveform: Waveform::Sine,
+            frequency: 440.0,
+            level: 0.5,
+
+            phase: 0.0,
+        }
+    }
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Oscillator {
+    fn inputs(&self) -> &[PortInfo] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::WAVEFORM => Waveform::ALL.iter().position(|waveform| *waveform == self.waveform).unwrap_or(0) as f32,
+            Self::FREQUENCY => self.frequency,
+            Self::LEVEL => self.level,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::WAVEFORM => self.waveform = Waveform::from_param(value),
+            Self::FREQUENCY => self.frequency = value,
+            Self::LEVEL => self.level = value,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let increment = (self.frequency / context.sample_rate).min(0.5);
+
+        for sample in outputs[0][..context.frames].iter_mut() {
+            *sample = self.level * self.waveform.value(self.phase, increment);
+            self.phase = (self.phase + increment).fract();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn render(waveform: Waveform, frequency: f32, frames: usize) -> Vec<f32> {
+        let mut oscillator = Oscillator::new();
+        oscillator.set_param(Oscillator::WAVEFORM, Waveform::ALL.iter().position(|shape| *shape == waveform).unwrap() as f32);
+        oscillator.set_param(Oscillator::FREQUENCY, frequency);
+        oscillator.set_param(Oscillator::LEVEL, 1.0);
+
+        let mut output = vec![0.0; frames];
+        oscillator.process(&ProcessContext::new(SAMPLE_RATE, frames), &[], &mut [&mut output]);
+        output
+    }
+
+    #[test]
+    fn plays_at_its_frequency() {
+        // A second of each waveform at 100 Hz rises through zero 100 times, counting the start of the next cycle
+        // for the waveforms which rise through zero at the start of each cycle
+        for waveform in Waveform::ALL.iter() {
+            let output = render(*waveform, 100.0, SAMPLE_RATE as usize + 1);
+            let rising = output.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+            assert_eq!(rising, 100, "{:?}", waveform);
+            assert!(output.iter().all(|sample| sample.abs() <= 1.0 + 1.0e-4), "{:?}", waveform);
+        }
+    }
+}
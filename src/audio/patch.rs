@@ -0,0 +1,367 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::node::*;
+use super::audio_file::{AudioFile, AudioFileError};
+use super::midi_file::{is_midi_file, MidiFile, MidiFileError};
+use super::graph::{AudioGraph, Connection, GraphError};
+use super::group::GROUP_OUTPUT;
+use super::modulation::{can_modulate, ModulatedNode, Modulation};
+use super::registry::NodeRegistry;
+
+// File extension used for saved patches
+pub const PATCH_EXTENSION: &str = "patch";
+
+#[derive(Debug)]
+pub enum PatchError {
+    Io(io::Error),
+    // The patch file could not be parsed
+    Format(serde_json::Error),
+    // The patch contains a node which is not in the registry
+    UnknownNode(String),
+    // A file used by a node could not be loaded
+    File(PathBuf, AudioFileError),
+    MidiFile(PathBuf, MidiFileError),
+    Graph(GraphError),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::Io(error) => write!(f, "{}", error),
+            PatchError::Format(error) => write!(f, "invalid patch file: {}", error),
+            PatchError::UnknownNode(name) => write!(f, "unknown node type '{}'", name),
+            PatchError::File(path, error) => write!(f, "{}: {}", path.display(), error),
+            PatchError::MidiFile(path, error) => write!(f, "{}: {}", path.display(), error),
+            PatchError::Graph(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl From<io::Error> for PatchError {
+    fn from(error: io::Error) -> Self {
+        PatchError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for PatchError {
+    fn from(error: serde_json::Error) -> Self {
+        PatchError::Format(error)
+    }
+}
+
+impl From<GraphError> for PatchError {
+    fn from(error: GraphError) -> Self {
+        PatchError::Graph(error)
+    }
+}
+
+// The saved settings of a single node in a patch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchNode {
+    // Registry name of the type of node
+    pub kind: String,
+    // Position of the node widget in the canvas
+    pub x: f32,
+    pub y: f32,
+    pub params: Vec<f32>,
+    // Paths of the files used by file parameters, with the parameter index
+    #[serde(default)]
+    pub files: Vec<(usize, PathBuf)>,
+    // Contents of text parameters, with the parameter index
+    #[serde(default)]
+    pub texts: Vec<(usize, String)>,
+    // Data returned by `AudioNode::state()`
+    #[serde(default)]
+    pub state: Vec<f32>,
+    // Patch returned by `AudioNode::subpatch()`
+    #[serde(default)]
+    pub subpatch: Option<Patch>,
+    // Parameters exposed as modulation inputs, which come after the inputs of the node
+    #[serde(default)]
+    pub modulations: Vec<Modulation>,
+    // Whether the node widget shows a scope of the node's output
+    #[serde(default)]
+    pub show_scope: bool,
+}
+
+impl PatchNode {
+    // Record the current settings of a node
+    pub fn new(kind: &str, x: f32, y: f32, node: &dyn AudioNode) -> Self {
+        let num_params = node.params().len();
+
+        Self {
+            kind: kind.to_string(),
+            x,
+            y,
+            params: (0..num_params).map(|index| node.get_param(index)).collect(),
+            files: (0..num_params)
+                .filter_map(|index| Some((index, node.editor_info()?.file_path(index)?.to_path_buf())))
+                .collect(),
+            texts: (0..num_params)
+                .filter_map(|index| node.text(index).map(|text| (index, text.to_string())))
+                .collect(),
+            state: node.state(),
+            subpatch: node.subpatch().cloned(),
+            modulations: Vec::new(),
+            show_scope: false,
+        }
+    }
+
+    // Store an edited parameter value. Indices past the parameters of the node are the depths of its modulations.
+    pub fn set_param(&mut self, index: usize, value: f32) {
+        let num_params = self.params.len();
+        if let Some(param) = self.params.get_mut(index) {
+            *param = value;
+        } else if let Some(modulation) = self.modulations.get_mut(index - num_params) {
+            modulation.depth = value;
+        }
+    }
+
+    // Value of a parameter, with the same indices as `set_param()`
+    pub fn param(&self, index: usize) -> f32 {
+        let num_params = self.params.len();
+        match self.params.get(index) {
+            Some(value) => *value,
+            None => self.modulations.get(index - num_params).map(|modulation| modulation.depth).unwrap_or(0.0),
+        }
+    }
+
+    // Expose a parameter as a modulation input, or remove its input if it already has one.
+    // Returns the position of the input among the modulation inputs.
+    pub fn toggle_modulation(&mut self, param: usize) -> usize {
+        match self.modulations.iter().position(|modulation| modulation.param == param) {
+            Some(position) => {
+                self.modulations.remove(position);
+                position
+            }
+            None => {
+                self.modulations.push(Modulation::new(param));
+                self.modulations.len() - 1
+            }
+        }
+    }
+
+    // Replace the file of a file parameter
+    pub fn set_file(&mut self, index: usize, path: PathBuf) {
+        self.files.retain(|(file_index, _)| *file_index != index);
+        self.files.push((index, path));
+    }
+
+    // Replace the contents of a text parameter
+    pub fn set_text(&mut self, index: usize, text: &str) {
+        self.texts.retain(|(text_index, _)| *text_index != index);
+        self.texts.push((index, text.to_string()));
+    }
+
+    // Whether the node plays the same as another apart from its parameters, so one can carry on from the other.
+    // The position of the widget and its scope don't change the sound.
+    pub fn same_setup(&self, other: &PatchNode) -> bool {
+        let same_subpatch = match (self.subpatch.as_ref(), other.subpatch.as_ref()) {
+            (Some(subpatch), Some(other)) => subpatch.same_setup(other),
+            (None, None) => true,
+            _ => false,
+        };
+
+        self.kind == other.kind
+            && self.params.len() == other.params.len()
+            && self.files == other.files
+            && self.texts == other.texts
+            && self.state == other.state
+            && self.modulations.iter().map(|modulation| modulation.param).eq(other.modulations.iter().map(|modulation| modulation.param))
+            && same_subpatch
+    }
+
+    // Create the node from the registry and restore its parameters and state, but not its files
+    pub fn create(&self, registry: &NodeRegistry) -> Result<Box<dyn AudioNode>, PatchError> {
+        let mut node = registry
+            .create(&self.kind)
+            .ok_or_else(|| PatchError::UnknownNode(self.kind.clone()))?;
+
+        for (index, value) in self.params.iter().enumerate() {
+            node.set_param(index, *value);
+        }
+        // An invalid text is kept by the node and reported through `AudioNode::error()`
+        for (index, text) in self.texts.iter() {
+            let _ = node.set_text(*index, text);
+        }
+        node.set_state(&self.state);
+        if let Some(subpatch) = self.subpatch.as_ref() {
+            node.set_subpatch(subpatch.clone());
+        }
+
+        if !self.modulations.is_empty() {
+            node = Box::new(ModulatedNode::new(node, self.modulations.clone()));
+        }
+
+        Ok(node)
+    }
+
+    // Describe the parameters of the node as `create()` would make it, followed by the depth of each modulation,
+    // without creating the node
+    pub fn param_infos(&self, registry: &NodeRegistry) -> Result<Vec<ParamInfo>, PatchError> {
+        let mut params = registry
+            .get(&self.kind)
+            .ok_or_else(|| PatchError::UnknownNode(self.kind.clone()))?
+            .params
+            .clone();
+
+        // Matches the depth parameters added by `ModulatedNode`, which skips parameters that can't be modulated
+        let depths: Vec<ParamInfo> = self
+            .modulations
+            .iter()
+            .filter(|modulation| params.get(modulation.param).map(can_modulate).unwrap_or(false))
+            .map(|modulation| ParamInfo::depth(modulation.param))
+            .collect();
+        params.extend(depths);
+
+        Ok(params)
+    }
+
+    // Load the files of the node, with audio files resampled to `sample_rate`, and pass them to the node
+    pub fn load_files(&self, node: &mut dyn AudioNode, sample_rate: f32) -> Result<(), PatchError> {
+        for (index, path) in self.files.iter() {
+            if is_midi_file(path) {
+                let file = MidiFile::load(path).map_err(|error| PatchError::MidiFile(path.clone(), error))?;
+                node.set_midi_file(*index, Arc::new(file));
+                continue;
+            }
+
+            let file = AudioFile::load_shared(path, sample_rate).map_err(|error| PatchError::File(path.clone(), error))?;
+            node.set_file(*index, file);
+        }
+
+        Ok(())
+    }
+}
+
+// A saved set of nodes and the connections between them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Patch {
+    pub nodes: Vec<PatchNode>,
+    // Connections between nodes, identified by their index in `nodes`
+    #[serde(default)]
+    pub connections: Vec<Connection>,
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PatchError> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PatchError> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    // Whether the patch has the same nodes and connections as another, apart from the parameters of its nodes
+    pub fn same_setup(&self, other: &Patch) -> bool {
+        self.nodes.len() == other.nodes.len()
+            && self.nodes.iter().zip(other.nodes.iter()).all(|(node, other)| node.same_setup(other))
+            && self.connections == other.connections
+    }
+
+    // Pair the nodes of the patch with the nodes of the patch it was edited from which play the same, as the index
+    // of each node in this patch and in the previous one. Nodes keep their order when others are added or removed,
+    // so the longest run of matching nodes in order is paired.
+    pub fn unchanged_nodes(&self, previous: &Patch) -> Vec<(usize, usize)> {
+        let (new, old) = (&self.nodes, &previous.nodes);
+        let same: Vec<Vec<bool>> = new.iter().map(|node| old.iter().map(|other| node.same_setup(other)).collect()).collect();
+
+        // Length of the longest run of pairs among the nodes from each index to the end of both patches
+        let mut lengths = vec![vec![0; old.len() + 1]; new.len() + 1];
+        for i in (0..new.len()).rev() {
+            for j in (0..old.len()).rev() {
+                lengths[i][j] = if same[i][j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        let mut pairs = Vec::with_capacity(lengths[0][0]);
+        let (mut i, mut j) = (0, 0);
+        while i < new.len() && j < old.len() {
+            if same[i][j] {
+                pairs.push((i, j));
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        pairs
+    }
+
+    // Create an audio graph containing the nodes and connections of the patch.
+    //
+    // The group output node in the patch of a group, or otherwise the first "Output" node, becomes the output of the graph.
+    pub fn build_graph(&self, registry: &NodeRegistry, sample_rate: f32, block_size: usize) -> Result<AudioGraph, PatchError> {
+        let mut graph = AudioGraph::new(sample_rate, block_size);
+
+        let output = self
+            .nodes
+            .iter()
+            .position(|patch_node| patch_node.kind == GROUP_OUTPUT)
+            .or_else(|| self.nodes.iter().position(|patch_node| patch_node.kind == "Output"));
+
+        let mut ids = Vec::with_capacity(self.nodes.len());
+        for (index, patch_node) in self.nodes.iter().enumerate() {
+            let mut node = patch_node.create(registry)?;
+            patch_node.load_files(node.as_mut(), sample_rate)?;
+            let id = graph.add_node(node)?;
+            if output == Some(index) {
+                graph.set_output(id)?;
+            }
+            ids.push(id);
+        }
+
+        for connection in self.connections.iter() {
+            let from = *ids.get(connection.from).ok_or(GraphError::InvalidNode)?;
+            let to = *ids.get(connection.to).ok_or(GraphError::InvalidNode)?;
+            graph.connect(Connection { from, to, ..*connection })?;
+        }
+
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::delay::Delay;
+
+    #[test]
+    fn param_infos_match_the_created_node() {
+        let registry = NodeRegistry::with_builtin_nodes();
+        let mut patch_node = PatchNode::new("Delay", 0.0, 0.0, &Delay::new());
+        patch_node.toggle_modulation(Delay::FEEDBACK);
+        patch_node.toggle_modulation(Delay::TIME);
+
+        let node = patch_node.create(&registry).unwrap();
+        let params = patch_node.param_infos(&registry).unwrap();
+        assert_eq!(params, node.params());
+
+        patch_node.set_param(params.len() - 1, -0.25);
+        for index in 0..params.len() {
+            assert_eq!(patch_node.param(index), patch_node.create(&registry).unwrap().get_param(index));
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use super::node::*;
+use super::event::{MidiEvent, MidiMessage, MAX_BLOCK_EVENTS};
+use super::envelope::Adsr;
+use super::expression::Expression;
+use super::graph::{AudioGraph, Connection, Output};
+use super::patch::{Patch, PatchNode};
+use super::registry::NodeRegistry;
+
+// Most voices a poly node can play at once
+pub const MAX_VOICES: usize = 16;
+// Number of notes remembered in the mono modes, one for each MIDI note
+const MAX_HELD_NOTES: usize = 128;
+// Voices whose last block peaked below this level, with no note playing, are finished and not processed
+const SILENCE: f32 = 1e-4;
+// Note whose pitch output by the voice node is 0 semitones
+const REFERENCE_NOTE: f32 = 60.0;
+
+const VOICE_OUTPUTS: &[PortInfo] = &[
+    PortInfo::control("Pitch"),
+    PortInfo::gate("Gate"),
+    PortInfo::control("Velocity"),
+    PortInfo::gate("Trigger"),
+];
+
+// The note played by a voice of a poly node, placed inside the voice patch.
+//
+// Pitch is in semitones relative to middle C and velocity is from 0 to 1. The gate is high while
+// the note is held, and the trigger is high for one sample when a note starts. A new note which
+// arrives while the gate is high only changes the pitch, unless the old note ended at the same
+// frame, in which case the gate drops for one sample so envelopes start again.
+pub struct VoiceInput {
+    note: Option<u8>,
+    pitch: f32,
+    velocity: f32,
+}
+
+impl VoiceInput {
+    pub fn new() -> Self {
+        Self {
+            note: None,
+            pitch: 0.0,
+            velocity: 0.0,
+        }
+    }
+}
+
+impl Default for VoiceInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for VoiceInput {
+    fn inputs(&self) -> &[PortInfo] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        VOICE_OUTPUTS
+    }
+
+    fn reset(&mut self) {
+        self.note = None;
+        self.pitch = 0.0;
+        self.velocity = 0.0;
+    }
+
+    fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let mut events = context.events.iter().peekable();
+
+        for i in 0..context.frames {
+            let mut released = false;
+            let mut triggered = false;
+
+            while let Some(event) = events.next_if(|event| event.frame <= i) {
+                match event.message {
+                    MidiMessage::NoteOn { note, velocity, .. } => {
+                        // Legato notes keep the velocity of the note they follow
+                        if self.note.is_none() {
+                            self.velocity = velocity as f32 / 127.0;
+                            triggered = true;
+                        }
+                        self.note = Some(note);
+                        self.pitch = note as f32 - REFERENCE_NOTE;
+                    }
+
+                    MidiMessage::NoteOff { note, .. } => {
+                        if self.note == Some(note) {
+                            self.note = None;
+                            released = true;
+                        }
+                    }
+
+                    MidiMessage::ControlChange { .. } => {}
+                }
+            }
+
+            let gate = self.note.is_some() && !(released && triggered);
+            let values = [self.pitch, if gate { 1.0 } else { 0.0 }, self.velocity, if triggered { 1.0 } else { 0.0 }];
+            for (output, value) in outputs.iter_mut().zip(values.iter()) {
+                output[i] = *value;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolyMode {
+    // Each note is given its own voice
+    Poly,
+    // One voice, started again by each note
+    Mono,
+    // One voice, which only changes pitch when a note is played while another is held
+    Legato,
+}
+
+impl PolyMode {
+    fn from_param(value: f32) -> Self {
+        match value.round() as i32 {
+            1 => PolyMode::Mono,
+            2 => PolyMode::Legato,
+            _ => PolyMode::Poly,
+        }
+    }
+}
+
+// Which voice is taken for a new note when every voice is playing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceStealing {
+    Oldest,
+    Quietest,
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::event("Events")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::stereo("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::discrete("Voices", 1.0, MAX_VOICES as f32, 8.0),
+    ParamInfo::choice("Mode", &["Poly", "Mono", "Legato"], 0.0),
+    ParamInfo::choice("Steal", &["Oldest", "Quietest"], 0.0),
+];
+
+struct Voice {
+    graph: AudioGraph,
+    // Events for the voice during the current block
+    events: Vec<MidiEvent>,
+    // Channel and note being played, or `None` once the note is released
+    note: Option<(u8, u8)>,
+    // When the note started, counted in notes
+    started: u64,
+    // Peak level of the last block the voice played
+    level: f32,
+}
+
+impl Voice {
+    fn send(&mut self, frame: usize, message: MidiMessage) {
+        if self.events.len() < MAX_BLOCK_EVENTS {
+            self.events.push(MidiEvent::new(frame, message));
+        }
+    }
+}
+
+// Plays a voice patch once for each note, summing the outputs of the voices.
+//
+// Every voice runs its own graph built from the same patch, which receives its notes through the
+// voice nodes inside it. When all of the voices are playing, a new note takes the voice which
+// started first or the one which is quietest. The mono modes play one voice and remember the
+// notes held down, going back to the last of them when the newest note is released.
+pub struct Poly {
+    patch: Patch,
+    num_voices: usize,
+    mode: PolyMode,
+    stealing: VoiceStealing,
+
+    voices: Vec<Voice>,
+    error: Option<String>,
+    // Channel, note and velocity of the notes held down in the mono modes, oldest first
+    held: Vec<(u8, u8, u8)>,
+    notes_started: u64,
+    sample_rate: f32,
+    // Zero until the node is prepared
+    block_size: usize,
+}
+
+impl Poly {
+    pub const VOICES: usize = 0;
+    pub const MODE: usize = 1;
+    pub const STEAL: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            patch: Self::default_patch(),
+            num_voices: 8,
+            mode: PolyMode::Poly,
+            stealing: VoiceStealing::Oldest,
+
+            voices: Vec::new(),
+            error: None,
+            held: Vec::with_capacity(MAX_HELD_NOTES),
+            notes_started: 0,
+            sample_rate: 0.0,
+            block_size: 0,
+        }
+    }
+
+    // A sine voice with an envelope, so a new poly node makes a sound straight away
+    fn default_patch() -> Patch {
+        let mut expression = Expression::new();
+        let _ = expression.set_text(Expression::FORMULA, "sin(2*pi*x*261.63*2^(in1/12)) * in2 * 0.2");
+
+        Patch {
+            nodes: vec![
+                PatchNode::new("Voice", 0.0, 100.0, &VoiceInput::new()),
+                PatchNode::new("Envelope", 200.0, 0.0, &Adsr::new()),
+                PatchNode::new("Expression", 450.0, 100.0, &expression),
+                PatchNode::new("Output", 700.0, 100.0, &Output),
+            ],
+            connections: vec![
+                Connection::new(0, 0, 2, 0),
+                Connection::new(0, 1, 1, 0),
+                Connection::new(1, 0, 2, 1),
+                Connection::new(2, 0, 3, 0),
+            ],
+        }
+    }
+
+    // Build a graph of the patch for every voice, which loads any files used by the patch
+    fn build_voices(&mut self) {
+        self.voices.clear();
+        self.held.clear();
+        self.error = None;
+
+        let registry = NodeRegistry::with_builtin_nodes();
+        for _ in 0..MAX_VOICES {
+            match self.patch.build_graph(&registry, self.sample_rate, self.block_size) {
+                Ok(graph) => self.voices.push(Voice {
+                    graph,
+                    events: Vec::with_capacity(MAX_BLOCK_EVENTS),
+                    note: None,
+                    started: 0,
+                    level: 0.0,
+                }),
+
+                Err(error) => {
+                    self.voices.clear();
+                    self.error = Some(error.to_string());
+                    return;
+                }
+            }
+        }
+    }
+
+    // Choose the voice for a new note, preferring a free voice which has finished its release
+    fn pick_voice(&self) -> Option<usize> {
+        let voices = &self.voices[..self.num_voices.min(self.voices.len())];
+
+        let quietest = |indices: &mut dyn Iterator<Item = usize>| {
+            indices.min_by(|a, b| voices[*a].level.partial_cmp(&voices[*b].level).unwrap_or(std::cmp::Ordering::Equal))
+        };
+
+        let free = quietest(&mut (0..voices.len()).filter(|index| voices[*index].note.is_none()));
+        if free.is_some() {
+            return free;
+        }
+
+        match self.stealing {
+            VoiceStealing::Oldest => (0..voices.len()).min_by_key(|index| voices[*index].started),
+            VoiceStealing::Quietest => quietest(&mut (0..voices.len())),
+        }
+    }
+
+    fn note_on_poly(&mut self, frame: usize, channel: u8, note: u8, velocity: u8) {
+        // Playing a note which is already sounding starts it again on the same voice
+        let index = match self.voices.iter().position(|voice| voice.note == Some((channel, note))) {
+            Some(index) => index,
+            None => match self.pick_voice() {
+                Some(index) => index,
+                None => return,
+            },
+        };
+
+        let voice = &mut self.voices[index];
+        if let Some((channel, note)) = voice.note {
+            voice.send(frame, MidiMessage::NoteOff { channel, note, velocity: 0 });
+        }
+        voice.send(frame, MidiMessage::NoteOn { channel, note, velocity });
+        voice.note = Some((channel, note));
+        voice.started = self.notes_started;
+        self.notes_started += 1;
+    }
+
+    fn note_off_poly(&mut self, frame: usize, channel: u8, note: u8, velocity: u8) {
+        if let Some(voice) = self.voices.iter_mut().find(|voice| voice.note == Some((channel, note))) {
+            voice.send(frame, MidiMessage::NoteOff { channel, note, velocity });
+            voice.note = None;
+        }
+    }
+
+    // Move the single voice of the mono modes from the note it is playing to another
+    fn change_mono_note(&mut self, frame: usize, from: Option<(u8, u8)>, to: (u8, u8, u8)) {
+        let legato = self.mode == PolyMode::Legato;
+        let voice = &mut self.voices[0];
+        let (channel, note, velocity) = to;
+
+        if let (Some((old_channel, old_note)), false) = (from, legato) {
+            voice.send(frame, MidiMessage::NoteOff { channel: old_channel, note: old_note, velocity: 0 });
+        }
+        voice.send(frame, MidiMessage::NoteOn { channel, note, velocity });
+        voice.note = Some((channel, note));
+        voice.started = self.notes_started;
+        self.notes_started += 1;
+    }
+
+    fn note_on_mono(&mut self, frame: usize, channel: u8, note: u8, velocity: u8) {
+        let playing = self.voices[0].note;
+
+        self.held.retain(|(held_channel, held_note, _)| (*held_channel, *held_note) != (channel, note));
+        if self.held.len() >= MAX_HELD_NOTES {
+            self.held.remove(0);
+        }
+        self.held.push((channel, note, velocity));
+
+        self.change_mono_note(frame, playing, (channel, note, velocity));
+    }
+
+    fn note_off_mono(&mut self, frame: usize, channel: u8, note: u8, velocity: u8) {
+        self.held.retain(|(held_channel, held_note, _)| (*held_channel, *held_note) != (channel, note));
+
+        let playing = self.voices[0].note;
+        if playing != Some((channel, note)) {
+            return;
+        }
+
+        match self.held.last().copied() {
+            Some(previous) => self.change_mono_note(frame, playing, previous),
+            None => {
+                let voice = &mut self.voices[0];
+                voice.send(frame, MidiMessage::NoteOff { channel, note, velocity });
+                voice.note = None;
+            }
+        }
+    }
+}
+
+impl Default for Poly {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Poly {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::VOICES => self.num_voices as f32,
+            Self::MODE => self.mode as u8 as f32,
+            Self::STEAL => self.stealing as u8 as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::VOICES => self.num_voices = value.round() as usize,
+            Self::MODE => self.mode = PolyMode::from_param(value),
+            Self::STEAL => {
+                self.stealing = if value >= 0.5 {
+                    VoiceStealing::Quietest
+                } else {
+                    VoiceStealing::Oldest
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn subpatch(&self) -> Option<&Patch> {
+        Some(&self.patch)
+    }
+
+    fn set_subpatch(&mut self, patch: Patch) {
+        self.patch = patch;
+        if self.block_size > 0 {
+            self.build_voices();
+        }
+    }
+
+    // Every voice plays the same patch, so the change is made to all of them
+    fn schedule_param(&mut self, path: &[usize], frame: usize, index: usize, value: f32) -> bool {
+        let mut scheduled = !self.voices.is_empty();
+        for voice in self.voices.iter_mut() {
+            scheduled &= voice.graph.schedule_param_path(path, frame, index, value);
+        }
+        scheduled
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_frames: usize) {
+        self.sample_rate = sample_rate;
+        self.block_size = max_frames;
+        self.build_voices();
+    }
+
+    fn reset(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.graph.reset();
+            voice.events.clear();
+            voice.note = None;
+            voice.level = 0.0;
+        }
+        self.held.clear();
+    }
+
+    fn input_events(&mut self, _index: usize, events: &[MidiEvent]) {
+        if self.voices.is_empty() {
+            return;
+        }
+
+        let mono = self.mode != PolyMode::Poly;
+        for event in events.iter() {
+            match event.message {
+                MidiMessage::NoteOn { channel, note, velocity } if mono => self.note_on_mono(event.frame, channel, note, velocity),
+                MidiMessage::NoteOn { channel, note, velocity } => self.note_on_poly(event.frame, channel, note, velocity),
+                MidiMessage::NoteOff { channel, note, velocity } if mono => self.note_off_mono(event.frame, channel, note, velocity),
+                MidiMessage::NoteOff { channel, note, velocity } => self.note_off_poly(event.frame, channel, note, velocity),
+
+                MidiMessage::ControlChange { .. } => {
+                    for voice in self.voices.iter_mut() {
+                        voice.send(event.frame, event.message);
+                    }
+                }
+            }
+        }
+    }
+
+    fn process(&mut self, context: &ProcessContext, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for output in outputs.iter_mut() {
+            for sample in output.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+
+        for voice in self.voices.iter_mut() {
+            // Voices with nothing left to play are skipped, which keeps unused voices cheap. Parameter changes
+            // still reach them, so they are ready for their next note.
+            if voice.events.is_empty() && voice.note.is_none() && voice.level < SILENCE {
+                voice.graph.apply_scheduled_params();
+                continue;
+            }
+
+            voice.graph.set_transport(context.transport);
+            voice.graph.process_with_inputs(context.frames, &[], &[], &voice.events);
+            voice.events.clear();
+
+            let mut level: f32 = 0.0;
+            for (channel, output) in outputs.iter_mut().enumerate() {
+                if let Some(buffer) = voice.graph.output_node_buffer(channel) {
+                    for (sample, value) in output.iter_mut().zip(buffer.iter()) {
+                        *sample += *value;
+                        level = level.max(value.abs());
+                    }
+                }
+            }
+            voice.level = level;
+        }
+    }
+}
+
+impl NodeEditorInfo for Poly {
+    fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{ChannelMode, ChannelNode};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const FRAMES: usize = 12;
+
+    fn note_on(frame: usize, note: u8) -> MidiEvent {
+        MidiEvent::new(frame, MidiMessage::NoteOn { channel: 0, note, velocity: 100 })
+    }
+
+    fn note_off(frame: usize, note: u8) -> MidiEvent {
+        MidiEvent::new(frame, MidiMessage::NoteOff { channel: 0, note, velocity: 0 })
+    }
+
+    // Poly node whose voices output their pitch on the left and their gate on the right
+    fn poly(mode: PolyMode, voices: usize) -> Poly {
+        let mut poly = Poly::new();
+        poly.set_param(Poly::VOICES, voices as f32);
+        poly.set_param(Poly::MODE, mode as u8 as f32);
+        poly.set_subpatch(Patch {
+            nodes: vec![
+                PatchNode::new("Voice", 0.0, 0.0, &VoiceInput::new()),
+                PatchNode::new("Stereo Merge", 0.0, 0.0, &ChannelNode::new(ChannelMode::Merge)),
+                PatchNode::new("Output", 0.0, 0.0, &Output),
+            ],
+            connections: vec![Connection::new(0, 0, 1, 0), Connection::new(0, 1, 1, 1), Connection::new(1, 0, 2, 0)],
+        });
+        poly.prepare(SAMPLE_RATE, FRAMES);
+        assert_eq!(poly.error(), None);
+        poly
+    }
+
+    fn play(poly: &mut Poly, events: &[MidiEvent]) -> (Vec<f32>, Vec<f32>) {
+        poly.input_events(0, events);
+        let (mut left, mut right) = (vec![0.0; FRAMES], vec![0.0; FRAMES]);
+        poly.process(&ProcessContext::new(SAMPLE_RATE, FRAMES), &[], &mut [&mut left, &mut right]);
+        (left, right)
+    }
+
+    #[test]
+    fn voice_input_retriggers_a_note_ending_at_the_same_frame() {
+        let mut voice = VoiceInput::new();
+        let events = [note_on(1, 62), note_off(3, 62), note_on(3, 64), note_off(5, 64)];
+        let mut context = ProcessContext::new(SAMPLE_RATE, 6);
+        context.events = &events;
+
+        let mut outputs = vec![vec![0.0; 6]; 4];
+        let mut buffers: Vec<&mut [f32]> = outputs.iter_mut().map(|output| output.as_mut_slice()).collect();
+        voice.process(&context, &[], &mut buffers);
+        assert_eq!(outputs[0], [0.0, 2.0, 2.0, 4.0, 4.0, 4.0]);
+        assert_eq!(outputs[1], [0.0, 1.0, 1.0, 0.0, 1.0, 0.0]);
+        assert!((outputs[2][1] - 100.0 / 127.0).abs() < 1.0e-6);
+        assert_eq!(outputs[3], [0.0, 1.0, 0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn notes_take_free_voices_then_steal_the_oldest() {
+        let mut poly = poly(PolyMode::Poly, 2);
+        let (left, _) = play(&mut poly, &[note_on(0, 62), note_on(0, 64)]);
+        assert_eq!(left[FRAMES - 1], 6.0);
+
+        // The third note takes the voice of the first, while the other voice carries on
+        let (left, _) = play(&mut poly, &[note_on(0, 67)]);
+        assert_eq!(left[FRAMES - 1], 11.0);
+    }
+
+    #[test]
+    fn mono_modes_go_back_to_the_held_note() {
+        // Mono starts the envelope again for each note, which legato does not
+        let events = [note_on(0, 60), note_on(4, 64), note_off(8, 64)];
+        let pitch = [0.0, 0.0, 0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 0.0, 0.0, 0.0, 0.0];
+
+        let (left, right) = play(&mut poly(PolyMode::Mono, 8), &events);
+        assert_eq!(left, pitch);
+        assert_eq!(right, [1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+
+        let (left, right) = play(&mut poly(PolyMode::Legato, 8), &events);
+        assert_eq!(left, pitch);
+        assert_eq!(right, [1.0; FRAMES]);
+    }
+}
@@ -0,0 +1,134 @@
+use super::node::*;
+use super::graph::Output;
+use super::envelope::Adsr;
+use super::lfo::Lfo;
+use super::clock::Clock;
+use super::sequencer::Sequencer;
+use super::midi_player::MidiPlayer;
+use super::oscillator::Oscillator;
+use super::noise::{Noise, NoiseColor};
+use super::delay::Delay;
+use super::reverb::Reverb;
+use super::convolution::Convolution;
+use super::dynamics::{Dynamics, DynamicsMode};
+use super::waveshaper::Waveshaper;
+use super::sampler::Sampler;
+use super::math::{MathNode, MathOp};
+use super::expression::Expression;
+use super::channels::{ChannelMode, ChannelNode};
+use super::poly::{Poly, VoiceInput};
+use super::scope::Scope;
+use super::group::{Group, GroupInput, GroupOutput, GROUP_INPUT, GROUP_OUTPUT};
+
+// Describes a type of node which can be added to a patch
+pub struct NodeDescriptor {
+    // Unique name shown in the node menu and stored in patch files
+    pub name: &'static str,
+    pub category: &'static str,
+    pub create: fn() -> Box<dyn AudioNode>,
+    // Parameters of the node, read once when it is registered so they can be shown without creating a node
+    pub params: Vec<ParamInfo>,
+}
+
+// List of every type of node available to the user
+pub struct NodeRegistry {
+    descriptors: Vec<NodeDescriptor>,
+}
+
+impl NodeRegistry {
+    pub fn new() -> Self {
+        Self {
+            descriptors: Vec::new(),
+        }
+    }
+
+    // Create a registry containing all of the built-in nodes
+    pub fn with_builtin_nodes() -> Self {
+        let mut registry = Self::new();
+
+        registry.register("Output", "Output", || Box::new(Output));
+
+        registry.register("Envelope", "Modulation", || Box::new(Adsr::new()));
+        registry.register("LFO", "Modulation", || Box::new(Lfo::new()));
+        registry.register("Clock", "Modulation", || Box::new(Clock::new()));
+        registry.register("Sequencer", "Modulation", || Box::new(Sequencer::new()));
+        registry.register("MIDI Player", "Modulation", || Box::new(MidiPlayer::new()));
+
+        registry.register("Oscillator", "Generators", || Box::new(Oscillator::new()));
+        registry.register("White Noise", "Generators", || Box::new(Noise::new(NoiseColor::White)));
+        registry.register("Pink Noise", "Generators", || Box::new(Noise::new(NoiseColor::Pink)));
+        registry.register("Brown Noise", "Generators", || Box::new(Noise::new(NoiseColor::Brown)));
+        registry.register("Random", "Modulation", || Box::new(Noise::new(NoiseColor::SampleAndHold)));
+        registry.register("Sampler", "Generators", || Box::new(Sampler::new()));
+
+        registry.register("Delay", "Effects", || Box::new(Delay::new()));
+        registry.register("Reverb", "Effects", || Box::new(Reverb::new()));
+        registry.register("Convolution", "Effects", || Box::new(Convolution::new()));
+
+        registry.register("Compressor", "Dynamics", || Box::new(Dynamics::new(DynamicsMode::Compressor)));
+        registry.register("Limiter", "Dynamics", || Box::new(Dynamics::new(DynamicsMode::Limiter)));
+        registry.register("Gate", "Dynamics", || Box::new(Dynamics::new(DynamicsMode::Gate)));
+
+        registry.register("Waveshaper", "Distortion", || Box::new(Waveshaper::new()));
+
+        registry.register("Add", "Math", || Box::new(MathNode::new(MathOp::Add)));
+        registry.register("Subtract", "Math", || Box::new(MathNode::new(MathOp::Subtract)));
+        registry.register("Multiply", "Math", || Box::new(MathNode::new(MathOp::Multiply)));
+        registry.register("Divide", "Math", || Box::new(MathNode::new(MathOp::Divide)));
+        registry.register("Min", "Math", || Box::new(MathNode::new(MathOp::Min)));
+        registry.register("Max", "Math", || Box::new(MathNode::new(MathOp::Max)));
+        registry.register("Abs", "Math", || Box::new(MathNode::new(MathOp::Abs)));
+        registry.register("Clamp", "Math", || Box::new(MathNode::new(MathOp::Clamp)));
+        registry.register("Map Range", "Math", || Box::new(MathNode::new(MathOp::MapRange)));
+        registry.register("Scale Offset", "Math", || Box::new(MathNode::new(MathOp::ScaleOffset)));
+        registry.register("Crossfade", "Math", || Box::new(MathNode::new(MathOp::Crossfade)));
+        registry.register("Compare", "Math", || Box::new(MathNode::new(MathOp::Compare)));
+        registry.register("Sample & Hold", "Math", || Box::new(MathNode::new(MathOp::SampleAndHold)));
+        registry.register("Slew Limiter", "Math", || Box::new(MathNode::new(MathOp::Slew)));
+        registry.register("Quantize", "Math", || Box::new(MathNode::new(MathOp::Quantize)));
+        registry.register("Expression", "Math", || Box::new(Expression::new()));
+
+        registry.register("Scope", "Analysis", || Box::new(Scope::new()));
+
+        registry.register("Stereo Merge", "Channels", || Box::new(ChannelNode::new(ChannelMode::Merge)));
+        registry.register("Stereo Split", "Channels", || Box::new(ChannelNode::new(ChannelMode::Split)));
+
+        registry.register("Poly", "Poly", || Box::new(Poly::new()));
+        registry.register("Voice", "Poly", || Box::new(VoiceInput::new()));
+
+        registry.register("Group", "Groups", || Box::new(Group::new()));
+        registry.register(GROUP_INPUT, "Groups", || Box::new(GroupInput::new("In")));
+        registry.register(GROUP_OUTPUT, "Groups", || Box::new(GroupOutput::new("Out")));
+
+        registry
+    }
+
+    // Add a type of node to the registry, replacing any existing node with the same name
+    pub fn register(&mut self, name: &'static str, category: &'static str, create: fn() -> Box<dyn AudioNode>) {
+        self.descriptors.retain(|descriptor| descriptor.name != name);
+        self.descriptors.push(NodeDescriptor {
+            name,
+            category,
+            create,
+            params: create().params().to_vec(),
+        });
+    }
+
+    pub fn descriptors(&self) -> &[NodeDescriptor] {
+        &self.descriptors
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NodeDescriptor> {
+        self.descriptors.iter().find(|descriptor| descriptor.name == name)
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn AudioNode>> {
+        self.get(name).map(|descriptor| (descriptor.create)())
+    }
+}
+
+impl Default for NodeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
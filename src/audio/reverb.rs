@@ -0,0 +1,250 @@
+use super::node::*;
+use super::delay::DelayLine;
+use super::graph::DEFAULT_SAMPLE_RATE;
+
+// Freeverb tunings, in samples at 44.1kHz
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const TUNING_SAMPLE_RATE: f32 = 44100.0;
+
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const SCALE_DAMPING: f32 = 0.4;
+const SCALE_WET: f32 = 3.0;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+// Longest pre-delay, in milliseconds
+const MAX_PRE_DELAY_MS: f32 = 500.0;
+
+fn pre_delay_length(sample_rate: f32) -> usize {
+    (MAX_PRE_DELAY_MS * 0.001 * sample_rate).ceil() as usize + 1
+}
+
+// Lowpass feedback comb filter
+struct Comb {
+    line: DelayLine,
+    length: usize,
+    filter_state: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Self {
+            line: DelayLine::new(length),
+            length,
+            filter_state: 0.0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.line.clear();
+        self.filter_state = 0.0;
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.line.read(self.length);
+        self.filter_state = output * (1.0 - damping) + self.filter_state * damping;
+        self.line.write(input + self.filter_state * feedback);
+        output
+    }
+}
+
+// Schroeder allpass filter
+struct Allpass {
+    line: DelayLine,
+    length: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Self {
+            line: DelayLine::new(length),
+            length,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.line.clear();
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.line.read(self.length);
+        self.line.write(input + delayed * ALLPASS_FEEDBACK);
+        delayed - input
+    }
+}
+
+// One side of the stereo reverb
+#[derive(Default)]
+struct Channel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Channel {
+    fn new(sample_rate: f32, spread: usize) -> Self {
+        let scale = |length: usize| ((length + spread) as f32 * sample_rate / TUNING_SAMPLE_RATE) as usize;
+        Self {
+            combs: COMB_TUNINGS.iter().map(|length| Comb::new(scale(*length))).collect(),
+            allpasses: ALLPASS_TUNINGS.iter().map(|length| Allpass::new(scale(*length))).collect(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.combs.iter_mut().for_each(Comb::clear);
+        self.allpasses.iter_mut().for_each(Allpass::clear);
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let mut output = 0.0;
+        for comb in self.combs.iter_mut() {
+            output += comb.process(input, feedback, damping);
+        }
+
+        for allpass in self.allpasses.iter_mut() {
+            output = allpass.process(output);
+        }
+
+        output
+    }
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::stereo("In")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::stereo("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Size", 0.0, 1.0, 0.5),
+    ParamInfo::new("Damping", 0.0, 1.0, 0.5),
+    ParamInfo::new("Pre-delay", 0.0, MAX_PRE_DELAY_MS, 0.0).with_skew(ParamSkew::Power(2.0)).with_unit("ms"),
+    ParamInfo::new("Width", 0.0, 1.0, 1.0),
+    ParamInfo::new("Mix", 0.0, 1.0, 0.3).with_style(ParamStyle::Slider),
+];
+
+// Stereo algorithmic reverb based on Freeverb.
+//
+// Both inputs are summed and pre-delayed before being fed to a bank of parallel comb filters
+// followed by serial allpass filters for each side. Contains no randomness, so renders are deterministic.
+pub struct Reverb {
+    size: f32,
+    damping: f32,
+    pre_delay: f32,
+    width: f32,
+    mix: f32,
+
+    sample_rate: f32,
+    pre_delay_line: DelayLine,
+    left: Channel,
+    right: Channel,
+}
+
+impl Reverb {
+    pub const SIZE: usize = 0;
+    pub const DAMPING: usize = 1;
+    pub const PRE_DELAY: usize = 2;
+    pub const WIDTH: usize = 3;
+    pub const MIX: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            size: 0.5,
+            damping: 0.5,
+            pre_delay: 0.0,
+            width: 1.0,
+            mix: 0.3,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            // The delay lines are only allocated once the node is prepared
+            pre_delay_line: DelayLine::new(0),
+            left: Channel::default(),
+            right: Channel::default(),
+        }
+    }
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Reverb {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::SIZE => self.size,
+            Self::DAMPING => self.damping,
+            Self::PRE_DELAY => self.pre_delay,
+            Self::WIDTH => self.width,
+            Self::MIX => self.mix,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::SIZE => self.size = value,
+            Self::DAMPING => self.damping = value,
+            Self::PRE_DELAY => self.pre_delay = value,
+            Self::WIDTH => self.width = value,
+            Self::MIX => self.mix = value,
+            _ => {}
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_frames: usize) {
+        self.sample_rate = sample_rate;
+        self.pre_delay_line.allocate(pre_delay_length(sample_rate));
+        self.left = Channel::new(sample_rate, 0);
+        self.right = Channel::new(sample_rate, STEREO_SPREAD);
+    }
+
+    fn reset(&mut self) {
+        self.pre_delay_line.clear();
+        self.left.clear();
+        self.right.clear();
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let (input_left, input_right) = (inputs[0], inputs[1]);
+
+        let feedback = self.size * SCALE_ROOM + OFFSET_ROOM;
+        let damping = self.damping * SCALE_DAMPING;
+        let wet = self.mix * SCALE_WET;
+        let wet_main = wet * (0.5 * self.width + 0.5);
+        let wet_cross = wet * (0.5 * (1.0 - self.width));
+        let dry = 1.0 - self.mix;
+        let pre_delay = (self.pre_delay * 0.001 * context.sample_rate).round() as usize;
+
+        for i in 0..context.frames {
+            let mut input = (input_left[i] + input_right[i]) * FIXED_GAIN;
+            if pre_delay > 0 {
+                let delayed = self.pre_delay_line.read(pre_delay);
+                self.pre_delay_line.write(input);
+                input = delayed;
+            }
+
+            let left = self.left.process(input, feedback, damping);
+            let right = self.right.process(input, feedback, damping);
+
+            outputs[0][i] = left * wet_main + right * wet_cross + input_left[i] * dry;
+            outputs[1][i] = right * wet_main + left * wet_cross + input_right[i] * dry;
+        }
+    }
+}
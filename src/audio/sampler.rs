@@ -0,0 +1,279 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use super::node::*;
+use super::audio_file::{AudioFile, AUDIO_EXTENSIONS};
+
+// Trigger input is considered high above this level
+const TRIGGER_THRESHOLD: f32 = 0.5;
+
+const INPUTS: &[PortInfo] = &[PortInfo::gate("Trigger"), PortInfo::control("Pitch")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::stereo("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::file("Sample", AUDIO_EXTENSIONS),
+    ParamInfo::marker("Start", 0, 0.0),
+    ParamInfo::marker("End", 0, 1.0),
+    ParamInfo::marker("Loop Start", 0, 0.0),
+    ParamInfo::marker("Loop End", 0, 1.0),
+    ParamInfo::toggle("Loop", 0.0),
+    ParamInfo::new("Pitch", -24.0, 24.0, 0.0).with_unit("st"),
+];
+
+// Read a channel at a fractional position using cubic interpolation
+fn read_cubic(channel: &[f32], position: f64) -> f32 {
+    let index = position.floor() as isize;
+    let t = (position - index as f64) as f32;
+    let at = |index: isize| channel[index.max(0).min(channel.len() as isize - 1) as usize];
+
+    let (xm1, x0, x1, x2) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+    let c1 = 0.5 * (x1 - xm1);
+    let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+    let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+    ((c3 * t + c2) * t + c1) * t + x0
+}
+
+// Plays an audio file from the start marker each time the trigger input rises.
+//
+// In one-shot mode playback stops at the end marker. In loop mode playback repeats between
+// the loop markers until the sample is triggered again.
+// The pitch input is added to the pitch parameter, both in semitones.
+pub struct Sampler {
+    file: Option<Arc<AudioFile>>,
+    start: f32,
+    end: f32,
+    loop_start: f32,
+    loop_end: f32,
+    looping: bool,
+    pitch: f32,
+
+    pitch_connected: bool,
+    trigger_high: bool,
+    playing: bool,
+    // Playback position in samples of the file
+    position: f64,
+}
+
+impl Sampler {
+    pub const SAMPLE: usize = 0;
+    pub const START: usize = 1;
+    pub const END: usize = 2;
+    pub const LOOP_START: usize = 3;
+    pub const LOOP_END: usize = 4;
+    pub const LOOP: usize = 5;
+    pub const PITCH: usize = 6;
+
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            start: 0.0,
+            end: 1.0,
+            loop_start: 0.0,
+            loop_end: 1.0,
+            looping: false,
+            pitch: 0.0,
+
+            pitch_connected: false,
+            trigger_high: false,
+            playing: false,
+            position: 0.0,
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Sampler {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::START => self.start,
+            Self::END => self.end,
+            Self::LOOP_START => self.loop_start,
+            Self::LOOP_END => self.loop_end,
+            Self::LOOP => self.looping as u32 as f32,
+            Self::PITCH => self.pitch,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::START => self.start = value,
+            Self::END => self.end = value,
+            Self::LOOP_START => self.loop_start = value,
+            Self::LOOP_END => self.loop_end = value,
+            Self::LOOP => self.looping = value >= 0.5,
+            Self::PITCH => self.pitch = value,
+            _ => {}
+        }
+    }
+
+    fn set_file(&mut self, index: usize, file: Arc<AudioFile>) {
+        if index != Self::SAMPLE || file.num_channels() == 0 {
+            return;
+        }
+
+        self.file = Some(file);
+        self.playing = false;
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn input_connected(&mut self, index: usize, connected: bool) {
+        if index == 1 {
+            self.pitch_connected = connected;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.trigger_high = false;
+        self.playing = false;
+        self.position = 0.0;
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let file = match self.file.as_ref() {
+            Some(file) => file,
+            None => {
+                for output in outputs.iter_mut() {
+                    for sample in output[..context.frames].iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+                return;
+            }
+        };
+
+        let length = file.len() as f64;
+        let start = self.start as f64 * length;
+        let end = self.end as f64 * length;
+        let loop_start = self.loop_start as f64 * length;
+        let loop_end = self.loop_end as f64 * length;
+        let can_loop = self.looping && loop_end > loop_start;
+
+        let left = &file.channels[0];
+        let right = &file.channels[file.num_channels().min(2) - 1];
+
+        // The file should already match the engine rate, but play at the right speed if it doesn't
+        let base_increment = (file.sample_rate / context.sample_rate) as f64;
+        let mut increment = base_increment * 2.0f64.powf(self.pitch as f64 / 12.0);
+
+        for i in 0..context.frames {
+            let trigger_high = inputs[0][i] > TRIGGER_THRESHOLD;
+            if trigger_high && !self.trigger_high {
+                self.position = start;
+                self.playing = true;
+            }
+            self.trigger_high = trigger_high;
+
+            if !self.playing {
+                outputs[0][i] = 0.0;
+                outputs[1][i] = 0.0;
+                continue;
+            }
+
+            outputs[0][i] = read_cubic(left, self.position);
+            outputs[1][i] = read_cubic(right, self.position);
+
+            if self.pitch_connected {
+                increment = base_increment * 2.0f64.powf((self.pitch + inputs[1][i]) as f64 / 12.0);
+            }
+            self.position += increment;
+
+            if can_loop && self.position >= loop_end {
+                self.position -= loop_end - loop_start;
+            } else if !can_loop && (self.position >= end || self.position >= length) {
+                self.playing = false;
+            }
+        }
+    }
+}
+
+impl NodeEditorInfo for Sampler {
+    fn file_path(&self, index: usize) -> Option<&Path> {
+        match index {
+            Self::SAMPLE => self.file.as_ref().map(|file| file.path.as_path()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // Sampler holding a file where each sample is its own index, triggered on the first frame
+    fn sampler(length: usize) -> Sampler {
+        let mut sampler = Sampler::new();
+        sampler.set_file(
+            Sampler::SAMPLE,
+            Arc::new(AudioFile {
+                path: "ramp.wav".into(),
+                sample_rate: SAMPLE_RATE,
+                channels: vec![(0..length).map(|i| i as f32).collect()],
+            }),
+        );
+        sampler
+    }
+
+    fn render(sampler: &mut Sampler, frames: usize) -> Vec<f32> {
+        let trigger = vec![1.0; frames];
+        let pitch = vec![0.0; frames];
+        let (mut left, mut right) = (vec![0.0; frames], vec![0.0; frames]);
+        sampler.process(&ProcessContext::new(SAMPLE_RATE, frames), &[&trigger, &pitch], &mut [&mut left, &mut right]);
+        assert_eq!(left, right);
+        left
+    }
+
+    #[test]
+    fn one_shot_stops_at_the_end_marker() {
+        let mut sampler = sampler(100);
+        sampler.set_param(Sampler::START, 0.1);
+        sampler.set_param(Sampler::END, 0.5);
+
+        let output = render(&mut sampler, 100);
+        assert_eq!(output[0], 10.0);
+        assert_eq!(output[39], 49.0);
+        assert!(output[40..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn loop_wraps_back_to_the_loop_start() {
+        let mut sampler = sampler(100);
+        sampler.set_param(Sampler::LOOP_START, 0.25);
+        sampler.set_param(Sampler::LOOP_END, 0.5);
+        sampler.set_param(Sampler::LOOP, 1.0);
+
+        // Playback runs from the start into the loop, then round it for as long as the sampler plays
+        let output = render(&mut sampler, 200);
+        assert_eq!(output[49], 49.0);
+        assert_eq!(output[50], 25.0);
+        assert_eq!(output[74], 49.0);
+        assert_eq!(output[75], 25.0);
+        assert_eq!(output[175], 25.0);
+    }
+}
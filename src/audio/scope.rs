@@ -0,0 +1,272 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use super::node::*;
+use super::meter::Meter;
+
+// Longest length of signal a scope can show, in milliseconds
+pub const MAX_TIMEBASE: f32 = 100.0;
+// Highest sample rate at which a scope can show its longest timebase. Above it the waveform is cut short.
+const MAX_SCOPE_SAMPLE_RATE: usize = 96000;
+// Number of samples kept by a scope tap. Finding a trigger point needs two windows of the longest timebase.
+pub const SCOPE_CAPACITY: usize = 2 * MAX_TIMEBASE as usize * MAX_SCOPE_SAMPLE_RATE / 1000;
+// Level shown at the bottom of a spectrum, in dB
+pub const SPECTRUM_FLOOR_DB: f32 = -90.0;
+
+// Sizes of FFT a scope can use for its spectrum, selected by a parameter
+pub const FFT_SIZES: &[usize] = &[256, 512, 1024, 2048, 4096];
+const FFT_SIZE_NAMES: &[&str] = &["256", "512", "1024", "2048", "4096"];
+
+const INPUTS: &[PortInfo] = &[PortInfo::new("In")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Trigger Level", -1.0, 1.0, 0.0).with_smoothing(Smoothing::None),
+    ParamInfo::new("Timebase", 1.0, MAX_TIMEBASE, 20.0)
+        .with_smoothing(Smoothing::None)
+        .with_skew(ParamSkew::Logarithmic)
+        .with_unit("ms"),
+    ParamInfo::choice("FFT Size", FFT_SIZE_NAMES, 2.0),
+];
+
+// Ring buffer of the latest samples of a signal, written on the audio thread and read by the UI.
+//
+// Cloning a tap shares the same buffer. There is a single writer and reading never blocks, so a read racing
+// with a write can return a few samples from the previous pass around the ring, which is fine for display.
+#[derive(Debug, Clone)]
+pub struct ScopeTap {
+    samples: Arc<Vec<AtomicU32>>,
+    // Number of samples written since the tap was created
+    written: Arc<AtomicUsize>,
+    sample_rate: Meter,
+}
+
+impl ScopeTap {
+    pub fn new() -> Self {
+        Self {
+            samples: Arc::new((0..SCOPE_CAPACITY).map(|_| AtomicU32::new(0)).collect()),
+            written: Arc::new(AtomicUsize::new(0)),
+            sample_rate: Meter::new(),
+        }
+    }
+
+    pub fn write(&self, samples: &[f32], sample_rate: f32) {
+        let start = self.written.load(Ordering::Relaxed);
+        for (index, sample) in samples.iter().enumerate() {
+            self.samples[(start + index) % SCOPE_CAPACITY].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.sample_rate.set(sample_rate);
+        self.written.store(start.wrapping_add(samples.len()), Ordering::Release);
+    }
+
+    // Number of samples written so far, which changes whenever new audio arrives
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+
+    // Sample rate of the signal, or 0 if nothing has been written yet
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate.get()
+    }
+
+    // Copy the latest samples, oldest first. Fewer are returned if fewer have been written.
+    pub fn latest(&self, frames: usize) -> Vec<f32> {
+        let end = self.written();
+        let frames = frames.min(SCOPE_CAPACITY).min(end);
+        (end - frames..end)
+            .map(|index| f32::from_bits(self.samples[index % SCOPE_CAPACITY].load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+impl Default for ScopeTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Taps are equal when they share the same buffer
+impl PartialEq for ScopeTap {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.samples, &other.samples)
+    }
+}
+
+// How a scope shows a signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScopeSettings {
+    // The waveform is drawn from where it rises through this level, so a periodic signal stands still
+    pub trigger_level: f32,
+    // Length of signal shown, in milliseconds
+    pub timebase: f32,
+    pub fft_size: usize,
+}
+
+impl ScopeSettings {
+    // Update the settings from a parameter of the scope node
+    pub fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Scope::TRIGGER_LEVEL => self.trigger_level = value,
+            Scope::TIMEBASE => self.timebase = value,
+            Scope::FFT_SIZE => self.fft_size = FFT_SIZES[(value.round() as usize).min(FFT_SIZES.len() - 1)],
+            _ => {}
+        }
+    }
+}
+
+impl Default for ScopeSettings {
+    fn default() -> Self {
+        Self {
+            trigger_level: 0.0,
+            timebase: 20.0,
+            fft_size: 1024,
+        }
+    }
+}
+
+// Find where to start showing `window` samples of a signal: the latest point at which it rises through
+// `level` that leaves a whole window after it, or the start of the latest window if it never does
+pub fn find_trigger(samples: &[f32], level: f32, window: usize) -> usize {
+    if samples.len() <= window {
+        return 0;
+    }
+
+    let last = samples.len() - window;
+    (1..=last)
+        .rev()
+        .find(|&index| samples[index - 1] < level && samples[index] >= level)
+        .unwrap_or(last)
+}
+
+// Works out the spectrum of a signal for display, keeping the FFT planned for the last size used
+pub struct Spectrum {
+    planner: FftPlanner<f32>,
+    fft: Option<Arc<dyn Fft<f32>>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    levels: Vec<f32>,
+}
+
+impl Spectrum {
+    pub fn new() -> Self {
+        Self {
+            planner: FftPlanner::new(),
+            fft: None,
+            window: Vec::new(),
+            buffer: Vec::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    // Level in dB of each frequency bin from 0 Hz up to half the sample rate, for a Hann windowed block of samples
+    pub fn analyze(&mut self, samples: &[f32]) -> &[f32] {
+        let size = samples.len();
+        if self.window.len() != size {
+            self.fft = Some(self.planner.plan_fft_forward(size));
+            self.window = (0..size)
+                .map(|index| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / size as f32).cos())
+                .collect();
+            self.buffer = vec![Complex::new(0.0, 0.0); size];
+            self.levels = vec![SPECTRUM_FLOOR_DB; size / 2];
+        }
+
+        let fft = match self.fft.as_ref() {
+            Some(fft) => fft,
+            None => return &self.levels,
+        };
+
+        for ((bin, sample), window) in self.buffer.iter_mut().zip(samples.iter()).zip(self.window.iter()) {
+            *bin = Complex::new(sample * window, 0.0);
+        }
+        fft.process(&mut self.buffer);
+
+        // A full scale sine has a magnitude of a quarter of the size with the Hann window
+        let scale = 4.0 / size as f32;
+        for (level, bin) in self.levels.iter_mut().zip(self.buffer.iter()) {
+            let magnitude = bin.norm() * scale;
+            *level = if magnitude > 0.0 { (20.0 * magnitude.log10()).max(SPECTRUM_FLOOR_DB) } else { SPECTRUM_FLOOR_DB };
+        }
+
+        &self.levels
+    }
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Oscilloscope and spectrum analyser, passing its input through unchanged.
+//
+// The signal is shown from the monitor of its output, so the node only holds the display settings.
+pub struct Scope {
+    settings: ScopeSettings,
+}
+
+impl Scope {
+    pub const TRIGGER_LEVEL: usize = 0;
+    pub const TIMEBASE: usize = 1;
+    pub const FFT_SIZE: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            settings: ScopeSettings::default(),
+        }
+    }
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Scope {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::TRIGGER_LEVEL => self.settings.trigger_level,
+            Self::TIMEBASE => self.settings.timebase,
+            Self::FFT_SIZE => FFT_SIZES.iter().position(|size| *size == self.settings.fft_size).unwrap_or(0) as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        self.settings.set_param(index, value);
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            output[..context.frames].copy_from_slice(&input[..context.frames]);
+        }
+    }
+}
+
+impl NodeEditorInfo for Scope {
+    fn scope(&self) -> Option<ScopeSettings> {
+        Some(self.settings)
+    }
+}
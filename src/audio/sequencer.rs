@@ -0,0 +1,354 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use super::node::*;
+
+// Most steps a pattern can have
+pub const MAX_STEPS: usize = 32;
+// Highest pitch of a step in semitones, the lowest is the negative of this
+pub const MAX_STEP_PITCH: f32 = 24.0;
+// Number of values saved for each step
+const STEP_VALUES: usize = 4;
+// Trigger inputs are considered high above this level
+const TRIGGER_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    // Offset in semitones
+    pub pitch: f32,
+    pub gate: bool,
+    // From 0 to 1
+    pub velocity: f32,
+    // Chance from 0 to 1 that the gate is played when the step is reached
+    pub probability: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            pitch: 0.0,
+            gate: false,
+            velocity: 0.8,
+            probability: 1.0,
+        }
+    }
+}
+
+// The steps of a sequencer.
+//
+// The pattern has a fixed size so it can be copied to the audio thread without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepPattern {
+    pub steps: [Step; MAX_STEPS],
+}
+
+impl Default for StepPattern {
+    // A gate on every beat of a 1/16 pattern
+    fn default() -> Self {
+        let mut steps = [Step::default(); MAX_STEPS];
+        for step in steps.iter_mut().step_by(4) {
+            step.gate = true;
+        }
+
+        Self { steps }
+    }
+}
+
+impl StepPattern {
+    // Create a pattern from values returned by `to_values()`, returning `None` if the number of values is wrong
+    pub fn from_values(values: &[f32]) -> Option<Self> {
+        if values.len() != MAX_STEPS * STEP_VALUES {
+            return None;
+        }
+
+        let mut pattern = Self::default();
+        for (step, values) in pattern.steps.iter_mut().zip(values.chunks(STEP_VALUES)) {
+            *step = Step {
+                pitch: values[0].clamp(-MAX_STEP_PITCH, MAX_STEP_PITCH),
+                gate: values[1] >= 0.5,
+                velocity: values[2].clamp(0.0, 1.0),
+                probability: values[3].clamp(0.0, 1.0),
+            };
+        }
+
+        Some(pattern)
+    }
+
+    // Pitch, gate, velocity and probability of each step in turn
+    pub fn to_values(&self) -> Vec<f32> {
+        self.steps
+            .iter()
+            .flat_map(|step| [step.pitch, step.gate as u8 as f32, step.velocity, step.probability])
+            .collect()
+    }
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::gate("Clock"), PortInfo::gate("Reset")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::control("Pitch"), PortInfo::gate("Gate"), PortInfo::control("Velocity")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::discrete("Steps", 1.0, MAX_STEPS as f32, 16.0),
+    ParamInfo::choice("Division", NOTE_DIVISION_NAMES, 1.0),
+    ParamInfo::new("Gate Length", 0.05, 1.0, 0.5).with_style(ParamStyle::Slider),
+];
+
+// Plays a pattern of steps, each with a pitch, gate, velocity and probability.
+//
+// While the clock input is unconnected the steps follow the song position of the transport at the
+// chosen note division, and each gate lasts for a fraction of the step. Once the clock input is
+// connected, each rising edge moves to the next step and the gate follows the clock. A rising edge
+// on the reset input makes the next clock play the first step.
+//
+// The pitch and velocity outputs hold the values of the last step which was played.
+pub struct Sequencer {
+    pattern: StepPattern,
+    length: usize,
+    division: f32,
+    gate_length: f32,
+
+    clock_connected: bool,
+    clock_high: bool,
+    reset_high: bool,
+    // Step being played, or `None` before the first step
+    step: Option<usize>,
+    // Index of the step of the song position which was last started when following the transport
+    transport_step: Option<i64>,
+    // Whether the gate of the current step passed its probability
+    step_active: bool,
+    pitch: f32,
+    velocity: f32,
+    rng: Pcg32,
+}
+
+impl Sequencer {
+    pub const STEPS: usize = 0;
+    pub const DIVISION: usize = 1;
+    pub const GATE_LENGTH: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            pattern: StepPattern::default(),
+            length: 16,
+            division: 1.0,
+            gate_length: 0.5,
+
+            clock_connected: false,
+            clock_high: false,
+            reset_high: false,
+            step: None,
+            transport_step: None,
+            step_active: false,
+            pitch: 0.0,
+            velocity: 0.0,
+            rng: Pcg32::seed_from_u64(0),
+        }
+    }
+
+    fn start_step(&mut self, index: usize) {
+        let step = self.pattern.steps[index];
+        self.step = Some(index);
+        self.step_active = step.gate && self.rng.gen::<f32>() < step.probability;
+        if self.step_active {
+            self.pitch = step.pitch;
+            self.velocity = step.velocity;
+        }
+    }
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Sequencer {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::STEPS => self.length as f32,
+            Self::DIVISION => self.division,
+            Self::GATE_LENGTH => self.gate_length,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::STEPS => self.length = value.round() as usize,
+            Self::DIVISION => self.division = value.round(),
+            Self::GATE_LENGTH => self.gate_length = value,
+            _ => {}
+        }
+    }
+
+    fn state(&self) -> Vec<f32> {
+        self.pattern.to_values()
+    }
+
+    fn set_state(&mut self, state: &[f32]) {
+        if let Some(pattern) = StepPattern::from_values(state) {
+            self.pattern = pattern;
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn input_connected(&mut self, index: usize, connected: bool) {
+        if index == 0 {
+            self.clock_connected = connected;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clock_high = false;
+        self.reset_high = false;
+        self.step = None;
+        self.transport_step = None;
+        self.step_active = false;
+        self.pitch = 0.0;
+        self.velocity = 0.0;
+        self.rng = Pcg32::seed_from_u64(0);
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let transport = context.transport;
+        let division = note_division_beats(self.division) as f64;
+
+        for i in 0..context.frames {
+            let reset_high = inputs[1][i] > TRIGGER_THRESHOLD;
+            if reset_high && !self.reset_high {
+                self.step = None;
+                self.transport_step = None;
+            }
+            self.reset_high = reset_high;
+
+            let gate = if self.clock_connected {
+                let clock_high = inputs[0][i] > TRIGGER_THRESHOLD;
+                if clock_high && !self.clock_high {
+                    let next = self.step.map(|step| step + 1).unwrap_or(0);
+                    self.start_step(next % self.length);
+                }
+                self.clock_high = clock_high;
+
+                self.step_active && clock_high
+            } else if transport.playing {
+                let steps = transport.position_at(i, context.sample_rate) / division;
+                let transport_step = steps.floor() as i64;
+                if self.transport_step != Some(transport_step) {
+                    self.start_step(transport_step.rem_euclid(self.length as i64) as usize);
+                    self.transport_step = Some(transport_step);
+                }
+
+                self.step_active && ((steps - steps.floor()) as f32) < self.gate_length
+            } else {
+                self.transport_step = None;
+                false
+            };
+
+            outputs[0][i] = self.pitch;
+            outputs[1][i] = if gate { 1.0 } else { 0.0 };
+            outputs[2][i] = self.velocity;
+        }
+    }
+}
+
+impl NodeEditorInfo for Sequencer {
+    fn step_pattern(&self) -> Option<StepPattern> {
+        Some(self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // Sequencer with three steps rising a semitone at a time, the second of which is silent
+    fn sequencer() -> Sequencer {
+        let mut pattern = StepPattern::default();
+        for (index, step) in pattern.steps.iter_mut().enumerate() {
+            *step = Step { pitch: index as f32, gate: index != 1, ..Step::default() };
+        }
+
+        let mut sequencer = Sequencer::new();
+        sequencer.set_state(&pattern.to_values());
+        sequencer.set_param(Sequencer::STEPS, 3.0);
+        sequencer
+    }
+
+    // Pitch and gate outputs
+    fn render(sequencer: &mut Sequencer, context: &ProcessContext, clock: &[f32], reset: &[f32]) -> (Vec<f32>, Vec<f32>) {
+        let (mut pitch, mut gate, mut velocity) = (vec![0.0; clock.len()], vec![0.0; clock.len()], vec![0.0; clock.len()]);
+        sequencer.process(context, &[clock, reset], &mut [&mut pitch, &mut gate, &mut velocity]);
+        (pitch, gate)
+    }
+
+    #[test]
+    fn pattern_survives_saving() {
+        let mut pattern = StepPattern::default();
+        pattern.steps[3] = Step { pitch: -7.0, gate: true, velocity: 0.25, probability: 0.5 };
+        assert_eq!(StepPattern::from_values(&pattern.to_values()), Some(pattern));
+        assert_eq!(StepPattern::from_values(&[0.0; 3]), None);
+    }
+
+    #[test]
+    fn clock_moves_through_the_steps_and_reset_goes_back_to_the_first() {
+        let mut sequencer = sequencer();
+        sequencer.input_connected(0, true);
+
+        // Each pulse of two frames plays the next step, wrapping after the third
+        let clock: Vec<f32> = (0..16).map(|i| if i % 4 < 2 { 1.0 } else { 0.0 }).collect();
+        let context = ProcessContext::new(SAMPLE_RATE, clock.len());
+        let (pitch, gate) = render(&mut sequencer, &context, &clock, &[0.0; 16]);
+        assert_eq!(pitch, [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 2.0, 2.0, 2.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(gate, [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+
+        // After the reset the next pulse plays the first step again rather than the second
+        let mut reset = [0.0; 16];
+        reset[2] = 1.0;
+        let (pitch, _) = render(&mut sequencer, &context, &clock, &reset);
+        assert_eq!(pitch[0], 0.0);
+        assert_eq!(pitch[4], 0.0);
+        assert_eq!(pitch[8], 0.0);
+        assert_eq!(pitch[12], 2.0);
+    }
+
+    #[test]
+    fn follows_the_song_position_at_its_division() {
+        let mut sequencer = sequencer();
+        sequencer.set_param(Sequencer::DIVISION, 1.0);
+
+        // At 120 bpm a 1/16 step lasts 6000 frames, with the gate held for half of it
+        let mut context = ProcessContext::new(SAMPLE_RATE, 18000);
+        context.transport.tempo = 120.0;
+        context.transport.play();
+        let silence = vec![0.0; 18000];
+        let (pitch, gate) = render(&mut sequencer, &context, &silence, &silence);
+        assert_eq!((pitch[0], gate[0], gate[2999], gate[3000]), (0.0, 1.0, 1.0, 0.0));
+        assert_eq!((pitch[6000], gate[6000]), (0.0, 0.0));
+        assert_eq!((pitch[12000], gate[12000]), (2.0, 1.0));
+
+        // Nothing plays while the transport is stopped
+        context.transport.stop();
+        let (_, gate) = render(&mut sequencer, &context, &silence, &silence);
+        assert!(gate.iter().all(|gate| *gate == 0.0));
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+// Song position, tempo and play state shared by every node.
+//
+// Positions are measured in beats, where a beat is a quarter note whatever the time signature.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transport {
+    pub playing: bool,
+    // Tempo in beats per minute
+    pub tempo: f32,
+    // Time signature, e.g. 6 and 8 for 6/8
+    pub numerator: u32,
+    pub denominator: u32,
+    // Song position in beats at the start of the current block
+    pub position: f64,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            playing: false,
+            tempo: 120.0,
+            numerator: 4,
+            denominator: 4,
+            position: 0.0,
+        }
+    }
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    // Stop playing. Stopping while already stopped returns to the start of the song.
+    pub fn stop(&mut self) {
+        if !self.playing {
+            self.position = 0.0;
+        }
+        self.playing = false;
+    }
+
+    // Length of a bar in beats
+    pub fn bar_length(&self) -> f64 {
+        self.numerator.max(1) as f64 * 4.0 / self.denominator.max(1) as f64
+    }
+
+    // Number of beats which pass during one sample
+    pub fn beats_per_sample(&self, sample_rate: f32) -> f64 {
+        self.tempo.max(1.0) as f64 / (60.0 * sample_rate as f64)
+    }
+
+    // Song position in beats of a frame in the current block
+    pub fn position_at(&self, frame: usize, sample_rate: f32) -> f64 {
+        if self.playing {
+            self.position + frame as f64 * self.beats_per_sample(sample_rate)
+        } else {
+            self.position
+        }
+    }
+
+    // Move the song position on by a block of frames, if playing
+    pub fn advance(&mut self, frames: usize, sample_rate: f32) {
+        self.position = self.position_at(frames, sample_rate);
+    }
+
+    // Bar, beat of the bar and sixteenth of the beat of the song position, counting from 1
+    pub fn bar_beat_sixteenth(&self) -> (u32, u32, u32) {
+        let bar_length = self.bar_length();
+        let bar = (self.position / bar_length).floor();
+        // Beats of the time signature, which are eighths in 6/8
+        let beat_length = 4.0 / self.denominator.max(1) as f64;
+        let in_bar = self.position - bar * bar_length;
+        let beat = (in_bar / beat_length).floor();
+        let sixteenth = ((in_bar - beat * beat_length) / 0.25).floor();
+
+        (bar as u32 + 1, beat as u32 + 1, sixteenth as u32 + 1)
+    }
+}
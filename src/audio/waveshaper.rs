@@ -0,0 +1,405 @@
+use super::node::*;
+
+// Number of points in a transfer curve, spread evenly over inputs from -1 to 1
+pub const CURVE_POINTS: usize = 129;
+// Number of taps in each half-band filter used for oversampling
+const HALFBAND_TAPS: usize = 31;
+// Number of 2x stages needed for the highest oversampling factor
+const MAX_OVERSAMPLING_STAGES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurvePreset {
+    Tanh,
+    HardClip,
+    Foldback,
+    Bitcrush,
+}
+
+impl CurvePreset {
+    pub const ALL: &'static [CurvePreset] = &[
+        CurvePreset::Tanh,
+        CurvePreset::HardClip,
+        CurvePreset::Foldback,
+        CurvePreset::Bitcrush,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CurvePreset::Tanh => "Tanh",
+            CurvePreset::HardClip => "Clip",
+            CurvePreset::Foldback => "Fold",
+            CurvePreset::Bitcrush => "Crush",
+        }
+    }
+
+    // Output of the preset for an input from -1 to 1
+    pub fn value(&self, x: f32) -> f32 {
+        match self {
+            CurvePreset::Tanh => (3.0 * x).tanh() / 3.0f32.tanh(),
+            CurvePreset::HardClip => (2.0 * x).clamp(-1.0, 1.0),
+            CurvePreset::Foldback => {
+                // Triangle wave which reflects the signal back each time it passes +/-1
+                let t = (3.0 * x + 1.0).rem_euclid(4.0);
+                if t < 2.0 {
+                    t - 1.0
+                } else {
+                    3.0 - t
+                }
+            }
+            CurvePreset::Bitcrush => (x * 8.0).round() / 8.0,
+        }
+    }
+}
+
+// Transfer function of a waveshaper, stored as evenly spaced points which can be drawn by hand.
+//
+// The curve has a fixed size so it can be copied to the audio thread without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferCurve {
+    points: [f32; CURVE_POINTS],
+}
+
+impl Default for TransferCurve {
+    fn default() -> Self {
+        Self::from_preset(CurvePreset::Tanh)
+    }
+}
+
+impl TransferCurve {
+    pub fn from_preset(preset: CurvePreset) -> Self {
+        let mut points = [0.0; CURVE_POINTS];
+        for (index, point) in points.iter_mut().enumerate() {
+            *point = preset.value(Self::point_input(index));
+        }
+
+        Self { points }
+    }
+
+    // Create a curve from saved points, returning `None` if the number of points is wrong
+    pub fn from_points(values: &[f32]) -> Option<Self> {
+        if values.len() != CURVE_POINTS {
+            return None;
+        }
+
+        let mut points = [0.0; CURVE_POINTS];
+        for (point, value) in points.iter_mut().zip(values.iter()) {
+            *point = value.clamp(-1.0, 1.0);
+        }
+
+        Some(Self { points })
+    }
+
+    pub fn points(&self) -> &[f32] {
+        &self.points
+    }
+
+    // Input value at which the point with the given index lies
+    pub fn point_input(index: usize) -> f32 {
+        2.0 * index as f32 / (CURVE_POINTS - 1) as f32 - 1.0
+    }
+
+    // Output of the curve for an input from -1 to 1, interpolating between points
+    pub fn value(&self, x: f32) -> f32 {
+        let position = (x.clamp(-1.0, 1.0) + 1.0) * 0.5 * (CURVE_POINTS - 1) as f32;
+        let index = (position as usize).min(CURVE_POINTS - 2);
+        let fraction = position - index as f32;
+        self.points[index] + (self.points[index + 1] - self.points[index]) * fraction
+    }
+
+    // Set every point between the inputs `x0` and `x1` to lie on a straight line, as when dragging across the curve
+    pub fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        let to_index = |x: f32| ((x.clamp(-1.0, 1.0) + 1.0) * 0.5 * (CURVE_POINTS - 1) as f32).round() as usize;
+        let (start, end) = if x0 <= x1 { (x0, x1) } else { (x1, x0) };
+
+        for index in to_index(start)..=to_index(end) {
+            let x = Self::point_input(index);
+            let t = if (x1 - x0).abs() > f32::EPSILON { (x - x0) / (x1 - x0) } else { 1.0 };
+            let y = y0 + (y1 - y0) * t.clamp(0.0, 1.0);
+            self.points[index] = y.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+// Linear phase low-pass filter with its cutoff at a quarter of the sample rate,
+// used when converting between the normal and the doubled sample rate
+#[derive(Debug, Clone)]
+struct HalfbandFilter {
+    history: [f32; HALFBAND_TAPS],
+    position: usize,
+}
+
+impl HalfbandFilter {
+    fn new() -> Self {
+        Self {
+            history: [0.0; HALFBAND_TAPS],
+            position: 0,
+        }
+    }
+
+    // Windowed sinc coefficients, normalised to unity gain at DC
+    fn coefficients() -> [f32; HALFBAND_TAPS] {
+        let mut coefficients = [0.0; HALFBAND_TAPS];
+        let centre = (HALFBAND_TAPS / 2) as f32;
+        for (index, coefficient) in coefficients.iter_mut().enumerate() {
+            let n = index as f32 - centre;
+            let sinc = if n == 0.0 {
+                0.5
+            } else {
+                (std::f32::consts::PI * 0.5 * n).sin() / (std::f32::consts::PI * n)
+            };
+            let phase = 2.0 * std::f32::consts::PI * index as f32 / (HALFBAND_TAPS - 1) as f32;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            *coefficient = sinc * window;
+        }
+
+        let sum: f32 = coefficients.iter().sum();
+        for coefficient in coefficients.iter_mut() {
+            *coefficient /= sum;
+        }
+
+        coefficients
+    }
+
+    fn reset(&mut self) {
+        self.history = [0.0; HALFBAND_TAPS];
+        self.position = 0;
+    }
+
+    fn process(&mut self, input: f32, coefficients: &[f32; HALFBAND_TAPS]) -> f32 {
+        self.history[self.position] = input;
+
+        let mut output = 0.0;
+        let mut index = self.position;
+        for coefficient in coefficients.iter() {
+            output += coefficient * self.history[index];
+            index = if index == 0 { HALFBAND_TAPS - 1 } else { index - 1 };
+        }
+
+        self.position = (self.position + 1) % HALFBAND_TAPS;
+        output
+    }
+}
+
+// Runs a function at 2x or 4x the sample rate by cascading 2x up and down sampling stages
+struct Oversampler {
+    coefficients: [f32; HALFBAND_TAPS],
+    up: [HalfbandFilter; MAX_OVERSAMPLING_STAGES],
+    down: [HalfbandFilter; MAX_OVERSAMPLING_STAGES],
+}
+
+impl Oversampler {
+    fn new() -> Self {
+        Self {
+            coefficients: HalfbandFilter::coefficients(),
+            up: [HalfbandFilter::new(), HalfbandFilter::new()],
+            down: [HalfbandFilter::new(), HalfbandFilter::new()],
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in self.up.iter_mut().chain(self.down.iter_mut()) {
+            filter.reset();
+        }
+    }
+
+    // Process one input sample with `stages` doublings of the sample rate
+    fn process<F: FnMut(f32) -> f32>(&mut self, input: f32, stages: usize, function: &mut F) -> f32 {
+        let stages = stages.min(MAX_OVERSAMPLING_STAGES);
+        Self::process_stage(&self.coefficients, &mut self.up[..stages], &mut self.down[..stages], input, function)
+    }
+
+    fn process_stage<F: FnMut(f32) -> f32>(
+        coefficients: &[f32; HALFBAND_TAPS],
+        up: &mut [HalfbandFilter],
+        down: &mut [HalfbandFilter],
+        input: f32,
+        function: &mut F,
+    ) -> f32 {
+        match (up.split_first_mut(), down.split_first_mut()) {
+            (Some((up_first, up_rest)), Some((down_first, down_rest))) => {
+                // Zero stuffing halves the level, so the first sample is doubled to compensate
+                let a = up_first.process(2.0 * input, coefficients);
+                let b = up_first.process(0.0, coefficients);
+
+                let a = Self::process_stage(coefficients, up_rest, down_rest, a, function);
+                let b = Self::process_stage(coefficients, up_rest, down_rest, b, function);
+
+                // Only every second filtered sample is kept
+                down_first.process(a, coefficients);
+                down_first.process(b, coefficients)
+            }
+
+            _ => function(input),
+        }
+    }
+}
+
+const INPUTS: &[PortInfo] = &[PortInfo::new("In")];
+const OUTPUTS: &[PortInfo] = &[PortInfo::new("Out")];
+const PARAMS: &[ParamInfo] = &[
+    ParamInfo::new("Drive", 0.0, 36.0, 0.0).with_unit("dB"),
+    ParamInfo::new("Output", -24.0, 12.0, 0.0).with_unit("dB"),
+    ParamInfo::choice("Oversampling", &["Off", "2x", "4x"], 0.0),
+];
+
+// Distortion which passes the signal through a transfer curve.
+//
+// The curve can be filled from a preset or drawn by hand, and is saved with the patch as the node state.
+// Oversampling by 2x (1) or 4x (2) reduces the aliasing caused by sharp corners in the curve.
+pub struct Waveshaper {
+    curve: TransferCurve,
+    drive: f32,
+    output: f32,
+    oversampling: usize,
+
+    oversampler: Oversampler,
+}
+
+impl Waveshaper {
+    pub const DRIVE: usize = 0;
+    pub const OUTPUT: usize = 1;
+    pub const OVERSAMPLING: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            curve: TransferCurve::default(),
+            drive: 0.0,
+            output: 0.0,
+            oversampling: 0,
+
+            oversampler: Oversampler::new(),
+        }
+    }
+
+    pub fn set_curve(&mut self, curve: TransferCurve) {
+        self.curve = curve;
+    }
+}
+
+impl Default for Waveshaper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Waveshaper {
+    fn inputs(&self) -> &[PortInfo] {
+        INPUTS
+    }
+
+    fn outputs(&self) -> &[PortInfo] {
+        OUTPUTS
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        PARAMS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            Self::DRIVE => self.drive,
+            Self::OUTPUT => self.output,
+            Self::OVERSAMPLING => self.oversampling as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        let value = match PARAMS.get(index) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match index {
+            Self::DRIVE => self.drive = value,
+            Self::OUTPUT => self.output = value,
+            Self::OVERSAMPLING => {
+                let oversampling = value.round() as usize;
+                if oversampling != self.oversampling {
+                    // The filter history belongs to the old rate
+                    self.oversampler.reset();
+                    self.oversampling = oversampling;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn state(&self) -> Vec<f32> {
+        self.curve.points().to_vec()
+    }
+
+    fn set_state(&mut self, state: &[f32]) {
+        if let Some(curve) = TransferCurve::from_points(state) {
+            self.curve = curve;
+        }
+    }
+
+    fn editor_info(&self) -> Option<&dyn NodeEditorInfo> {
+        Some(self)
+    }
+
+    fn reset(&mut self) {
+        self.oversampler.reset();
+    }
+
+    fn process(&mut self, context: &ProcessContext, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let drive = 10.0f32.powf(self.drive / 20.0);
+        let gain = 10.0f32.powf(self.output / 20.0);
+        let curve = &self.curve;
+        let mut shape = |x: f32| curve.value(x * drive);
+
+        for i in 0..context.frames {
+            outputs[0][i] = self.oversampler.process(inputs[0][i], self.oversampling, &mut shape) * gain;
+        }
+    }
+}
+
+impl NodeEditorInfo for Waveshaper {
+    fn transfer_curve(&self) -> Option<TransferCurve> {
+        Some(self.curve)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(waveshaper: &mut Waveshaper, input: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.0; input.len()];
+        waveshaper.process(&ProcessContext::new(48000.0, input.len()), &[input], &mut [&mut output]);
+        output
+    }
+
+    #[test]
+    fn curve_interpolates_and_draws() {
+        let mut curve = TransferCurve::from_preset(CurvePreset::HardClip);
+        assert_eq!(curve.value(0.25), 0.5);
+        assert_eq!(curve.value(-0.8), -1.0);
+        assert_eq!(curve.value(2.0), 1.0);
+
+        // A line drawn from the middle to the right end replaces the points between them
+        curve.draw_line(0.0, 0.0, 1.0, -1.0);
+        assert!((curve.value(0.5) + 0.5).abs() < 1.0e-6);
+        assert_eq!(curve.value(1.0), -1.0);
+        assert_eq!(curve.value(-0.25), -0.5);
+
+        assert_eq!(TransferCurve::from_points(curve.points()), Some(curve));
+        assert_eq!(TransferCurve::from_points(&[0.0; 4]), None);
+    }
+
+    #[test]
+    fn shapes_through_the_curve_at_every_oversampling() {
+        let mut waveshaper = Waveshaper::new();
+        waveshaper.set_state(TransferCurve::from_preset(CurvePreset::HardClip).points());
+        waveshaper.set_param(Waveshaper::DRIVE, 20.0 * 2.0f32.log10());
+
+        // Twice the drive and the clipping curve's slope of two take 0.2 to 0.8 at every oversampling rate
+        for oversampling in 0..3 {
+            waveshaper.set_param(Waveshaper::OVERSAMPLING, oversampling as f32);
+            waveshaper.reset();
+            let output = render(&mut waveshaper, &[0.2; 256]);
+            assert!((output[255] - 0.8).abs() < 1.0e-3, "{} at {}", output[255], oversampling);
+        }
+    }
+}
@@ -2,6 +2,7 @@ use tuix::*;
 use ui::*;
 
 pub mod ui;
+pub mod audio;
 
 const STYLE: &str = r#"
     .node {
@@ -16,6 +17,72 @@ const STYLE: &str = r#"
         background-color: #303099;
     }
 
+    .node_menu {
+        background-color: #252525;
+        border-width: 1px;
+        border-color: #646464;
+    }
+
+    .node_menu_item:hover {
+        background-color: #303099;
+    }
+
+    .curve_preset:hover {
+        background-color: #303099;
+    }
+
+    .step_lane:hover {
+        background-color: #303099;
+    }
+
+    .transport_bar {
+        background-color: #252525;
+    }
+
+    .transport_button:hover {
+        background-color: #303099;
+    }
+
+    .subpatch_button:hover {
+        background-color: #303099;
+    }
+
+    .breadcrumbs {
+        background-color: #252525;
+    }
+
+    .breadcrumb:hover {
+        background-color: #303099;
+    }
+
+    .param_option:hover {
+        background-color: #303099;
+    }
+
+    .inspector {
+        background-color: #252525;
+    }
+
+    .inspector_header {
+        background-color: #202020;
+    }
+
+    .inspector_button:hover {
+        background-color: #303099;
+    }
+
+    .scope_button:hover {
+        background-color: #5050c0;
+    }
+
+    .inspector_heading {
+        background-color: #202020;
+    }
+
+    .error_bar {
+        background-color: #802020;
+    }
+
 
 "#;
 
@@ -31,9 +98,7 @@ fn main() {
 
         let column = Column::new().build(state, window, |builder| builder);
 
-        NodeView::new().build(state, column, |builder| {
-            builder
-        });
+        EngineHost::new().build(state, column, |builder| builder);
     });
 
     app.run();
@@ -0,0 +1,182 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::{CurvePreset, TransferCurve, CURVE_POINTS};
+
+// Padding between the edge of the editor and the curve
+const PADDING: f32 = 6.0;
+// Height of the row of preset buttons below the curve
+const PRESET_ROW_HEIGHT: f32 = 20.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurveEvent {
+    // Sent up the tree whenever the curve is drawn on or replaced by a preset
+    CurveChanged(TransferCurve),
+}
+
+// Editor for the transfer curve of a waveshaper.
+//
+// Dragging across the plot draws the curve by hand and the buttons below replace it with a preset.
+pub struct CurveEditor {
+    curve: TransferCurve,
+    drawing: bool,
+    // Curve coordinates of the previous mouse position while drawing
+    prev_x: f32,
+    prev_y: f32,
+    // Preset buttons and the preset each one applies
+    presets: Vec<(Entity, CurvePreset)>,
+}
+
+impl CurveEditor {
+    pub fn new(curve: TransferCurve) -> Self {
+        Self {
+            curve,
+            drawing: false,
+            prev_x: 0.0,
+            prev_y: 0.0,
+            presets: Vec::new(),
+        }
+    }
+
+    // Area of the editor in which the curve is drawn
+    fn plot_bounds(bounds: BoundingBox) -> BoundingBox {
+        BoundingBox {
+            x: bounds.x + PADDING,
+            y: bounds.y + PADDING,
+            w: bounds.w - 2.0 * PADDING,
+            h: bounds.h - 2.0 * PADDING - PRESET_ROW_HEIGHT,
+        }
+    }
+
+    // Convert a window position into curve coordinates, where both axes go from -1 to 1
+    fn curve_position(state: &State, entity: Entity, x: f32, y: f32) -> (f32, f32) {
+        let mut transform = state.data.get_transform(entity);
+        transform.inverse();
+        let (x, y) = transform.transform_point(x, y);
+
+        let plot = Self::plot_bounds(state.data.get_bounds(entity));
+        let cx = 2.0 * (x - plot.x) / plot.w - 1.0;
+        let cy = 1.0 - 2.0 * (y - plot.y) / plot.h;
+        (cx.clamp(-1.0, 1.0), cy.clamp(-1.0, 1.0))
+    }
+
+    fn curve_changed(&self, state: &mut State, entity: Entity) {
+        state.insert_event(Event::new(CurveEvent::CurveChanged(self.curve)).target(entity).origin(entity));
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+}
+
+impl Widget for CurveEditor {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        let row = Row::new().build(state, entity, |builder|
+            builder
+                .set_height(Pixels(PRESET_ROW_HEIGHT))
+                .set_top(Stretch(1.0))
+        );
+
+        for preset in CurvePreset::ALL.iter() {
+            let button = Label::new(preset.name()).build(state, row, |builder|
+                builder
+                    .set_child_space(Stretch(1.0))
+                    .class("curve_preset")
+            );
+
+            self.presets.push((button, *preset));
+        }
+
+        entity
+            .set_height(state, Pixels(120.0))
+            .set_space(state, Pixels(5.0))
+            .class(state, "curve_editor")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left {
+                        let picked = self.presets.iter().find(|(button, _)| *button == event.target).map(|(_, preset)| *preset);
+                        if let Some(preset) = picked {
+                            self.curve = TransferCurve::from_preset(preset);
+                            self.curve_changed(state, entity);
+                            event.consume();
+                        }
+
+                        if event.target == entity {
+                            let (x, y) = Self::curve_position(state, entity, state.mouse.cursorx, state.mouse.cursory);
+                            self.curve.draw_line(x, y, x, y);
+                            self.prev_x = x;
+                            self.prev_y = y;
+                            self.drawing = true;
+                            state.capture(entity);
+                            self.curve_changed(state, entity);
+                            // Stop the parent node from being moved
+                            event.consume();
+                        }
+                    }
+                }
+
+                WindowEvent::MouseUp(button) => {
+                    if *button == MouseButton::Left && self.drawing {
+                        self.drawing = false;
+                        state.release(entity);
+                    }
+                }
+
+                WindowEvent::MouseMove(x, y) => {
+                    if self.drawing {
+                        let (x, y) = Self::curve_position(state, entity, *x, *y);
+                        self.curve.draw_line(self.prev_x, self.prev_y, x, y);
+                        self.prev_x = x;
+                        self.prev_y = y;
+                        self.curve_changed(state, entity);
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(entity);
+        let transform = state.data.get_transform(entity);
+        let plot = Self::plot_bounds(bounds);
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        let mut path = Path::new();
+        path.rounded_rect(bounds.x, bounds.y, bounds.w, bounds.h, 3.0);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        let mut path = Path::new();
+        path.move_to(plot.x, plot.y + plot.h / 2.0);
+        path.line_to(plot.x + plot.w, plot.y + plot.h / 2.0);
+        path.move_to(plot.x + plot.w / 2.0, plot.y);
+        path.line_to(plot.x + plot.w / 2.0, plot.y + plot.h);
+        let mut paint = Paint::color(femtovg::Color::rgb(60, 60, 60));
+        paint.set_line_width(1.0);
+        canvas.stroke_path(&mut path, paint);
+
+        let mut path = Path::new();
+        for (index, value) in self.curve.points().iter().enumerate() {
+            let x = plot.x + plot.w * index as f32 / (CURVE_POINTS - 1) as f32;
+            let y = plot.y + plot.h * 0.5 * (1.0 - value);
+            if index == 0 {
+                path.move_to(x, y);
+            } else {
+                path.line_to(x, y);
+            }
+        }
+        let mut paint = Paint::color(femtovg::Color::rgb(200, 200, 200));
+        paint.set_line_width(2.0);
+        canvas.stroke_path(&mut path, paint);
+
+        canvas.restore();
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use tuix::*;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::node_view::*;
+use super::transport_bar::*;
+
+// Most frames rendered at a time on the audio thread. Devices which ask for more are filled in several chunks.
+const MAX_BUFFER_FRAMES: usize = 8192;
+
+use crate::audio::{Engine, EngineController, Meter, DEFAULT_BLOCK_SIZE};
+
+// Plays the patch being edited through the default output device, holding the transport bar and the node view.
+//
+// The node view sends the whole patch whenever its nodes or wires change, which is built into a new graph for
+// the engine, while parameter edits are passed straight to the engine.
+pub struct EngineHost {
+    controller: Option<EngineController>,
+    // Kept open for as long as the host lives, as dropping it stops the audio
+    _stream: Option<cpal::Stream>,
+    // Errors reported by the stream on the audio thread
+    stream_errors: Option<Receiver<String>>,
+    transport_bar: Entity,
+    node_view: Entity,
+}
+
+impl EngineHost {
+    pub fn new() -> Self {
+        Self {
+            controller: None,
+            _stream: None,
+            stream_errors: None,
+            transport_bar: Entity::null(),
+            node_view: Entity::null(),
+        }
+    }
+
+    // Open the default output device and start the engine playing through it, returning its song position
+    fn start(&mut self) -> Result<Meter, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| String::from("No audio output device found"))?;
+        let supported = device.default_output_config().map_err(|error| error.to_string())?;
+        let sample_format = supported.sample_format();
+        let max_frames = match supported.buffer_size() {
+            cpal::SupportedBufferSize::Range { max, .. } => (*max as usize).clamp(1, MAX_BUFFER_FRAMES),
+            cpal::SupportedBufferSize::Unknown => MAX_BUFFER_FRAMES,
+        };
+        let config: cpal::StreamConfig = supported.into();
+
+        let (engine, controller) = Engine::new(config.sample_rate.0 as f32, DEFAULT_BLOCK_SIZE);
+        let (error_sender, errors) = mpsc::channel();
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, max_frames, engine, error_sender),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, max_frames, engine, error_sender),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, max_frames, engine, error_sender),
+        }
+        .map_err(|error| error.to_string())?;
+        stream.play().map_err(|error| error.to_string())?;
+
+        let position = controller.position();
+        self.controller = Some(controller);
+        self._stream = Some(stream);
+        self.stream_errors = Some(errors);
+        Ok(position)
+    }
+
+    fn show_error(&self, state: &mut State, entity: Entity, error: String) {
+        state.insert_event(Event::new(EngineEvent::Error(error)).direct(self.node_view).origin(entity));
+    }
+}
+
+// Open an output stream in the sample format of the device. The engine renders into a buffer of floats allocated
+// here for up to `max_frames` frames, so the audio thread never allocates, and longer buffers are rendered in chunks.
+fn build_stream<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    max_frames: usize,
+    mut engine: Engine,
+    errors: Sender<String>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let channels = (config.channels as usize).max(1);
+    let sample_rate = config.sample_rate.0 as f64;
+    let mut buffer = vec![0.0f32; max_frames * channels];
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let now = Instant::now();
+            for (chunk, samples) in data.chunks_mut(buffer.len()).enumerate() {
+                let start = Duration::from_secs_f64((chunk * max_frames) as f64 / sample_rate);
+                let buffer = &mut buffer[..samples.len()];
+                engine.render(buffer, channels, now + start);
+                for (output, sample) in samples.iter_mut().zip(buffer.iter()) {
+                    *output = T::from(sample);
+                }
+            }
+        },
+        move |error| {
+            let _ = errors.send(error.to_string());
+        },
+    )
+}
+
+impl Widget for EngineHost {
+    type Ret = Entity;
+    type Data = ();
+
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        self.transport_bar = TransportBar::new().build(state, entity, |builder| builder);
+        self.node_view = NodeView::new().build(state, entity, |builder| builder);
+
+        match self.start() {
+            Ok(position) => {
+                state.insert_event(Event::new(TransportEvent::Position(position)).direct(self.transport_bar).origin(entity));
+            }
+
+            Err(error) => self.show_error(state, entity, format!("Failed to start audio: {}", error)),
+        }
+
+        entity
+            .set_layout_type(state, LayoutType::Column)
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        // Errors from the audio thread are shown when the next event passes through
+        let errors: Vec<String> = self.stream_errors.iter().flat_map(|errors| errors.try_iter()).collect();
+        for error in errors {
+            self.show_error(state, entity, format!("Audio error: {}", error));
+        }
+
+        if let Some(patch_event) = event.message.downcast() {
+            match patch_event {
+                PatchEvent::ParamChanged(change) => {
+                    if let Some(controller) = self.controller.as_ref() {
+                        controller.change_param(*change);
+                    }
+                    event.consume();
+                }
+
+                PatchEvent::Changed(patch) => {
+                    // The new graph has new monitors, which feed the wire levels, scopes and inspector
+                    if let Some(controller) = self.controller.as_mut() {
+                        match controller.set_patch(patch) {
+                            Ok(monitors) => {
+                                state.insert_event(Event::new(EngineEvent::Monitors(monitors)).direct(self.node_view).origin(entity));
+                            }
+
+                            Err(error) => self.show_error(state, entity, format!("Failed to play patch: {}", error)),
+                        }
+                    }
+                    event.consume();
+                }
+
+                PatchEvent::Probe(probe) => {
+                    if let Some(controller) = self.controller.as_mut() {
+                        controller.set_probe(*probe);
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(transport_event) = event.message.downcast() {
+            match transport_event {
+                TransportEvent::Changed(transport) => {
+                    if let Some(controller) = self.controller.as_mut() {
+                        controller.set_transport(*transport);
+                    }
+                    event.consume();
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::{AdsrShape, shape_curve, MAX_STAGE_TIME};
+
+// Distance in pixels within which a breakpoint can be grabbed
+const HANDLE_RADIUS: f32 = 6.0;
+// Padding between the edge of the editor and the envelope
+const PADDING: f32 = 6.0;
+// How much the curvature changes per pixel dragged
+const CURVE_DRAG_SPEED: f32 = 0.02;
+// Number of line segments used to draw each curved stage
+const CURVE_SEGMENTS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvelopeEvent {
+    // Sent up the tree whenever a breakpoint is dragged
+    ShapeChanged(AdsrShape),
+    // Sent to an editor when a parameter of its node is edited elsewhere
+    ParamChanged(usize, f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Handle {
+    Attack,
+    Decay,
+    Release,
+    AttackCurve,
+    DecayCurve,
+    ReleaseCurve,
+}
+
+// Screen positions of the envelope breakpoints, computed from the shape and the bounds of the editor
+struct Breakpoints {
+    start: (f32, f32),
+    peak: (f32, f32),
+    sustain_start: (f32, f32),
+    sustain_end: (f32, f32),
+    end: (f32, f32),
+    // Width given to each of the attack, decay, sustain and release stages
+    zone_width: f32,
+    height: f32,
+}
+
+impl Breakpoints {
+    fn new(shape: &AdsrShape, bounds: BoundingBox) -> Self {
+        let left = bounds.x + PADDING;
+        let top = bounds.y + PADDING;
+        let zone_width = (bounds.w - 2.0 * PADDING) / 4.0;
+        let height = bounds.h - 2.0 * PADDING;
+
+        let level_y = |level: f32| top + height * (1.0 - level);
+        // Use a square root scale so that short times still get a usable amount of space
+        let stage_width = |time: f32| zone_width * (time / MAX_STAGE_TIME).sqrt();
+
+        let peak_x = left + stage_width(shape.attack);
+        let sustain_x = peak_x + stage_width(shape.decay);
+        let sustain_end_x = sustain_x + zone_width;
+        let end_x = sustain_end_x + stage_width(shape.release);
+
+        Self {
+            start: (left, level_y(0.0)),
+            peak: (peak_x, level_y(1.0)),
+            sustain_start: (sustain_x, level_y(shape.sustain)),
+            sustain_end: (sustain_end_x, level_y(shape.sustain)),
+            end: (end_x, level_y(0.0)),
+            zone_width,
+            height,
+        }
+    }
+
+    // Position of the handle used to bend the stage between `from` and `to`
+    fn curve_handle(from: (f32, f32), to: (f32, f32), curvature: f32) -> (f32, f32) {
+        let t = shape_curve(0.5, curvature);
+        (from.0 + (to.0 - from.0) * 0.5, from.1 + (to.1 - from.1) * t)
+    }
+
+    fn handle_position(&self, shape: &AdsrShape, handle: Handle) -> (f32, f32) {
+        match handle {
+            Handle::Attack => self.peak,
+            Handle::Decay => self.sustain_start,
+            Handle::Release => self.end,
+            Handle::AttackCurve => Self::curve_handle(self.start, self.peak, shape.attack_curve),
+            Handle::DecayCurve => Self::curve_handle(self.peak, self.sustain_start, shape.decay_curve),
+            Handle::ReleaseCurve => Self::curve_handle(self.sustain_end, self.end, shape.release_curve),
+        }
+    }
+}
+
+// Interactive editor for the stages of an ADSR envelope.
+//
+// The square handles set the stage times (and the sustain level) and the round handles bend each stage.
+pub struct EnvelopeEditor {
+    shape: AdsrShape,
+    // The handle currently being dragged
+    dragging: Option<Handle>,
+    // Local mouse position of the previous move event while dragging
+    prev_x: f32,
+    prev_y: f32,
+}
+
+impl EnvelopeEditor {
+    pub fn new(shape: AdsrShape) -> Self {
+        Self {
+            shape,
+            dragging: None,
+            prev_x: 0.0,
+            prev_y: 0.0,
+        }
+    }
+
+    // Convert a window position into the (untransformed) coordinate space of the widget bounds
+    fn local_position(state: &State, entity: Entity, x: f32, y: f32) -> (f32, f32) {
+        let mut transform = state.data.get_transform(entity);
+        transform.inverse();
+        transform.transform_point(x, y)
+    }
+
+    fn handle_at(&self, bounds: BoundingBox, x: f32, y: f32) -> Option<Handle> {
+        let breakpoints = Breakpoints::new(&self.shape, bounds);
+        [
+            Handle::Attack,
+            Handle::Decay,
+            Handle::Release,
+            Handle::AttackCurve,
+            Handle::DecayCurve,
+            Handle::ReleaseCurve,
+        ]
+        .iter()
+        .cloned()
+        .find(|handle| {
+            let (hx, hy) = breakpoints.handle_position(&self.shape, *handle);
+            (hx - x).abs() <= HANDLE_RADIUS && (hy - y).abs() <= HANDLE_RADIUS
+        })
+    }
+
+    fn drag_handle(&mut self, bounds: BoundingBox, handle: Handle, x: f32, y: f32) {
+        let breakpoints = Breakpoints::new(&self.shape, bounds);
+        // Inverse of the square root scale used to lay out the stages
+        let stage_time = |width: f32| {
+            let ratio = (width / breakpoints.zone_width).clamp(0.0, 1.0);
+            MAX_STAGE_TIME * ratio * ratio
+        };
+        let dy = y - self.prev_y;
+
+        match handle {
+            Handle::Attack => {
+                self.shape.attack = stage_time(x - breakpoints.start.0);
+            }
+
+            Handle::Decay => {
+                self.shape.decay = stage_time(x - breakpoints.peak.0);
+                let level = 1.0 - (y - bounds.y - PADDING) / breakpoints.height;
+                self.shape.sustain = level.clamp(0.0, 1.0);
+            }
+
+            Handle::Release => {
+                self.shape.release = stage_time(x - breakpoints.sustain_end.0);
+            }
+
+            // The attack rises while the other stages fall, so dragging up bends them in opposite directions
+            Handle::AttackCurve => {
+                self.shape.attack_curve = (self.shape.attack_curve + dy * CURVE_DRAG_SPEED).clamp(-1.0, 1.0);
+            }
+
+            Handle::DecayCurve => {
+                self.shape.decay_curve = (self.shape.decay_curve - dy * CURVE_DRAG_SPEED).clamp(-1.0, 1.0);
+            }
+
+            Handle::ReleaseCurve => {
+                self.shape.release_curve = (self.shape.release_curve - dy * CURVE_DRAG_SPEED).clamp(-1.0, 1.0);
+            }
+        }
+    }
+}
+
+impl Widget for EnvelopeEditor {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        entity
+            .set_height(state, Pixels(100.0))
+            .set_space(state, Pixels(5.0))
+            .class(state, "envelope_editor")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(envelope_event) = event.message.downcast() {
+            if let EnvelopeEvent::ParamChanged(index, value) = envelope_event {
+                if event.target == entity {
+                    self.shape.set_param(*index, *value);
+                    state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if event.target == entity && *button == MouseButton::Left {
+                        let (x, y) = Self::local_position(state, entity, state.mouse.cursorx, state.mouse.cursory);
+                        let bounds = state.data.get_bounds(entity);
+                        self.dragging = self.handle_at(bounds, x, y);
+                        if self.dragging.is_some() {
+                            self.prev_x = x;
+                            self.prev_y = y;
+                            state.capture(entity);
+                        }
+                        // Stop the parent node from being moved
+                        event.consume();
+                    }
+                }
+
+                WindowEvent::MouseUp(button) => {
+                    if *button == MouseButton::Left && self.dragging.is_some() {
+                        self.dragging = None;
+                        state.release(entity);
+                    }
+                }
+
+                WindowEvent::MouseMove(x, y) => {
+                    if let Some(handle) = self.dragging {
+                        let (x, y) = Self::local_position(state, entity, *x, *y);
+                        let bounds = state.data.get_bounds(entity);
+                        self.drag_handle(bounds, handle, x, y);
+                        self.prev_x = x;
+                        self.prev_y = y;
+
+                        state.insert_event(Event::new(EnvelopeEvent::ShapeChanged(self.shape)).target(entity).origin(entity));
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(entity);
+        let transform = state.data.get_transform(entity);
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        let mut path = Path::new();
+        path.rounded_rect(bounds.x, bounds.y, bounds.w, bounds.h, 3.0);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        let breakpoints = Breakpoints::new(&self.shape, bounds);
+
+        let mut path = Path::new();
+        path.move_to(breakpoints.start.0, breakpoints.start.1);
+        let stages = [
+            (breakpoints.start, breakpoints.peak, self.shape.attack_curve),
+            (breakpoints.peak, breakpoints.sustain_start, self.shape.decay_curve),
+            (breakpoints.sustain_start, breakpoints.sustain_end, 0.0),
+            (breakpoints.sustain_end, breakpoints.end, self.shape.release_curve),
+        ];
+        for (from, to, curvature) in stages.iter() {
+            for i in 1..=CURVE_SEGMENTS {
+                let t = i as f32 / CURVE_SEGMENTS as f32;
+                let x = from.0 + (to.0 - from.0) * t;
+                let y = from.1 + (to.1 - from.1) * shape_curve(t, *curvature);
+                path.line_to(x, y);
+            }
+        }
+        let mut paint = Paint::color(femtovg::Color::rgb(200, 200, 200));
+        paint.set_line_width(2.0);
+        canvas.stroke_path(&mut path, paint);
+
+        for handle in [Handle::Attack, Handle::Decay, Handle::Release].iter() {
+            let (x, y) = breakpoints.handle_position(&self.shape, *handle);
+            let mut path = Path::new();
+            path.rect(x - 3.0, y - 3.0, 6.0, 6.0);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(0, 160, 0)));
+        }
+
+        for handle in [Handle::AttackCurve, Handle::DecayCurve, Handle::ReleaseCurve].iter() {
+            let (x, y) = breakpoints.handle_position(&self.shape, *handle);
+            let mut path = Path::new();
+            path.circle(x, y, 3.0);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(120, 120, 120)));
+        }
+
+        canvas.restore();
+    }
+}
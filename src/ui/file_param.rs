@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl,
+};
+
+use super::waveform_view::*;
+
+use crate::audio::{is_midi_file, AudioFile, MidiFile, PendingAudioFile, DEFAULT_SAMPLE_RATE};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileParamEvent {
+    // Sent up the tree with the parameter index once a file has been decoded and resampled
+    FileLoaded(usize, Arc<AudioFile>),
+    // Sent up the tree with the parameter index once a MIDI file has been parsed
+    MidiFileLoaded(usize, Arc<MidiFile>),
+}
+
+// Parameter row which shows the name of the current file and opens a file dialog when clicked.
+//
+// Audio files are loaded on a background thread and the row shows the progress while it waits.
+// MIDI files are small enough to be loaded straight away.
+pub struct FileParam {
+    index: usize,
+    name: String,
+    extensions: &'static [&'static str],
+    // File to load when the widget is built
+    path: Option<PathBuf>,
+    pending: Option<PendingAudioFile>,
+    // Waveform view which is sent the file once it has loaded
+    preview: Entity,
+
+    file_label: Entity,
+}
+
+impl FileParam {
+    pub fn new(index: usize, name: &str, extensions: &'static [&'static str]) -> Self {
+        Self {
+            index,
+            name: name.to_string(),
+            extensions,
+            path: None,
+            pending: None,
+            preview: Entity::null(),
+
+            file_label: Entity::null(),
+        }
+    }
+
+    // Start loading a file as soon as the widget is built
+    pub fn with_file(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    // Show the loaded file in a waveform view
+    pub fn with_preview(mut self, preview: Entity) -> Self {
+        self.preview = preview;
+        self
+    }
+
+    fn start_loading(&mut self, state: &mut State, entity: Entity, path: PathBuf) {
+        if is_midi_file(&path) {
+            match MidiFile::load(&path) {
+                Ok(file) => {
+                    self.file_label
+                        .set_text(state, &file.file_name())
+                        .set_color(state, Color::white());
+                    state.insert_event(Event::new(FileParamEvent::MidiFileLoaded(self.index, Arc::new(file))).target(entity).origin(entity));
+                }
+
+                Err(error) => {
+                    self.file_label
+                        .set_text(state, &error.to_string())
+                        .set_color(state, Color::rgb(220, 60, 60));
+                }
+            }
+            state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+            return;
+        }
+
+        self.file_label
+            .set_text(state, "Loading...")
+            .set_color(state, Color::rgb(150, 150, 150));
+        self.pending = Some(AudioFile::load_in_background(path, DEFAULT_SAMPLE_RATE));
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+}
+
+impl Widget for FileParam {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        Label::new(&self.name).build(state, entity, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        self.file_label = Label::new("Load...").build(state, entity, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(5.0))
+                .set_space(Pixels(0.0))
+                .set_right(Pixels(5.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_hoverable(false)
+                .class("file_param")
+        );
+
+        if let Some(path) = self.path.take() {
+            self.start_loading(state, entity, path);
+        }
+
+        entity
+            .set_height(state, Pixels(30.0))
+            .set_layout_type(state, LayoutType::Row)
+            .set_child_space(state, Stretch(1.0))
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if event.target == entity && *button == MouseButton::Left {
+                        let picked = rfd::FileDialog::new()
+                            .add_filter(&self.name, self.extensions)
+                            .pick_file();
+
+                        if let Some(path) = picked {
+                            self.start_loading(state, entity, path);
+                        }
+
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    // The row has nothing of its own to draw, so drawing is only used to check on a file being loaded
+    fn on_draw(&mut self, state: &mut State, entity: Entity, _canvas: &mut Canvas<OpenGl>) {
+        let result = match self.pending.as_ref() {
+            Some(pending) => pending.poll(),
+            None => return,
+        };
+
+        match result {
+            Some(Ok(file)) => {
+                let file = Arc::new(file);
+                self.file_label
+                    .set_text(state, &file.file_name())
+                    .set_color(state, Color::white());
+
+                if self.preview != Entity::null() {
+                    state.insert_event(Event::new(WaveformEvent::SetFile(file.clone())).direct(self.preview).origin(entity));
+                }
+                state.insert_event(Event::new(FileParamEvent::FileLoaded(self.index, file)).target(entity).origin(entity));
+                self.pending = None;
+            }
+
+            Some(Err(error)) => {
+                self.file_label
+                    .set_text(state, &error.to_string())
+                    .set_color(state, Color::rgb(220, 60, 60));
+                self.pending = None;
+            }
+
+            None => {}
+        }
+
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+}
@@ -0,0 +1,111 @@
+use tuix::*;
+
+use crate::audio::ParamInfo;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FloatParamEvent {
+    // Sent up the tree with the parameter index and the new value when the value is edited.
+    // Sent by every widget editing a float parameter, always with a value within the range of the parameter.
+    ValueChanged(usize, f32),
+    // Sent directly to a widget editing a float parameter to show a value set somewhere else, such as in the
+    // inspector. The widget doesn't send `ValueChanged` for it.
+    SetValue(usize, f32),
+    // Sent up the tree with the parameter index when the parameter is right clicked, to show its menu
+    ContextMenu(usize),
+}
+
+// Parameter with a label and a textbox holding a number and its unit, which is kept within the range of the parameter
+pub struct FloatParam {
+    index: usize,
+    info: ParamInfo,
+    value: f32,
+
+    textbox: Entity,
+}
+
+impl FloatParam {
+    pub fn new(index: usize, info: &ParamInfo, value: f32) -> Self {
+        Self {
+            index,
+            info: info.clone(),
+            value,
+
+            textbox: Entity::null(),
+        }
+    }
+}
+
+impl Widget for FloatParam {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        Label::new(self.info.name).build(state, entity, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        self.textbox = Textbox::new(&self.info.format(self.value)).build(state, entity, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(5.0))
+                .set_space(Pixels(0.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_right(Pixels(5.0))
+                .set_color(Color::white())
+                .set_opacity(1.0)
+        );
+
+        entity
+            .set_height(state, Pixels(30.0))
+            .set_child_space(state, Stretch(1.0))
+            .set_layout_type(state, LayoutType::Row)
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(MouseButton::Right) => {
+                    state.insert_event(Event::new(FloatParamEvent::ContextMenu(self.index)).target(entity).origin(entity));
+                    event.consume();
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(param_event) = event.message.downcast() {
+            match param_event {
+                FloatParamEvent::SetValue(index, value) => {
+                    if *index == self.index {
+                        self.value = *value;
+                        self.textbox.set_text(state, &self.info.format(self.value));
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(textbox_event) = event.message.downcast() {
+            match textbox_event {
+                TextboxEvent::ValueChanged(text) => {
+                    if event.origin == self.textbox {
+                        // Text which isn't a valid value puts back the last value
+                        if let Some(value) = self.info.parse(text) {
+                            self.value = value;
+                            state.insert_event(Event::new(FloatParamEvent::ValueChanged(self.index, self.value)).target(entity).origin(entity));
+                        }
+                        self.textbox.set_text(state, &self.info.format(self.value));
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use tuix::*;
+
+// Width of the inspector panel
+const INSPECTOR_WIDTH: f32 = 260.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DockSide {
+    Left,
+    Right,
+}
+
+// Panel docked to one side of the node view, showing every setting of the selected node.
+//
+// Building the inspector returns the container its contents are added to. The buttons in its header move it
+// to the other side.
+pub struct Inspector {
+    side: DockSide,
+    left_button: Entity,
+    right_button: Entity,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self {
+            side: DockSide::Right,
+            left_button: Entity::null(),
+            right_button: Entity::null(),
+        }
+    }
+
+    fn dock(&mut self, state: &mut State, entity: Entity, side: DockSide) {
+        self.side = side;
+        match side {
+            DockSide::Left => entity.set_left(state, Pixels(0.0)).set_right(state, Stretch(1.0)),
+            DockSide::Right => entity.set_left(state, Stretch(1.0)).set_right(state, Pixels(0.0)),
+        };
+    }
+
+    fn add_button(state: &mut State, parent: Entity, text: &str) -> Entity {
+        Label::new(text).build(state, parent, |builder|
+            builder
+                .set_width(Pixels(45.0))
+                .set_child_space(Stretch(1.0))
+                .class("inspector_button")
+        )
+    }
+}
+
+impl Widget for Inspector {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        let header = Row::new().build(state, entity, |builder|
+            builder
+                .set_height(Pixels(25.0))
+                .class("inspector_header")
+        );
+
+        Label::new("Inspector").build(state, header, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(10.0))
+                .set_hoverable(false)
+        );
+
+        self.left_button = Self::add_button(state, header, "Left");
+        self.right_button = Self::add_button(state, header, "Right");
+
+        let content = Column::new().build(state, entity, |builder|
+            builder
+                .set_height(Auto)
+        );
+
+        entity
+            .set_width(state, Pixels(INSPECTOR_WIDTH))
+            .set_height(state, Stretch(1.0))
+            .set_position_type(state, PositionType::SelfDirected)
+            .set_z_order(state, 5)
+            .class(state, "inspector");
+        self.dock(state, entity, self.side);
+
+        content
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left {
+                        if event.target == self.left_button {
+                            self.dock(state, entity, DockSide::Left);
+                            event.consume();
+                        }
+
+                        if event.target == self.right_button {
+                            self.dock(state, entity, DockSide::Right);
+                            event.consume();
+                        }
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::Meter;
+
+// Gain reduction shown by a full meter, in dB
+const METER_RANGE: f32 = 24.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeterEvent {
+    // Sent to a gain reduction meter or a modulated parameter control when the engine builds a new graph, with the
+    // meter of the node playing in it
+    SetMeter(Meter),
+}
+
+// Horizontal bar showing the gain reduction published by a dynamics node.
+//
+// The bar grows from the right edge as the gain is reduced.
+pub struct GainReductionMeter {
+    meter: Meter,
+    // Value drawn in the previous frame, used to decide if another frame is needed
+    prev_value: f32,
+}
+
+impl GainReductionMeter {
+    pub fn new(meter: Meter) -> Self {
+        Self {
+            meter,
+            prev_value: 0.0,
+        }
+    }
+}
+
+impl Widget for GainReductionMeter {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        entity
+            .set_height(state, Pixels(8.0))
+            .set_space(state, Pixels(5.0))
+            .set_hoverable(state, false)
+            .class(state, "gain_reduction_meter")
+    }
+
+    fn on_event(&mut self, state: &mut State, _entity: Entity, event: &mut Event) {
+        if let Some(meter_event) = event.message.downcast() {
+            match meter_event {
+                MeterEvent::SetMeter(meter) => {
+                    self.meter = meter.clone();
+                    state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    event.consume();
+                }
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(entity);
+        let transform = state.data.get_transform(entity);
+        let value = self.meter.get();
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        let mut path = Path::new();
+        path.rect(bounds.x, bounds.y, bounds.w, bounds.h);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        let width = bounds.w * (value / METER_RANGE).clamp(0.0, 1.0);
+        let mut path = Path::new();
+        path.rect(bounds.x + bounds.w - width, bounds.y, width, bounds.h);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(220, 140, 40)));
+
+        canvas.restore();
+
+        // Keep redrawing while the meter is moving
+        if value != self.prev_value || value > 0.0 {
+            state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+        }
+        self.prev_value = value;
+    }
+}
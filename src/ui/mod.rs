@@ -7,8 +7,61 @@ pub use node_widget::*;
 pub mod socket_widget;
 pub use socket_widget::*;
 
+pub mod envelope_editor;
+pub use envelope_editor::*;
+
+pub mod file_param;
+pub use file_param::*;
+
+pub mod waveform_view;
+pub use waveform_view::*;
+
+pub mod meter_widget;
+pub use meter_widget::*;
+
+pub mod curve_editor;
+pub use curve_editor::*;
+
+pub mod step_grid;
+pub use step_grid::*;
+
+pub mod text_param;
+pub use text_param::*;
+
+pub mod float_param;
+pub use float_param::*;
+
+pub mod param_control;
+pub use param_control::*;
+
+pub mod param_toggle;
+pub use param_toggle::*;
+
+pub mod param_choice;
+pub use param_choice::*;
+
+pub mod transport_bar;
+pub use transport_bar::*;
+
+pub mod subpatch_button;
+pub use subpatch_button::*;
+
+pub mod scope_view;
+pub use scope_view::*;
+
+pub mod monitor_view;
+pub use monitor_view::*;
+
+pub mod inspector;
+pub use inspector::*;
+
+pub mod engine_host;
+pub use engine_host::*;
+
 use tuix::*;
 
+use crate::audio::NodeMonitor;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum NodeEvent {
     TrySnap(Entity, Entity),
@@ -19,4 +72,23 @@ pub enum NodeEvent {
     Snap(Entity, Entity),
     Connecting,
     Disconnect,
-}
\ No newline at end of file
+    // Sent up the tree by an input socket when a wire from an output socket is connected to it or pulled off it,
+    // with the output socket and the input socket
+    Connected(Entity, Entity),
+    Disconnected(Entity, Entity),
+    // Sent to an input socket to mark its wire as a feedback connection, which is drawn differently
+    Feedback(bool),
+    // Sent to an input socket with the number of channels carried by its wire, which is drawn thicker for more channels
+    Channels(usize),
+    // Sent to an input socket when a wire dragged to it is refused, which flashes the socket red
+    Rejected,
+    // Sent to an input socket with the monitor of the node its wire comes from and the output port,
+    // so the wire shows the level of its signal while audio is running
+    Levels(Option<NodeMonitor>, usize),
+    // Sent to an input socket to show or hide the signal flowing along its wire
+    Flow(bool),
+    // Sent up the tree by an output socket when it is alt-clicked, to hear its signal on its own
+    Probe,
+    // Sent to an output socket to mark it as the one being heard
+    Probed(bool),
+}
@@ -0,0 +1,133 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::NodeMonitor;
+
+// Lowest level shown by the level bars, in dB
+const LEVEL_FLOOR_DB: f32 = -60.0;
+
+// Position from 0 to 1 of a level along a level bar
+pub fn level_position(level: f32) -> f32 {
+    if level <= 0.0 {
+        return 0.0;
+    }
+
+    let db = 20.0 * level.log10();
+    (1.0 - db / LEVEL_FLOOR_DB).clamp(0.0, 1.0)
+}
+
+// Processing time and output levels of a node while audio is running.
+//
+// Each output channel has a bar showing its RMS level, with a line at its peak. The bar turns red when the channel clips.
+pub struct MonitorView {
+    monitor: NodeMonitor,
+    cpu_label: Entity,
+    cpu_bar: Entity,
+    level_bars: Vec<Entity>,
+    // Values last drawn, to redraw only while they change
+    prev_cpu_load: f32,
+    prev_peaks: Vec<f32>,
+}
+
+impl MonitorView {
+    pub fn new(monitor: NodeMonitor) -> Self {
+        Self {
+            prev_peaks: vec![0.0; monitor.num_outputs()],
+            monitor,
+            cpu_label: Entity::null(),
+            cpu_bar: Entity::null(),
+            level_bars: Vec::new(),
+            prev_cpu_load: 0.0,
+        }
+    }
+
+    fn add_row(state: &mut State, entity: Entity, name: &str) -> (Entity, Entity) {
+        let row = Row::new().build(state, entity, |builder|
+            builder
+                .set_height(Pixels(20.0))
+                .set_child_space(Stretch(1.0))
+        );
+
+        let label = Label::new(name).build(state, row, |builder|
+            builder
+                .set_width(Pixels(90.0))
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        let bar = Element::new().build(state, row, |builder|
+            builder
+                .set_height(Pixels(8.0))
+                .set_right(Pixels(10.0))
+                .set_hoverable(false)
+        );
+
+        (label, bar)
+    }
+
+    fn draw_bar(canvas: &mut Canvas<OpenGl>, bounds: BoundingBox, position: f32, color: femtovg::Color) {
+        let mut path = Path::new();
+        path.rect(bounds.x, bounds.y, bounds.w, bounds.h);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        let mut path = Path::new();
+        path.rect(bounds.x, bounds.y, bounds.w * position, bounds.h);
+        canvas.fill_path(&mut path, Paint::color(color));
+    }
+}
+
+impl Widget for MonitorView {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        let (cpu_label, cpu_bar) = Self::add_row(state, entity, "CPU");
+        self.cpu_label = cpu_label;
+        self.cpu_bar = cpu_bar;
+
+        for channel in 0..self.monitor.num_outputs() {
+            let (_, bar) = Self::add_row(state, entity, &format!("Out {}", channel + 1));
+            self.level_bars.push(bar);
+        }
+
+        entity.set_height(state, Auto)
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let transform = state.data.get_transform(entity);
+        let cpu_load = self.monitor.cpu_load();
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        Self::draw_bar(canvas, state.data.get_bounds(self.cpu_bar), cpu_load.min(1.0), femtovg::Color::rgb(80, 160, 220));
+
+        let mut changed = cpu_load != self.prev_cpu_load;
+        for (channel, bar) in self.level_bars.iter().enumerate() {
+            let bounds = state.data.get_bounds(*bar);
+            let peak = self.monitor.peak(channel);
+            let color = if self.monitor.clipping(channel) { femtovg::Color::rgb(220, 60, 60) } else { femtovg::Color::rgb(80, 200, 100) };
+            Self::draw_bar(canvas, bounds, level_position(self.monitor.rms(channel)), color);
+
+            let x = bounds.x + bounds.w * level_position(peak);
+            let mut path = Path::new();
+            path.rect(x - 1.0, bounds.y, 2.0, bounds.h);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(200, 200, 200)));
+
+            changed |= peak != self.prev_peaks[channel];
+            self.prev_peaks[channel] = peak;
+        }
+
+        canvas.restore();
+
+        // Keep redrawing while audio is running
+        if changed {
+            self.cpu_label.set_text(state, &format!("CPU {:.1}%", cpu_load * 100.0));
+            state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+        }
+        self.prev_cpu_load = cpu_load;
+    }
+}
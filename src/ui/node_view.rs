@@ -1,9 +1,92 @@
-
+use std::path::Path;
+use std::time::Instant;
 
 use tuix::*;
 
+use super::NodeEvent;
 use super::node_widget::*;
 use super::socket_widget::*;
+use super::meter_widget::*;
+use super::envelope_editor::*;
+use super::file_param::*;
+use super::curve_editor::*;
+use super::step_grid::*;
+use super::waveform_view::*;
+use super::text_param::*;
+use super::float_param::*;
+use super::param_control::*;
+use super::subpatch_button::*;
+use super::scope_view::*;
+use super::monitor_view::*;
+use super::inspector::*;
+
+use crate::audio::{
+    can_modulate, collapse_into_group, creates_cycle, library_assets, save_library_asset, AudioNode, Connection, Group, LibraryAsset, NodeMonitor,
+    NodePath, NodeRegistry, ParamChange, Patch, PatchNode, PortInfo, PortKind, LIBRARY_DIRECTORY, MAX_PATH_DEPTH, PATCH_EXTENSION,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchEvent {
+    // Sent up the tree when a parameter is edited, timestamped so the engine can apply it at the right frame
+    ParamChanged(ParamChange),
+    // Sent up the tree when an output is probed, with the path to its node through any groups being edited
+    // and the output port, for the engine to hear it in place of the output node. None goes back to normal routing.
+    Probe(Option<(NodePath, usize)>),
+    // Sent up the tree with the top level patch when nodes or wires are added, removed or rebuilt, for the engine
+    // to build into a new graph. Parameter edits are sent on their own with `ParamChanged`.
+    Changed(Patch),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    // Sent to the node view by the engine when it builds the graph of the patch, with the monitor of each node
+    // in the top level patch by its index, for the inspector to show
+    Monitors(Vec<Option<NodeMonitor>>),
+    // Sent to the node view by the engine when the patch can't be played, to show in the error bar
+    Error(String),
+}
+
+// Furthest distance from a wire a click can be to pick it, in pixels
+const WIRE_PICK_DISTANCE: f32 = 6.0;
+
+// A node widget in the canvas
+struct CanvasNode {
+    entity: Entity,
+    // Settings saved with the patch
+    patch_node: PatchNode,
+    sockets: NodeSockets,
+    // Ports of the node when its widget was built
+    inputs: Vec<PortInfo>,
+    outputs: Vec<PortInfo>,
+    // Scope strip shown at the bottom of the node
+    scope: Option<Entity>,
+    // Editor of the breakpoints of an envelope node
+    envelope: Option<Entity>,
+}
+
+// A patch containing the patch being edited, kept while the node view shows the inner patch
+struct ParentPatch {
+    // Hidden canvas holding the widgets of the patch
+    canvas: Entity,
+    nodes: Vec<CanvasNode>,
+    connections: Vec<Connection>,
+    // Index in `nodes` of the node whose patch is being edited
+    node: usize,
+    translate_x: f32,
+    translate_y: f32,
+    scale: f64,
+}
+
+// Title shown on the widget of a node, which is the name of a group or otherwise the type of node
+fn node_title(patch_node: &PatchNode) -> String {
+    if patch_node.kind == "Group" {
+        if let Some((_, name)) = patch_node.texts.iter().find(|(index, _)| *index == Group::NAME) {
+            return name.clone();
+        }
+    }
+
+    patch_node.kind.clone()
+}
 
 pub struct NodeView {
     translate_x: f32,
@@ -15,6 +98,54 @@ pub struct NodeView {
     panning: bool,
 
     canvas: Entity,
+
+    registry: NodeRegistry,
+    // Popup listing the nodes which can be added
+    menu: Entity,
+    // Menu entries and the name of the node each one adds
+    menu_items: Vec<(Entity, &'static str)>,
+    // Menu entries for the patches saved in the library
+    library_items: Vec<(Entity, LibraryAsset)>,
+    // Position in the canvas where a node picked from the menu is placed
+    menu_x: f32,
+    menu_y: f32,
+    // Popup shown when a parameter is right clicked, with an entry which exposes the parameter as a modulation input
+    // or removes its input, and the index of the node and the parameter it was opened for
+    param_menu: Entity,
+    param_menu_item: Entity,
+    param_menu_target: Option<(usize, usize)>,
+
+    // Node widgets in the canvas and the settings saved with the patch for each one
+    nodes: Vec<CanvasNode>,
+    // Wires between the nodes, identified by their index in `nodes`
+    connections: Vec<Connection>,
+    // Node widgets which are selected, e.g. to be grouped
+    selection: Vec<Entity>,
+
+    // Patches containing the patch being edited, outermost first
+    parents: Vec<ParentPatch>,
+    // Bar showing the path to the patch being edited, hidden at the top level
+    breadcrumbs: Entity,
+    // Labels in the bar, one for each level, clicked to go back to that level
+    crumbs: Vec<Entity>,
+    // Bar along the bottom showing the last problem, hidden until there is one. Clicking it hides it again.
+    error_bar: Entity,
+
+    // Panel showing the selected node, the container its contents are added to, and the contents for the current node
+    inspector: Entity,
+    inspector_content: Entity,
+    inspector_body: Entity,
+    // Index of the node shown in the inspector
+    inspected: Option<usize>,
+    // Controls in the inspector, which are kept in step with the node widget as parameters are edited
+    inspector_params: ParamWidgets,
+    // Processing time and output levels of the nodes in the top level patch while audio is running
+    monitors: Vec<Option<NodeMonitor>>,
+    inspector_visible: bool,
+    // Whether wires show their signal flowing from output to input
+    show_flow: bool,
+    // Node and output port heard on their own in the patch being edited
+    probe: Option<(usize, usize)>,
 }
 
 impl NodeView {
@@ -29,158 +160,1029 @@ impl NodeView {
             panning: false,
 
             canvas: Entity::null(),
-        }
-    }
-}
 
-impl Widget for NodeView {
-    type Ret = Entity;
-    type Data = ();
+            registry: NodeRegistry::with_builtin_nodes(),
+            menu: Entity::null(),
+            menu_items: Vec::new(),
+            library_items: Vec::new(),
+            menu_x: 0.0,
+            menu_y: 0.0,
+            param_menu: Entity::null(),
+            param_menu_item: Entity::null(),
+            param_menu_target: None,
 
-    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+            nodes: Vec::new(),
+            connections: Vec::new(),
+            selection: Vec::new(),
+
+            parents: Vec::new(),
+            breadcrumbs: Entity::null(),
+            crumbs: Vec::new(),
+            error_bar: Entity::null(),
+
+            inspector: Entity::null(),
+            inspector_content: Entity::null(),
+            inspector_body: Entity::null(),
+            inspected: None,
+            inspector_params: ParamWidgets::default(),
+            monitors: Vec::new(),
+            inspector_visible: true,
+            show_flow: false,
+            probe: None,
+        }
+    }
 
-        self.canvas = Element::new().build(state, entity, |builder| 
+    fn build_canvas(state: &mut State, entity: Entity) -> Entity {
+        Element::new().build(state, entity, |builder| 
             builder
             .set_clip_widget(entity)
             //.set_background_color(Color::rgb(50,50,200))
-        );
+        )
+    }
 
-        let sine = NodeWidget::new("Sine").build(state, self.canvas, |builder| 
+    fn build_breadcrumbs(&mut self, state: &mut State, entity: Entity) {
+        self.breadcrumbs = Row::new().build(state, entity, |builder| 
             builder
+                .set_height(Pixels(25.0))
+                .set_position_type(PositionType::SelfDirected)
+                .set_display(Display::None)
+                .set_z_order(10)
+                .class("breadcrumbs")
         );
+    }
 
-        let row = Row::new().build(state, sine, |builder| 
-            builder
-                .set_height(Pixels(30.0))
-                .set_child_space(Stretch(1.0))
-        );
+    // Show a label for the top level patch and for each node whose patch has been opened
+    fn update_breadcrumbs(&mut self, state: &mut State) {
+        for crumb in self.crumbs.drain(..) {
+            state.remove(crumb);
+        }
 
-        Label::new("Out").build(state, row, |builder| 
-            builder
-                .set_child_space(Stretch(1.0))
-                .set_child_right(Pixels(5.0))
-                .set_space(Pixels(0.0))
-                .set_hoverable(false)
-        );
+        if self.parents.is_empty() {
+            self.breadcrumbs.set_display(state, Display::None);
+            return;
+        }
 
-        OutputSocket::new().build(state, row, |builder| 
-            builder
-                .set_left(Stretch(0.0))
-                .set_right(Pixels(-10.0))
-        );
+        let mut names = vec![String::from("Patch")];
+        for parent in self.parents.iter() {
+            names.push(format!("> {}", node_title(&parent.nodes[parent.node].patch_node)));
+        }
 
-        let row = Row::new().build(state, sine, |builder| 
-            builder
-                .set_height(Pixels(30.0))
-                .set_child_space(Stretch(1.0))
-        );
-    
-        InputSocket::new().build(state, row, |builder| 
-            builder
-                .set_left(Pixels(-10.0))
-                .set_right(Stretch(0.0))
-        );
-    
-        Label::new("Freq").build(state, row, |builder| 
-            builder
-                .set_child_space(Stretch(1.0))
-                .set_child_left(Pixels(5.0))
-                .set_space(Pixels(0.0))
-                .set_hoverable(false)
-        );
+        for name in names.iter() {
+            let crumb = Label::new(name).build(state, self.breadcrumbs, |builder| 
+                builder
+                    .set_width(Pixels(100.0))
+                    .set_child_space(Stretch(1.0))
+                    .set_child_left(Pixels(5.0))
+                    .class("breadcrumb")
+            );
+            self.crumbs.push(crumb);
+        }
+
+        self.breadcrumbs.set_display(state, Display::Flex);
+    }
 
-        Textbox::new("440").build(state, row, |builder| 
+    fn build_error_bar(&mut self, state: &mut State, entity: Entity) {
+        self.error_bar = Label::new("").build(state, entity, |builder| 
             builder
+                .set_height(Pixels(25.0))
+                .set_top(Stretch(1.0))
                 .set_child_space(Stretch(1.0))
-                .set_child_left(Pixels(5.0))
-                .set_space(Pixels(0.0))
-                .set_background_color(Color::rgb(15, 15, 15))
-                .set_right(Pixels(5.0))
-                .set_color(Color::white())
-                .set_opacity(1.0)
+                .set_child_left(Pixels(10.0))
+                .set_position_type(PositionType::SelfDirected)
+                .set_display(Display::None)
+                .set_z_order(10)
+                .class("error_bar")
         );
+    }
 
-        let amplify = NodeWidget::new("Amplify").build(state, self.canvas, |builder| 
-            builder
-                .set_left(Pixels(200.0))
-                .set_top(Pixels(200.0))
-        );
+    // Show a problem which doesn't belong to a node, such as a patch which failed to open
+    fn show_error(&self, state: &mut State, text: &str) {
+        self.error_bar.set_text(state, text);
+        self.error_bar.set_display(state, Display::Flex);
+    }
 
-        let row = Row::new().build(state, amplify, |builder| 
+    fn build_menu(&mut self, state: &mut State, entity: Entity) {
+        self.menu = Element::new().build(state, entity, |builder| 
             builder
-                .set_height(Pixels(30.0))
-                .set_child_space(Stretch(1.0))
+                .set_width(Pixels(150.0))
+                .set_height(Auto)
+                .set_position_type(PositionType::SelfDirected)
+                .set_display(Display::None)
+                .set_z_order(10)
+                .class("node_menu")
         );
-    
-        InputSocket::new().build(state, row, |builder| 
+
+        for descriptor in self.registry.descriptors() {
+            let item = Self::add_menu_item(state, self.menu, descriptor.name);
+            self.menu_items.push((item, descriptor.name));
+        }
+
+        for asset in library_assets(Path::new(LIBRARY_DIRECTORY)) {
+            self.add_library_item(state, asset);
+        }
+    }
+
+    fn build_param_menu(&mut self, state: &mut State, entity: Entity) {
+        self.param_menu = Element::new().build(state, entity, |builder| 
             builder
-                .set_left(Pixels(-10.0))
-                .set_right(Stretch(0.0))
+                .set_width(Pixels(150.0))
+                .set_height(Auto)
+                .set_position_type(PositionType::SelfDirected)
+                .set_display(Display::None)
+                .set_z_order(10)
+                .class("node_menu")
         );
-    
-        Label::new("In").build(state, row, |builder| 
+
+        self.param_menu_item = Self::add_menu_item(state, self.param_menu, "");
+    }
+
+    fn add_menu_item(state: &mut State, menu: Entity, text: &str) -> Entity {
+        Label::new(text).build(state, menu, |builder| 
             builder
+                .set_height(Pixels(25.0))
                 .set_child_space(Stretch(1.0))
                 .set_child_left(Pixels(5.0))
-                .set_space(Pixels(0.0))
-                .set_hoverable(false)
-        );
+                .class("node_menu_item")
+        )
+    }
 
-        let row = Row::new().build(state, amplify, |builder| 
-            builder
-                .set_height(Pixels(30.0))
-                .set_child_space(Stretch(1.0))
-        );
+    // Add a menu entry for a library patch, replacing any entry with the same name
+    fn add_library_item(&mut self, state: &mut State, asset: LibraryAsset) {
+        if let Some(index) = self.library_items.iter().position(|(_, existing)| existing.name == asset.name) {
+            let (item, _) = self.library_items.remove(index);
+            state.remove(item);
+        }
 
-        Label::new("Out").build(state, row, |builder| 
-            builder
-                .set_child_space(Stretch(1.0))
-                .set_child_right(Pixels(5.0))
-                .set_space(Pixels(0.0))
-                .set_hoverable(false)
-        );
+        let item = Self::add_menu_item(state, self.menu, &format!("{} (library)", asset.name));
+        self.library_items.push((item, asset));
+    }
+
+    // Convert a window position into a position in the canvas, where nodes are placed
+    fn canvas_position(&self, state: &State, x: f32, y: f32) -> (f32, f32) {
+        let mut transform = state.data.get_transform(self.canvas);
+        transform.inverse();
+        let (cx, cy) = transform.transform_point(x, y);
+        (cx - state.data.get_posx(self.canvas), cy - state.data.get_posy(self.canvas))
+    }
+
+    // Show the node menu at the cursor
+    fn open_menu(&mut self, state: &mut State, entity: Entity) {
+        let (x, y) = (state.mouse.cursorx, state.mouse.cursory);
+
+        let (menu_x, menu_y) = self.canvas_position(state, x, y);
+        self.menu_x = menu_x;
+        self.menu_y = menu_y;
+
+        self.menu
+            .set_left(state, Pixels(x - state.data.get_posx(entity)))
+            .set_top(state, Pixels(y - state.data.get_posy(entity)))
+            .set_display(state, Display::Flex);
+    }
+
+    fn close_menu(&mut self, state: &mut State) {
+        self.menu.set_display(state, Display::None);
+        self.param_menu.set_display(state, Display::None);
+        self.param_menu_target = None;
+    }
+
+    // Show the menu of a parameter at the cursor
+    fn open_param_menu(&mut self, state: &mut State, entity: Entity, node_index: usize, param: usize) {
+        let modulated = self.nodes[node_index].patch_node.modulations.iter().any(|modulation| modulation.param == param);
+        let text = if modulated { "Remove input" } else { "Expose as input" };
+        self.param_menu_item.set_text(state, text);
+        self.param_menu_target = Some((node_index, param));
+
+        let (x, y) = (state.mouse.cursorx, state.mouse.cursory);
+        self.param_menu
+            .set_left(state, Pixels(x - state.data.get_posx(entity)))
+            .set_top(state, Pixels(y - state.data.get_posy(entity)))
+            .set_display(state, Display::Flex);
+    }
+
+    // Add a modulation input for a parameter, or remove its input, and rebuild the node with its new sockets.
+    //
+    // Modulation inputs follow the inputs of the node, so wires to the inputs after a removed one move down.
+    fn toggle_modulation(&mut self, state: &mut State, node_index: usize, param: usize) {
+        let num_inputs = self.nodes[node_index].inputs.len();
+        let patch_node = &mut self.nodes[node_index].patch_node;
+        let first_modulation = num_inputs - patch_node.modulations.len();
+        let was_modulated = patch_node.modulations.iter().any(|modulation| modulation.param == param);
+        let position = patch_node.toggle_modulation(param);
+
+        if was_modulated {
+            let removed = first_modulation + position;
+            let connections: Vec<Connection> = self
+                .connections
+                .iter()
+                .filter(|connection| connection.to == node_index && connection.input >= removed)
+                .copied()
+                .collect();
+            for connection in connections {
+                self.disconnect_wire(state, connection);
+                self.connections.retain(|existing| *existing != connection);
+                if connection.input > removed {
+                    self.connections.push(Connection {
+                        input: connection.input - 1,
+                        ..connection
+                    });
+                }
+            }
+        }
+
+        self.rebuild_node(state, node_index);
+        if self.inspected == Some(node_index) {
+            self.update_inspector(state);
+        }
+        self.patch_changed(state);
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+
+    // Add a node from the registry to the canvas at the position the menu was opened
+    fn add_node(&mut self, state: &mut State, name: &str) -> Option<Entity> {
+        let node = self.registry.create(name)?;
+        let patch_node = PatchNode::new(name, self.menu_x, self.menu_y, node.as_ref());
+
+        let index = self.push_node(state, patch_node, node.as_ref());
+        self.patch_changed(state);
+        Some(self.nodes[index].entity)
+    }
 
-        OutputSocket::new().build(state, row, |builder| 
+    // Build the widget for the node at an index of the patch
+    fn build_node(&mut self, state: &mut State, index: usize, patch_node: PatchNode, node: &dyn AudioNode) -> CanvasNode {
+        let (x, y) = (patch_node.x, patch_node.y);
+        let mut node_widget = NodeWidget::new(&node_title(&patch_node));
+        let info = node.editor_info();
+        // Scope nodes always show their scope
+        let settings = info.and_then(|info| info.scope());
+        if settings.is_some() {
+            node_widget = node_widget.without_scope_button();
+        }
+        let container = node_widget.build(state, self.canvas, |builder| 
             builder
-                .set_left(Stretch(0.0))
-                .set_right(Pixels(-10.0))
+                .set_left(Pixels(x))
+                .set_top(Pixels(y))
         );
+        let monitor = self.monitor(index);
+        let sockets = NodeWidget::add_audio_node(state, container, node, &patch_node.files, monitor.as_ref());
+
+        let envelope = info
+            .and_then(|info| info.envelope_shape())
+            .map(|shape| EnvelopeEditor::new(shape).build(state, container, |builder| builder));
+
+        let mut scope = None;
+        if patch_node.show_scope || settings.is_some() {
+            let tap = monitor.as_ref().and_then(|monitor| monitor.scope());
+            let mut scope_view = ScopeView::new(settings.unwrap_or_default()).with_tap(tap);
+            if settings.is_some() {
+                scope_view = scope_view.following_params();
+            }
+            scope = Some(scope_view.build(state, container, |builder| builder));
+        }
+
+        CanvasNode {
+            entity: container.get_parent(state).unwrap(),
+            patch_node,
+            sockets,
+            inputs: node.inputs().to_vec(),
+            outputs: node.outputs().to_vec(),
+            scope,
+            envelope,
+        }
+    }
+
+    // Build the widget for a node and add it to the patch, returning its index
+    fn push_node(&mut self, state: &mut State, patch_node: PatchNode, node: &dyn AudioNode) -> usize {
+        let canvas_node = self.build_node(state, self.nodes.len(), patch_node, node);
+        self.nodes.push(canvas_node);
+
+        self.nodes.len() - 1
+    }
+
+    // Rebuild the widget of a node from its patch settings, e.g. after a file has been replaced
+    fn rebuild_node(&mut self, state: &mut State, index: usize) {
+        let old_entity = self.nodes[index].entity;
+        let mut patch_node = self.nodes[index].patch_node.clone();
+        patch_node.x = state.data.get_posx(old_entity) - state.data.get_posx(self.canvas);
+        patch_node.y = state.data.get_posy(old_entity) - state.data.get_posy(self.canvas);
+
+        let node = match patch_node.create(&self.registry) {
+            Ok(node) => node,
+            Err(error) => {
+                self.show_error(state, &format!("Failed to rebuild node: {}", error));
+                return;
+            }
+        };
+
+        state.remove(old_entity);
+        self.nodes[index] = self.build_node(state, index, patch_node, node.as_ref());
+        let entity = self.nodes[index].entity;
+
+        if let Some(selected) = self.selection.iter_mut().find(|selected| **selected == old_entity) {
+            *selected = entity;
+            NodeWidget::set_selected(state, entity, true);
+        }
+
+        // The probed output may no longer exist
+        if let Some((node, output)) = self.probe {
+            if node == index {
+                if output < self.nodes[index].sockets.outputs.len() {
+                    self.mark_probe(state, true);
+                } else {
+                    self.set_probe(state, None);
+                }
+            }
+        }
+
+        // The ports of the node may have changed, so wires to ports which no longer exist are removed
+        // and the rest are attached to the new sockets
+        let connections: Vec<Connection> = self
+            .connections
+            .iter()
+            .filter(|connection| connection.from == index || connection.to == index)
+            .copied()
+            .collect();
+        for connection in connections {
+            if self.sockets(connection).is_some() {
+                self.connect_wire(state, connection);
+            } else {
+                self.disconnect_wire(state, connection);
+                self.connections.retain(|existing| *existing != connection);
+            }
+        }
+    }
+
+    // Output and input sockets at the ends of a connection
+    fn sockets(&self, connection: Connection) -> Option<(Entity, Entity)> {
+        let output = *self.nodes.get(connection.from)?.sockets.outputs.get(connection.output)?;
+        let input = *self.nodes.get(connection.to)?.sockets.inputs.get(connection.input)?;
+        Some((output, input))
+    }
+
+    // Find the connection between an output and an input socket
+    fn socket_connection(&self, output: Entity, input: Entity) -> Option<Connection> {
+        let (from, output) = self.nodes.iter().enumerate().find_map(|(index, node)| {
+            node.sockets.outputs.iter().position(|socket| *socket == output).map(|port| (index, port))
+        })?;
+        let (to, input) = self.nodes.iter().enumerate().find_map(|(index, node)| {
+            node.sockets.inputs.iter().position(|socket| *socket == input).map(|port| (index, port))
+        })?;
+
+        Some(Connection::new(from, output, to, input))
+    }
+
+    // Draw the wire of a connection, as if it had been dragged between the sockets
+    fn connect_wire(&self, state: &mut State, connection: Connection) {
+        if let Some((output, input)) = self.sockets(connection) {
+            state.insert_event(Event::new(NodeEvent::ConnectOutput).direct(input).origin(output));
+        }
+    }
+
+    // Remove the wire drawn to the input of a connection
+    fn disconnect_wire(&self, state: &mut State, connection: Connection) {
+        if let Some(input) = self.nodes.get(connection.to).and_then(|node| node.sockets.inputs.get(connection.input)) {
+            state.insert_event(Event::new(NodeEvent::Disconnect).direct(*input).origin(*input));
+        }
+    }
+
+    // The output port a connection starts from
+    fn output_port(&self, connection: Connection) -> Option<PortInfo> {
+        self.nodes.get(connection.from)?.outputs.get(connection.output).cloned()
+    }
+
+    // The input port a connection ends at
+    fn input_port(&self, connection: Connection) -> Option<PortInfo> {
+        self.nodes.get(connection.to)?.inputs.get(connection.input).cloned()
+    }
+
+    // Remove a wire which can't be connected, flashing its input socket and saying why in the error bar
+    fn reject_wire(&self, state: &mut State, connection: Connection, reason: &str) {
+        self.disconnect_wire(state, connection);
+        if let Some(input) = self.nodes.get(connection.to).and_then(|node| node.sockets.inputs.get(connection.input)) {
+            state.insert_event(Event::new(NodeEvent::Rejected).direct(*input).origin(*input));
+        }
+        self.show_error(state, reason);
+    }
+
+    // Record a wire connected in the canvas. An input socket holds one wire, which replaces any before it.
+    //
+    // Events only travel between event ports, so a wire between an event port and a signal port is refused.
+    // A wire which closes a loop becomes a feedback connection, which is delayed by a block. Events can't be
+    // delayed, so a wire closing a loop of event connections is refused instead.
+    //
+    // Returns whether the patch changed, which it doesn't when the wire of a connection already in the patch is drawn.
+    fn wire_connected(&mut self, state: &mut State, output: Entity, input: Entity) -> bool {
+        let mut connection = match self.socket_connection(output, input) {
+            Some(connection) => connection,
+            None => return false,
+        };
+
+        let previous = self
+            .connections
+            .iter()
+            .find(|existing| existing.to == connection.to && existing.input == connection.input)
+            .copied();
+        self.connections
+            .retain(|existing| !(existing.to == connection.to && existing.input == connection.input));
+
+        let port = self.output_port(connection);
+        let from_events = port.as_ref().map(|port| port.kind) == Some(PortKind::Event);
+        let to_events = self.input_port(connection).map(|port| port.kind) == Some(PortKind::Event);
+        if from_events != to_events {
+            self.reject_wire(state, connection, "Event ports can only be connected to event ports");
+            return previous.is_some();
+        }
+
+        if creates_cycle(&self.connections, connection) {
+            if from_events {
+                self.reject_wire(state, connection, "Event connections can't form a loop");
+                return previous.is_some();
+            }
+            connection.feedback = true;
+        }
+
+        self.connections.push(connection);
+        state.insert_event(Event::new(NodeEvent::Feedback(connection.feedback)).direct(input).origin(input));
+        let channels = port.map(|port| port.channels).unwrap_or(1);
+        state.insert_event(Event::new(NodeEvent::Channels(channels)).direct(input).origin(input));
+        self.send_levels(state, connection);
+        state.insert_event(Event::new(NodeEvent::Flow(self.show_flow)).direct(input).origin(input));
+
+        previous != Some(connection)
+    }
+
+    // Give the wire of a connection the monitor of the node it comes from, to show the level of its signal
+    fn send_levels(&self, state: &mut State, connection: Connection) {
+        if let Some(input) = self.nodes.get(connection.to).and_then(|node| node.sockets.inputs.get(connection.input)) {
+            let monitor = self.monitor(connection.from);
+            state.insert_event(Event::new(NodeEvent::Levels(monitor, connection.output)).direct(*input).origin(*input));
+        }
+    }
 
-        let output = NodeWidget::new("Output").build(state, self.canvas, |builder| 
+    // Hear an output of a node on its own, or go back to normal routing
+    fn set_probe(&mut self, state: &mut State, probe: Option<(usize, usize)>) {
+        if probe == self.probe {
+            return;
+        }
+
+        let path = match probe {
+            Some((node, output)) => match self.node_path(node) {
+                Some(path) => Some((path, output)),
+                None => {
+                    self.show_error(state, &format!("Nodes inside more than {} groups can't be probed", MAX_PATH_DEPTH - 1));
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        self.mark_probe(state, false);
+        self.probe = probe;
+        self.mark_probe(state, true);
+
+        state.insert_event(Event::new(PatchEvent::Probe(path)).target(self.canvas).origin(self.canvas));
+    }
+
+    // Alt-clicking the probed output again goes back to normal routing
+    fn toggle_probe(&mut self, state: &mut State, node: usize, output: usize) {
+        let port = self
+            .nodes
+            .get(node)
+            .and_then(|canvas_node| canvas_node.outputs.get(output).cloned());
+        if port.map(|port| port.kind) == Some(PortKind::Event) {
+            self.show_error(state, "Event outputs can't be probed");
+            return;
+        }
+
+        if self.probe == Some((node, output)) {
+            self.set_probe(state, None);
+        } else {
+            self.set_probe(state, Some((node, output)));
+        }
+    }
+
+    fn mark_probe(&self, state: &mut State, probed: bool) {
+        let socket = self
+            .probe
+            .and_then(|(node, output)| self.nodes.get(node)?.sockets.outputs.get(output).copied());
+        if let Some(socket) = socket {
+            state.insert_event(Event::new(NodeEvent::Probed(probed)).direct(socket).origin(socket));
+        }
+    }
+
+    // Find the wire passing closest to a point in the window, if any is close enough to be clicked
+    fn pick_wire(&self, state: &State, x: f32, y: f32) -> Option<Connection> {
+        let mut closest = None;
+        let mut closest_distance = WIRE_PICK_DISTANCE;
+        for connection in self.connections.iter() {
+            let (output, input) = match self.sockets(*connection) {
+                Some(sockets) => sockets,
+                None => continue,
+            };
+
+            // Wires are drawn in the coordinates of their input socket
+            let mut transform = state.data.get_transform(input);
+            transform.inverse();
+            let (px, py) = transform.transform_point(x, y);
+
+            let points = wire_points(state.data.get_bounds(output), state.data.get_bounds(input));
+            for step in 0..=32 {
+                let (wx, wy) = bezier_point(points, step as f32 / 32.0);
+                let distance = ((wx - px) * (wx - px) + (wy - py) * (wy - py)).sqrt();
+                if distance < closest_distance {
+                    closest_distance = distance;
+                    closest = Some(*connection);
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn wire_disconnected(&mut self, output: Entity, input: Entity) {
+        if let Some(connection) = self.socket_connection(output, input) {
+            self.connections
+                .retain(|existing| !(existing.to == connection.to && existing.input == connection.input));
+        }
+    }
+
+    // Record an edited parameter of a node in the current patch and pass it on to the engine and to the other
+    // widgets showing it. `origin` is the widget the parameter was edited with, which already shows the value.
+    fn param_changed(&mut self, state: &mut State, entity: Entity, origin: Entity, node_index: usize, index: usize, value: f32) {
+        self.nodes[node_index].patch_node.set_param(index, value);
+        self.show_param(state, node_index, index, value, origin);
+        if let Some(scope) = self.nodes[node_index].scope {
+            state.insert_event(Event::new(ScopeEvent::ParamChanged(index, value)).direct(scope).origin(entity));
+        }
+        if let Some(envelope) = self.nodes[node_index].envelope {
+            state.insert_event(Event::new(EnvelopeEvent::ParamChanged(index, value)).direct(envelope).origin(entity));
+        }
+
+        let path = match self.node_path(node_index) {
+            Some(path) => path,
+            None => {
+                self.show_error(state, &format!("Nodes inside more than {} groups can't be edited while playing", MAX_PATH_DEPTH - 1));
+                return;
+            }
+        };
+        let change = ParamChange {
+            path,
+            index,
+            value,
+            time: Instant::now(),
+        };
+        state.insert_event(Event::new(PatchEvent::ParamChanged(change)).target(entity).origin(entity));
+    }
+
+    // Update the controls of a parameter in the node widget and the inspector in place, apart from `origin`
+    fn show_param(&self, state: &mut State, node_index: usize, index: usize, value: f32, origin: Entity) {
+        let mut widgets = vec![&self.nodes[node_index].sockets.params];
+        if self.inspected == Some(node_index) {
+            widgets.push(&self.inspector_params);
+        }
+
+        for widgets in widgets {
+            for (_, control) in widgets.controls.iter().filter(|(param, control)| *param == index && *control != origin) {
+                state.insert_event(Event::new(FloatParamEvent::SetValue(index, value)).direct(*control).origin(origin));
+            }
+            for (_, view) in widgets.markers.iter().filter(|(param, view)| *param == index && *view != origin) {
+                state.insert_event(Event::new(WaveformEvent::SetMarker(index, value)).direct(*view).origin(origin));
+            }
+        }
+    }
+
+    // Path to a node of the patch being shown from the root patch, through the groups which are open
+    fn node_path(&self, node_index: usize) -> Option<NodePath> {
+        let mut path: Vec<usize> = self.parents.iter().map(|parent| parent.node).collect();
+        path.push(node_index);
+        NodePath::new(&path)
+    }
+
+    // Find the index of the node containing a widget. Widgets in the inspector belong to the node it shows.
+    fn node_index(&self, state: &State, entity: Entity) -> Option<usize> {
+        if self.in_inspector(state, entity) {
+            return self.inspected;
+        }
+
+        let mut entity = entity;
+        loop {
+            if let Some(index) = self.nodes.iter().position(|node| node.entity == entity) {
+                return Some(index);
+            }
+            entity = entity.get_parent(state)?;
+        }
+    }
+
+    // Monitor of a node while audio is running. Only the graph of the top level patch is monitored.
+    fn monitor(&self, index: usize) -> Option<NodeMonitor> {
+        if self.parents.is_empty() {
+            self.monitors.get(index).cloned().flatten()
+        } else {
+            None
+        }
+    }
+
+    fn in_inspector(&self, state: &State, entity: Entity) -> bool {
+        let mut entity = entity;
+        loop {
+            if entity == self.inspector {
+                return true;
+            }
+            entity = match entity.get_parent(state) {
+                Some(parent) => parent,
+                None => return false,
+            };
+        }
+    }
+
+    // Show the last selected node in the inspector. The parameters are shown from their descriptions in the registry,
+    // so the node isn't created.
+    fn update_inspector(&mut self, state: &mut State) {
+        if self.inspector_body != Entity::null() {
+            state.remove(self.inspector_body);
+        }
+        self.inspector_params = ParamWidgets::default();
+        let body = Column::new().build(state, self.inspector_content, |builder| 
             builder
-                .set_left(Pixels(300.0))
-                .set_top(Pixels(300.0))
+                .set_height(Auto)
         );
+        self.inspector_body = body;
 
-        let row = Row::new().build(state, output, |builder| 
+        self.inspected = self
+            .selection
+            .last()
+            .and_then(|selected| self.nodes.iter().position(|node| node.entity == *selected));
+        let index = match self.inspected {
+            Some(index) => index,
+            None => {
+                Self::add_inspector_line(state, body, "No node selected");
+                return;
+            }
+        };
+
+        let patch_node = self.nodes[index].patch_node.clone();
+        let params = match patch_node.param_infos(&self.registry) {
+            Ok(params) => params,
+            Err(error) => {
+                Self::add_inspector_line(state, body, &error.to_string());
+                return;
+            }
+        };
+
+        Self::add_inspector_heading(state, body, &node_title(&patch_node));
+        let category = self.registry.get(&patch_node.kind).map(|descriptor| descriptor.category).unwrap_or("");
+        let entity = self.nodes[index].entity;
+        let x = state.data.get_posx(entity) - state.data.get_posx(self.canvas);
+        let y = state.data.get_posy(entity) - state.data.get_posy(self.canvas);
+        Self::add_inspector_line(state, body, &format!("Type: {}", patch_node.kind));
+        Self::add_inspector_line(state, body, &format!("Category: {}", category));
+        Self::add_inspector_line(state, body, &format!("Position: {:.0}, {:.0}", x, y));
+
+        Self::add_inspector_heading(state, body, "Parameters");
+        let values: Vec<f32> = (0..params.len()).map(|param| patch_node.param(param)).collect();
+        let monitor = self.monitor(index);
+        self.inspector_params = NodeWidget::add_params(state, body, &params, &values, &patch_node.texts, &patch_node.files, monitor.as_ref());
+
+        Self::add_inspector_heading(state, body, "Inputs");
+        for port in self.nodes[index].inputs.iter() {
+            Self::add_inspector_line(state, body, &format!("{} ({:?}, {} ch)", port.name, port.kind, port.channels));
+        }
+
+        Self::add_inspector_heading(state, body, "Outputs");
+        for port in self.nodes[index].outputs.iter() {
+            Self::add_inspector_line(state, body, &format!("{} ({:?}, {} ch)", port.name, port.kind, port.channels));
+        }
+
+        Self::add_inspector_heading(state, body, "Performance");
+        match monitor {
+            Some(monitor) => {
+                MonitorView::new(monitor).build(state, body, |builder| builder);
+            }
+            None => Self::add_inspector_line(state, body, "Not running"),
+        }
+    }
+
+    fn add_inspector_heading(state: &mut State, parent: Entity, text: &str) {
+        Label::new(text).build(state, parent, |builder| 
             builder
-                .set_height(Pixels(30.0))
+                .set_height(Pixels(25.0))
                 .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(10.0))
+                .set_top(Pixels(5.0))
+                .set_hoverable(false)
+                .class("inspector_heading")
         );
-    
-        InputSocket::new().build(state, row, |builder| 
-            builder
-                .set_left(Pixels(-10.0))
-                .set_right(Stretch(0.0))
-        );
-    
-        Label::new("In").build(state, row, |builder| 
+    }
+
+    fn add_inspector_line(state: &mut State, parent: Entity, text: &str) {
+        Label::new(text).build(state, parent, |builder| 
             builder
+                .set_height(Pixels(20.0))
                 .set_child_space(Stretch(1.0))
-                .set_child_left(Pixels(5.0))
-                .set_space(Pixels(0.0))
+                .set_child_left(Pixels(15.0))
                 .set_hoverable(false)
         );
+    }
+
+    // Keep the node widget and the inspector in step after a file of a node is chosen in one of them
+    fn node_edited(&mut self, state: &mut State, node_index: usize, origin: Entity) {
+        if self.in_inspector(state, origin) {
+            self.rebuild_node(state, node_index);
+        } else if self.inspected == Some(node_index) {
+            self.update_inspector(state);
+        }
+    }
+
+    // Select a clicked node. With shift held, the node is added to or removed from the selection instead.
+    fn select_node(&mut self, state: &mut State, index: usize) {
+        let node = self.nodes[index].entity;
+        if state.modifiers.shift {
+            if let Some(position) = self.selection.iter().position(|selected| *selected == node) {
+                self.selection.remove(position);
+                NodeWidget::set_selected(state, node, false);
+            } else {
+                self.selection.push(node);
+                NodeWidget::set_selected(state, node, true);
+            }
+        } else if !self.selection.contains(&node) {
+            self.clear_selection(state);
+            self.selection.push(node);
+            NodeWidget::set_selected(state, node, true);
+        }
+        self.update_inspector(state);
+    }
+
+    fn clear_selection(&mut self, state: &mut State) {
+        for node in self.selection.drain(..) {
+            NodeWidget::set_selected(state, node, false);
+        }
+        self.update_inspector(state);
+    }
+
+    // Replace the selected nodes with a group containing them
+    fn group_selection(&mut self, state: &mut State) {
+        let mut indices: Vec<usize> = self
+            .selection
+            .iter()
+            .filter_map(|selected| self.nodes.iter().position(|node| node.entity == *selected))
+            .collect();
+        indices.sort_unstable();
+        if indices.is_empty() {
+            return;
+        }
+
+        self.store_positions(state);
+        match collapse_into_group(&self.current_patch(), &indices, &self.registry) {
+            Ok(patch) => {
+                self.selection.clear();
+                self.show_patch(state, patch);
+                self.patch_changed(state);
+            }
+
+            Err(error) => self.show_error(state, &format!("Failed to group nodes: {}", error)),
+        }
+    }
+
+    // Record where each node widget has been moved to
+    fn store_positions(&mut self, state: &State) {
+        let canvas_x = state.data.get_posx(self.canvas);
+        let canvas_y = state.data.get_posy(self.canvas);
+        for node in self.nodes.iter_mut() {
+            node.patch_node.x = state.data.get_posx(node.entity) - canvas_x;
+            node.patch_node.y = state.data.get_posy(node.entity) - canvas_y;
+        }
+    }
+
+    // The patch being edited, which may be inside a node
+    fn current_patch(&self) -> Patch {
+        Patch {
+            nodes: self.nodes.iter().map(|node| node.patch_node.clone()).collect(),
+            connections: self.connections.clone(),
+        }
+    }
+
+    // The top level patch, including any changes to the opened subpatches
+    fn root_patch(&mut self, state: &State) -> Patch {
+        self.store_positions(state);
+
+        let mut patch = self.current_patch();
+        for parent in self.parents.iter().rev() {
+            let mut nodes: Vec<PatchNode> = parent.nodes.iter().map(|node| node.patch_node.clone()).collect();
+            nodes[parent.node].subpatch = Some(patch);
+            patch = Patch {
+                nodes,
+                connections: parent.connections.clone(),
+            };
+        }
+
+        patch
+    }
+
+    // Send the top level patch up to the engine after its nodes or wires have changed
+    fn patch_changed(&mut self, state: &mut State) {
+        let patch = self.root_patch(state);
+        state.insert_event(Event::new(PatchEvent::Changed(patch)).target(self.canvas).origin(self.canvas));
+    }
+
+    // Patch the view starts with, an oscillator playing into the output
+    fn default_patch(&self) -> Patch {
+        let nodes = [("Oscillator", 0.0, 0.0), ("Output", 300.0, 150.0)]
+            .iter()
+            .filter_map(|(kind, x, y)| {
+                let node = self.registry.create(kind)?;
+                Some(PatchNode::new(kind, *x, *y, node.as_ref()))
+            })
+            .collect();
+
+        Patch {
+            nodes,
+            connections: vec![Connection::new(0, 0, 1, 0)],
+        }
+    }
+
+    // Add the nodes and connections of a patch to the canvas, with the nodes moved by an offset
+    fn add_patch(&mut self, state: &mut State, patch: Patch, offset_x: f32, offset_y: f32) {
+        let mut index = Vec::with_capacity(patch.nodes.len());
+        for mut patch_node in patch.nodes {
+            patch_node.x += offset_x;
+            patch_node.y += offset_y;
+
+            match patch_node.create(&self.registry) {
+                Ok(node) => index.push(Some(self.push_node(state, patch_node, node.as_ref()))),
+                Err(error) => {
+                    self.show_error(state, &format!("Failed to build node: {}", error));
+                    index.push(None);
+                }
+            }
+        }
+
+        for connection in patch.connections {
+            let from = index.get(connection.from).copied().flatten();
+            let to = index.get(connection.to).copied().flatten();
+            if let (Some(from), Some(to)) = (from, to) {
+                let connection = Connection { from, to, ..connection };
+                if self.sockets(connection).is_some() {
+                    self.connections.push(connection);
+                    self.connect_wire(state, connection);
+                }
+            }
+        }
+    }
+
+    // Replace the nodes in the canvas with the nodes of a patch
+    fn show_patch(&mut self, state: &mut State, patch: Patch) {
+        self.clear_selection(state);
+        self.set_probe(state, None);
+        for node in self.nodes.drain(..) {
+            state.remove(node.entity);
+        }
+        self.connections.clear();
+
+        self.add_patch(state, patch, 0.0, 0.0);
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+
+    // Show the patch inside a node in place of the patch being edited
+    fn open_subpatch(&mut self, state: &mut State, entity: Entity, index: usize) {
+        let subpatch = match self.nodes[index].patch_node.subpatch.clone() {
+            Some(subpatch) => subpatch,
+            None => return,
+        };
+
+        self.store_positions(state);
+        self.clear_selection(state);
+        self.set_probe(state, None);
+        self.canvas.set_display(state, Display::None);
+        self.parents.push(ParentPatch {
+            canvas: self.canvas,
+            nodes: std::mem::take(&mut self.nodes),
+            connections: std::mem::take(&mut self.connections),
+            node: index,
+            translate_x: self.translate_x,
+            translate_y: self.translate_y,
+            scale: self.scale,
+        });
+
+        self.canvas = Self::build_canvas(state, entity);
+        self.translate_x = 0.0;
+        self.translate_y = 0.0;
+        self.scale = 1.0;
+        self.show_patch(state, subpatch);
+
+        self.update_breadcrumbs(state);
+    }
+
+    // Go back to the patch containing the one being edited, storing the changes in its node
+    fn close_subpatch(&mut self, state: &mut State) {
+        let parent = match self.parents.pop() {
+            Some(parent) => parent,
+            None => return,
+        };
+
+        self.store_positions(state);
+        let subpatch = self.current_patch();
+        self.clear_selection(state);
+        self.set_probe(state, None);
+        self.nodes.clear();
+        // Removing the canvas removes the node widgets inside it
+        state.remove(self.canvas);
+
+        self.canvas = parent.canvas;
+        self.canvas.set_display(state, Display::Flex);
+        self.nodes = parent.nodes;
+        self.connections = parent.connections;
+        self.translate_x = parent.translate_x;
+        self.translate_y = parent.translate_y;
+        self.scale = parent.scale;
+
+        // The ports of a group come from its patch, so the node is rebuilt
+        self.nodes[parent.node].patch_node.subpatch = Some(subpatch);
+        self.rebuild_node(state, parent.node);
+
+        self.update_breadcrumbs(state);
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+
+    // Save a node to the library under its title, so it can be added to other patches from the menu
+    fn save_to_library(&mut self, state: &mut State, index: usize) {
+        self.store_positions(state);
+
+        let mut patch_node = self.nodes[index].patch_node.clone();
+        patch_node.x = 0.0;
+        patch_node.y = 0.0;
+        let patch = Patch {
+            nodes: vec![patch_node],
+            connections: Vec::new(),
+        };
+
+        match save_library_asset(Path::new(LIBRARY_DIRECTORY), &node_title(&self.nodes[index].patch_node), &patch) {
+            Ok(asset) => self.add_library_item(state, asset),
+            Err(error) => self.show_error(state, &format!("Failed to save to library: {}", error)),
+        }
+    }
 
-        // for i in 1..800 {
-        //     let rand_x = rand::thread_rng().gen_range(0, 800);
-        //     let rand_y = rand::thread_rng().gen_range(0,600);
-        //     NodeWidget::new().build(state, entity, |builder| 
-        //         builder
-        //             .set_left(Pixels(rand_x as f32))
-        //             .set_top(Pixels(rand_y as f32))
-        //     );
-        // }
+    // Add the nodes of a library patch at the position the menu was opened
+    fn add_library_asset(&mut self, state: &mut State, asset: &LibraryAsset) {
+        match Patch::load(&asset.path) {
+            Ok(patch) => {
+                let (x, y) = (self.menu_x, self.menu_y);
+                self.add_patch(state, patch, x, y);
+                self.patch_changed(state);
+                state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+            }
+
+            Err(error) => self.show_error(state, &format!("Failed to add {}: {}", asset.name, error)),
+        }
+    }
+
+    fn save_patch(&mut self, state: &mut State) {
+        let path = match rfd::FileDialog::new().add_filter("Patch", &[PATCH_EXTENSION]).save_file() {
+            Some(path) => path.with_extension(PATCH_EXTENSION),
+            None => return,
+        };
+
+        let patch = self.root_patch(state);
+        if let Err(error) = patch.save(&path) {
+            self.show_error(state, &format!("Failed to save patch: {}", error));
+        }
+    }
+
+    // Replace the nodes in the canvas with the nodes of a saved patch
+    fn open_patch(&mut self, state: &mut State, path: &Path) {
+        let patch = match Patch::load(path) {
+            Ok(patch) => patch,
+            Err(error) => {
+                self.show_error(state, &format!("Failed to open patch: {}", error));
+                return;
+            }
+        };
+
+        while !self.parents.is_empty() {
+            self.close_subpatch(state);
+        }
+
+        self.show_patch(state, patch);
+        self.patch_changed(state);
+    }
+}
+
+impl Widget for NodeView {
+    type Ret = Entity;
+    type Data = ();
+
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+
+        self.canvas = Self::build_canvas(state, entity);
+
+        self.build_menu(state, entity);
+        self.build_param_menu(state, entity);
+        self.inspector_content = Inspector::new().build(state, entity, |builder| builder);
+        self.inspector = self.inspector_content.get_parent(state).unwrap();
+        self.update_inspector(state);
+        self.build_breadcrumbs(state, entity);
+        self.build_error_bar(state, entity);
+        let patch = self.default_patch();
+        self.add_patch(state, patch, 0.0, 0.0);
+        self.patch_changed(state);
 
         state.set_focus(entity);
 
@@ -203,6 +1205,53 @@ impl Widget for NodeView {
                             self.prev_translate_y = self.translate_y;
                         }
                     //}
+
+                    if *button == MouseButton::Right && (event.target == entity || event.target == self.canvas) {
+                        self.open_menu(state, entity);
+                    }
+
+                    if *button == MouseButton::Left && state.modifiers.alt && (event.target == entity || event.target == self.canvas) {
+                        if let Some(connection) = self.pick_wire(state, state.mouse.cursorx, state.mouse.cursory) {
+                            self.toggle_probe(state, connection.from, connection.output);
+                            event.consume();
+                            return;
+                        }
+                    }
+
+                    if *button == MouseButton::Left {
+                        let picked = self.menu_items.iter().find(|(item, _)| *item == event.target).map(|(_, name)| *name);
+                        if let Some(name) = picked {
+                            self.add_node(state, name);
+                        }
+                        let asset = self.library_items.iter().find(|(item, _)| *item == event.target).map(|(_, asset)| asset.clone());
+                        if let Some(asset) = asset {
+                            self.add_library_asset(state, &asset);
+                        }
+                        if let Some((node_index, param)) = self.param_menu_target {
+                            if event.target == self.param_menu_item {
+                                self.toggle_modulation(state, node_index, param);
+                            }
+                        }
+                        self.close_menu(state);
+
+                        if self.in_inspector(state, event.target) {
+                            // Clicks in the inspector leave the selection as it is
+                        } else if let Some(node_index) = self.node_index(state, event.target) {
+                            self.select_node(state, node_index);
+                        } else if event.target == entity || event.target == self.canvas {
+                            self.clear_selection(state);
+                        }
+
+                        if event.target == self.error_bar {
+                            self.error_bar.set_display(state, Display::None);
+                        }
+
+                        if let Some(depth) = self.crumbs.iter().position(|crumb| *crumb == event.target) {
+                            while self.parents.len() > depth {
+                                self.close_subpatch(state);
+                            }
+                        }
+                    }
                 }
 
                 WindowEvent::MouseUp(button) => {
@@ -248,7 +1297,37 @@ impl Widget for NodeView {
                 WindowEvent::KeyDown(code, key) => {
                     println!("Key: {:?} {:?}", code, key);
                     match *code {
+                        Code::KeyS if state.modifiers.ctrl => {
+                            self.save_patch(state);
+                        }
+
+                        Code::KeyO if state.modifiers.ctrl => {
+                            let picked = rfd::FileDialog::new()
+                                .add_filter("Patch", &[PATCH_EXTENSION])
+                                .pick_file();
+
+                            if let Some(path) = picked {
+                                self.open_patch(state, &path);
+                            }
+                        }
+
+                        Code::KeyG if state.modifiers.ctrl => {
+                            self.group_selection(state);
+                        }
 
+                        Code::KeyF if state.modifiers.ctrl => {
+                            self.show_flow = !self.show_flow;
+                            let nodes = self.nodes.iter().chain(self.parents.iter().flat_map(|parent| parent.nodes.iter()));
+                            for input in nodes.flat_map(|node| node.sockets.inputs.iter()) {
+                                state.insert_event(Event::new(NodeEvent::Flow(self.show_flow)).direct(*input).origin(*input));
+                            }
+                        }
+
+                        Code::KeyI if state.modifiers.ctrl => {
+                            self.inspector_visible = !self.inspector_visible;
+                            let display = if self.inspector_visible { Display::Flex } else { Display::None };
+                            self.inspector.set_display(state, display);
+                        }
 
                         _=> {}
                     }
@@ -257,5 +1336,241 @@ impl Widget for NodeView {
                 _=> {}
             }
         }
+
+        if let Some(node_event) = event.message.downcast() {
+            match node_event {
+                NodeEvent::Connected(output, input) => {
+                    if self.wire_connected(state, *output, *input) {
+                        self.patch_changed(state);
+                    }
+                    event.consume();
+                }
+
+                NodeEvent::Disconnected(output, input) => {
+                    self.wire_disconnected(*output, *input);
+                    self.patch_changed(state);
+                    event.consume();
+                }
+
+                NodeEvent::Probe => {
+                    let probe = self.nodes.iter().enumerate().find_map(|(index, node)| {
+                        node.sockets.outputs.iter().position(|socket| *socket == event.origin).map(|output| (index, output))
+                    });
+                    if let Some((node, output)) = probe {
+                        self.toggle_probe(state, node, output);
+                    }
+                    event.consume();
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(node_widget_event) = event.message.downcast() {
+            match node_widget_event {
+                NodeWidgetEvent::ScopeToggled => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        let show_scope = &mut self.nodes[node_index].patch_node.show_scope;
+                        *show_scope = !*show_scope;
+                        self.rebuild_node(state, node_index);
+                        // The engine only keeps the samples of nodes showing a scope
+                        self.patch_changed(state);
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    }
+                    event.consume();
+                }
+
+                NodeWidgetEvent::DoubleClicked => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        if self.nodes[node_index].patch_node.subpatch.is_some() {
+                            self.open_subpatch(state, entity, node_index);
+                        }
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(file_event) = event.message.downcast() {
+            match file_event {
+                FileParamEvent::FileLoaded(index, file) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.nodes[node_index].patch_node.set_file(*index, file.path.clone());
+                        self.node_edited(state, node_index, event.origin);
+                        self.patch_changed(state);
+                    }
+                    event.consume();
+                }
+
+                FileParamEvent::MidiFileLoaded(index, file) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.nodes[node_index].patch_node.set_file(*index, file.path.clone());
+                        self.node_edited(state, node_index, event.origin);
+                        self.patch_changed(state);
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(waveform_event) = event.message.downcast() {
+            match waveform_event {
+                WaveformEvent::MarkerChanged(index, value) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.param_changed(state, entity, event.origin, node_index, *index, *value);
+                    }
+                    event.consume();
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(param_event) = event.message.downcast() {
+            match param_event {
+                FloatParamEvent::ValueChanged(index, value) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.param_changed(state, entity, event.origin, node_index, *index, *value);
+                    }
+                    event.consume();
+                }
+
+                FloatParamEvent::ContextMenu(index) => {
+                    self.close_menu(state);
+                    // Depths of modulation inputs come after the parameters of the node and can't be modulated themselves,
+                    // nor can parameters which switch or are costly to change
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        let patch_node = &self.nodes[node_index].patch_node;
+                        let modulatable = self
+                            .registry
+                            .get(&patch_node.kind)
+                            .and_then(|descriptor| descriptor.params.get(*index))
+                            .map(can_modulate)
+                            .unwrap_or(false);
+                        if *index < patch_node.params.len() && modulatable {
+                            self.open_param_menu(state, entity, node_index, *index);
+                        }
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(text_event) = event.message.downcast() {
+            match text_event {
+                // Text can change the ports of a node, so the widget is rebuilt with the new sockets
+                TextParamEvent::TextChanged(index, text) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.nodes[node_index].patch_node.set_text(*index, text);
+                        self.rebuild_node(state, node_index);
+                        if self.inspected == Some(node_index) {
+                            self.update_inspector(state);
+                        }
+                        self.patch_changed(state);
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(step_event) = event.message.downcast() {
+            match step_event {
+                StepEvent::PatternChanged(pattern) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.nodes[node_index].patch_node.state = pattern.to_values();
+                        self.patch_changed(state);
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(envelope_event) = event.message.downcast() {
+            match envelope_event {
+                EnvelopeEvent::ShapeChanged(shape) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        for index in 0..self.nodes[node_index].patch_node.params.len() {
+                            let value = shape.get_param(index);
+                            if value != self.nodes[node_index].patch_node.params[index] {
+                                self.param_changed(state, entity, event.origin, node_index, index, value);
+                            }
+                        }
+                    }
+                    event.consume();
+                }
+
+                EnvelopeEvent::ParamChanged(..) => {}
+            }
+        }
+
+        if let Some(subpatch_event) = event.message.downcast() {
+            match subpatch_event {
+                SubpatchEvent::Open => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.open_subpatch(state, entity, node_index);
+                    }
+                    event.consume();
+                }
+
+                SubpatchEvent::SaveToLibrary => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.save_to_library(state, node_index);
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(curve_event) = event.message.downcast() {
+            match curve_event {
+                CurveEvent::CurveChanged(curve) => {
+                    if let Some(node_index) = self.node_index(state, event.origin) {
+                        self.nodes[node_index].patch_node.state = curve.points().to_vec();
+                        self.patch_changed(state);
+                    }
+                    event.consume();
+                }
+            }
+        }
+
+        if let Some(engine_event) = event.message.downcast() {
+            match engine_event {
+                EngineEvent::Monitors(monitors) => {
+                    self.monitors = monitors.clone();
+                    for (index, node) in self.nodes.iter().enumerate() {
+                        let monitor = self.monitor(index);
+                        if let Some(scope) = node.scope {
+                            let tap = monitor.as_ref().and_then(|monitor| monitor.scope());
+                            state.insert_event(Event::new(ScopeEvent::SetTap(tap)).direct(scope).origin(entity));
+                        }
+
+                        // Meters show the values of the node playing in the new graph
+                        let monitor = match monitor {
+                            Some(monitor) => monitor,
+                            None => continue,
+                        };
+                        if let (Some(meter_widget), Some(meter)) = (node.sockets.meter, monitor.meter()) {
+                            state.insert_event(Event::new(MeterEvent::SetMeter(meter)).direct(meter_widget).origin(entity));
+                        }
+                        for (param, control) in node.sockets.params.modulations.iter() {
+                            if let Some(modulation) = monitor.modulation(*param) {
+                                state.insert_event(Event::new(MeterEvent::SetMeter(modulation)).direct(*control).origin(entity));
+                            }
+                        }
+                    }
+                    for connection in self.connections.iter() {
+                        self.send_levels(state, *connection);
+                    }
+                    self.update_inspector(state);
+                    event.consume();
+                }
+
+                EngineEvent::Error(error) => {
+                    self.show_error(state, error);
+                    event.consume();
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+}
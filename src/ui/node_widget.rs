@@ -1,5 +1,8 @@
 
 
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
 use tuix::*;
 use femtovg::{
     Canvas, renderer::OpenGl, Align, Baseline, FillRule, FontId, ImageFlags, ImageId, LineCap, LineJoin,
@@ -7,8 +10,50 @@ use femtovg::{
 };
 
 use super::socket_widget::*;
+use super::file_param::*;
+use super::meter_widget::*;
+use super::curve_editor::*;
+use super::step_grid::*;
+use super::waveform_view::*;
+use super::text_param::*;
+use super::float_param::*;
+use super::param_control::*;
+use super::param_toggle::*;
+use super::param_choice::*;
+use super::subpatch_button::*;
+
+use crate::audio::{AudioNode, NodeMonitor, ParamInfo, ParamKind, ParamStyle};
+
+// Longest time between two clicks on a node for them to count as a double click
+pub const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeWidgetEvent {
+    // Sent up the tree when the node is double clicked
+    DoubleClicked,
+    // Sent up the tree when the scope button in the corner of the node is clicked, to show or hide its scope
+    ScopeToggled,
+}
 
+// Sockets added for the ports of an audio node, in the order of the ports, and the widgets showing its meters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeSockets {
+    pub inputs: Vec<Entity>,
+    pub outputs: Vec<Entity>,
+    pub meter: Option<Entity>,
+    pub params: ParamWidgets,
+}
 
+// Widgets added for the parameters of a node, with the index of the parameter each one edits
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParamWidgets {
+    // Every control editing a float parameter, including the depths of modulations
+    pub controls: Vec<(usize, Entity)>,
+    // Waveform view each marker parameter is dragged on
+    pub markers: Vec<(usize, Entity)>,
+    // Controls of parameters moved by a modulation input
+    pub modulations: Vec<(usize, Entity)>,
+}
 
 pub struct NodeWidget {
     selected: bool,
@@ -24,6 +69,11 @@ pub struct NodeWidget {
     prev_translate_y: f32,
 
     name: String,
+    last_click: Option<Instant>,
+
+    // Button showing or hiding a scope of the node's output, left out for nodes which always show one
+    has_scope_button: bool,
+    scope_button: Entity,
 }
 
 impl NodeWidget {
@@ -41,39 +91,58 @@ impl NodeWidget {
             translate_y: 0.0,
 
             name: name.to_string(),
+            last_click: None,
+
+            has_scope_button: true,
+            scope_button: Entity::null(),
         }
     }
 
-    fn add_input_socket(&mut self, state: &mut State, entity: Entity) {
-        let row = Row::new().build(state, entity, |builder| 
+    pub fn without_scope_button(mut self) -> Self {
+        self.has_scope_button = false;
+        self
+    }
+
+    // Highlight the border of a node which is selected
+    pub fn set_selected(state: &mut State, node: Entity, selected: bool) {
+        let color = if selected { Color::rgb(80, 80, 220) } else { Color::rgb(100, 100, 100) };
+        node.set_border_color(state, color);
+    }
+
+    // Add a row with a labelled input socket to the container of a node, returning the socket
+    pub fn add_input_socket(state: &mut State, container: Entity, name: &str) -> Entity {
+        let row = Row::new().build(state, container, |builder| 
             builder
                 .set_height(Pixels(30.0))
                 .set_child_space(Stretch(1.0))
         );
 
-        InputSocket::new().build(state, row, |builder| 
+        let socket = InputSocket::new().build(state, row, |builder| 
             builder
                 .set_left(Pixels(-10.0))
                 .set_right(Stretch(0.0))
         );
 
-        Label::new("Input").build(state, row, |builder| 
+        Label::new(name).build(state, row, |builder| 
             builder
                 .set_child_space(Stretch(1.0))
                 .set_child_left(Pixels(5.0))
                 .set_space(Pixels(0.0))
                 .set_hoverable(false)
         );
+
+        socket
     }
 
-    fn add_output_socket(&mut self, state: &mut State, entity: Entity) {
-        let row = Row::new().build(state, entity, |builder| 
+    // Add a row with a labelled output socket to the container of a node, returning the socket
+    pub fn add_output_socket(state: &mut State, container: Entity, name: &str) -> Entity {
+        let row = Row::new().build(state, container, |builder| 
             builder
                 .set_height(Pixels(30.0))
                 .set_child_space(Stretch(1.0))
         );
 
-        Label::new("Output").build(state, row, |builder| 
+        Label::new(name).build(state, row, |builder| 
             builder
                 .set_child_space(Stretch(1.0))
                 .set_child_right(Pixels(5.0))
@@ -85,7 +154,173 @@ impl NodeWidget {
             builder
                 .set_left(Stretch(0.0))
                 .set_right(Pixels(-10.0))
-        );
+        )
+    }
+
+    // Add the sockets, parameters and editors of an audio node to the container of a node.
+    //
+    // `files` lists the files already chosen for file parameters, with the parameter index. Meters are shown from
+    // the monitor of the node playing in the engine, if there is one.
+    pub fn add_audio_node(
+        state: &mut State,
+        container: Entity,
+        node: &dyn AudioNode,
+        files: &[(usize, PathBuf)],
+        monitor: Option<&NodeMonitor>,
+    ) -> NodeSockets {
+        let mut sockets = NodeSockets::default();
+        if node.meter().is_some() {
+            let meter = monitor.and_then(|monitor| monitor.meter()).unwrap_or_default();
+            sockets.meter = Some(GainReductionMeter::new(meter).build(state, container, |builder| builder));
+        }
+
+        for port in node.outputs() {
+            sockets.outputs.push(Self::add_output_socket(state, container, &port.name));
+        }
+
+        for port in node.inputs() {
+            sockets.inputs.push(Self::add_input_socket(state, container, &port.name));
+        }
+
+        sockets.params = Self::add_editors(state, container, node, files, monitor);
+
+        sockets
+    }
+
+    // Add widgets for the parameters of an audio node and any editors it has, such as a step grid
+    pub fn add_editors(
+        state: &mut State,
+        container: Entity,
+        node: &dyn AudioNode,
+        files: &[(usize, PathBuf)],
+        monitor: Option<&NodeMonitor>,
+    ) -> ParamWidgets {
+        let params = node.params();
+        let values: Vec<f32> = (0..params.len()).map(|index| node.get_param(index)).collect();
+        let texts: Vec<(usize, String)> = (0..params.len())
+            .filter_map(|index| node.text(index).map(|text| (index, text.to_string())))
+            .collect();
+        let widgets = Self::add_params(state, container, params, &values, &texts, files, monitor);
+
+        let info = node.editor_info();
+        if let Some(error) = info.and_then(|info| info.error()) {
+            Label::new(error).build(state, container, |builder|
+                builder
+                    .set_height(Pixels(30.0))
+                    .set_child_space(Stretch(1.0))
+                    .set_child_left(Pixels(15.0))
+                    .set_space(Pixels(0.0))
+                    .set_color(Color::rgb(220, 60, 60))
+                    .set_hoverable(false)
+                    .class("node_error")
+            );
+        }
+
+        if let Some(curve) = info.and_then(|info| info.transfer_curve()) {
+            CurveEditor::new(curve).build(state, container, |builder| builder);
+        }
+
+        if let Some(pattern) = info.and_then(|info| info.step_pattern()) {
+            StepGrid::new(pattern).build(state, container, |builder| builder);
+        }
+
+        if node.subpatch().is_some() {
+            SubpatchButton::new("Open Patch", SubpatchEvent::Open).build(state, container, |builder| builder);
+            SubpatchButton::new("Save to Library", SubpatchEvent::SaveToLibrary).build(state, container, |builder| builder);
+        }
+
+        widgets
+    }
+
+    // Add widgets for parameters from their descriptions, so the inspector can show the parameters of a node
+    // without creating it. `values` holds the value of each parameter and `texts` and `files` the contents of
+    // text and file parameters, with the parameter index.
+    pub fn add_params(
+        state: &mut State,
+        container: Entity,
+        params: &[ParamInfo],
+        values: &[f32],
+        texts: &[(usize, String)],
+        files: &[(usize, PathBuf)],
+        monitor: Option<&NodeMonitor>,
+    ) -> ParamWidgets {
+        let mut widgets = ParamWidgets::default();
+        for (index, param) in params.iter().enumerate() {
+            match param.kind {
+                ParamKind::Float => {
+                    let value = values[index];
+                    // Parameters moved by a modulation input have a depth parameter, which comes after the others
+                    let depth = params.iter().position(|depth| depth.kind == ParamKind::Depth { param: index });
+                    let modulated = depth.is_some();
+                    match param.style {
+                        ParamStyle::Knob | ParamStyle::Slider => {
+                            let mut control = ParamControl::new(index, param, value);
+                            if modulated {
+                                let modulation = monitor.and_then(|monitor| monitor.modulation(index)).unwrap_or_default();
+                                control = control.with_modulation(modulation);
+                            }
+                            let control = control.build(state, container, |builder| builder);
+                            if modulated {
+                                widgets.modulations.push((index, control));
+                            }
+                            widgets.controls.push((index, control));
+                        }
+
+                        ParamStyle::Toggle => {
+                            let control = ParamToggle::new(index, param, value).build(state, container, |builder| builder);
+                            widgets.controls.push((index, control));
+                        }
+
+                        ParamStyle::Choice => {
+                            let control = ParamChoice::new(index, param, value).build(state, container, |builder| builder);
+                            widgets.controls.push((index, control));
+                        }
+
+                        ParamStyle::Number => {
+                            let control = FloatParam::new(index, param, value).build(state, container, |builder| builder);
+                            widgets.controls.push((index, control));
+                        }
+                    }
+
+                    // The depth of a modulation input is set just below the parameter it moves
+                    if let Some(depth) = depth {
+                        let control = ParamControl::new(depth, &params[depth], values[depth]).build(state, container, |builder| builder);
+                        widgets.controls.push((depth, control));
+                    }
+                }
+
+                ParamKind::File { extensions } => {
+                    let mut file_param = FileParam::new(index, param.name, extensions);
+
+                    // Marker parameters are edited by dragging them on a waveform of the file
+                    let markers: Vec<(usize, f32)> = params
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, marker)| marker.kind == ParamKind::Marker { file: index })
+                        .map(|(marker_index, _)| (marker_index, values[marker_index]))
+                        .collect();
+                    if !markers.is_empty() {
+                        let preview = WaveformView::new(markers.clone()).build(state, container, |builder| builder);
+                        widgets.markers.extend(markers.iter().map(|(marker_index, _)| (*marker_index, preview)));
+                        file_param = file_param.with_preview(preview);
+                    }
+
+                    if let Some((_, path)) = files.iter().find(|(file_index, _)| *file_index == index) {
+                        file_param = file_param.with_file(path.clone());
+                    }
+                    file_param.build(state, container, |builder| builder);
+                }
+
+                ParamKind::Marker { .. } | ParamKind::Depth { .. } => {}
+
+                ParamKind::Text => {
+                    let text = texts.iter().find(|(text_index, _)| *text_index == index).map(|(_, text)| text.as_str());
+                    TextParam::new(index, param.name, text.unwrap_or_default()).build(state, container, |builder| builder);
+                }
+            }
+        }
+
+        widgets
     }
 }
 
@@ -106,12 +341,27 @@ impl Widget for NodeWidget {
                 .class("node_label")
         );
 
+        if self.has_scope_button {
+            self.scope_button = Label::new("~").build(state, entity, |builder|
+                builder
+                    .set_width(Pixels(20.0))
+                    .set_height(Pixels(20.0))
+                    .set_child_space(Stretch(1.0))
+                    .set_left(Stretch(1.0))
+                    .set_right(Pixels(5.0))
+                    .set_top(Pixels(5.0))
+                    .set_border_radius(Pixels(3.0))
+                    .set_position_type(PositionType::SelfDirected)
+                    .class("scope_button")
+            );
+        }
+
         Element::new().build(state, entity, |builder| builder.set_height(Pixels(10.0)));
         
         let conatiner = Element::new().build(state, entity, |builder| builder.set_height(Auto));
         
-        // self.add_input_socket(state, entity);
-        // self.add_output_socket(state, entity);
+        // NodeWidget::add_input_socket(state, conatiner, "Input");
+        // NodeWidget::add_output_socket(state, conatiner, "Output");
 
         Element::new().build(state, entity, |builder| builder.set_height(Pixels(10.0)));
         
@@ -134,8 +384,21 @@ impl Widget for NodeWidget {
         if let Some(window_event) = event.message.downcast() {
             match window_event {
                 WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left && event.target == self.scope_button {
+                        state.insert_event(Event::new(NodeWidgetEvent::ScopeToggled).target(entity).origin(entity));
+                        event.consume();
+                    }
+
                     if event.target == entity {
                         if *button == MouseButton::Left {
+                            let now = Instant::now();
+                            if self.last_click.map(|last| now - last < DOUBLE_CLICK_TIME).unwrap_or(false) {
+                                state.insert_event(Event::new(NodeWidgetEvent::DoubleClicked).target(entity).origin(entity));
+                                self.last_click = None;
+                            } else {
+                                self.last_click = Some(now);
+                            }
+
                             self.moving = true;
                             state.capture(entity);
                             self.prev_translate_x = self.translate_x;
@@ -0,0 +1,139 @@
+use tuix::*;
+
+use super::float_param::FloatParamEvent;
+
+use crate::audio::ParamInfo;
+
+// Parameter choosing between named settings, such as a waveform or a note division.
+//
+// Clicking the button showing the current setting drops down a list of every setting below it.
+pub struct ParamChoice {
+    index: usize,
+    info: ParamInfo,
+    selected: usize,
+    open: bool,
+
+    button: Entity,
+    list: Entity,
+    // Entry in the list for each setting, in the order of `ParamInfo::options`
+    options: Vec<Entity>,
+}
+
+impl ParamChoice {
+    pub fn new(index: usize, info: &ParamInfo, value: f32) -> Self {
+        Self {
+            index,
+            info: info.clone(),
+            selected: info.clamp(value).round() as usize,
+            open: false,
+
+            button: Entity::null(),
+            list: Entity::null(),
+            options: Vec::new(),
+        }
+    }
+
+    fn set_open(&mut self, state: &mut State, open: bool) {
+        self.open = open;
+        let display = if open { Display::Flex } else { Display::None };
+        self.list.set_display(state, display);
+    }
+}
+
+impl Widget for ParamChoice {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        let row = Row::new().build(state, entity, |builder|
+            builder
+                .set_height(Pixels(30.0))
+                .set_child_space(Stretch(1.0))
+        );
+
+        Label::new(self.info.name).build(state, row, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        self.button = Label::new(&self.info.format(self.selected as f32)).build(state, row, |builder|
+            builder
+                .set_width(Pixels(90.0))
+                .set_height(Pixels(20.0))
+                .set_child_space(Stretch(1.0))
+                .set_right(Pixels(5.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_border_radius(Pixels(3.0))
+                .class("param_choice")
+        );
+
+        self.list = Element::new().build(state, entity, |builder|
+            builder
+                .set_height(Auto)
+                .set_width(Pixels(90.0))
+                .set_left(Stretch(1.0))
+                .set_right(Pixels(5.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_display(Display::None)
+        );
+
+        for option in self.info.options.iter() {
+            let entry = Label::new(option).build(state, self.list, |builder|
+                builder
+                    .set_height(Pixels(20.0))
+                    .set_child_space(Stretch(1.0))
+                    .class("param_option")
+            );
+            self.options.push(entry);
+        }
+
+        entity.set_height(state, Auto)
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(MouseButton::Right) => {
+                    state.insert_event(Event::new(FloatParamEvent::ContextMenu(self.index)).target(entity).origin(entity));
+                    event.consume();
+                }
+
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left {
+                        if event.target == self.button {
+                            self.set_open(state, !self.open);
+                            event.consume();
+                        }
+
+                        if let Some(selected) = self.options.iter().position(|option| *option == event.target) {
+                            self.selected = selected;
+                            self.button.set_text(state, self.info.options[selected]);
+                            self.set_open(state, false);
+                            state.insert_event(Event::new(FloatParamEvent::ValueChanged(self.index, selected as f32)).target(entity).origin(entity));
+                            // Stop the parent node from being moved
+                            event.consume();
+                        }
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(param_event) = event.message.downcast() {
+            match param_event {
+                FloatParamEvent::SetValue(index, value) => {
+                    if *index == self.index {
+                        self.selected = self.info.clamp(*value).round() as usize;
+                        self.button.set_text(state, &self.info.format(self.selected as f32));
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, LineCap, Paint, Path, Solidity,
+};
+
+use super::float_param::FloatParamEvent;
+use super::meter_widget::MeterEvent;
+use super::node_widget::DOUBLE_CLICK_TIME;
+
+use crate::audio::{Meter, ParamInfo, ParamStyle};
+
+// Distance in pixels the mouse moves up a knob to turn it from one end of its range to the other
+const KNOB_DRAG_DISTANCE: f32 = 200.0;
+// Fraction of the usual change made by dragging while shift is held, for fine adjustments
+const FINE_DRAG_SCALE: f32 = 0.1;
+// Angle of the start of the knob arc, clockwise from pointing right, and the angle it sweeps through
+const KNOB_START_ANGLE: f32 = 0.75 * PI;
+const KNOB_SWEEP: f32 = 1.5 * PI;
+
+// Knob or slider editing a float parameter, with the name of the parameter and its value with its unit.
+//
+// Knobs are dragged up and down and sliders side to side, in steps given by the skew of the parameter.
+// Holding shift while dragging makes finer changes, and double clicking resets the parameter to its default.
+// A parameter moved by a modulation input also shows the value it has been moved to, as an outer ring on a
+// knob or a thin bar under a slider.
+pub struct ParamControl {
+    index: usize,
+    info: ParamInfo,
+    value: f32,
+
+    // Position from 0 to 1 of the control while dragging, which may be between the steps of a discrete parameter
+    position: f32,
+    dragging: bool,
+    prev_x: f32,
+    prev_y: f32,
+    last_click: Option<Instant>,
+
+    // Value of a modulated parameter while audio is running, and the value last drawn
+    modulation: Option<Meter>,
+    prev_modulated: f32,
+
+    value_label: Entity,
+    control: Entity,
+}
+
+impl ParamControl {
+    pub fn new(index: usize, info: &ParamInfo, value: f32) -> Self {
+        Self {
+            index,
+            info: info.clone(),
+            value: info.clamp(value),
+
+            position: 0.0,
+            dragging: false,
+            prev_x: 0.0,
+            prev_y: 0.0,
+            last_click: None,
+
+            modulation: None,
+            prev_modulated: value,
+
+            value_label: Entity::null(),
+            control: Entity::null(),
+        }
+    }
+
+    pub fn with_modulation(mut self, modulation: Meter) -> Self {
+        self.modulation = Some(modulation);
+        self
+    }
+
+    fn is_slider(&self) -> bool {
+        self.info.style == ParamStyle::Slider
+    }
+
+    fn set_value(&mut self, state: &mut State, entity: Entity, value: f32) {
+        if value == self.value {
+            return;
+        }
+
+        self.show_value(state, value);
+        state.insert_event(Event::new(FloatParamEvent::ValueChanged(self.index, value)).target(entity).origin(entity));
+    }
+
+    fn show_value(&mut self, state: &mut State, value: f32) {
+        self.value = value;
+        self.value_label.set_text(state, &self.info.format(value));
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+}
+
+impl Widget for ParamControl {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        Label::new(self.info.name).build(state, entity, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        self.value_label = Label::new(&self.info.format(self.value)).build(state, entity, |builder|
+            builder
+                .set_width(Pixels(65.0))
+                .set_child_space(Stretch(1.0))
+                .set_child_right(Pixels(5.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        // Area the knob or slider is drawn in
+        let width = if self.is_slider() { 70.0 } else { 30.0 };
+        self.control = Element::new().build(state, entity, |builder|
+            builder
+                .set_width(Pixels(width))
+                .set_right(Pixels(5.0))
+                .set_hoverable(false)
+        );
+
+        entity
+            .set_height(state, Pixels(30.0))
+            .set_child_space(state, Stretch(1.0))
+            .set_layout_type(state, LayoutType::Row)
+            .class(state, "param_control")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(MouseButton::Right) => {
+                    state.insert_event(Event::new(FloatParamEvent::ContextMenu(self.index)).target(entity).origin(entity));
+                    event.consume();
+                }
+
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left && event.target == entity {
+                        let now = Instant::now();
+                        if self.last_click.map(|last| now - last < DOUBLE_CLICK_TIME).unwrap_or(false) {
+                            self.last_click = None;
+                            self.set_value(state, entity, self.info.default);
+                        } else {
+                            self.last_click = Some(now);
+                            self.position = self.info.normalize(self.value);
+                            self.prev_x = state.mouse.cursorx;
+                            self.prev_y = state.mouse.cursory;
+                            self.dragging = true;
+                            state.capture(entity);
+                        }
+                        // Stop the parent node from being moved
+                        event.consume();
+                    }
+                }
+
+                WindowEvent::MouseUp(button) => {
+                    if *button == MouseButton::Left && self.dragging {
+                        self.dragging = false;
+                        state.release(entity);
+                    }
+                }
+
+                WindowEvent::MouseMove(x, y) => {
+                    if self.dragging {
+                        // Each movement is added on, so holding or letting go of shift mid-drag doesn't make the value jump
+                        let mut change = if self.is_slider() {
+                            let width = state.data.get_bounds(self.control).w.max(1.0);
+                            (*x - self.prev_x) / width
+                        } else {
+                            (self.prev_y - *y) / KNOB_DRAG_DISTANCE
+                        };
+                        if state.modifiers.shift {
+                            change *= FINE_DRAG_SCALE;
+                        }
+                        self.prev_x = *x;
+                        self.prev_y = *y;
+
+                        self.position = (self.position + change).clamp(0.0, 1.0);
+                        let value = self.info.denormalize(self.position);
+                        self.set_value(state, entity, value);
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(param_event) = event.message.downcast() {
+            match param_event {
+                FloatParamEvent::SetValue(index, value) => {
+                    if *index == self.index {
+                        self.show_value(state, *value);
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(meter_event) = event.message.downcast() {
+            match meter_event {
+                MeterEvent::SetMeter(modulation) => {
+                    self.modulation = Some(modulation.clone());
+                    state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    event.consume();
+                }
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(self.control);
+        let transform = state.data.get_transform(entity);
+        let position = self.info.normalize(self.value);
+        let modulated_value = self.modulation.as_ref().map(|modulation| modulation.get());
+        let modulated = modulated_value.map(|value| self.info.normalize(value));
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        if self.is_slider() {
+            let y = bounds.y + bounds.h / 2.0 - 3.0;
+            let mut path = Path::new();
+            path.rounded_rect(bounds.x, y, bounds.w, 6.0, 3.0);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+            let mut path = Path::new();
+            path.rounded_rect(bounds.x, y, bounds.w * position, 6.0, 3.0);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(80, 160, 220)));
+
+            if let Some(modulated) = modulated {
+                let mut path = Path::new();
+                path.rect(bounds.x, y + 8.0, bounds.w * modulated, 2.0);
+                canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(230, 150, 50)));
+            }
+
+            let mut path = Path::new();
+            path.circle(bounds.x + bounds.w * position, y + 3.0, 5.0);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(200, 200, 200)));
+        } else {
+            let cx = bounds.x + bounds.w / 2.0;
+            let cy = bounds.y + bounds.h / 2.0;
+            let radius = bounds.w.min(bounds.h) / 2.0 - 3.0;
+
+            let mut path = Path::new();
+            path.arc(cx, cy, radius, KNOB_START_ANGLE, KNOB_START_ANGLE + KNOB_SWEEP, Solidity::Hole);
+            let mut paint = Paint::color(femtovg::Color::rgb(15, 15, 15));
+            paint.set_line_width(4.0);
+            paint.set_line_cap(LineCap::Round);
+            canvas.stroke_path(&mut path, paint);
+
+            if let Some(modulated) = modulated {
+                let mut path = Path::new();
+                path.arc(cx, cy, radius + 3.0, KNOB_START_ANGLE, KNOB_START_ANGLE + KNOB_SWEEP * modulated, Solidity::Hole);
+                let mut paint = Paint::color(femtovg::Color::rgb(230, 150, 50));
+                paint.set_line_width(2.0);
+                canvas.stroke_path(&mut path, paint);
+            }
+
+            let angle = KNOB_START_ANGLE + KNOB_SWEEP * position;
+            if position > 0.0 {
+                let mut path = Path::new();
+                path.arc(cx, cy, radius, KNOB_START_ANGLE, angle, Solidity::Hole);
+                let mut paint = Paint::color(femtovg::Color::rgb(80, 160, 220));
+                paint.set_line_width(4.0);
+                paint.set_line_cap(LineCap::Round);
+                canvas.stroke_path(&mut path, paint);
+            }
+
+            let mut path = Path::new();
+            path.move_to(cx, cy);
+            path.line_to(cx + angle.cos() * radius, cy + angle.sin() * radius);
+            let mut paint = Paint::color(femtovg::Color::rgb(200, 200, 200));
+            paint.set_line_width(2.0);
+            paint.set_line_cap(LineCap::Round);
+            canvas.stroke_path(&mut path, paint);
+        }
+
+        canvas.restore();
+
+        // Keep redrawing while the modulation is moving the parameter
+        if let Some(value) = modulated_value {
+            if value != self.prev_modulated {
+                state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+            }
+            self.prev_modulated = value;
+        }
+    }
+}
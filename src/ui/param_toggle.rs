@@ -0,0 +1,102 @@
+use tuix::*;
+
+use super::float_param::FloatParamEvent;
+
+use crate::audio::ParamInfo;
+
+// On/off parameter with a label and a button which switches it when clicked
+pub struct ParamToggle {
+    index: usize,
+    info: ParamInfo,
+    on: bool,
+
+    button: Entity,
+}
+
+impl ParamToggle {
+    pub fn new(index: usize, info: &ParamInfo, value: f32) -> Self {
+        Self {
+            index,
+            info: info.clone(),
+            on: value >= 0.5,
+
+            button: Entity::null(),
+        }
+    }
+
+    fn update_button(&self, state: &mut State) {
+        let value = if self.on { 1.0 } else { 0.0 };
+        let color = if self.on { Color::rgb(80, 160, 220) } else { Color::rgb(15, 15, 15) };
+        self.button
+            .set_text(state, &self.info.format(value))
+            .set_background_color(state, color);
+    }
+}
+
+impl Widget for ParamToggle {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        Label::new(self.info.name).build(state, entity, |builder|
+            builder
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        self.button = Element::new().build(state, entity, |builder|
+            builder
+                .set_width(Pixels(50.0))
+                .set_height(Pixels(20.0))
+                .set_child_space(Stretch(1.0))
+                .set_right(Pixels(5.0))
+                .set_border_radius(Pixels(3.0))
+                .class("param_toggle")
+        );
+        self.update_button(state);
+
+        entity
+            .set_height(state, Pixels(30.0))
+            .set_child_space(state, Stretch(1.0))
+            .set_layout_type(state, LayoutType::Row)
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(MouseButton::Right) => {
+                    state.insert_event(Event::new(FloatParamEvent::ContextMenu(self.index)).target(entity).origin(entity));
+                    event.consume();
+                }
+
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left && event.target == self.button {
+                        self.on = !self.on;
+                        self.update_button(state);
+                        let value = if self.on { 1.0 } else { 0.0 };
+                        state.insert_event(Event::new(FloatParamEvent::ValueChanged(self.index, value)).target(entity).origin(entity));
+                        // Stop the parent node from being moved
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(param_event) = event.message.downcast() {
+            match param_event {
+                FloatParamEvent::SetValue(index, value) => {
+                    if *index == self.index {
+                        self.on = *value >= 0.5;
+                        self.update_button(state);
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
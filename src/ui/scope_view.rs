@@ -0,0 +1,207 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::{find_trigger, ScopeSettings, ScopeTap, Spectrum, SCOPE_CAPACITY, SPECTRUM_FLOOR_DB};
+
+// Lowest frequency shown by the spectrum, in Hz
+const SPECTRUM_MIN_FREQUENCY: f32 = 20.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeEvent {
+    // Sent to a scope when the engine starts, with the tap of the output it shows, or None when it stops
+    SetTap(Option<ScopeTap>),
+    // Sent to a scope when a parameter of its node is edited, which changes the settings of a scope node
+    ParamChanged(usize, f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeMode {
+    Waveform,
+    Spectrum,
+}
+
+// Strip showing the output of a node as a waveform or a spectrum while audio is running.
+//
+// Clicking the strip switches between the two. The waveform is drawn from a rising edge through the
+// trigger level, and the spectrum on a logarithmic frequency scale.
+pub struct ScopeView {
+    tap: Option<ScopeTap>,
+    settings: ScopeSettings,
+    // Whether the settings follow the parameters of the node, for the scope node
+    follow_params: bool,
+    mode: ScopeMode,
+    spectrum: Spectrum,
+    // Samples written to the tap when last drawn, to redraw only while audio is running
+    prev_written: usize,
+}
+
+impl ScopeView {
+    pub fn new(settings: ScopeSettings) -> Self {
+        Self {
+            tap: None,
+            settings,
+            follow_params: false,
+            mode: ScopeMode::Waveform,
+            spectrum: Spectrum::new(),
+            prev_written: 0,
+        }
+    }
+
+    pub fn with_tap(mut self, tap: Option<ScopeTap>) -> Self {
+        self.tap = tap;
+        self
+    }
+
+    pub fn following_params(mut self) -> Self {
+        self.follow_params = true;
+        self
+    }
+
+    fn draw_waveform(&self, canvas: &mut Canvas<OpenGl>, bounds: BoundingBox, tap: &ScopeTap) {
+        // The tap holds two windows at the longest timebase up to a sample rate, so above it the window is shortened
+        let window = ((0.001 * self.settings.timebase * tap.sample_rate()) as usize).min(SCOPE_CAPACITY / 2);
+        if window < 2 {
+            return;
+        }
+
+        // Read twice the window so there is room to find a trigger point before the latest window
+        let samples = tap.latest(window * 2);
+        let start = find_trigger(&samples, self.settings.trigger_level, window);
+        let samples = &samples[start..(start + window).min(samples.len())];
+
+        let mid_y = bounds.y + bounds.h / 2.0;
+        let trigger_y = mid_y - self.settings.trigger_level * bounds.h / 2.0;
+        let mut path = Path::new();
+        path.move_to(bounds.x, trigger_y);
+        path.line_to(bounds.x + bounds.w, trigger_y);
+        canvas.stroke_path(&mut path, Paint::color(femtovg::Color::rgb(60, 60, 60)));
+
+        // Draw at most one point per pixel
+        let points = (bounds.w as usize).max(2).min(samples.len());
+        let mut path = Path::new();
+        for point in 0..points {
+            let index = point * (samples.len() - 1) / (points - 1).max(1);
+            let x = bounds.x + bounds.w * point as f32 / (points - 1).max(1) as f32;
+            let y = mid_y - samples[index].clamp(-1.0, 1.0) * bounds.h / 2.0;
+            if point == 0 {
+                path.move_to(x, y);
+            } else {
+                path.line_to(x, y);
+            }
+        }
+        let mut paint = Paint::color(femtovg::Color::rgb(80, 200, 100));
+        paint.set_line_width(1.5);
+        canvas.stroke_path(&mut path, paint);
+    }
+
+    fn draw_spectrum(&mut self, canvas: &mut Canvas<OpenGl>, bounds: BoundingBox, tap: &ScopeTap) {
+        let samples = tap.latest(self.settings.fft_size);
+        let nyquist = tap.sample_rate() / 2.0;
+        if samples.len() < self.settings.fft_size || nyquist <= SPECTRUM_MIN_FREQUENCY {
+            return;
+        }
+
+        let levels = self.spectrum.analyze(&samples);
+        let bin_width = nyquist / levels.len() as f32;
+        let octaves = (nyquist / SPECTRUM_MIN_FREQUENCY).log2();
+
+        // Each pixel shows the loudest bin between its frequency and the next pixel's
+        let columns = (bounds.w as usize).max(2);
+        let mut path = Path::new();
+        for column in 0..columns {
+            let low = SPECTRUM_MIN_FREQUENCY * 2.0f32.powf(octaves * column as f32 / columns as f32);
+            let high = SPECTRUM_MIN_FREQUENCY * 2.0f32.powf(octaves * (column + 1) as f32 / columns as f32);
+            let first = ((low / bin_width) as usize).min(levels.len() - 1);
+            let last = ((high / bin_width) as usize).max(first + 1).min(levels.len());
+            let level = levels[first..last].iter().fold(SPECTRUM_FLOOR_DB, |loudest, level| loudest.max(*level));
+
+            let x = bounds.x + bounds.w * column as f32 / (columns - 1) as f32;
+            let y = bounds.y + bounds.h * (level / SPECTRUM_FLOOR_DB).clamp(0.0, 1.0);
+            if column == 0 {
+                path.move_to(x, y);
+            } else {
+                path.line_to(x, y);
+            }
+        }
+        let mut paint = Paint::color(femtovg::Color::rgb(80, 160, 220));
+        paint.set_line_width(1.5);
+        canvas.stroke_path(&mut path, paint);
+    }
+}
+
+impl Widget for ScopeView {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        entity
+            .set_height(state, Pixels(80.0))
+            .set_space(state, Pixels(5.0))
+            .class(state, "scope")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(scope_event) = event.message.downcast() {
+            match scope_event {
+                ScopeEvent::SetTap(tap) => {
+                    self.tap = tap.clone();
+                    state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                }
+
+                ScopeEvent::ParamChanged(index, value) => {
+                    if self.follow_params {
+                        self.settings.set_param(*index, *value);
+                    }
+                }
+            }
+            event.consume();
+        }
+
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left && event.target == entity {
+                        self.mode = match self.mode {
+                            ScopeMode::Waveform => ScopeMode::Spectrum,
+                            ScopeMode::Spectrum => ScopeMode::Waveform,
+                        };
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                        // Stop the parent node from being moved
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(entity);
+        let transform = state.data.get_transform(entity);
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        let mut path = Path::new();
+        path.rect(bounds.x, bounds.y, bounds.w, bounds.h);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        if let Some(tap) = self.tap.clone() {
+            match self.mode {
+                ScopeMode::Waveform => self.draw_waveform(canvas, bounds, &tap),
+                ScopeMode::Spectrum => self.draw_spectrum(canvas, bounds, &tap),
+            }
+
+            // Keep redrawing while audio is arriving
+            let written = tap.written();
+            if written != self.prev_written {
+                state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+            }
+            self.prev_written = written;
+        }
+
+        canvas.restore();
+    }
+}
@@ -1,15 +1,79 @@
+use std::time::Instant;
+
 use tuix::*;
 use femtovg::{
     Canvas, renderer::OpenGl, Paint, Path,
 };
 
 use super::NodeEvent;
+use super::monitor_view::level_position;
+
+use crate::audio::NodeMonitor;
+
+// Number of dots moving along a wire to show the signal flowing
+const FLOW_DOTS: usize = 4;
+// Time for a dot to travel the length of a wire, in seconds
+const FLOW_PERIOD: f32 = 1.5;
+
+// Most lines drawn side by side for the channels of a wire
+const MAX_WIRE_LINES: usize = 4;
+// Number of straight segments used to draw each line of a multi-channel wire
+const WIRE_LINE_SEGMENTS: usize = 32;
+// Time an input socket flashes after a wire to it is refused, in seconds
+const REJECT_FLASH_TIME: f32 = 0.6;
+
+// Control points of the curve of a wire between the bounds of an output and an input socket
+pub fn wire_points(output_bounds: BoundingBox, input_bounds: BoundingBox) -> [(f32, f32); 4] {
+    let (start_x, start_y) = (output_bounds.x + output_bounds.w / 2.0, output_bounds.y + output_bounds.h / 2.0);
+    let (end_x, end_y) = (input_bounds.x + input_bounds.w / 2.0, input_bounds.y + input_bounds.h / 2.0);
+    let mid_x = (end_x - start_x) / 2.0;
+    [(start_x, start_y), (end_x - mid_x, start_y), (start_x + mid_x, end_y), (end_x, end_y)]
+}
+
+// Point along a cubic bezier curve at `t` from 0 to 1
+pub fn bezier_point(points: [(f32, f32); 4], t: f32) -> (f32, f32) {
+    let u = 1.0 - t;
+    let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+    points.iter().zip(weights.iter()).fold((0.0, 0.0), |(x, y), ((px, py), weight)| (x + px * weight, y + py * weight))
+}
+
+// Path following the curve of a wire at a distance to one side of it, for one channel of a multi-channel wire
+fn offset_wire_path(points: [(f32, f32); 4], offset: f32) -> Path {
+    let mut path = Path::new();
+    for segment in 0..=WIRE_LINE_SEGMENTS {
+        let t = segment as f32 / WIRE_LINE_SEGMENTS as f32;
+        let (x, y) = bezier_point(points, t);
+        // The curve's direction from the points just either side of this one
+        let (before_x, before_y) = bezier_point(points, (t - 0.01).max(0.0));
+        let (after_x, after_y) = bezier_point(points, (t + 0.01).min(1.0));
+        let (dx, dy) = (after_x - before_x, after_y - before_y);
+        let length = (dx * dx + dy * dy).sqrt().max(1.0e-6);
+        let (x, y) = (x - dy / length * offset, y + dx / length * offset);
+        if segment == 0 {
+            path.move_to(x, y);
+        } else {
+            path.line_to(x, y);
+        }
+    }
+    path
+}
 
 
 // Widget for the connecting wire between an output and input socket
 pub struct ConnectionWidget {
     output_socket: Entity,
     input_socket: Entity,
+    // Feedback wires carry the signal of the previous block to close a loop
+    feedback: bool,
+    channels: usize,
+    // Monitor of the node the wire comes from and its output port, while audio is running
+    levels: Option<(NodeMonitor, usize)>,
+    // Whether dots are drawn moving along the wire while it carries a signal
+    flow: bool,
+    // Levels drawn in the previous frame, to redraw only while they change
+    prev_rms: f32,
+    prev_peak: f32,
+    created: Instant,
 }
 
 impl ConnectionWidget {
@@ -17,6 +81,13 @@ impl ConnectionWidget {
         Self {
             input_socket,
             output_socket: Entity::null(),
+            feedback: false,
+            channels: 1,
+            levels: None,
+            flow: false,
+            prev_rms: 0.0,
+            prev_peak: 0.0,
+            created: Instant::now(),
         }
     }
 }
@@ -39,14 +110,81 @@ impl Widget for ConnectionWidget {
             let input_bounds = state.data.get_bounds(self.input_socket);
             let output_bounds = state.data.get_bounds(self.output_socket);
 
+            let points = wire_points(output_bounds, input_bounds);
+
             let mut path = Path::new();
-            path.move_to(output_bounds.x + output_bounds.w / 2.0, output_bounds.y + output_bounds.h / 2.0);
-            let mid_x = ((input_bounds.x + input_bounds.w / 2.0) - (output_bounds.x + output_bounds.w / 2.0)) / 2.0;
-            path.bezier_to((input_bounds.x + input_bounds.w / 2.0) - mid_x, output_bounds.y + output_bounds.h / 2.0, (output_bounds.x + output_bounds.w / 2.0) + mid_x, input_bounds.y + input_bounds.h / 2.0, input_bounds.x + input_bounds.w / 2.0, input_bounds.y + input_bounds.h / 2.0);
-            let mut paint = Paint::color(femtovg::Color::rgb(200, 200, 200));
-            paint.set_line_width(2.0);
-            canvas.stroke_path(&mut path, paint);
+            path.move_to(points[0].0, points[0].1);
+            path.bezier_to(points[1].0, points[1].1, points[2].0, points[2].1, points[3].0, points[3].1);
+
+            // While audio is running the wire is brighter and thicker the louder its signal, and red when it clips
+            let levels = self.levels.as_ref().map(|(monitor, port)| (monitor.port_rms(*port), monitor.port_peak(*port)));
+            let (rms, peak) = levels.unwrap_or((0.0, 0.0));
+            let clipping = self.levels.as_ref().map(|(monitor, port)| monitor.port_clipping(*port)).unwrap_or(false);
+            let level = level_position(rms);
+            let mut color = if self.feedback { femtovg::Color::rgb(230, 150, 50) } else { femtovg::Color::rgb(200, 200, 200) };
+            if levels.is_some() {
+                if clipping {
+                    color = femtovg::Color::rgb(220, 60, 60);
+                } else {
+                    let brightness = 0.35 + 0.65 * level;
+                    color = femtovg::Color::rgbf(color.r * brightness, color.g * brightness, color.b * brightness);
+                }
+            }
+            let mut width = 2.0;
+            if levels.is_some() {
+                width += 2.0 * level;
+            }
+
+            // Wires with several channels are drawn as a line for each channel side by side
+            let lines = self.channels.clamp(1, MAX_WIRE_LINES);
+            if lines == 1 {
+                let mut paint = Paint::color(color);
+                paint.set_line_width(width);
+                canvas.stroke_path(&mut path, paint);
+            } else {
+                let spacing = width + 1.5;
+                for line in 0..lines {
+                    let offset = (line as f32 - (lines - 1) as f32 / 2.0) * spacing;
+                    let mut path = offset_wire_path(points, offset);
+                    let mut paint = Paint::color(color);
+                    paint.set_line_width(width);
+                    canvas.stroke_path(&mut path, paint);
+                }
+            }
+
+            // Feedback wires are marked with a ring half way along, standing for the delay
+            if self.feedback {
+                let mid_x = (output_bounds.x + output_bounds.w / 2.0 + input_bounds.x + input_bounds.w / 2.0) / 2.0;
+                let mid_y = (output_bounds.y + output_bounds.h / 2.0 + input_bounds.y + input_bounds.h / 2.0) / 2.0;
+                let mut ring = Path::new();
+                ring.circle(mid_x, mid_y, 5.0);
+                canvas.fill_path(&mut ring, Paint::color(femtovg::Color::rgb(30, 30, 30)));
+                let mut paint = Paint::color(color);
+                paint.set_line_width(2.0);
+                canvas.stroke_path(&mut ring, paint);
+            }
+
+            // Dots move from the output to the input while there is a signal
+            let flowing = self.flow && level > 0.0;
+            if flowing {
+                let phase = (self.created.elapsed().as_secs_f32() / FLOW_PERIOD).fract();
+                for dot in 0..FLOW_DOTS {
+                    let t = (phase + dot as f32 / FLOW_DOTS as f32).fract();
+                    let (x, y) = bezier_point(points, t);
+                    let mut path = Path::new();
+                    path.circle(x, y, 2.0 + level);
+                    canvas.fill_path(&mut path, Paint::color(color));
+                }
+            }
+
             canvas.restore();
+
+            // Keep redrawing while the levels change or the dots are moving
+            if rms != self.prev_rms || peak != self.prev_peak || flowing {
+                state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+            }
+            self.prev_rms = rms;
+            self.prev_peak = peak;
         }
     }
 
@@ -60,6 +198,25 @@ impl Widget for ConnectionWidget {
 
                 NodeEvent::Disconnect => {
                     self.output_socket = Entity::null();
+                    self.feedback = false;
+                    self.channels = 1;
+                    self.levels = None;
+                }
+
+                NodeEvent::Feedback(feedback) => {
+                    self.feedback = *feedback;
+                }
+
+                NodeEvent::Channels(channels) => {
+                    self.channels = *channels;
+                }
+
+                NodeEvent::Levels(monitor, port) => {
+                    self.levels = monitor.clone().map(|monitor| (monitor, *port));
+                }
+
+                NodeEvent::Flow(flow) => {
+                    self.flow = *flow;
                 }
 
                 _=> {}
@@ -79,6 +236,8 @@ pub struct InputSocket {
     snapped_socket: Entity,
     // Flag to determine if the connection is snapping to the hovered socket
     snapping: bool,
+    // When a wire to the socket was last refused, to flash the socket
+    rejected: Option<Instant>,
 }
 
 impl InputSocket {
@@ -89,6 +248,7 @@ impl InputSocket {
             connected_output: Entity::null(),
             snapping: false,
             snapped_socket: Entity::null(),
+            rejected: None,
         }
     }
 }
@@ -164,6 +324,7 @@ impl Widget for InputSocket {
                 WindowEvent::MouseOut => {
                     
                     if self.connected_output != Entity::null() && self.connecting {
+                        state.insert_event(Event::new(NodeEvent::Disconnected(self.connected_output, entity)).target(entity).origin(entity));
                         state.insert_event(Event::new(NodeEvent::Disconnect).direct(entity).origin(entity));
                         state.insert_event(Event::new(NodeEvent::Disconnect).direct(self.connected_output).origin(entity));
                         self.connecting = false;
@@ -181,6 +342,7 @@ impl Widget for InputSocket {
                     if event.target == entity && event.origin != entity {
                         self.connected_output = event.origin;
                         state.insert_event(Event::new(NodeEvent::ConnectSockets(event.origin)).direct(self.connection).origin(entity));
+                        state.insert_event(Event::new(NodeEvent::Connected(event.origin, entity)).target(entity).origin(entity));
                     }
                 }
 
@@ -199,6 +361,20 @@ impl Widget for InputSocket {
                     }
                 }
 
+                // Passed on to the wire
+                NodeEvent::Feedback(_) | NodeEvent::Channels(_) | NodeEvent::Levels(..) | NodeEvent::Flow(_) => {
+                    if event.target == entity {
+                        state.insert_event(Event::new(node_event.clone()).direct(self.connection).origin(entity));
+                    }
+                }
+
+                NodeEvent::Rejected => {
+                    if event.target == entity {
+                        self.rejected = Some(Instant::now());
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    }
+                }
+
                 _=> {}
             }
         }
@@ -327,6 +503,17 @@ impl Widget for InputSocket {
 
         canvas.fill_path(&mut path, paint);
 
+        // A socket which refused a wire is ringed in red, fading out
+        let flash = self.rejected.map(|rejected| 1.0 - rejected.elapsed().as_secs_f32() / REJECT_FLASH_TIME).unwrap_or(0.0);
+        if flash > 0.0 {
+            let mut ring = Path::new();
+            ring.circle(bounds.w / 2.0, bounds.h / 2.0, bounds.w / 2.0 + 2.0);
+            let mut paint = Paint::color(femtovg::Color::rgbaf(0.9, 0.25, 0.25, flash));
+            paint.set_line_width(3.0);
+            canvas.stroke_path(&mut ring, paint);
+            state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+        }
+
         canvas.restore();
 
         // canvas.save();
@@ -374,6 +561,8 @@ pub struct OutputSocket {
     snapped_socket: Entity,
 
     snapping: bool,
+    // Whether the signal of this output is being heard on its own
+    probed: bool,
 }
 
 impl OutputSocket {
@@ -382,6 +571,7 @@ impl OutputSocket {
             connecting: false,
             snapping: false,
             snapped_socket: Entity::null(),
+            probed: false,
         }
     }
 }
@@ -411,7 +601,10 @@ impl Widget for OutputSocket {
         if let Some(window_event) = event.message.downcast() {
             match window_event {
                 WindowEvent::MouseDown(button) => {
-                    if *button == MouseButton::Left {
+                    if *button == MouseButton::Left && state.modifiers.alt {
+                        state.insert_event(Event::new(NodeEvent::Probe).target(entity).origin(entity));
+                        event.consume();
+                    } else if *button == MouseButton::Left {
                         state.capture(entity);
                         self.connecting = true;
                         state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
@@ -420,7 +613,7 @@ impl Widget for OutputSocket {
                 }
 
                 WindowEvent::MouseUp(button) => {
-                    if *button == MouseButton::Left {
+                    if *button == MouseButton::Left && self.connecting {
                         state.release(entity);
                         self.connecting = false;
                         state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
@@ -473,6 +666,11 @@ impl Widget for OutputSocket {
                     }
                 }
 
+                NodeEvent::Probed(probed) => {
+                    self.probed = *probed;
+                    state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                }
+
                 _=> {}
             }
         }
@@ -600,6 +798,15 @@ impl Widget for OutputSocket {
 
         canvas.fill_path(&mut path, paint);
 
+        // A probed output is ringed, as the only signal being heard
+        if self.probed {
+            let mut ring = Path::new();
+            ring.circle(bounds.w / 2.0, bounds.h / 2.0, bounds.w / 2.0 + 2.0);
+            let mut paint = Paint::color(femtovg::Color::rgb(230, 200, 50));
+            paint.set_line_width(2.0);
+            canvas.stroke_path(&mut ring, paint);
+        }
+
         canvas.restore();
 
         
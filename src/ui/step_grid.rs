@@ -0,0 +1,270 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::{StepPattern, MAX_STEPS, MAX_STEP_PITCH};
+
+// Padding between the edge of the grid and the steps
+const PADDING: f32 = 6.0;
+// Height of the row of lane buttons below the steps
+const LANE_ROW_HEIGHT: f32 = 20.0;
+// Steps shown in each row of the grid
+const STEPS_PER_ROW: usize = 16;
+// Gap between neighbouring steps
+const STEP_GAP: f32 = 1.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepEvent {
+    // Sent up the tree whenever a step is edited
+    PatternChanged(StepPattern),
+}
+
+// The value of each step which is shown and edited by the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepLane {
+    Gate,
+    Pitch,
+    Velocity,
+    Probability,
+}
+
+impl StepLane {
+    pub const ALL: &'static [StepLane] = &[
+        StepLane::Gate,
+        StepLane::Pitch,
+        StepLane::Velocity,
+        StepLane::Probability,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StepLane::Gate => "Gate",
+            StepLane::Pitch => "Pitch",
+            StepLane::Velocity => "Vel",
+            StepLane::Probability => "Prob",
+        }
+    }
+}
+
+// Editor for the steps of a sequencer, shown as two rows of steps.
+//
+// The buttons below the steps pick which value is edited. Clicking a step toggles its gate,
+// or for the other values sets the value from the height of the cursor within the step.
+// Dragging across steps applies the same edit to each of them.
+pub struct StepGrid {
+    pattern: StepPattern,
+    lane: StepLane,
+    drawing: bool,
+    // Gate given to each step dragged over, set by the first step clicked
+    drag_gate: bool,
+    // Lane buttons and the lane each one selects
+    lanes: Vec<(Entity, StepLane)>,
+}
+
+impl StepGrid {
+    pub fn new(pattern: StepPattern) -> Self {
+        Self {
+            pattern,
+            lane: StepLane::Gate,
+            drawing: false,
+            drag_gate: false,
+            lanes: Vec::new(),
+        }
+    }
+
+    // Area of the grid in which the steps are drawn
+    fn plot_bounds(bounds: BoundingBox) -> BoundingBox {
+        BoundingBox {
+            x: bounds.x + PADDING,
+            y: bounds.y + PADDING,
+            w: bounds.w - 2.0 * PADDING,
+            h: bounds.h - 2.0 * PADDING - LANE_ROW_HEIGHT,
+        }
+    }
+
+    fn step_bounds(plot: BoundingBox, index: usize) -> BoundingBox {
+        let rows = MAX_STEPS / STEPS_PER_ROW;
+        let w = plot.w / STEPS_PER_ROW as f32;
+        let h = plot.h / rows as f32;
+
+        BoundingBox {
+            x: plot.x + w * (index % STEPS_PER_ROW) as f32,
+            y: plot.y + h * (index / STEPS_PER_ROW) as f32,
+            w: w - STEP_GAP,
+            h: h - STEP_GAP,
+        }
+    }
+
+    // Find the step under a window position, with the height of the position in the step from 0 at the bottom to 1 at the top
+    fn step_at(state: &State, entity: Entity, x: f32, y: f32) -> Option<(usize, f32)> {
+        let mut transform = state.data.get_transform(entity);
+        transform.inverse();
+        let (x, y) = transform.transform_point(x, y);
+
+        let plot = Self::plot_bounds(state.data.get_bounds(entity));
+        if x < plot.x || x >= plot.x + plot.w || y < plot.y || y >= plot.y + plot.h {
+            return None;
+        }
+
+        let rows = MAX_STEPS / STEPS_PER_ROW;
+        let column = (((x - plot.x) / plot.w * STEPS_PER_ROW as f32) as usize).min(STEPS_PER_ROW - 1);
+        let row = (((y - plot.y) / plot.h * rows as f32) as usize).min(rows - 1);
+        let index = row * STEPS_PER_ROW + column;
+
+        let step = Self::step_bounds(plot, index);
+        let height = (1.0 - (y - step.y) / step.h).clamp(0.0, 1.0);
+        Some((index, height))
+    }
+
+    fn edit_step(&mut self, index: usize, height: f32) {
+        let step = &mut self.pattern.steps[index];
+        match self.lane {
+            StepLane::Gate => step.gate = self.drag_gate,
+            StepLane::Pitch => step.pitch = ((2.0 * height - 1.0) * MAX_STEP_PITCH).round(),
+            StepLane::Velocity => step.velocity = height,
+            StepLane::Probability => step.probability = height,
+        }
+    }
+
+    fn pattern_changed(&self, state: &mut State, entity: Entity) {
+        state.insert_event(Event::new(StepEvent::PatternChanged(self.pattern)).target(entity).origin(entity));
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+
+    fn select_lane(&mut self, state: &mut State, lane: StepLane) {
+        self.lane = lane;
+        for (button, button_lane) in self.lanes.iter() {
+            let color = if *button_lane == lane { Color::rgb(60, 60, 60) } else { Color::rgba(0, 0, 0, 0) };
+            button.set_background_color(state, color);
+        }
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+}
+
+impl Widget for StepGrid {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        let row = Row::new().build(state, entity, |builder|
+            builder
+                .set_height(Pixels(LANE_ROW_HEIGHT))
+                .set_top(Stretch(1.0))
+        );
+
+        for lane in StepLane::ALL.iter() {
+            let button = Label::new(lane.name()).build(state, row, |builder|
+                builder
+                    .set_child_space(Stretch(1.0))
+                    .class("step_lane")
+            );
+
+            self.lanes.push((button, *lane));
+        }
+        self.select_lane(state, self.lane);
+
+        entity
+            .set_height(state, Pixels(120.0))
+            .set_space(state, Pixels(5.0))
+            .class(state, "step_grid")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left {
+                        let picked = self.lanes.iter().find(|(button, _)| *button == event.target).map(|(_, lane)| *lane);
+                        if let Some(lane) = picked {
+                            self.select_lane(state, lane);
+                            event.consume();
+                        }
+
+                        if event.target == entity {
+                            if let Some((index, height)) = Self::step_at(state, entity, state.mouse.cursorx, state.mouse.cursory) {
+                                self.drag_gate = !self.pattern.steps[index].gate;
+                                self.edit_step(index, height);
+                                self.drawing = true;
+                                state.capture(entity);
+                                self.pattern_changed(state, entity);
+                            }
+                            // Stop the parent node from being moved
+                            event.consume();
+                        }
+                    }
+                }
+
+                WindowEvent::MouseUp(button) => {
+                    if *button == MouseButton::Left && self.drawing {
+                        self.drawing = false;
+                        state.release(entity);
+                    }
+                }
+
+                WindowEvent::MouseMove(x, y) => {
+                    if self.drawing {
+                        if let Some((index, height)) = Self::step_at(state, entity, *x, *y) {
+                            self.edit_step(index, height);
+                            self.pattern_changed(state, entity);
+                        }
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(entity);
+        let transform = state.data.get_transform(entity);
+        let plot = Self::plot_bounds(bounds);
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        let mut path = Path::new();
+        path.rounded_rect(bounds.x, bounds.y, bounds.w, bounds.h, 3.0);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        for (index, step) in self.pattern.steps.iter().enumerate() {
+            let cell = Self::step_bounds(plot, index);
+
+            // Every fourth step is lighter to make the beats easier to count
+            let shade = if index % 4 == 0 { 45 } else { 35 };
+            let mut path = Path::new();
+            path.rect(cell.x, cell.y, cell.w, cell.h);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(shade, shade, shade)));
+
+            // Values of steps without a gate are dimmed as they aren't played
+            let color = if step.gate {
+                femtovg::Color::rgb(80, 80, 220)
+            } else {
+                femtovg::Color::rgb(60, 60, 90)
+            };
+
+            let mut path = Path::new();
+            match self.lane {
+                StepLane::Gate => {
+                    if step.gate {
+                        path.rect(cell.x, cell.y, cell.w, cell.h);
+                    }
+                }
+                StepLane::Pitch => {
+                    let centre = cell.y + cell.h / 2.0;
+                    let top = centre - cell.h / 2.0 * step.pitch / MAX_STEP_PITCH;
+                    path.rect(cell.x, top.min(centre), cell.w, (top - centre).abs().max(1.0));
+                }
+                StepLane::Velocity => {
+                    path.rect(cell.x, cell.y + cell.h * (1.0 - step.velocity), cell.w, cell.h * step.velocity);
+                }
+                StepLane::Probability => {
+                    path.rect(cell.x, cell.y + cell.h * (1.0 - step.probability), cell.w, cell.h * step.probability);
+                }
+            }
+            canvas.fill_path(&mut path, Paint::color(color));
+        }
+
+        canvas.restore();
+    }
+}
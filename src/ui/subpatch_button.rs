@@ -0,0 +1,54 @@
+use tuix::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubpatchEvent {
+    // Show the patch inside the node in the node view
+    Open,
+    // Save the node to the library so it can be added to other patches
+    SaveToLibrary,
+}
+
+// Button on a node which contains a patch, such as a poly node or a group, which sends an event up the tree when clicked
+pub struct SubpatchButton {
+    text: String,
+    event: SubpatchEvent,
+}
+
+impl SubpatchButton {
+    pub fn new(text: &str, event: SubpatchEvent) -> Self {
+        Self {
+            text: text.to_string(),
+            event,
+        }
+    }
+}
+
+impl Widget for SubpatchButton {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        entity
+            .set_text(state, &self.text)
+            .set_height(state, Pixels(25.0))
+            .set_child_space(state, Stretch(1.0))
+            .set_space(state, Pixels(5.0))
+            .set_background_color(state, Color::rgb(15, 15, 15))
+            .class(state, "subpatch_button")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left && event.target == entity {
+                        state.insert_event(Event::new(self.event.clone()).target(entity).origin(entity));
+                        // Stop the parent node from being moved
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use tuix::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextParamEvent {
+    // Sent up the tree with the parameter index when the text is edited
+    TextChanged(usize, String),
+}
+
+// Parameter with a label above a textbox wide enough for a line of text, such as a formula
+pub struct TextParam {
+    index: usize,
+    name: String,
+    text: String,
+
+    textbox: Entity,
+}
+
+impl TextParam {
+    pub fn new(index: usize, name: &str, text: &str) -> Self {
+        Self {
+            index,
+            name: name.to_string(),
+            text: text.to_string(),
+
+            textbox: Entity::null(),
+        }
+    }
+}
+
+impl Widget for TextParam {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        Label::new(&self.name).build(state, entity, |builder|
+            builder
+                .set_height(Pixels(30.0))
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(15.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        self.textbox = Textbox::new(&self.text).build(state, entity, |builder|
+            builder
+                .set_height(Pixels(30.0))
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(5.0))
+                .set_left(Pixels(15.0))
+                .set_right(Pixels(5.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_color(Color::white())
+                .class("text_param")
+        );
+
+        entity
+            .set_height(state, Auto)
+            .set_layout_type(state, LayoutType::Column)
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(textbox_event) = event.message.downcast() {
+            match textbox_event {
+                TextboxEvent::ValueChanged(text) => {
+                    if event.origin == self.textbox {
+                        self.text = text.clone();
+                        state.insert_event(Event::new(TextParamEvent::TextChanged(self.index, text.clone())).target(entity).origin(entity));
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+}
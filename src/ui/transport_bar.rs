@@ -0,0 +1,233 @@
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl,
+};
+
+use crate::audio::{Meter, Transport};
+
+// Slowest and fastest tempo which can be typed in
+const MIN_TEMPO: f32 = 20.0;
+const MAX_TEMPO: f32 = 300.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportEvent {
+    // Sent up the tree whenever the transport is started, stopped or edited, so the engine can follow it
+    Changed(Transport),
+    // Sent to the bar by the engine when it starts, with the song position it updates after each buffer
+    Position(Meter),
+}
+
+// Bar of transport controls shown above the node view, with play and stop buttons,
+// the tempo, the time signature and the song position.
+//
+// The song position shown is the one reached by the engine, which moves it on while playing.
+pub struct TransportBar {
+    transport: Transport,
+    // Song position of the engine, or None while no audio is running
+    position: Option<Meter>,
+
+    play_button: Entity,
+    stop_button: Entity,
+    tempo_box: Entity,
+    signature_box: Entity,
+    position_label: Entity,
+}
+
+impl TransportBar {
+    pub fn new() -> Self {
+        Self {
+            transport: Transport::new(),
+            position: None,
+
+            play_button: Entity::null(),
+            stop_button: Entity::null(),
+            tempo_box: Entity::null(),
+            signature_box: Entity::null(),
+            position_label: Entity::null(),
+        }
+    }
+
+    fn add_button(state: &mut State, parent: Entity, text: &str) -> Entity {
+        Label::new(text).build(state, parent, |builder|
+            builder
+                .set_width(Pixels(50.0))
+                .set_child_space(Stretch(1.0))
+                .set_space(Pixels(5.0))
+                .class("transport_button")
+        )
+    }
+
+    fn add_textbox(state: &mut State, parent: Entity, name: &str, text: &str) -> Entity {
+        Label::new(name).build(state, parent, |builder|
+            builder
+                .set_width(Pixels(40.0))
+                .set_child_space(Stretch(1.0))
+                .set_child_right(Pixels(5.0))
+                .set_space(Pixels(0.0))
+                .set_hoverable(false)
+        );
+
+        Textbox::new(text).build(state, parent, |builder|
+            builder
+                .set_width(Pixels(60.0))
+                .set_child_space(Stretch(1.0))
+                .set_child_left(Pixels(5.0))
+                .set_space(Pixels(5.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_color(Color::white())
+        )
+    }
+
+    fn position_text(&self) -> String {
+        let (bar, beat, sixteenth) = self.transport.bar_beat_sixteenth();
+        format!("{}.{}.{}", bar, beat, sixteenth)
+    }
+
+    // Catch up with the song position of the engine, so an edit to the transport carries on from where it is
+    fn sync_position(&mut self) {
+        if let Some(position) = self.position.as_ref() {
+            self.transport.position = position.get() as f64;
+        }
+    }
+
+    fn changed(&mut self, state: &mut State, entity: Entity) {
+        self.play_button.set_text(state, if self.transport.playing { "Pause" } else { "Play" });
+        self.position_label.set_text(state, &self.position_text());
+        state.insert_event(Event::new(TransportEvent::Changed(self.transport)).target(entity).origin(entity));
+        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+    }
+
+    fn set_tempo(&mut self, text: &str) {
+        if let Ok(tempo) = text.trim().parse::<f32>() {
+            if tempo.is_finite() {
+                self.transport.tempo = tempo.clamp(MIN_TEMPO, MAX_TEMPO);
+            }
+        }
+    }
+
+    // Parse a time signature such as "6/8"
+    fn set_signature(&mut self, text: &str) {
+        let mut parts = text.split('/').map(|part| part.trim().parse::<u32>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(numerator)), Some(Ok(denominator)), None)
+                if numerator > 0 && numerator <= 32 && [1, 2, 4, 8, 16, 32].contains(&denominator) =>
+            {
+                self.transport.numerator = numerator;
+                self.transport.denominator = denominator;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Widget for TransportBar {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        self.play_button = Self::add_button(state, entity, "Play");
+        self.stop_button = Self::add_button(state, entity, "Stop");
+
+        let tempo = self.transport.tempo.to_string();
+        self.tempo_box = Self::add_textbox(state, entity, "BPM", &tempo);
+        let signature = format!("{}/{}", self.transport.numerator, self.transport.denominator);
+        self.signature_box = Self::add_textbox(state, entity, "Time", &signature);
+
+        self.position_label = Label::new(&self.position_text()).build(state, entity, |builder|
+            builder
+                .set_width(Pixels(80.0))
+                .set_child_space(Stretch(1.0))
+                .set_space(Pixels(5.0))
+                .set_background_color(Color::rgb(15, 15, 15))
+                .set_hoverable(false)
+        );
+
+        entity
+            .set_height(state, Pixels(40.0))
+            .set_layout_type(state, LayoutType::Row)
+            .set_child_space(state, Stretch(1.0))
+            .set_child_left(state, Pixels(5.0))
+            .class(state, "transport_bar")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if *button == MouseButton::Left {
+                        if event.target == self.play_button {
+                            self.sync_position();
+                            if self.transport.playing {
+                                self.transport.playing = false;
+                            } else {
+                                self.transport.play();
+                            }
+                            self.changed(state, entity);
+                            event.consume();
+                        } else if event.target == self.stop_button {
+                            self.sync_position();
+                            self.transport.stop();
+                            self.changed(state, entity);
+                            event.consume();
+                        }
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(textbox_event) = event.message.downcast() {
+            match textbox_event {
+                TextboxEvent::ValueChanged(text) => {
+                    // Invalid text is replaced by the current value
+                    if event.origin == self.tempo_box || event.origin == self.signature_box {
+                        self.sync_position();
+                    }
+                    if event.origin == self.tempo_box {
+                        self.set_tempo(text);
+                        self.tempo_box.set_text(state, &self.transport.tempo.to_string());
+                        self.changed(state, entity);
+                        event.consume();
+                    } else if event.origin == self.signature_box {
+                        self.set_signature(text);
+                        let signature = format!("{}/{}", self.transport.numerator, self.transport.denominator);
+                        self.signature_box.set_text(state, &signature);
+                        self.changed(state, entity);
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(transport_event) = event.message.downcast() {
+            match transport_event {
+                TransportEvent::Position(position) => {
+                    self.position = Some(position.clone());
+                    event.consume();
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    // The bar has nothing of its own to draw, so drawing is used to show the song position of the engine
+    fn on_draw(&mut self, state: &mut State, _entity: Entity, _canvas: &mut Canvas<OpenGl>) {
+        let position = match self.position.as_ref() {
+            Some(position) => position.get() as f64,
+            None => return,
+        };
+
+        if position != self.transport.position {
+            self.transport.position = position;
+            self.position_label.set_text(state, &self.position_text());
+        }
+
+        // Keep checking while the engine moves the position on
+        if self.transport.playing {
+            state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use tuix::*;
+use femtovg::{
+    Canvas, renderer::OpenGl, Paint, Path,
+};
+
+use crate::audio::AudioFile;
+
+// Number of columns of peaks in the waveform thumbnail
+const THUMBNAIL_COLUMNS: usize = 180;
+// Distance in pixels within which a marker can be grabbed
+const HANDLE_RADIUS: f32 = 5.0;
+// Padding between the edge of the view and the waveform
+const PADDING: f32 = 4.0;
+// Colours given to markers in the order they are added
+const MARKER_COLORS: &[(u8, u8, u8)] = &[(0, 160, 0), (200, 60, 60), (220, 140, 40), (220, 140, 40)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaveformEvent {
+    // Sent directly to the view once its file has been loaded
+    SetFile(Arc<AudioFile>),
+    // Sent up the tree with the parameter index and new position whenever a marker is dragged
+    MarkerChanged(usize, f32),
+    // Sent directly to the view with the parameter index and position of a marker moved somewhere else, such as in
+    // the inspector
+    SetMarker(usize, f32),
+}
+
+// Thumbnail of an audio file with draggable markers for positions in the file, such as loop points
+pub struct WaveformView {
+    // Lowest and highest sample in each column of the thumbnail
+    peaks: Vec<(f32, f32)>,
+    // Parameter index and position from 0 to 1 of each marker
+    markers: Vec<(usize, f32)>,
+    // Index into `markers` of the marker being dragged
+    dragging: Option<usize>,
+}
+
+impl WaveformView {
+    pub fn new(markers: Vec<(usize, f32)>) -> Self {
+        Self {
+            peaks: Vec::new(),
+            markers,
+            dragging: None,
+        }
+    }
+
+    fn compute_peaks(&mut self, file: &AudioFile) {
+        self.peaks.clear();
+        let length = file.len();
+        if length == 0 {
+            return;
+        }
+
+        for column in 0..THUMBNAIL_COLUMNS {
+            let start = column * length / THUMBNAIL_COLUMNS;
+            let end = ((column + 1) * length / THUMBNAIL_COLUMNS).max(start + 1).min(length);
+
+            let mut peak = (0.0f32, 0.0f32);
+            for channel in file.channels.iter() {
+                for sample in channel[start..end].iter() {
+                    peak.0 = peak.0.min(*sample);
+                    peak.1 = peak.1.max(*sample);
+                }
+            }
+            self.peaks.push(peak);
+        }
+    }
+
+    // Area of the view in which the waveform is drawn
+    fn plot_bounds(bounds: BoundingBox) -> BoundingBox {
+        BoundingBox {
+            x: bounds.x + PADDING,
+            y: bounds.y + PADDING,
+            w: bounds.w - 2.0 * PADDING,
+            h: bounds.h - 2.0 * PADDING,
+        }
+    }
+
+    // Convert a window position into the (untransformed) coordinate space of the widget bounds
+    fn local_position(state: &State, entity: Entity, x: f32, y: f32) -> (f32, f32) {
+        let mut transform = state.data.get_transform(entity);
+        transform.inverse();
+        transform.transform_point(x, y)
+    }
+}
+
+impl Widget for WaveformView {
+    type Ret = Entity;
+    type Data = ();
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        entity
+            .set_height(state, Pixels(60.0))
+            .set_space(state, Pixels(5.0))
+            .class(state, "waveform_view")
+    }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        if let Some(window_event) = event.message.downcast() {
+            match window_event {
+                WindowEvent::MouseDown(button) => {
+                    if event.target == entity && *button == MouseButton::Left {
+                        let (x, _) = Self::local_position(state, entity, state.mouse.cursorx, state.mouse.cursory);
+                        let plot = Self::plot_bounds(state.data.get_bounds(entity));
+                        self.dragging = self
+                            .markers
+                            .iter()
+                            .position(|(_, value)| (plot.x + plot.w * value - x).abs() <= HANDLE_RADIUS);
+                        if self.dragging.is_some() {
+                            state.capture(entity);
+                        }
+                        // Stop the parent node from being moved
+                        event.consume();
+                    }
+                }
+
+                WindowEvent::MouseUp(button) => {
+                    if *button == MouseButton::Left && self.dragging.is_some() {
+                        self.dragging = None;
+                        state.release(entity);
+                    }
+                }
+
+                WindowEvent::MouseMove(x, y) => {
+                    if let Some(marker) = self.dragging {
+                        let (x, _) = Self::local_position(state, entity, *x, *y);
+                        let plot = Self::plot_bounds(state.data.get_bounds(entity));
+                        let value = ((x - plot.x) / plot.w).clamp(0.0, 1.0);
+                        self.markers[marker].1 = value;
+
+                        let index = self.markers[marker].0;
+                        state.insert_event(Event::new(WaveformEvent::MarkerChanged(index, value)).target(entity).origin(entity));
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    }
+                }
+
+                _=> {}
+            }
+        }
+
+        if let Some(waveform_event) = event.message.downcast() {
+            match waveform_event {
+                WaveformEvent::SetFile(file) => {
+                    if event.target == entity {
+                        self.compute_peaks(file);
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                    }
+                }
+
+                WaveformEvent::SetMarker(index, value) => {
+                    if let Some(marker) = self.markers.iter_mut().find(|(marker_index, _)| marker_index == index) {
+                        marker.1 = *value;
+                        state.insert_event(Event::new(WindowEvent::Redraw).target(Entity::root()));
+                        event.consume();
+                    }
+                }
+
+                _=> {}
+            }
+        }
+    }
+
+    fn on_draw(&mut self, state: &mut State, entity: Entity, canvas: &mut Canvas<OpenGl>) {
+        let bounds = state.data.get_bounds(entity);
+        let transform = state.data.get_transform(entity);
+        let plot = Self::plot_bounds(bounds);
+
+        canvas.save();
+        canvas.set_transform(transform[0], transform[1], transform[2], transform[3], transform[4], transform[5]);
+
+        let mut path = Path::new();
+        path.rounded_rect(bounds.x, bounds.y, bounds.w, bounds.h, 3.0);
+        canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(15, 15, 15)));
+
+        let centre = plot.y + plot.h / 2.0;
+        let mut path = Path::new();
+        for (column, (min, max)) in self.peaks.iter().enumerate() {
+            let x = plot.x + plot.w * (column as f32 + 0.5) / THUMBNAIL_COLUMNS as f32;
+            path.move_to(x, centre - max.clamp(-1.0, 1.0) * plot.h / 2.0);
+            path.line_to(x, centre - min.clamp(-1.0, 1.0) * plot.h / 2.0 + 1.0);
+        }
+        let mut paint = Paint::color(femtovg::Color::rgb(150, 150, 150));
+        paint.set_line_width(1.0);
+        canvas.stroke_path(&mut path, paint);
+
+        for (marker, (_, value)) in self.markers.iter().enumerate() {
+            let (r, g, b) = MARKER_COLORS[marker % MARKER_COLORS.len()];
+            let x = plot.x + plot.w * value;
+
+            let mut path = Path::new();
+            path.move_to(x, bounds.y);
+            path.line_to(x, bounds.y + bounds.h);
+            let mut paint = Paint::color(femtovg::Color::rgb(r, g, b));
+            paint.set_line_width(1.0);
+            canvas.stroke_path(&mut path, paint);
+
+            let mut path = Path::new();
+            path.rect(x - 3.0, bounds.y, 6.0, 6.0);
+            canvas.fill_path(&mut path, Paint::color(femtovg::Color::rgb(r, g, b)));
+        }
+
+        canvas.restore();
+    }
+}